@@ -0,0 +1,182 @@
+use once_cell::sync::OnceCell;
+use serde_json::json;
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use crate::{perms::settle, utils::run_accession_of};
+
+/// Who is downloading: user, host and process
+struct Actor {
+    user: String,
+    host: String,
+    pid: u32,
+}
+
+static AUDIT: OnceCell<(PathBuf, Actor)> = OnceCell::new();
+
+// INFO: concurrent downloads append to the same log
+static AUDIT_LOCK: Mutex<()> = Mutex::new(());
+
+/// What happened to a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Downloaded,
+    RetrieverFailed,
+    ChecksumFailed,
+    GaveUp,
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Downloaded => write!(f, "downloaded"),
+            Action::RetrieverFailed => write!(f, "retriever_failed"),
+            Action::ChecksumFailed => write!(f, "checksum_failed"),
+            Action::GaveUp => write!(f, "gave_up"),
+        }
+    }
+}
+
+/// One audited transfer
+#[derive(Debug, Clone)]
+pub struct Entry<'a> {
+    pub action: Action,
+    pub url: &'a str,
+    pub file: &'a Path,
+    pub retriever: &'a str,
+    pub expected_md5: &'a str,
+    pub observed_md5: Option<&'a str>,
+}
+
+/// Set the file the audit log is appended to. Only the first call has effect.
+///
+/// Unlike the batch report, the log is never truncated: entries of every
+/// invocation pile up, so it can be kept as a governance record.
+///
+/// # Arguments
+/// * `path` - The audit log, from `--audit-log`; `None` audits nothing.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::audit::init_audit;
+/// use std::path::PathBuf;
+/// init_audit(Some(PathBuf::from("/shared/rsfq.audit.jsonl")));
+/// ```
+pub fn init_audit(path: Option<PathBuf>) {
+    if let Some(path) = path {
+        let actor = Actor {
+            user: user(),
+            host: host(),
+            pid: std::process::id(),
+        };
+        log::info!("Auditing downloads to {}", path.display());
+        let _ = AUDIT.set((path, actor));
+    }
+}
+
+/// Append a transfer to the audit log, one JSON object per line: when, who
+/// (user, host, pid), what (run, file, bytes), from where (URL, retriever)
+/// and the checksum result (`ok`, `mismatch` or `skipped` with `--force`).
+///
+/// Without an audit log, this is a no-op.
+///
+/// # Arguments
+/// * `entry` - The transfer.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::audit::{audit, Action, Entry};
+/// use std::path::Path;
+/// audit(&Entry {
+///     action: Action::Downloaded,
+///     url: "ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR123456/SRR123456_1.fastq.gz",
+///     file: Path::new("DOWNLOADS/SRR123456_1.fastq.gz"),
+///     retriever: "aria2c",
+///     expected_md5: "d41d8cd98f00b204e9800998ecf8427e",
+///     observed_md5: Some("d41d8cd98f00b204e9800998ecf8427e"),
+/// });
+/// ```
+pub fn audit(entry: &Entry) {
+    let Some((path, actor)) = AUDIT.get() else {
+        return;
+    };
+
+    let name = entry
+        .file
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let checksum = match entry.observed_md5 {
+        None => "skipped",
+        Some(observed) if observed == entry.expected_md5 => "ok",
+        Some(_) => "mismatch",
+    };
+    let line = json!({
+        "time": humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+        "user": actor.user,
+        "host": actor.host,
+        "pid": actor.pid,
+        "action": entry.action.to_string(),
+        "run": run_accession_of(&name),
+        "url": entry.url,
+        "file": entry.file.to_string_lossy(),
+        "bytes": std::fs::metadata(entry.file).map(|m| m.len()).ok(),
+        "retriever": entry.retriever,
+        "expected_md5": entry.expected_md5,
+        "observed_md5": entry.observed_md5,
+        "checksum": checksum,
+    });
+
+    let _guard = AUDIT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut handle| handle.write_all(format!("{}\n", line).as_bytes()));
+
+    match written {
+        Ok(()) => settle(path),
+        Err(e) => log::warn!(
+            "WARNING: Could not write audit log {}!: {}",
+            path.display(),
+            e
+        ),
+    }
+}
+
+/// Get the name of the user running rsfq.
+fn user() -> String {
+    if let Ok(user) = std::env::var("USER").or_else(|_| std::env::var("LOGNAME")) {
+        return user;
+    }
+
+    #[cfg(unix)]
+    {
+        // SAFETY: getuid cannot fail
+        format!("uid:{}", unsafe { libc::getuid() })
+    }
+    #[cfg(not(unix))]
+    {
+        "-".to_string()
+    }
+}
+
+/// Get the name of the machine running rsfq.
+pub(crate) fn host() -> String {
+    #[cfg(unix)]
+    {
+        let mut name = [0u8; 256];
+        // SAFETY: the buffer is writable and its length is passed along
+        if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } == 0 {
+            let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            return String::from_utf8_lossy(&name[..end]).to_string();
+        }
+    }
+    std::env::var("HOSTNAME").unwrap_or_else(|_| "-".to_string())
+}
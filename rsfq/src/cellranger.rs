@@ -0,0 +1,171 @@
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::utils::base_name;
+
+const RUN_ACCESSION: &str = "run_accession";
+const SAMPLE_ALIAS: &str = "sample_alias";
+const SAMPLE_ACCESSION: &str = "sample_accession";
+
+static ENABLED: OnceCell<bool> = OnceCell::new();
+static SUFFIX_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:_([1-4]))?\.f(?:ast)?q\.gz$")
+        .unwrap_or_else(|e| panic!("Failed to compile SUFFIX_RE regex: {}", e))
+});
+
+/// Sample, sample number and lane of every run seen so far, plus the
+/// number and lanes used per sample
+#[derive(Default)]
+struct Lanes {
+    runs: HashMap<String, (String, usize, usize)>,
+    samples: HashMap<String, (usize, usize)>,
+}
+
+static LANES: Lazy<Mutex<Lanes>> = Lazy::new(|| Mutex::new(Lanes::default()));
+
+/// Name output files after the CellRanger convention. Only the first call has effect.
+///
+/// # Arguments
+/// * `enabled` - Whether `--cellranger-names` was given.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::cellranger::init_cellranger;
+/// init_cellranger(true);
+/// ```
+pub fn init_cellranger(enabled: bool) {
+    let _ = ENABLED.set(enabled);
+}
+
+/// Check whether `--cellranger-names` is on.
+///
+/// # Returns
+/// `true` if output files are named after the CellRanger convention.
+pub fn cellranger_enabled() -> bool {
+    ENABLED.get().copied().unwrap_or(false)
+}
+
+/// Give every run a sample name and a lane, before any of them is downloaded.
+///
+/// The sample name comes from the rename map if the run is in it, then from
+/// `sample_alias`, then from `sample_accession`. Samples are numbered `S1`,
+/// `S2`... in the order they first appear, and runs of the same sample
+/// become lanes `L001`, `L002`... in the order given; runs that already have
+/// a lane keep it.
+///
+/// # Arguments
+/// * `runs` - The run metadata, in the order of the resolved plan.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::cellranger::{assign_lanes, init_cellranger};
+/// use std::collections::HashMap;
+/// init_cellranger(true);
+/// assign_lanes(&[HashMap::from([
+///     ("run_accession".to_string(), "SRR123456".to_string()),
+///     ("sample_alias".to_string(), "pbmc_10k".to_string()),
+/// ])]);
+/// ```
+pub fn assign_lanes<'a>(runs: impl IntoIterator<Item = &'a HashMap<String, String>>) {
+    if !cellranger_enabled() {
+        return;
+    }
+
+    let mut lanes = LANES.lock().unwrap_or_else(|e| e.into_inner());
+    for run in runs {
+        let Some(accession) = run.get(RUN_ACCESSION) else {
+            continue;
+        };
+        if lanes.runs.contains_key(accession) {
+            continue;
+        }
+
+        let sample = sanitize(&sample_of(accession, run));
+        let (number, lane) = {
+            let next = lanes.samples.len() + 1;
+            let (number, used) = lanes.samples.entry(sample.clone()).or_insert((next, 0));
+            *used += 1;
+            (*number, *used)
+        };
+
+        log::info!(
+            "{} is lane L{:03} of sample {} (S{})",
+            accession,
+            lane,
+            sample,
+            number
+        );
+        lanes.runs.insert(accession.clone(), (sample, number, lane));
+    }
+}
+
+/// Get the CellRanger name of a run's FASTQ file.
+///
+/// `_1`, `_2`, `_3` and `_4` files become `R1`, `R2`, `I1` and `I2`; an
+/// unsuffixed file becomes `R1`.
+///
+/// # Arguments
+/// * `accession` - The run accession.
+/// * `suffix` - What follows the accession in the file name, e.g. `_1.fastq.gz`.
+///
+/// # Returns
+/// A name like `pbmc_10k_S1_L001_R1_001.fastq.gz`, or `None` if the run has
+/// no lane or the file is not a FASTQ.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::cellranger::cellranger_name;
+/// let name = cellranger_name("SRR123456", "_2.fastq.gz");
+/// ```
+pub fn cellranger_name(accession: &str, suffix: &str) -> Option<String> {
+    if !cellranger_enabled() {
+        return None;
+    }
+
+    let read = match SUFFIX_RE.captures(suffix)?.get(1).map(|m| m.as_str()) {
+        None | Some("1") => "R1",
+        Some("2") => "R2",
+        Some("3") => "I1",
+        _ => "I2",
+    };
+
+    let lanes = LANES.lock().unwrap_or_else(|e| e.into_inner());
+    let (sample, number, lane) = lanes.runs.get(accession)?;
+
+    Some(format!(
+        "{}_S{}_L{:03}_{}_001.fastq.gz",
+        sample, number, lane, read
+    ))
+}
+
+/// Pick the sample name of a run.
+fn sample_of(accession: &str, run: &HashMap<String, String>) -> String {
+    let mapped = base_name(accession);
+    if mapped != accession {
+        return mapped;
+    }
+
+    [SAMPLE_ALIAS, SAMPLE_ACCESSION]
+        .iter()
+        .filter_map(|field| run.get(*field))
+        .find(|value| !value.trim().is_empty())
+        .cloned()
+        .unwrap_or_else(|| accession.to_string())
+}
+
+/// Keep only the characters CellRanger accepts in sample names.
+fn sanitize(sample: &str) -> String {
+    sample
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,161 @@
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use once_cell::sync::OnceCell;
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{fasta::lines_per_record, perms::install, preempt::track_file, utils::part_path};
+
+const FASTQ_SUFFIXES: &[&str] = &[".fastq.gz", ".fq.gz", ".fasta.gz"];
+
+static CHUNK_READS: OnceCell<u64> = OnceCell::new();
+
+/// Split every downloaded FASTQ into chunks of `reads` reads. Only the
+/// first call has effect.
+///
+/// # Arguments
+/// * `reads` - The reads per chunk, from `--chunk-reads`; `None` keeps whole files.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::chunk::init_chunks;
+/// init_chunks(Some(4_000_000));
+/// ```
+pub fn init_chunks(reads: Option<u64>) {
+    if let Some(reads) = reads {
+        let _ = CHUNK_READS.set(reads);
+    }
+}
+
+/// Get the number of reads per chunk.
+///
+/// # Returns
+/// The reads per chunk, or `None` if files are kept whole.
+pub fn chunk_reads() -> Option<u64> {
+    CHUNK_READS.get().copied()
+}
+
+/// Get the path of a chunk of a FASTQ, e.g. `SRR123456_1.chunk0001.fastq.gz`.
+///
+/// # Arguments
+/// * `fastq` - The FASTQ being split.
+/// * `idx` - The chunk number, starting at 1.
+///
+/// # Returns
+/// The chunk path, next to the FASTQ.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::chunk::chunk_path;
+/// use std::path::Path;
+/// assert_eq!(
+///     chunk_path(Path::new("SRR123456_1.fastq.gz"), 2),
+///     Path::new("SRR123456_1.chunk0002.fastq.gz")
+/// );
+/// ```
+pub fn chunk_path(fastq: &Path, idx: usize) -> PathBuf {
+    let name = fastq
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (stem, suffix) = FASTQ_SUFFIXES
+        .iter()
+        .find_map(|suffix| Some((name.strip_suffix(suffix)?, *suffix)))
+        .unwrap_or((name.as_str(), ""));
+
+    fastq.with_file_name(format!("{}.chunk{:04}{}", stem, idx, suffix))
+}
+
+/// List the chunks a FASTQ was already split into.
+///
+/// # Arguments
+/// * `fastq` - The FASTQ, which may no longer exist.
+///
+/// # Returns
+/// The chunks, in order; empty if chunking is off or the file was not split.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::chunk::existing_chunks;
+/// use std::path::Path;
+/// let chunks = existing_chunks(Path::new("DOWNLOADS/SRR123456_1.fastq.gz"));
+/// ```
+pub fn existing_chunks(fastq: &Path) -> Vec<PathBuf> {
+    if chunk_reads().is_none() {
+        return vec![];
+    }
+
+    (1..)
+        .map(|idx| chunk_path(fastq, idx))
+        .take_while(|chunk| chunk.exists())
+        .collect()
+}
+
+/// Split a gzipped FASTQ (or FASTA) into gzipped chunks of `reads` records
+/// and remove it.
+///
+/// `_1` and `_2` files of a run hold the same number of records, so chunks
+/// with the same number hold the same mates. Chunks left over from a
+/// previous split are replaced.
+///
+/// # Arguments
+/// * `fastq` - The gzipped FASTQ.
+/// * `reads` - The records per chunk.
+///
+/// # Returns
+/// The chunks, in order.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::chunk::split_fastq;
+/// use std::path::Path;
+/// let chunks = split_fastq(Path::new("DOWNLOADS/SRR123456_1.fastq.gz"), 4_000_000).unwrap();
+/// ```
+pub fn split_fastq(fastq: &Path, reads: u64) -> std::io::Result<Vec<PathBuf>> {
+    for chunk in existing_chunks(fastq) {
+        std::fs::remove_file(chunk)?;
+    }
+
+    let mut lines = BufReader::new(MultiGzDecoder::new(File::open(fastq)?)).lines();
+    let mut chunks = Vec::new();
+
+    while let Some(first) = lines.next() {
+        let chunk = chunk_path(fastq, chunks.len() + 1);
+        let part = part_path(&chunk);
+        let _partial = track_file(&part);
+        let mut writer =
+            GzEncoder::new(BufWriter::new(File::create(&part)?), Compression::default());
+
+        writer.write_all(first?.as_bytes())?;
+        writer.write_all(b"\n")?;
+        for line in lines
+            .by_ref()
+            .take((reads * lines_per_record(fastq) - 1) as usize)
+        {
+            writer.write_all(line?.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+
+        writer.finish()?.flush()?;
+        install(&part, &chunk)?;
+        chunks.push(chunk);
+    }
+
+    // INFO: an empty FASTQ has nothing to split and stays as it is
+    if chunks.is_empty() {
+        return Ok(vec![fastq.to_path_buf()]);
+    }
+
+    std::fs::remove_file(fastq)?;
+    log::info!(
+        "Split {} into {} chunks of {} reads",
+        fastq.display(),
+        chunks.len(),
+        reads
+    );
+
+    Ok(chunks)
+}
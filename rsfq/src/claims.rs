@@ -0,0 +1,204 @@
+use once_cell::sync::OnceCell;
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tokio::task::JoinHandle;
+
+use crate::audit::host;
+use crate::preempt::{track_file, Tracked};
+
+pub const CLAIM_EXT: &str = "claim";
+pub const CLAIM_TIMEOUT: &str = "24h";
+// INFO: a held claim is touched this many times per claim timeout
+const HEARTBEATS_PER_TIMEOUT: u32 = 4;
+
+static CLAIMS: OnceCell<(PathBuf, Duration)> = OnceCell::new();
+
+/// A claimed accession, kept fresh while held and released when dropped
+pub struct Claim {
+    path: Option<PathBuf>,
+    heartbeat: Option<JoinHandle<()>>,
+    _tracked: Option<Tracked>,
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if let Some(heartbeat) = &self.heartbeat {
+            heartbeat.abort();
+        }
+        if let Some(path) = &self.path {
+            if let Err(e) = std::fs::remove_file(path) {
+                log::warn!(
+                    "WARNING: Could not release claim {}!: {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Share the work of an accession list with other machines through a
+/// directory they can all reach (e.g. on NFS). Only the first call has
+/// effect.
+///
+/// # Arguments
+/// * `dir` - The shared claims directory, from `--claims-dir`; `None` claims nothing.
+/// * `timeout` - How old a claim must be to be taken over, from `--claim-timeout`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::claims::init_claims;
+/// use std::path::PathBuf;
+/// use std::time::Duration;
+/// init_claims(Some(PathBuf::from("/shared/claims")), Duration::from_secs(86_400));
+/// ```
+pub fn init_claims(dir: Option<PathBuf>, timeout: Duration) {
+    if let Some(dir) = dir {
+        std::fs::create_dir_all(&dir).unwrap_or_else(|e| {
+            log::error!("ERROR: Could not create claims directory!: {}", e);
+            std::process::exit(1);
+        });
+        log::info!("Sharing accessions through claims in {}", dir.display());
+        let _ = CLAIMS.set((dir, timeout));
+    }
+}
+
+/// Claim an accession, so other machines sharing the claims directory skip
+/// it while it is processed here.
+///
+/// A claim is a `<accession>.claim` file created atomically; it is touched
+/// while held, so a long transfer never looks stale, and removed once the
+/// accession is done, or on SIGTERM. Claims left by a machine that died are
+/// taken over once older than the claim timeout.
+///
+/// # Arguments
+/// * `accession` - The accession to claim.
+///
+/// # Returns
+/// The claim to hold while processing, or `None` if another machine holds it.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::claims::claim;
+/// if let Some(_claim) = claim("SRR123456") {
+///     // download SRR123456
+/// }
+/// ```
+pub fn claim(accession: &str) -> Option<Claim> {
+    let Some((dir, timeout)) = CLAIMS.get() else {
+        return Some(Claim {
+            path: None,
+            heartbeat: None,
+            _tracked: None,
+        });
+    };
+
+    let path = dir.join(format!("{}.{}", accession, CLAIM_EXT));
+    if !try_claim(&path) {
+        if !take_over(&path, *timeout) || !try_claim(&path) {
+            log::info!("{} is claimed by another machine, skipping it", accession);
+            return None;
+        }
+        log::warn!("WARNING: Took over the stale claim of {}", accession);
+    }
+
+    Some(Claim {
+        heartbeat: heartbeat(&path, *timeout),
+        _tracked: Some(track_file(&path)),
+        path: Some(path),
+    })
+}
+
+/// Create a claim file, failing if it already exists.
+///
+/// The claim is written to a file of its own first and hard-linked into
+/// place, which fails if the claim exists, so it never appears half-written.
+/// That file is named after the machine and the process, as processes of
+/// different machines sharing the directory may have the same PID.
+fn try_claim(path: &Path) -> bool {
+    let (host, pid) = (host(), std::process::id());
+    let generation = path.with_extension(format!("{}.{}.{}", CLAIM_EXT, host, pid));
+    let written =
+        File::create(&generation).and_then(|mut file| writeln!(file, "{}\t{}", host, pid));
+    let linked = written.and_then(|()| std::fs::hard_link(&generation, path));
+    let _ = std::fs::remove_file(&generation);
+
+    match linked {
+        Ok(()) => true,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => false,
+        Err(e) => {
+            log::error!("ERROR: Could not claim {}!: {}", path.display(), e);
+            false
+        }
+    }
+}
+
+/// Check whether a claim file was last touched longer than `timeout` ago.
+fn is_stale(path: &Path, timeout: Duration) -> bool {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age > timeout)
+}
+
+/// Move a stale claim out of the way.
+///
+/// The rename is atomic, so only one of several machines racing for the
+/// same claim moves it. What was moved is checked again: if another machine
+/// took the claim over in between, it is a fresh claim and is linked back.
+fn take_over(path: &Path, timeout: Duration) -> bool {
+    if !is_stale(path, timeout) {
+        return false;
+    }
+
+    let moved = path.with_extension(format!(
+        "{}.stale.{}.{}",
+        CLAIM_EXT,
+        host(),
+        std::process::id()
+    ));
+    if std::fs::rename(path, &moved).is_err() {
+        return false;
+    }
+
+    let stale = is_stale(&moved, timeout);
+    if !stale {
+        // INFO: fails if yet another claim was made meanwhile, which then holds it
+        let _ = std::fs::hard_link(&moved, path);
+    }
+    let _ = std::fs::remove_file(&moved);
+    stale
+}
+
+/// Touch a held claim every fraction of the timeout, so other machines do
+/// not take it over during a long transfer.
+fn heartbeat(path: &Path, timeout: Duration) -> Option<JoinHandle<()>> {
+    let runtime = tokio::runtime::Handle::try_current().ok()?;
+    let path = path.to_path_buf();
+    let every = (timeout / HEARTBEATS_PER_TIMEOUT).max(Duration::from_secs(1));
+
+    Some(runtime.spawn(async move {
+        let mut interval = tokio::time::interval(every);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let touched = OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(SystemTime::now()));
+            if let Err(e) = touched {
+                log::warn!(
+                    "WARNING: Could not refresh claim {}!: {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+    }))
+}
@@ -1,9 +1,20 @@
-use clap::{ArgAction, ArgGroup, Parser};
-use std::{path::PathBuf, str::FromStr};
+use clap::{ArgAction, ArgGroup, Parser, Subcommand};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use crate::{
-    provs::Provider,
-    utils::{Layout, Retriever},
+    claims::CLAIM_TIMEOUT,
+    collision::OnCollision,
+    downsample::{Coverage, GenomeSize},
+    merge::MergeBy,
+    net::{Header, HostLimit, Transport, CONNECTIONS_PER_FILE, CONNECT_TIMEOUT, REQUEST_TIMEOUT},
+    perms::{create_dirs, Group, Mode},
+    progress::PROGRESS_INTERVAL,
+    provs::{ena::ResultType, Provider},
+    runtable::read_run_table,
+    utils::{Checksum, Layout, Protocol, Retriever, RetrieverArgs, ARIA2C_CONNECTIONS},
 };
 
 #[derive(Debug, Parser)]
@@ -13,27 +24,33 @@ use crate::{
     env!("CARGO_PKG_VERSION"),
     about = "A CLI tool for downloading FASTQ files from ENA or SRA")]
 #[command(
+    subcommand_negates_reqs = true,
     group(
-        ArgGroup::new("nextflow")
+        ArgGroup::new("nextflow_opts")
         .required(false)
+        .multiple(true)
         .args(&["executor", "queue", "queue_size"]))
 )]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+
     #[arg(
         short = 'a',
         long = "accession",
-        required = true,
+        required_unless_present_any = ["organism", "plan_in"],
+        conflicts_with = "organism",
         value_name = "ACCESSSION",
         help = "A valid ENA or SRA accession"
     )]
-    pub accession: AccessionType,
+    pub accession: Option<AccessionType>,
 
     #[arg(
         short = 'o',
         long = "outdir",
         value_name = "OUTDIR",
         // default_value = "./DOWLOADS",
-        help = "Directory to write FASTQs to"
+        help = "Directory to write FASTQs to; files are written with a .part suffix and renamed once complete and verified"
     )]
     pub outdir: Option<PathBuf>,
 
@@ -77,10 +94,26 @@ pub struct Args {
         num_args(0..=1),
         require_equals(true),
         action = ArgAction::Set,
-        help = "Overwrite existing files"
+        help = "Deprecated: same as --overwrite --skip-verify"
     )]
     pub force: bool,
 
+    #[arg(
+        long = "overwrite",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Download existing files again; the new copies are still checked against their MD5"
+    )]
+    pub overwrite: bool,
+
+    #[arg(
+        long = "skip-verify",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Trust downloaded files without checking their MD5 (they are kept out of the state manifest)"
+    )]
+    pub skip_verify: bool,
+
     #[arg(
         short = 'g',
         long = "group-by-experiment",
@@ -91,7 +124,7 @@ pub struct Args {
         num_args(0..=1),
         require_equals(true),
         action = ArgAction::Set,
-        help = "Group FASTQs by experiment"
+        help = "Merge the FASTQs of runs of the same experiment into one file per mate"
     )]
     pub group_by_experiment: bool,
 
@@ -105,10 +138,19 @@ pub struct Args {
         num_args(0..=1),
         require_equals(true),
         action = ArgAction::Set,
-        help = "Group FASTQs by sample"
+        help = "Merge the FASTQs of runs of the same sample into one file per mate"
     )]
     pub group_by_sample: bool,
 
+    #[arg(
+        long = "merge-by",
+        required = false,
+        value_name = "KEY",
+        conflicts_with_all = ["group_by_sample", "group_by_experiment"],
+        help = "Merge the FASTQs of runs sharing a sample, experiment or library (ENA library_name) into one file per mate, listing the merged runs as lanes in <group>.lanes.tsv"
+    )]
+    pub merge_by: Option<MergeBy>,
+
     #[arg(
         short = 'p',
         long = "prefix",
@@ -132,6 +174,85 @@ pub struct Args {
     )]
     pub nextflow: bool,
 
+    #[arg(
+        long = "nf-generate",
+        required = false,
+        requires("nextflow"),
+        action = ArgAction::SetTrue,
+        help = "Only write the Nextflow script, config and joblist, do not run Nextflow"
+    )]
+    pub nf_generate: bool,
+
+    #[arg(
+        long = "keep-nf-logs",
+        required = false,
+        requires("nextflow"),
+        action = ArgAction::SetTrue,
+        help = "Keep .nextflow.log and the .nextflow history after the run"
+    )]
+    pub keep_nf_logs: bool,
+
+    #[arg(
+        long = "report",
+        required = false,
+        value_name = "FILE",
+        help = "Write a run/status/file/md5 TSV report of the batch [Nextflow: merged into OUTDIR/rsfq.report by default]"
+    )]
+    pub report: Option<PathBuf>,
+
+    #[arg(
+        long = "audit-log",
+        required = false,
+        value_name = "FILE",
+        help = "Append a JSON line per transfer (who, when, URL, file, checksum result) to FILE; never truncated"
+    )]
+    pub audit_log: Option<PathBuf>,
+
+    #[arg(
+        long = "timings",
+        required = false,
+        value_name = "FILE",
+        conflicts_with = "nextflow",
+        help = "Write per-accession metadata, transfer and verification times, retry counts and hints to FILE as JSON"
+    )]
+    pub timings: Option<PathBuf>,
+
+    #[arg(
+        long = "html-report",
+        required = false,
+        value_name = "FILE",
+        conflicts_with = "nextflow",
+        help = "Write a self-contained HTML report of the batch (summary, per-accession table, failures, throughput chart) to FILE"
+    )]
+    pub html_report: Option<PathBuf>,
+
+    #[arg(
+        long = "plan-out",
+        required = false,
+        value_name = "FILE",
+        conflicts_with_all = ["nextflow", "claims_dir"],
+        help = "Write the resolved download plan (every accession with the metadata of its runs) to FILE as JSON"
+    )]
+    pub plan_out: Option<PathBuf>,
+
+    #[arg(
+        long = "plan-in",
+        required = false,
+        value_name = "FILE",
+        conflicts_with_all = ["accession", "organism", "nextflow", "claims_dir"],
+        help = "Download the runs of a plan written by --plan-out verbatim, without asking ENA again"
+    )]
+    pub plan_in: Option<PathBuf>,
+
+    #[arg(
+        long = "multiqc",
+        required = false,
+        action = ArgAction::SetTrue,
+        conflicts_with = "nextflow",
+        help = "Write rsfq_mqc.json to the output directory, summarizing runs, sizes and verification for MultiQC"
+    )]
+    pub multiqc: bool,
+
     #[arg(
         short = 'e',
         long = "executor",
@@ -160,6 +281,16 @@ pub struct Args {
     )]
     pub queue: String,
 
+    #[arg(
+        long = "cluster-options",
+        required = false,
+        value_name = "OPTIONS",
+        requires("nextflow"),
+        allow_hyphen_values = true,
+        help = "Extra scheduler flags for every Nextflow job, e.g. '--account=foo --qos=high'"
+    )]
+    pub cluster_options: Option<String>,
+
     #[arg(
         short = 'M',
         long = "metadata",
@@ -174,6 +305,16 @@ pub struct Args {
     )]
     pub metadata: bool,
 
+    #[arg(
+        long = "metadata-fields",
+        required = false,
+        value_name = "FIELDS",
+        value_delimiter = ',',
+        default_value = "run_accession,sample_accession,library_strategy,library_layout,instrument_model,read_count,fastq_bytes",
+        help = "Comma-separated fields shown in the --metadata table on a terminal"
+    )]
+    pub metadata_fields: Vec<String>,
+
     #[arg(short = 'D',
         long = "check",
         required = false,
@@ -186,11 +327,94 @@ pub struct Args {
         long = "tool",
         required = false,
         value_name = "TOOL",
-        help = "Downloader tool to use for downloading FASTQ files",
+        help = "Downloader tool to use for downloading FASTQ files: aria2c, wget, curl, native (built-in, needs no tool) or plugin:<name>. native, wget and curl downloads are hashed as they arrive; aria2c and plugin ones are read again to verify their MD5",
         default_value("aria2c")
     )]
     pub retriever: Retriever,
 
+    #[arg(
+        long = "fallback",
+        required = false,
+        value_name = "TOOLS",
+        value_delimiter = ',',
+        default_value("aria2c,wget,curl,native"),
+        help = "Retrievers tried in order when the selected one is not installed or keeps failing"
+    )]
+    pub fallback: Vec<Retriever>,
+
+    #[arg(
+        long = "aria2c-connections",
+        required = false,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=16),
+        default_value_t = ARIA2C_CONNECTIONS,
+        help = "Connections aria2c opens per file (-x/-s), at most 16"
+    )]
+    pub aria2c_connections: usize,
+
+    #[arg(
+        long = "protocol",
+        required = false,
+        value_name = "PROTOCOL",
+        help = "Scheme for URLs listed without one, like ENA's fastq_ftp: ftp, http or https [default: each retriever's own]"
+    )]
+    pub protocol: Option<Protocol>,
+
+    #[arg(
+        long = "transport",
+        required = false,
+        value_name = "TRANSPORT",
+        help = "Reach ENA files only over ftp or https, rewriting their URLs whatever --protocol says [default: as listed, then https]"
+    )]
+    pub transport: Option<Transport>,
+
+    #[arg(
+        long = "retriever-args",
+        required = false,
+        value_name = "[HOST=]ARGS",
+        action = ArgAction::Append,
+        allow_hyphen_values = true,
+        help = "Extra arguments appended to every -T wget/aria2c/curl command, or only for HOST, e.g. '--limit-rate=10m' [repeatable]"
+    )]
+    pub retriever_args: Vec<RetrieverArgs>,
+
+    #[arg(
+        long = "no-fallback",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Only use the selected retriever, never fall back to another one"
+    )]
+    pub no_fallback: bool,
+
+    #[arg(
+        long = "connections-per-file",
+        required = false,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        default_value_t = CONNECTIONS_PER_FILE,
+        help = "Parallel range requests per file with -T native, for files of 16 MB or more"
+    )]
+    pub connections_per_file: usize,
+
+    #[arg(
+        long = "auto-concurrency",
+        required = false,
+        value_name = "MAX",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        conflicts_with = "nextflow",
+        help = "Tune the number of simultaneous downloads between 1 and MAX from observed throughput and errors, starting low"
+    )]
+    pub auto_concurrency: Option<usize>,
+
+    #[arg(
+        long = "cpu-slots",
+        required = false,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        help = "Run at most N threads of hashing, dumping and compression at once, shared by every accession [default: number of CPUs]"
+    )]
+    pub cpu_slots: Option<usize>,
+
     #[arg(
         short = 'Q',
         long = "queue-size",
@@ -201,6 +425,15 @@ pub struct Args {
     )]
     pub queue_size: usize,
 
+    #[arg(
+        long = "array-size",
+        required = false,
+        value_name = "SIZE",
+        requires("nextflow"),
+        help = "Submit Nextflow tasks as scheduler array jobs of this size"
+    )]
+    pub array_size: Option<usize>,
+
     #[arg(
         short = 'L',
         long = "layout",
@@ -217,61 +450,1154 @@ pub struct Args {
         required = false,
         value_name = "PROVIDER",
         default_value("ena"),
-        help = "Provider to use for downloading FASTQ files"
+        help = "Provider to use for downloading FASTQ files: ena, sra, gsa, aws, gcp, drs or plugin:<name>"
     )]
     pub provider: Provider,
-}
 
-/// Check the arguments and make sure they are valid
-impl Args {
-    pub fn check(&self) {
-        // INFO: if dir already exists, do not overwrite
+    #[arg(
+        long = "hash",
+        required = false,
+        value_name = "ALGORITHM",
+        default_value("md5"),
+        help = "Hash for local integrity manifests and existing-file checks [md5, blake3, xxh3]; ENA is always checked with MD5"
+    )]
+    pub hash: Checksum,
 
-        if let Some(outdir) = &self.outdir {
-            if !outdir.exists() {
-                std::fs::create_dir_all(outdir).unwrap_or_else(|e| {
-                    log::error!("ERROR: Failed to create output directory!: {}", e);
-                    std::process::exit(1);
-                });
-            }
-        }
+    #[arg(
+        long = "store",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Save the run metadata fetched from ENA into the local store (--db) for `rsfq meta query`"
+    )]
+    pub store: bool,
 
-        if self.group_by_experiment && self.group_by_sample {
-            log::error!("ERROR: Cannot group by experiment and sample at the same time!");
-            std::process::exit(1);
-        }
+    #[arg(
+        long = "db",
+        required = false,
+        global = true,
+        value_name = "FILE",
+        help = "Local metadata store [default: ~/.rsfq/metadata.sqlite]"
+    )]
+    pub db: Option<PathBuf>,
 
-        log::info!("All arguments were parsed correctly!")
-    }
-}
+    #[arg(
+        long = "plugins-dir",
+        required = false,
+        global = true,
+        value_name = "DIR",
+        help = "Directory with rsfq-provider-<name>/rsfq-retriever-<name> plugins [default: ~/.rsfq/plugins]"
+    )]
+    pub plugins_dir: Option<PathBuf>,
 
-/// Enum representing the different types of accessions
-#[derive(Debug, Clone)]
-pub enum AccessionType {
-    Single(String),
-    List(Vec<String>),
-}
+    #[arg(
+        long = "metadata-out",
+        required = false,
+        value_name = "FILE",
+        conflicts_with = "nextflow",
+        help = "Write the metadata of every run to FILE, as Parquet if it ends in .parquet and TSV otherwise"
+    )]
+    pub metadata_out: Option<PathBuf>,
 
-/// Parse a string into an AccessionType
-impl FromStr for AccessionType {
-    type Err = String;
+    #[arg(
+        long = "metadata-file",
+        required = false,
+        value_name = "TSV",
+        conflicts_with = "organism",
+        help = "Read run metadata from a saved ENA file report instead of querying the ENA API"
+    )]
+    pub metadata_file: Option<PathBuf>,
 
-    /// Parse a string into an AccessionType
-    ///
-    /// # Arguments
-    /// * `s` - The string to parse.
-    ///
-    /// # Returns
-    /// * `Result<Self, Self::Err>` - The parsed AccessionType.
-    ///
-    /// # Examples
-    /// ```rust, no_run
-    /// use rsfq::cli::AccessionType;
-    /// use std::str::FromStr;
-    /// let accession = AccessionType::from_str("PRJEDNA12345");
-    /// ```
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let path = PathBuf::from(s);
+    #[arg(
+        long = "offline",
+        required = false,
+        global = true,
+        action = ArgAction::SetTrue,
+        help = "Never touch the network: answer metadata from --metadata-file or the local store (--db) and only verify/report files already downloaded"
+    )]
+    pub offline: bool,
+
+    #[arg(
+        long = "limit",
+        required = false,
+        value_name = "N",
+        help = "Keep at most N runs of each expanded study/sample/experiment, in accession order"
+    )]
+    pub limit: Option<usize>,
+
+    #[arg(
+        long = "offset",
+        required = false,
+        value_name = "N",
+        default_value("0"),
+        help = "Skip the first N runs of each expanded study/sample/experiment, in accession order"
+    )]
+    pub offset: usize,
+
+    #[arg(
+        long = "sample-runs",
+        required = false,
+        value_name = "N",
+        help = "Randomly keep N runs of each expanded study/sample/experiment"
+    )]
+    pub sample_runs: Option<usize>,
+
+    #[arg(
+        long = "seed",
+        required = false,
+        value_name = "SEED",
+        requires("sample_runs"),
+        help = "Seed for --sample-runs, for a reproducible selection"
+    )]
+    pub seed: Option<u64>,
+
+    #[arg(
+        long = "strategy",
+        required = false,
+        value_name = "STRATEGIES",
+        value_delimiter = ',',
+        help = "Only keep runs of expanded studies/samples with one of these library strategies, e.g. RNA-Seq,WGS"
+    )]
+    pub strategy: Vec<String>,
+
+    #[arg(
+        long = "selection",
+        required = false,
+        value_name = "SELECTIONS",
+        value_delimiter = ',',
+        help = "Only keep runs of expanded studies/samples with one of these library selections, e.g. PolyA,PCR"
+    )]
+    pub selection: Vec<String>,
+
+    #[arg(
+        long = "source",
+        required = false,
+        value_name = "SOURCES",
+        value_delimiter = ',',
+        help = "Only keep runs of expanded studies/samples with one of these library sources, e.g. GENOMIC,TRANSCRIPTOMIC,METAGENOMIC"
+    )]
+    pub source: Vec<String>,
+
+    #[arg(
+        long = "target-coverage",
+        required = false,
+        value_name = "COVERAGE",
+        requires("genome_size"),
+        help = "Keep only the reads of each run needed for this coverage, e.g. 30x; the first reads are kept and the rest is never downloaded, so this is not a random subsample"
+    )]
+    pub target_coverage: Option<Coverage>,
+
+    #[arg(
+        long = "genome-size",
+        required = false,
+        value_name = "SIZE",
+        requires("target_coverage"),
+        help = "Genome size used by --target-coverage, e.g. 3.1g, 4.6m"
+    )]
+    pub genome_size: Option<GenomeSize>,
+
+    #[arg(
+        long = "chunk-reads",
+        required = false,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Split every downloaded FASTQ into gzipped chunks of N reads, with mates in sync across _1/_2"
+    )]
+    pub chunk_reads: Option<u64>,
+
+    #[arg(
+        long = "fasta",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Write reads as gzipped FASTA (.fasta.gz), dropping qualities"
+    )]
+    pub fasta: bool,
+
+    #[arg(
+        long = "prefix-read-ids",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Prefix read IDs with their run accession when merging runs, so IDs from different runs cannot collide"
+    )]
+    pub prefix_read_ids: bool,
+
+    #[arg(
+        long = "verify-existing",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Hash files already on disk before skipping them; by default a matching size is enough"
+    )]
+    pub verify_existing: bool,
+
+    #[arg(
+        long = "chmod",
+        required = false,
+        value_name = "MODE",
+        help = "Octal mode given to every created file, e.g. 0644; by default the umask decides"
+    )]
+    pub chmod: Option<Mode>,
+
+    #[arg(
+        long = "dir-mode",
+        required = false,
+        value_name = "MODE",
+        help = "Octal mode given to every created directory, e.g. 0755; by default the umask decides"
+    )]
+    pub dir_mode: Option<Mode>,
+
+    #[arg(
+        long = "group",
+        required = false,
+        value_name = "GROUP",
+        help = "Unix group (name or id) given to every created file and directory; directories also get the setgid bit"
+    )]
+    pub group: Option<Group>,
+
+    #[arg(
+        long = "rename-map",
+        required = false,
+        value_name = "TSV",
+        help = "ACCESSION<TAB>NAME file or SraRunTable (SampleName column); output files are named after NAME instead of the run accession"
+    )]
+    pub rename_map: Option<PathBuf>,
+
+    #[arg(
+        long = "on-collision",
+        required = false,
+        value_name = "POLICY",
+        default_value_t = OnCollision::Error,
+        help = "What to do when two runs map to the same output name: error, suffix (append -2, -3...), overwrite (the later run wins) or skip (the earlier run wins)"
+    )]
+    pub on_collision: OnCollision,
+
+    #[arg(
+        long = "max-runtime",
+        required = false,
+        value_name = "DURATION",
+        conflicts_with = "nextflow",
+        help = "Stop starting new downloads after this long (e.g. 12h, 90m) and exit with code 3; rerun to resume"
+    )]
+    pub max_runtime: Option<humantime::Duration>,
+
+    #[arg(
+        long = "retain",
+        required = false,
+        value_name = "DURATION",
+        help = "Record downloads as kept for this long (e.g. 90d) in the state manifest; `rsfq expire` removes them afterwards"
+    )]
+    pub retain: Option<humantime::Duration>,
+
+    #[arg(
+        long = "check-read-ids",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Sample the first reads of each downloaded file and fail it if the read IDs name another run"
+    )]
+    pub check_read_ids: bool,
+
+    #[arg(
+        long = "raw-signal",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Also download the submitted FAST5/POD5 archives of Oxford Nanopore runs"
+    )]
+    pub raw_signal: bool,
+
+    #[arg(
+        long = "pacbio-bam",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Download the submitted subreads/CCS BAM (and .pbi) of PacBio runs instead of FASTQ"
+    )]
+    pub pacbio_bam: bool,
+
+    #[arg(
+        long = "cellranger-names",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Name FASTQs SampleName_S1_L00X_R1_001.fastq.gz (samples numbered in input order, their runs become lanes) for cellranger count"
+    )]
+    pub cellranger_names: bool,
+
+    #[arg(
+        long = "connect-timeout",
+        required = false,
+        value_name = "SECONDS",
+        default_value("30"),
+        help = "Seconds to wait for an HTTP connection to be established"
+    )]
+    pub connect_timeout: u64,
+
+    #[arg(
+        long = "request-timeout",
+        required = false,
+        value_name = "SECONDS",
+        default_value("300"),
+        help = "Seconds to wait for a whole HTTP request, 0 to disable"
+    )]
+    pub request_timeout: u64,
+
+    #[arg(
+        long = "host-limit",
+        required = false,
+        value_name = "HOST=N",
+        action = ArgAction::Append,
+        help = "Maximum simultaneous transfers against a single host [repeatable]"
+    )]
+    pub host_limits: Vec<HostLimit>,
+
+    #[arg(
+        long = "datahub",
+        required = false,
+        value_name = "DCC",
+        help = "ENA data hub (dcc_xxx) to query, password is read from $RSFQ_DATAHUB_PASSWORD"
+    )]
+    pub datahub: Option<String>,
+
+    #[arg(
+        long = "credentials",
+        required = false,
+        value_name = "FILE",
+        help = "netrc-formatted credentials file [default: ~/.netrc]"
+    )]
+    pub credentials: Option<PathBuf>,
+
+    #[arg(
+        long = "header",
+        required = false,
+        value_name = "HEADER",
+        action = ArgAction::Append,
+        help = "Extra 'Name: value' HTTP header for metadata and download requests to EBI hosts, or the --header-host hosts [repeatable]"
+    )]
+    pub headers: Vec<Header>,
+
+    #[arg(
+        long = "header-host",
+        required = false,
+        value_name = "HOST",
+        action = ArgAction::Append,
+        requires = "headers",
+        help = "Send the --header headers to HOST and its subdomains instead of EBI hosts [repeatable]"
+    )]
+    pub header_hosts: Vec<String>,
+
+    #[arg(
+        long = "proxy",
+        required = false,
+        value_name = "URL",
+        help = "Proxy for metadata requests and downloads, overriding http_proxy/https_proxy/ftp_proxy"
+    )]
+    pub proxy: Option<String>,
+
+    #[arg(
+        long = "organism",
+        required = false,
+        value_name = "ORGANISM",
+        help = "Download every public run of a species (e.g. 'Mus musculus'), combined with --layout"
+    )]
+    pub organism: Option<String>,
+
+    #[arg(
+        short = 'y',
+        long = "yes",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Do not ask for confirmation before large downloads"
+    )]
+    pub yes: bool,
+
+    #[arg(
+        long = "pick",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Interactively pick which runs to download when an accession expands to many runs"
+    )]
+    pub pick: bool,
+
+    #[arg(
+        long = "all-runs",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Download every run an accession expands to, not only the first [implied by --pick, --strategy, --selection, --source, --limit, --offset, --sample-runs, merging and --cellranger-names]"
+    )]
+    pub all_runs: bool,
+
+    #[arg(
+        long = "no-lock",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Do not lock the output directory; only safe when concurrent runs never touch the same files"
+    )]
+    pub no_lock: bool,
+
+    #[arg(
+        long = "per-run-dirs",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Write the files of each run into its own OUTDIR/<run> subdirectory instead of OUTDIR itself"
+    )]
+    pub per_run_dirs: bool,
+
+    #[arg(
+        long = "cross-validate",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "With -P sra, aws or gcp, check read/base counts against ENA and record both providers' MD5s in <run>.crosscheck.tsv"
+    )]
+    pub cross_validate: bool,
+
+    #[arg(
+        long = "no-preflight",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Do not check that ENA is up before a batch of 10 or more accessions"
+    )]
+    pub no_preflight: bool,
+
+    #[arg(
+        long = "no-cross-archive",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Never fetch runs ENA does not list, or lists without FASTQs yet, from NCBI SRA instead"
+    )]
+    pub no_cross_archive: bool,
+
+    #[arg(
+        long = "verbose",
+        required = false,
+        action = ArgAction::SetTrue,
+        help = "Log debug lines too, such as everything the retrievers print"
+    )]
+    pub verbose: bool,
+
+    #[arg(
+        long = "timeout",
+        required = false,
+        value_name = "MINUTES",
+        value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..),
+        help = "Stop a transfer that made no progress for MINUTES and count it as a failed attempt [default: wait forever]"
+    )]
+    pub timeout: Option<u64>,
+
+    #[arg(
+        long = "progress-interval",
+        required = false,
+        value_name = "SECONDS",
+        default_value_t = PROGRESS_INTERVAL,
+        help = "Seconds between progress lines (bytes so far, rate, ETA) of each transfer; 0 turns them off"
+    )]
+    pub progress_interval: u64,
+
+    #[arg(
+        long = "claims-dir",
+        required = false,
+        value_name = "DIR",
+        help = "Shared directory (e.g. on NFS) where machines running the same accession list claim accessions, so each one is processed once"
+    )]
+    pub claims_dir: Option<PathBuf>,
+
+    #[arg(
+        long = "claim-timeout",
+        required = false,
+        value_name = "DURATION",
+        default_value = CLAIM_TIMEOUT,
+        help = "Age after which a claim left by a dead machine is taken over (e.g. 24h, 90m)"
+    )]
+    pub claim_timeout: humantime::Duration,
+}
+
+/// Check the arguments and make sure they are valid
+impl Args {
+    /// Get what runs are merged by, from `--merge-by` or the `--group-by-*` flags.
+    ///
+    /// # Returns
+    /// The merge key, or `None` if runs are kept apart.
+    pub fn merge_by(&self) -> Option<MergeBy> {
+        if self.group_by_sample {
+            Some(MergeBy::Sample)
+        } else if self.group_by_experiment {
+            Some(MergeBy::Experiment)
+        } else {
+            self.merge_by
+        }
+    }
+
+    /// Whether existing files are downloaded again, from `--overwrite` or
+    /// the deprecated `--force`.
+    ///
+    /// # Returns
+    /// `true` to overwrite.
+    pub fn overwrite(&self) -> bool {
+        self.overwrite || self.force
+    }
+
+    /// Whether downloads skip their MD5 check, from `--skip-verify` or the
+    /// deprecated `--force`.
+    ///
+    /// # Returns
+    /// `true` to trust files without checksum.
+    pub fn skip_verify(&self) -> bool {
+        self.skip_verify || self.force
+    }
+
+    /// Whether an accession expanding to several runs downloads all of
+    /// them, from `--all-runs` or any flag selecting among them; otherwise
+    /// only the first run is, as before expansions were selectable.
+    ///
+    /// # Returns
+    /// `true` to keep every run of an expansion.
+    pub fn all_runs(&self) -> bool {
+        self.all_runs
+            || self.pick
+            || !self.strategy.is_empty()
+            || !self.selection.is_empty()
+            || !self.source.is_empty()
+            || self.limit.is_some()
+            || self.offset > 0
+            || self.sample_runs.is_some()
+            || self.merge_by().is_some()
+            || self.cellranger_names
+    }
+
+    pub fn check(&self) {
+        // INFO: if dir already exists, do not overwrite
+
+        if let Some(outdir) = &self.outdir {
+            if !outdir.exists() {
+                create_dirs(outdir).unwrap_or_else(|e| {
+                    log::error!("ERROR: Failed to create output directory!: {}", e);
+                    std::process::exit(1);
+                });
+            }
+        }
+
+        if self.group_by_experiment && self.group_by_sample {
+            log::error!("ERROR: Cannot group by experiment and sample at the same time!");
+            std::process::exit(1);
+        }
+
+        if self.force {
+            log::warn!(
+                "WARNING: --force is deprecated, use --overwrite to download again and --skip-verify to skip the MD5 check"
+            );
+        }
+
+        if self.cross_validate && !self.provider.dumps_sra() {
+            log::warn!(
+                "WARNING: --cross-validate only applies to runs fetched with -P sra, aws or gcp"
+            );
+        }
+
+        if self.prefix_read_ids && self.merge_by().is_none() {
+            log::warn!(
+                "WARNING: --prefix-read-ids only applies when merging runs with --merge-by, --group-by-sample or --group-by-experiment"
+            );
+        }
+
+        if self.offline && matches!(self.command, Some(Commands::Bench { .. })) {
+            log::error!("ERROR: rsfq bench downloads files, it cannot run with --offline!");
+            std::process::exit(1);
+        }
+
+        // INFO: Nextflow tasks see one accession each, so lanes restart per task
+        if self.cellranger_names && self.nextflow {
+            log::warn!(
+                "WARNING: With --nextflow, --cellranger-names numbers lanes within each accession; pass studies or samples rather than runs of the same sample"
+            );
+        }
+
+        log::info!("All arguments were parsed correctly!")
+    }
+
+    /// Flags that every Nextflow task must inherit from this invocation but
+    /// that may hold credentials: extra headers, the proxy and retriever
+    /// arguments. They are kept out of the Nextflow script and the task
+    /// commands it logs.
+    ///
+    /// # Returns
+    /// * `Vec<String>` - The flags, their values quoted for the shell.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use clap::Parser;
+    /// use rsfq::cli::Args;
+    ///
+    /// let args = Args::parse_from([
+    ///     "rsfq", "-a", "SRR123456", "--header", "Authorization: Bearer $TOKEN",
+    /// ]);
+    /// assert_eq!(
+    ///     args.forwarded_secrets(),
+    ///     vec!["--header 'Authorization: Bearer $TOKEN'".to_string()]
+    /// );
+    /// ```
+    pub fn forwarded_secrets(&self) -> Vec<String> {
+        let mut flags = self
+            .retriever_args
+            .iter()
+            .map(|args| format!("--retriever-args {}", shell_quote(&args.to_string())))
+            .collect::<Vec<String>>();
+        for header in &self.headers {
+            flags.push(format!("--header {}", shell_quote(&header.to_string())));
+        }
+        if let Some(proxy) = &self.proxy {
+            flags.push(format!("--proxy {}", shell_quote(proxy)));
+        }
+
+        flags
+    }
+
+    /// Flags that every Nextflow task must inherit from this invocation
+    ///
+    /// # Returns
+    /// * `Vec<String>` - The flags, ready to be appended to the task command.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use clap::Parser;
+    /// use rsfq::cli::Args;
+    ///
+    /// let args = Args::parse_from(["rsfq", "-a", "SRR123456", "--datahub", "dcc_abcd"]);
+    /// assert_eq!(
+    ///     args.forwarded_flags(),
+    ///     vec![
+    ///         "--datahub dcc_abcd".to_string(),
+    ///         "--fallback aria2c,wget,curl,native".to_string(),
+    ///     ]
+    /// );
+    /// ```
+    pub fn forwarded_flags(&self) -> Vec<String> {
+        let mut flags = self
+            .host_limits
+            .iter()
+            .map(|limit| format!("--host-limit {}={}", limit.host, limit.limit))
+            .collect::<Vec<String>>();
+
+        if let Some(slots) = self.cpu_slots {
+            flags.push(format!("--cpu-slots {}", slots));
+        }
+        if self.connections_per_file != CONNECTIONS_PER_FILE {
+            flags.push(format!(
+                "--connections-per-file {}",
+                self.connections_per_file
+            ));
+        }
+        if let Some(datahub) = &self.datahub {
+            flags.push(format!("--datahub {}", datahub));
+        }
+
+        // INFO: tasks run inside their own work dir
+        if let Some(credentials) = &self.credentials {
+            flags.push(format!("--credentials {}", task_path(credentials)));
+        }
+
+        if self.aria2c_connections != ARIA2C_CONNECTIONS {
+            flags.push(format!("--aria2c-connections {}", self.aria2c_connections));
+        }
+        if let Some(protocol) = self.protocol {
+            flags.push(format!("--protocol {}", protocol));
+        }
+        if let Some(transport) = self.transport {
+            flags.push(format!("--transport {}", transport));
+        }
+        for host in &self.header_hosts {
+            flags.push(format!("--header-host {}", shell_quote(host)));
+        }
+
+        if self.connect_timeout != CONNECT_TIMEOUT {
+            flags.push(format!("--connect-timeout {}", self.connect_timeout));
+        }
+
+        if self.request_timeout != REQUEST_TIMEOUT {
+            flags.push(format!("--request-timeout {}", self.request_timeout));
+        }
+
+        if let Some(limit) = self.limit {
+            flags.push(format!("--limit {}", limit));
+        }
+
+        if self.offset > 0 {
+            flags.push(format!("--offset {}", self.offset));
+        }
+
+        if let Some(n) = self.sample_runs {
+            flags.push(format!("--sample-runs {}", n));
+        }
+
+        if self.all_runs {
+            flags.push("--all-runs".to_string());
+        }
+
+        if let Some(seed) = self.seed {
+            flags.push(format!("--seed {}", seed));
+        }
+
+        if !self.strategy.is_empty() {
+            flags.push(format!(
+                "--strategy {}",
+                shell_quote(&self.strategy.join(","))
+            ));
+        }
+
+        if !self.selection.is_empty() {
+            flags.push(format!(
+                "--selection {}",
+                shell_quote(&self.selection.join(","))
+            ));
+        }
+
+        if !self.source.is_empty() {
+            flags.push(format!("--source {}", shell_quote(&self.source.join(","))));
+        }
+
+        if let (Some(coverage), Some(size)) = (self.target_coverage, self.genome_size) {
+            flags.push(format!("--target-coverage {}x", coverage.0));
+            flags.push(format!("--genome-size {}", size.0));
+        }
+
+        if let Some(reads) = self.chunk_reads {
+            flags.push(format!("--chunk-reads {}", reads));
+        }
+
+        if self.fasta {
+            flags.push("--fasta".to_string());
+        }
+
+        // INFO: each task merges the runs of the accession it was given
+        if self.group_by_sample {
+            flags.push("--group-by-sample".to_string());
+        }
+
+        if self.group_by_experiment {
+            flags.push("--group-by-experiment".to_string());
+        }
+
+        if let Some(merge_by) = self.merge_by {
+            flags.push(format!("--merge-by {}", merge_by));
+        }
+
+        if self.prefix_read_ids {
+            flags.push("--prefix-read-ids".to_string());
+        }
+
+        if self.verify_existing {
+            flags.push("--verify-existing".to_string());
+        }
+
+        if self.overwrite() {
+            flags.push("--overwrite".to_string());
+        }
+
+        if self.skip_verify() {
+            flags.push("--skip-verify".to_string());
+        }
+
+        if let Some(retain) = &self.retain {
+            flags.push(format!("--retain {}", retain));
+        }
+
+        if self.per_run_dirs {
+            flags.push("--per-run-dirs".to_string());
+        }
+
+        if self.verbose {
+            flags.push("--verbose".to_string());
+        }
+
+        if let Some(minutes) = self.timeout {
+            flags.push(format!("--timeout {}", minutes));
+        }
+
+        if self.progress_interval != PROGRESS_INTERVAL {
+            flags.push(format!("--progress-interval {}", self.progress_interval));
+        }
+
+        if self.cross_validate {
+            flags.push("--cross-validate".to_string());
+        }
+
+        if let Some(mode) = self.chmod {
+            flags.push(format!("--chmod {}", mode));
+        }
+
+        if let Some(mode) = self.dir_mode {
+            flags.push(format!("--dir-mode {}", mode));
+        }
+
+        // INFO: tasks run inside their own work dir
+        if let Some(dir) = &self.claims_dir {
+            flags.push(format!("--claims-dir {}", task_path(dir)));
+            flags.push(format!("--claim-timeout {}", self.claim_timeout));
+        }
+
+        if self.no_cross_archive {
+            flags.push("--no-cross-archive".to_string());
+        }
+
+        if self.no_fallback {
+            flags.push("--no-fallback".to_string());
+        } else {
+            let chain = self
+                .fallback
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            flags.push(format!("--fallback {}", chain.join(",")));
+        }
+
+        if let Some(group) = self.group {
+            flags.push(format!("--group {}", group));
+        }
+
+        // INFO: tasks run inside their own work dir
+        if let Some(map) = &self.rename_map {
+            flags.push(format!("--rename-map {}", task_path(map)));
+        }
+        if self.on_collision != OnCollision::Error {
+            flags.push(format!("--on-collision {}", self.on_collision));
+        }
+
+        if let Some(report) = &self.metadata_file {
+            flags.push(format!("--metadata-file {}", task_path(report)));
+        }
+
+        // INFO: tasks run inside their own work dir
+        if let Some(log) = &self.audit_log {
+            let log = std::path::absolute(log).unwrap_or_else(|_| log.clone());
+            flags.push(format!(
+                "--audit-log {}",
+                shell_quote(&log.display().to_string())
+            ));
+        }
+
+        if self.offline {
+            flags.push("--offline".to_string());
+            if let Some(db) = &self.db {
+                flags.push(format!("--db {}", task_path(db)));
+            }
+        }
+
+        if let Some(dir) = &self.plugins_dir {
+            flags.push(format!("--plugins-dir {}", task_path(dir)));
+        }
+
+        if self.check_read_ids {
+            flags.push("--check-read-ids".to_string());
+        }
+
+        if self.raw_signal {
+            flags.push("--raw-signal".to_string());
+        }
+
+        if self.pacbio_bam {
+            flags.push("--pacbio-bam".to_string());
+        }
+
+        if self.cellranger_names {
+            flags.push("--cellranger-names".to_string());
+        }
+
+        if self.hash != Checksum::Md5 {
+            flags.push(format!("--hash {}", self.hash));
+        }
+
+        flags
+    }
+}
+
+/// Resolve a path for Nextflow tasks, which run inside their own work dir,
+/// and quote it for their shell.
+fn task_path(path: &Path) -> String {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    shell_quote(&path.display().to_string())
+}
+
+/// Quote a value for the shell, so the Nextflow process passes it to rsfq
+/// verbatim whatever quotes or `$` it holds.
+///
+/// # Arguments
+/// * `value` - The value to quote.
+///
+/// # Returns
+/// The value in single quotes, its own single quotes escaped.
+///
+/// # Examples
+/// ```rust
+/// use rsfq::cli::shell_quote;
+/// assert_eq!(shell_quote("X-Note: it's $HOME"), r"'X-Note: it'\''s $HOME'");
+/// ```
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Subcommands other than the default download mode
+#[derive(Debug, Clone, Subcommand)]
+pub enum Commands {
+    /// Run a free-text ENA portal query and list (or download) the matching runs
+    Search {
+        #[arg(
+            long = "query",
+            required = true,
+            value_name = "QUERY",
+            help = "ENA portal query, e.g. 'instrument_platform=OXFORD_NANOPORE AND tax_eq(9606)'"
+        )]
+        query: String,
+
+        #[arg(
+            long = "result",
+            required = false,
+            value_name = "TYPE",
+            default_value_t = ResultType::ReadRun,
+            help = "ENA result type to search: read_run, read_experiment, read_study or analysis"
+        )]
+        result: ResultType,
+
+        #[arg(
+            long = "fields",
+            required = false,
+            value_name = "FIELDS",
+            value_delimiter = ',',
+            help = "Comma-separated metadata fields to print as TSV instead of bare accessions"
+        )]
+        fields: Vec<String>,
+
+        #[arg(
+            long = "download",
+            required = false,
+            action = ArgAction::SetTrue,
+            help = "Download the runs of the matching results instead of printing them"
+        )]
+        download: bool,
+    },
+    /// List runs missing locally, local files outside the accessions, and changed checksums
+    Diff {
+        #[arg(
+            short = 'a',
+            long = "accession",
+            required = true,
+            value_name = "ACCESSSION",
+            help = "A valid ENA or SRA accession, a comma-separated list or a .txt"
+        )]
+        accession: AccessionType,
+
+        #[arg(
+            short = 'o',
+            long = "outdir",
+            required = true,
+            value_name = "OUTDIR",
+            help = "Local directory to compare against"
+        )]
+        outdir: PathBuf,
+    },
+    /// Remove local FASTQs that do not belong to the runs of the given accessions
+    Prune {
+        #[arg(
+            short = 'a',
+            long = "accession",
+            required = true,
+            value_name = "ACCESSSION",
+            help = "The accessions whose runs are kept: a valid ENA or SRA accession, a comma-separated list or a .txt"
+        )]
+        accession: AccessionType,
+
+        #[arg(
+            short = 'o',
+            long = "outdir",
+            required = true,
+            value_name = "OUTDIR",
+            help = "Local directory to prune"
+        )]
+        outdir: PathBuf,
+
+        #[arg(
+            long = "dry-run",
+            required = false,
+            action = ArgAction::SetTrue,
+            help = "Only list the files that would be removed"
+        )]
+        dry_run: bool,
+
+        #[arg(
+            short = 'y',
+            long = "yes",
+            required = false,
+            action = ArgAction::SetTrue,
+            help = "Do not ask for confirmation before removing files"
+        )]
+        yes: bool,
+    },
+    /// Remove downloads whose --retain period is over
+    Expire {
+        #[arg(
+            short = 'o',
+            long = "outdir",
+            required = true,
+            value_name = "OUTDIR",
+            help = "Output directory whose state manifest is checked"
+        )]
+        outdir: PathBuf,
+
+        #[arg(
+            long = "dry-run",
+            required = false,
+            action = ArgAction::SetTrue,
+            help = "Only list the files that have expired"
+        )]
+        dry_run: bool,
+
+        #[arg(
+            short = 'y',
+            long = "yes",
+            required = false,
+            action = ArgAction::SetTrue,
+            help = "Do not ask for confirmation before removing files"
+        )]
+        yes: bool,
+    },
+    /// Check the FASTQs of a directory, however they were downloaded, against ENA checksums
+    Audit {
+        #[arg(
+            required = true,
+            value_name = "DIR",
+            help = "Directory whose .fastq.gz/.fq.gz files are checked, run accessions inferred from their names"
+        )]
+        dir: PathBuf,
+    },
+    /// Summarize runs, bases, sizes, platforms and layouts per accession before downloading
+    Report {
+        #[arg(
+            short = 'a',
+            long = "accession",
+            required = true,
+            value_name = "ACCESSSION",
+            help = "A valid ENA or SRA accession, a comma-separated list or a .txt"
+        )]
+        accession: AccessionType,
+    },
+    /// Print what accessions expand into, as a study → sample → experiment → run tree
+    Tree {
+        #[arg(
+            short = 'a',
+            long = "accession",
+            required = true,
+            value_name = "ACCESSSION",
+            help = "A valid ENA or SRA accession, a comma-separated list or a .txt"
+        )]
+        accession: AccessionType,
+
+        #[arg(
+            long = "json",
+            required = false,
+            action = ArgAction::SetTrue,
+            help = "Print the hierarchy as JSON instead of a tree"
+        )]
+        json: bool,
+    },
+    /// Time every installed retriever on a small FASTQ and pick the fastest
+    Bench {
+        #[arg(
+            short = 'a',
+            long = "accession",
+            required = true,
+            value_name = "ACCESSSION",
+            help = "A valid ENA or SRA accession whose smallest FASTQ is downloaded"
+        )]
+        accession: String,
+
+        #[arg(
+            long = "timeout",
+            required = false,
+            value_name = "SECONDS",
+            default_value("120"),
+            help = "Seconds each retriever gets before it counts as failed"
+        )]
+        timeout: u64,
+
+        #[arg(
+            long = "save",
+            required = false,
+            action = ArgAction::SetTrue,
+            help = "Save the fastest retriever as the default for the file's site, used when -T is not given"
+        )]
+        save: bool,
+    },
+    /// Work with the local metadata store filled by --store
+    Meta {
+        #[command(subcommand)]
+        command: MetaCommands,
+    },
+    /// List the provider and retriever plugins found in the plugins directory
+    Plugins,
+}
+
+/// Subcommands of `rsfq meta`
+#[derive(Debug, Clone, Subcommand)]
+pub enum MetaCommands {
+    /// Select stored runs with a filter over ENA fields and list (or download) them
+    Query {
+        #[arg(
+            required = true,
+            value_name = "FILTER",
+            help = "Filter over ENA fields, e.g. 'library_strategy = \"RNA-Seq\" AND read_count > 1e7'"
+        )]
+        filter: String,
+
+        #[arg(
+            long = "fields",
+            required = false,
+            value_name = "FIELDS",
+            value_delimiter = ',',
+            help = "Comma-separated metadata fields to print as TSV instead of bare run accessions"
+        )]
+        fields: Vec<String>,
+
+        #[arg(
+            long = "download",
+            required = false,
+            action = ArgAction::SetTrue,
+            help = "Download the matching runs instead of printing them"
+        )]
+        download: bool,
+    },
+}
+
+/// Enum representing the different types of accessions
+#[derive(Debug, Clone)]
+pub enum AccessionType {
+    Single(String),
+    List(Vec<String>),
+}
+
+/// Parse a string into an AccessionType
+impl FromStr for AccessionType {
+    type Err = String;
+
+    /// Parse a string into an AccessionType
+    ///
+    /// # Arguments
+    /// * `s` - The string to parse.
+    ///
+    /// # Returns
+    /// * `Result<Self, Self::Err>` - The parsed AccessionType.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::cli::AccessionType;
+    /// use std::str::FromStr;
+    /// let accession = AccessionType::from_str("PRJEDNA12345");
+    /// let table = AccessionType::from_str("SraRunTable.csv");
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let path = PathBuf::from(s);
+
+        // INFO: NCBI Run Selector exports (SraRunTable.csv/.txt)
+        if path
+            .extension()
+            .is_some_and(|ext| ext == "csv" || ext == "txt")
+        {
+            if let Some(rows) = read_run_table(&path)? {
+                let accessions = rows.into_iter().map(|row| row.run).collect();
+                return Ok(AccessionType::List(accessions));
+            }
+        }
 
         // INFO: assuming .txt file as input
         if let Some(ext) = path.extension() {
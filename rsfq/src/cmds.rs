@@ -0,0 +1,10 @@
+pub mod audit;
+pub mod bench;
+pub mod diff;
+pub mod expire;
+pub mod meta;
+pub mod plugins;
+pub mod prune;
+pub mod report;
+pub mod search;
+pub mod tree;
@@ -0,0 +1,137 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    core::md5sum,
+    provs::ena::fetch_run_info,
+    utils::{local_fastqs, run_accession_of, validate_query},
+};
+
+const FASTQ_FTP: &str = "fastq_ftp";
+const FASTQ_MD5: &str = "fastq_md5";
+const UNKNOWN_RUN: &str = "-";
+
+/// Status of a local file checked against the checksum ENA lists for it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditStatus {
+    Pass,
+    Fail,
+    Unknown,
+}
+
+/// Display the name of the `AuditStatus` instance.
+impl std::fmt::Display for AuditStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditStatus::Pass => write!(f, "PASS"),
+            AuditStatus::Fail => write!(f, "FAIL"),
+            AuditStatus::Unknown => write!(f, "UNKNOWN"),
+        }
+    }
+}
+
+/// Check the FASTQs of a directory that rsfq did not necessarily download
+/// against the checksums ENA lists for them.
+///
+/// Files are found by name (`.fastq.gz`/`.fq.gz`) and their run accession
+/// inferred from it. Prints one `STATUS\trun_accession\tfile` line per file:
+/// `PASS` (MD5 matches ENA), `FAIL` (it does not) or `UNKNOWN` (no run could
+/// be inferred, or ENA lists no such file for the run).
+///
+/// # Arguments
+///
+/// * `dir` - The directory to audit.
+/// * `attempts` - The maximum number of metadata attempts.
+/// * `sleep` - The number of seconds to sleep between attempts.
+///
+/// # Returns
+///
+/// The status of every file found.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::cmds::audit::audit_dir;
+/// use std::path::Path;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let results = audit_dir(Path::new("/data/legacy"), 3, 5).await;
+///     println!("{} files audited", results.len());
+/// }
+/// ```
+pub async fn audit_dir(
+    dir: &Path,
+    attempts: usize,
+    sleep: usize,
+) -> Vec<(AuditStatus, String, PathBuf)> {
+    let files = local_fastqs(dir)
+        .into_iter()
+        .map(|path| {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            (run_accession_of(&name), name, path)
+        })
+        .collect::<Vec<_>>();
+
+    // INFO: file name -> expected md5, one metadata request per run
+    let runs = files
+        .iter()
+        .filter_map(|(run, _, _)| run.clone())
+        .collect::<BTreeSet<String>>();
+    let mut remote = HashMap::new();
+    for run in &runs {
+        // INFO: a legacy lake may hold runs ENA does not know, they end up UNKNOWN
+        let found = match fetch_run_info(validate_query(run), attempts, sleep).await {
+            Ok(found) => found,
+            Err(e) => {
+                log::warn!("WARNING: {}", e);
+                continue;
+            }
+        };
+        for info in found {
+            let ftps = info.get(FASTQ_FTP).map(String::as_str).unwrap_or_default();
+            let md5s = info.get(FASTQ_MD5).map(String::as_str).unwrap_or_default();
+
+            for (ftp, md5) in ftps.split(';').zip(md5s.split(';')) {
+                if let Some(name) = Path::new(ftp).file_name() {
+                    if !md5.is_empty() {
+                        remote.insert(name.to_string_lossy().to_string(), md5.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    let mut results = Vec::new();
+    for (run, name, path) in files {
+        let status = match (&run, remote.get(&name)) {
+            (Some(_), Some(md5)) => {
+                if md5sum(&path).await.as_deref() == Some(md5.as_str()) {
+                    AuditStatus::Pass
+                } else {
+                    AuditStatus::Fail
+                }
+            }
+            _ => AuditStatus::Unknown,
+        };
+        let run = run.unwrap_or_else(|| UNKNOWN_RUN.to_string());
+        println!("{}\t{}\t{}", status, run, path.display());
+        results.push((status, run, path));
+    }
+
+    let count = |status: AuditStatus| results.iter().filter(|(s, ..)| *s == status).count();
+    log::info!(
+        "{} files audited: {} passed, {} failed, {} unknown",
+        results.len(),
+        count(AuditStatus::Pass),
+        count(AuditStatus::Fail),
+        count(AuditStatus::Unknown)
+    );
+
+    results
+}
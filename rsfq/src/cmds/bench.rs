@@ -0,0 +1,231 @@
+use std::{
+    collections::HashMap,
+    io::IsTerminal,
+    path::Path,
+    process::Stdio,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    net::{fetch, host_of},
+    provs::ena::get_run_info,
+    runner::runner,
+    sites::save_site_retriever,
+    table::{render_table, terminal_width},
+    utils::{human_bytes, validate_query, Retriever},
+};
+
+const FASTQ_FTP: &str = "fastq_ftp";
+const FASTQ_BYTES: &str = "fastq_bytes";
+const COLUMNS: &[&str] = &["retriever", "status", "bytes", "seconds", "throughput"];
+const RETRIEVERS: &[Retriever] = &[
+    Retriever::Aria2c,
+    Retriever::Wget,
+    Retriever::Curl,
+    Retriever::Native,
+];
+
+/// Outcome of one retriever fetching the test file
+#[derive(Debug, Clone)]
+pub struct Trial {
+    pub retriever: Retriever,
+    pub status: &'static str,
+    pub bytes: u64,
+    pub seconds: f64,
+}
+
+impl Trial {
+    /// Get the throughput in bytes per second, 0 if the fetch failed.
+    ///
+    /// # Returns
+    /// The throughput.
+    pub fn throughput(&self) -> f64 {
+        if self.status == "ok" && self.seconds > 0.0 {
+            self.bytes as f64 / self.seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// Get the trial as a row of named cells.
+    ///
+    /// # Arguments
+    /// * `human` - Whether sizes should be human readable.
+    ///
+    /// # Returns
+    /// The cells, keyed by column name.
+    pub fn row(&self, human: bool) -> HashMap<String, String> {
+        let (bytes, throughput) = if human {
+            (
+                human_bytes(self.bytes),
+                format!("{}/s", human_bytes(self.throughput() as u64)),
+            )
+        } else {
+            (self.bytes.to_string(), format!("{:.0}", self.throughput()))
+        };
+
+        HashMap::from([
+            ("retriever".to_string(), self.retriever.to_string()),
+            ("status".to_string(), self.status.to_string()),
+            ("bytes".to_string(), bytes),
+            ("seconds".to_string(), format!("{:.2}", self.seconds)),
+            ("throughput".to_string(), throughput),
+        ])
+    }
+}
+
+/// Download the smallest FASTQ of an accession with every installed
+/// retriever and report their throughput.
+///
+/// Each retriever fetches the same file into a scratch directory that is
+/// removed afterwards; a fetch still running after `timeout` counts as
+/// failed. With `save`, the fastest retriever becomes the default for the
+/// file's site, used whenever `-T` is not given.
+///
+/// # Arguments
+///
+/// * `accession` - The accession to take the test file from.
+/// * `timeout` - The seconds each retriever gets.
+/// * `save` - Whether to save the winner as the site default.
+/// * `attempts` - The maximum number of metadata attempts.
+/// * `sleep` - The number of seconds to sleep between attempts.
+///
+/// # Returns
+///
+/// One trial per installed retriever.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::cmds::bench::bench;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let trials = bench("SRR123456", 60, true, 3, 5).await;
+/// }
+/// ```
+pub async fn bench(
+    accession: &str,
+    timeout: u64,
+    save: bool,
+    attempts: usize,
+    sleep: usize,
+) -> Vec<Trial> {
+    let runs = get_run_info(validate_query(accession), attempts, sleep).await;
+    let Some((url, size)) = smallest_file(&runs) else {
+        log::error!("ERROR: {} has no FASTQ files to benchmark with!", accession);
+        std::process::exit(1);
+    };
+    log::info!(
+        "Benchmarking retrievers on {} ({})",
+        url,
+        size.map(human_bytes)
+            .unwrap_or_else(|| "unknown size".to_string())
+    );
+
+    let scratch = std::env::temp_dir().join(format!("rsfq-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&scratch).unwrap_or_else(|e| {
+        log::error!("ERROR: Could not create {}!: {}", scratch.display(), e);
+        std::process::exit(1);
+    });
+
+    let mut trials = Vec::new();
+    for retriever in RETRIEVERS {
+        if !retriever.is_installed() {
+            log::warn!("WARNING: {} is not installed, skipping it", retriever);
+            continue;
+        }
+        trials.push(trial(retriever, &url, &scratch, timeout).await);
+    }
+    let _ = std::fs::remove_dir_all(&scratch);
+
+    let columns = COLUMNS.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+    if std::io::stdout().is_terminal() {
+        let rows = trials.iter().map(|t| t.row(true)).collect::<Vec<_>>();
+        println!("{}", render_table(&rows, &columns, terminal_width()));
+    } else {
+        println!("{}", columns.join("\t"));
+        for row in trials.iter().map(|t| t.row(false)) {
+            let cells = columns
+                .iter()
+                .map(|column| row[column].as_str())
+                .collect::<Vec<&str>>();
+            println!("{}", cells.join("\t"));
+        }
+    }
+
+    let winner = trials
+        .iter()
+        .filter(|t| t.throughput() > 0.0)
+        .max_by(|a, b| a.throughput().total_cmp(&b.throughput()));
+    match winner {
+        Some(winner) if save => match save_site_retriever(&host_of(&url), &winner.retriever) {
+            Ok(path) => log::info!(
+                "Saved {} as the default retriever for {} in {}",
+                winner.retriever,
+                host_of(&url),
+                path.display()
+            ),
+            Err(e) => log::error!("ERROR: Could not save the default retriever!: {}", e),
+        },
+        Some(winner) => log::info!("Fastest retriever: {}", winner.retriever),
+        None => log::error!("ERROR: No retriever could fetch {}!", url),
+    }
+
+    trials
+}
+
+/// Get the smallest FASTQ of a set of runs, with its size if known.
+fn smallest_file(runs: &[HashMap<String, String>]) -> Option<(String, Option<u64>)> {
+    runs.iter()
+        .flat_map(|run| {
+            let urls = run.get(FASTQ_FTP).map(String::as_str).unwrap_or_default();
+            let sizes = run.get(FASTQ_BYTES).map(String::as_str).unwrap_or_default();
+            urls.split(';')
+                .zip(sizes.split(';').chain(std::iter::repeat("")))
+                .filter(|(url, _)| !url.is_empty())
+                .map(|(url, size)| (url.to_string(), size.parse::<u64>().ok()))
+                .collect::<Vec<_>>()
+        })
+        .min_by_key(|(_, size)| size.unwrap_or(u64::MAX))
+}
+
+/// Time one retriever fetching a file.
+async fn trial(retriever: &Retriever, url: &str, scratch: &Path, timeout: u64) -> Trial {
+    let name = Path::new(url)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let output = scratch.join(format!("{}.{}", retriever, name));
+
+    let start = Instant::now();
+    let status = match retriever.materialize(url, &output) {
+        Some(mut cmd) => {
+            cmd.stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .kill_on_drop(true);
+            match tokio::time::timeout(Duration::from_secs(timeout), runner().run(cmd)).await {
+                Ok(Ok(outcome)) if outcome.success() => "ok",
+                Ok(_) => "failed",
+                Err(_) => "timeout",
+            }
+        }
+        None => match tokio::time::timeout(Duration::from_secs(timeout), fetch(url, &output, &[]))
+            .await
+        {
+            Ok(Ok(_)) => "ok",
+            Ok(_) => "failed",
+            Err(_) => "timeout",
+        },
+    };
+    let seconds = start.elapsed().as_secs_f64();
+    let bytes = std::fs::metadata(&output).map(|m| m.len()).unwrap_or(0);
+    log::info!("{}: {} after {:.2}s", retriever, status, seconds);
+
+    Trial {
+        retriever: retriever.clone(),
+        status,
+        bytes,
+        seconds,
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cli::AccessionType,
+    core::md5sum,
+    provs::ena::get_run_info,
+    utils::{local_fastqs, run_accession_of, run_dir, validate_query},
+};
+
+const FASTQ_FTP: &str = "fastq_ftp";
+const FASTQ_MD5: &str = "fastq_md5";
+const RUN_ACCESSION: &str = "run_accession";
+
+/// Status of a file when comparing the remote project against a local directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffStatus {
+    Missing,
+    Extra,
+    Changed,
+}
+
+/// Display the name of the `DiffStatus` instance.
+impl std::fmt::Display for DiffStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffStatus::Missing => write!(f, "MISSING"),
+            DiffStatus::Extra => write!(f, "EXTRA"),
+            DiffStatus::Changed => write!(f, "CHANGED"),
+        }
+    }
+}
+
+/// Compare the runs of one or more accessions against a local directory.
+///
+/// Prints one `STATUS\trun_accession\tfile` line per difference:
+/// `MISSING` (remote but not local), `EXTRA` (local but not part of the
+/// accessions) and `CHANGED` (local checksum differs from ENA).
+///
+/// # Arguments
+///
+/// * `accession` - The accessions to compare.
+/// * `outdir` - The local directory holding the FASTQs.
+/// * `attempts` - The maximum number of metadata attempts.
+/// * `sleep` - The number of seconds to sleep between attempts.
+///
+/// # Returns
+///
+/// The differences found.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::cli::AccessionType;
+/// use rsfq::cmds::diff::diff;
+/// use std::path::PathBuf;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let accession = AccessionType::Single("PRJEB12345".to_string());
+///     let diffs = diff(&accession, &PathBuf::from("DOWNLOADS"), 3, 5).await;
+///     println!("{} differences", diffs.len());
+/// }
+/// ```
+pub async fn diff(
+    accession: &AccessionType,
+    outdir: &Path,
+    attempts: usize,
+    sleep: usize,
+) -> Vec<(DiffStatus, String, PathBuf)> {
+    let accessions = match accession {
+        AccessionType::Single(accession) => vec![accession.clone()],
+        AccessionType::List(accessions) => accessions.clone(),
+    };
+
+    // INFO: file name -> (run accession, expected md5)
+    let mut remote = HashMap::new();
+    for accession in accessions {
+        let query = validate_query(&accession);
+        for run in get_run_info(query, attempts, sleep).await {
+            let Some(run_accession) = run.get(RUN_ACCESSION) else {
+                continue;
+            };
+            let ftps = run.get(FASTQ_FTP).map(String::as_str).unwrap_or_default();
+            let md5s = run.get(FASTQ_MD5).map(String::as_str).unwrap_or_default();
+
+            for (ftp, md5) in ftps.split(';').zip(md5s.split(';')) {
+                if let Some(name) = Path::new(ftp).file_name() {
+                    remote.insert(
+                        name.to_string_lossy().to_string(),
+                        (run_accession.clone(), md5.to_string()),
+                    );
+                }
+            }
+        }
+    }
+
+    let runs = remote
+        .values()
+        .map(|(run, _)| run.clone())
+        .collect::<HashSet<String>>();
+    let mut local = HashSet::new();
+    let mut diffs = Vec::new();
+
+    for path in local_fastqs(outdir) {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        local.insert(name.clone());
+
+        match remote.get(&name) {
+            Some((run, md5)) => {
+                if md5sum(&path).await.as_deref() != Some(md5.as_str()) {
+                    diffs.push((DiffStatus::Changed, run.clone(), path));
+                }
+            }
+            None => {
+                let run = run_accession_of(&name).unwrap_or_else(|| "-".to_string());
+                if !runs.contains(&run) {
+                    diffs.push((DiffStatus::Extra, run, path));
+                }
+            }
+        }
+    }
+
+    let mut missing = remote
+        .iter()
+        .filter(|(name, _)| !local.contains(*name))
+        .map(|(name, (run, _))| {
+            (
+                DiffStatus::Missing,
+                run.clone(),
+                run_dir(outdir, run).join(name),
+            )
+        })
+        .collect::<Vec<_>>();
+    missing.sort_by(|a, b| a.2.cmp(&b.2));
+    diffs.extend(missing);
+
+    for (status, run, path) in &diffs {
+        println!("{}\t{}\t{}", status, run, path.display());
+    }
+    log::info!(
+        "{} remote files, {} local files, {} differences",
+        remote.len(),
+        local.len(),
+        diffs.len()
+    );
+
+    diffs
+}
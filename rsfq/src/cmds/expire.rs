@@ -0,0 +1,110 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    state::{forget, State},
+    utils::{confirm, human_bytes},
+};
+
+/// Remove the downloads of an output directory whose retention period,
+/// recorded with `--retain`, is over.
+///
+/// Prints one `EXPIRED\trun_accession\tfile` line per removed file, or
+/// `EXPIRING` with `dry_run`. Files recorded without `--retain` are kept.
+/// Removed files are dropped from the state manifest.
+///
+/// # Arguments
+///
+/// * `outdir` - The output directory.
+/// * `dry_run` - Whether to only list the files.
+/// * `yes` - Whether to remove them without asking.
+///
+/// # Returns
+///
+/// The expired files, removed unless `dry_run`.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::cmds::expire::expire;
+/// use std::path::Path;
+/// let expired = expire(Path::new("DOWNLOADS"), true, false);
+/// ```
+pub fn expire(outdir: &Path, dry_run: bool, yes: bool) -> Vec<PathBuf> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let state = State::load(outdir);
+    let mut runs = state.runs().cloned().collect::<Vec<String>>();
+    runs.sort();
+
+    let expired = runs
+        .iter()
+        .flat_map(|run| state.entries(run))
+        .filter(|entry| entry.expired(now))
+        .map(|entry| (entry.run.clone(), outdir.join(&entry.file)))
+        .collect::<Vec<(String, PathBuf)>>();
+    if expired.is_empty() {
+        log::info!("Nothing has expired in {}", outdir.display());
+        return Vec::new();
+    }
+
+    let bytes = expired
+        .iter()
+        .map(|(_, path)| std::fs::metadata(path).map(|m| m.len()).unwrap_or(0))
+        .sum::<u64>();
+
+    if dry_run {
+        for (run, path) in &expired {
+            println!("EXPIRING\t{}\t{}", run, path.display());
+        }
+        log::info!(
+            "{} files ({}) have expired, drop --dry-run to remove them",
+            expired.len(),
+            human_bytes(bytes)
+        );
+        return expired.into_iter().map(|(_, path)| path).collect();
+    }
+
+    if !yes
+        && !confirm(&format!(
+            "Remove {} expired files ({}) from {}?",
+            expired.len(),
+            human_bytes(bytes),
+            outdir.display()
+        ))
+    {
+        log::error!("ERROR: Aborted by user! Re-run with --yes to skip this prompt");
+        std::process::exit(1);
+    }
+
+    let mut removed = Vec::new();
+    for (run, path) in expired {
+        // INFO: a file already removed by hand only has to leave the manifest
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                log::error!("ERROR: Could not remove {}!: {}", path.display(), e);
+            }
+            _ => {
+                println!("EXPIRED\t{}\t{}", run, path.display());
+                if let Some(dir) = path.parent().filter(|dir| *dir != outdir) {
+                    let _ = std::fs::remove_dir(dir);
+                }
+                removed.push(path);
+            }
+        }
+    }
+    forget(outdir, &removed);
+    log::info!(
+        "Removed {} expired files ({}) from {}",
+        removed.len(),
+        human_bytes(bytes),
+        outdir.display()
+    );
+
+    removed
+}
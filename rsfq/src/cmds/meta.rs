@@ -0,0 +1,65 @@
+use crate::{
+    cli::{AccessionType, Args},
+    cmds::search::print_runs,
+    core::get_fastqs,
+    store::{default_db, Store},
+};
+
+const RUN_ACCESSION: &str = "run_accession";
+
+/// Select runs from the local metadata store and print or download them.
+///
+/// # Arguments
+///
+/// * `filter` - A filter over ENA fields, e.g. `library_strategy = "RNA-Seq" AND read_count > 1e7`.
+/// * `fields` - Optional metadata fields to print as TSV columns.
+/// * `download` - Whether to download the matching runs right away.
+/// * `args` - Command line arguments driving the download.
+///
+/// # Returns
+///
+/// * `()` - Nothing.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use clap::Parser;
+/// use rsfq::cli::Args;
+/// use rsfq::cmds::meta::query;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let args = Args::parse_from(["rsfq", "meta", "query", "read_count > 1e7"]);
+///     query("read_count > 1e7", &[], false, args).await;
+/// }
+/// ```
+pub async fn query(filter: &str, fields: &[String], download: bool, mut args: Args) {
+    let db = args.db.clone().unwrap_or_else(default_db);
+    if !db.exists() {
+        log::error!(
+            "ERROR: No metadata store at {}, fill it with --store first!",
+            db.display()
+        );
+        std::process::exit(1);
+    }
+
+    let data = Store::open(&db)
+        .and_then(|store| store.query(filter))
+        .unwrap_or_else(|e| {
+            log::error!("ERROR: Could not query {}!: {}", db.display(), e);
+            std::process::exit(1);
+        });
+    log::info!("Filter matched {} stored runs", data.len());
+
+    if download {
+        let runs = data
+            .iter()
+            .filter_map(|run| run.get(RUN_ACCESSION).cloned())
+            .collect::<Vec<String>>();
+        args.accession = Some(AccessionType::List(runs));
+        get_fastqs(args).await;
+        return;
+    }
+
+    print_runs(&data, fields);
+}
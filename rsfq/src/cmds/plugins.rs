@@ -0,0 +1,25 @@
+use crate::plugins::discover;
+
+/// List the installed plugins as `kind<TAB>name<TAB>path` lines.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::cmds::plugins::list_plugins;
+/// list_plugins();
+/// ```
+pub fn list_plugins() {
+    let plugins = discover();
+    if plugins.is_empty() {
+        log::warn!("WARNING: No plugins found!");
+    }
+
+    for plugin in plugins {
+        println!(
+            "{}\t{}\t{}",
+            plugin.kind,
+            plugin.name,
+            plugin.path.display()
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cli::AccessionType,
+    provs::ena::get_run_info,
+    state::forget,
+    utils::{confirm, human_bytes, local_fastqs, run_accession_of, validate_query},
+};
+
+const RUN_ACCESSION: &str = "run_accession";
+
+/// Remove the FASTQs of an output directory that do not belong to the runs
+/// of the given accessions, keeping a mirror in line with an approved list.
+///
+/// Prints one `PRUNED\trun_accession\tfile` line per removed file, or
+/// `EXTRA` with `dry_run`. Files whose run cannot be inferred from their
+/// name (e.g. merged groups) are kept. Removed files are dropped from the
+/// state manifest, and run directories left empty are removed.
+///
+/// # Arguments
+///
+/// * `accession` - The accessions whose runs are kept.
+/// * `outdir` - The output directory to prune.
+/// * `dry_run` - Whether to only list the files.
+/// * `yes` - Whether to remove them without asking.
+/// * `attempts` - The maximum number of metadata attempts.
+/// * `sleep` - The number of seconds to sleep between attempts.
+///
+/// # Returns
+///
+/// The files outside the accessions, removed unless `dry_run`.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::cli::AccessionType;
+/// use rsfq::cmds::prune::prune;
+/// use std::path::Path;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let accession = AccessionType::List(vec!["PRJEB1".to_string(), "SRR123456".to_string()]);
+///     let extra = prune(&accession, Path::new("DOWNLOADS"), true, false, 3, 5).await;
+/// }
+/// ```
+pub async fn prune(
+    accession: &AccessionType,
+    outdir: &Path,
+    dry_run: bool,
+    yes: bool,
+    attempts: usize,
+    sleep: usize,
+) -> Vec<PathBuf> {
+    let accessions = match accession {
+        AccessionType::Single(accession) => vec![accession.clone()],
+        AccessionType::List(accessions) => accessions.clone(),
+    };
+
+    let mut runs = HashSet::new();
+    for accession in accessions {
+        for run in get_run_info(validate_query(&accession), attempts, sleep).await {
+            if let Some(run) = run.get(RUN_ACCESSION) {
+                runs.insert(run.clone());
+            }
+        }
+    }
+
+    let mut kept = 0;
+    let mut extra = Vec::new();
+    for path in local_fastqs(outdir) {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match run_accession_of(&name) {
+            Some(run) if !runs.contains(&run) => extra.push((run, path)),
+            Some(_) => {}
+            None => kept += 1,
+        }
+    }
+    if kept > 0 {
+        log::info!(
+            "Keeping {} files whose run could not be inferred from their name",
+            kept
+        );
+    }
+
+    let bytes = extra
+        .iter()
+        .map(|(_, path)| std::fs::metadata(path).map(|m| m.len()).unwrap_or(0))
+        .sum::<u64>();
+    if extra.is_empty() {
+        log::info!("Nothing to prune in {}", outdir.display());
+        return Vec::new();
+    }
+
+    if dry_run {
+        for (run, path) in &extra {
+            println!("EXTRA\t{}\t{}", run, path.display());
+        }
+        log::info!(
+            "{} files ({}) would be pruned, drop --dry-run to remove them",
+            extra.len(),
+            human_bytes(bytes)
+        );
+        return extra.into_iter().map(|(_, path)| path).collect();
+    }
+
+    if !yes
+        && !confirm(&format!(
+            "Remove {} files ({}) from {}?",
+            extra.len(),
+            human_bytes(bytes),
+            outdir.display()
+        ))
+    {
+        log::error!("ERROR: Aborted by user! Re-run with --yes to skip this prompt");
+        std::process::exit(1);
+    }
+
+    let mut removed = Vec::new();
+    for (run, path) in extra {
+        match std::fs::remove_file(&path) {
+            Ok(()) => {
+                println!("PRUNED\t{}\t{}", run, path.display());
+                // INFO: per-run directories are dropped once empty
+                if let Some(dir) = path.parent().filter(|dir| *dir != outdir) {
+                    let _ = std::fs::remove_dir(dir);
+                }
+                removed.push(path);
+            }
+            Err(e) => log::error!("ERROR: Could not remove {}!: {}", path.display(), e),
+        }
+    }
+    forget(outdir, &removed);
+    log::info!("Pruned {} files from {}", removed.len(), outdir.display());
+
+    removed
+}
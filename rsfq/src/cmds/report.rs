@@ -0,0 +1,221 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::IsTerminal,
+};
+
+use crate::{
+    cli::AccessionType,
+    provs::ena::get_run_info,
+    table::{render_table, terminal_width},
+    utils::{human_bytes, validate_query},
+};
+
+const BASE_COUNT: &str = "base_count";
+const FASTQ_BYTES: &str = "fastq_bytes";
+const INSTRUMENT_PLATFORM: &str = "instrument_platform";
+const LIBRARY_LAYOUT: &str = "library_layout";
+const TOTAL: &str = "TOTAL";
+const COLUMNS: &[&str] = &[
+    "accession",
+    "runs",
+    "bases",
+    "fastq_bytes",
+    "platforms",
+    "layouts",
+];
+
+/// Size and composition of the runs behind one accession
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub accession: String,
+    pub runs: usize,
+    pub bases: u64,
+    pub fastq_bytes: u64,
+    pub platforms: BTreeMap<String, usize>,
+    pub layouts: BTreeMap<String, usize>,
+}
+
+impl Summary {
+    /// Summarize the runs of an accession.
+    ///
+    /// # Arguments
+    /// * `accession` - The accession the runs were expanded from.
+    /// * `runs` - The run metadata, as returned by ENA.
+    ///
+    /// # Returns
+    /// A `Summary` instance.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::cmds::report::Summary;
+    /// use std::collections::HashMap;
+    /// let runs = vec![HashMap::from([("base_count".to_string(), "1000".to_string())])];
+    /// assert_eq!(Summary::from_runs("PRJEB12345", &runs).bases, 1000);
+    /// ```
+    pub fn from_runs(accession: &str, runs: &[HashMap<String, String>]) -> Self {
+        let mut summary = Summary {
+            accession: accession.to_string(),
+            runs: runs.len(),
+            ..Default::default()
+        };
+
+        for run in runs {
+            summary.bases += sum_of(run.get(BASE_COUNT));
+            summary.fastq_bytes += sum_of(run.get(FASTQ_BYTES));
+            *summary
+                .platforms
+                .entry(label(run.get(INSTRUMENT_PLATFORM)))
+                .or_default() += 1;
+            *summary
+                .layouts
+                .entry(label(run.get(LIBRARY_LAYOUT)))
+                .or_default() += 1;
+        }
+
+        summary
+    }
+
+    /// Add another summary into this one.
+    ///
+    /// # Arguments
+    /// * `other` - The summary to add.
+    pub fn absorb(&mut self, other: &Summary) {
+        self.runs += other.runs;
+        self.bases += other.bases;
+        self.fastq_bytes += other.fastq_bytes;
+        for (platform, count) in &other.platforms {
+            *self.platforms.entry(platform.clone()).or_default() += count;
+        }
+        for (layout, count) in &other.layouts {
+            *self.layouts.entry(layout.clone()).or_default() += count;
+        }
+    }
+
+    /// Get the summary as a row of named cells.
+    ///
+    /// # Arguments
+    /// * `human` - Whether sizes should be human readable.
+    ///
+    /// # Returns
+    /// The cells, keyed by column name.
+    pub fn row(&self, human: bool) -> HashMap<String, String> {
+        let breakdown = |counts: &BTreeMap<String, usize>| {
+            counts
+                .iter()
+                .map(|(key, count)| format!("{}:{}", key, count))
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        let (bases, bytes) = if human {
+            (human_bases(self.bases), human_bytes(self.fastq_bytes))
+        } else {
+            (self.bases.to_string(), self.fastq_bytes.to_string())
+        };
+
+        HashMap::from([
+            ("accession".to_string(), self.accession.clone()),
+            ("runs".to_string(), self.runs.to_string()),
+            ("bases".to_string(), bases),
+            ("fastq_bytes".to_string(), bytes),
+            ("platforms".to_string(), breakdown(&self.platforms)),
+            ("layouts".to_string(), breakdown(&self.layouts)),
+        ])
+    }
+}
+
+/// Summarize the runs of one or more accessions before downloading them.
+///
+/// On a terminal the summary is a table with human readable sizes; otherwise
+/// it is a TSV with raw numbers. A `TOTAL` row is added for several accessions.
+///
+/// # Arguments
+///
+/// * `accession` - The accessions to summarize.
+/// * `attempts` - The maximum number of metadata attempts.
+/// * `sleep` - The number of seconds to sleep between attempts.
+///
+/// # Returns
+///
+/// One summary per accession.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::cli::AccessionType;
+/// use rsfq::cmds::report::summarize;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let accession = AccessionType::List(vec!["PRJEB1".to_string(), "PRJEB2".to_string()]);
+///     let summaries = summarize(&accession, 3, 5).await;
+/// }
+/// ```
+pub async fn summarize(accession: &AccessionType, attempts: usize, sleep: usize) -> Vec<Summary> {
+    let accessions = match accession {
+        AccessionType::Single(accession) => vec![accession.clone()],
+        AccessionType::List(accessions) => accessions.clone(),
+    };
+
+    let mut summaries = Vec::new();
+    for accession in accessions {
+        let runs = get_run_info(validate_query(&accession), attempts, sleep).await;
+        summaries.push(Summary::from_runs(&accession, &runs));
+    }
+
+    let mut rows = summaries.clone();
+    if rows.len() > 1 {
+        let mut total = Summary {
+            accession: TOTAL.to_string(),
+            ..Default::default()
+        };
+        summaries.iter().for_each(|summary| total.absorb(summary));
+        rows.push(total);
+    }
+
+    let columns = COLUMNS.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+    if std::io::stdout().is_terminal() {
+        let rows = rows.iter().map(|s| s.row(true)).collect::<Vec<_>>();
+        println!("{}", render_table(&rows, &columns, terminal_width()));
+    } else {
+        println!("{}", columns.join("\t"));
+        for row in rows.iter().map(|s| s.row(false)) {
+            let cells = columns
+                .iter()
+                .map(|column| row[column].as_str())
+                .collect::<Vec<&str>>();
+            println!("{}", cells.join("\t"));
+        }
+    }
+
+    summaries
+}
+
+/// Sum a `;`-separated numeric field, as ENA reports one value per file.
+fn sum_of(value: Option<&String>) -> u64 {
+    value
+        .map(|value| value.split(';').filter_map(|v| v.parse::<u64>().ok()).sum())
+        .unwrap_or_default()
+}
+
+/// Get a breakdown label, `unknown` for missing fields.
+fn label(value: Option<&String>) -> String {
+    value.cloned().unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Format a base count with a metric suffix, e.g. `1.2 Gbp`.
+fn human_bases(bases: u64) -> String {
+    const UNITS: &[&str] = &["bp", "Kbp", "Mbp", "Gbp", "Tbp", "Pbp"];
+
+    let mut size = bases as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bases, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
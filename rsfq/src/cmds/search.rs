@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use crate::{
+    cli::{AccessionType, Args},
+    core::get_fastqs,
+    provs::ena::{fetch_results, ResultType},
+};
+
+const RUN_ACCESSION: &str = "run_accession";
+
+/// Run a free-text ENA portal query and print or download the matching
+/// results.
+///
+/// Without `fields`, one accession is printed per line so the output can
+/// be fed back to `rsfq -a runs.txt`. Experiments and studies download as
+/// the runs they hold; analyses cannot be downloaded as FASTQ. Exits if the
+/// query fails or `download` is asked for analyses.
+///
+/// # Arguments
+///
+/// * `query` - A raw ENA portal query, e.g. `tax_eq(9606) AND library_strategy="RNA-Seq"`.
+/// * `result` - The ENA result type to search, `read_run` for runs and
+///   `read_experiment`, `read_study` or `analysis` for the others.
+/// * `fields` - Optional metadata fields to print as TSV columns.
+/// * `download` - Whether to download the matching results right away.
+/// * `args` - Command line arguments driving the download.
+///
+/// # Returns
+///
+/// * `()` - Nothing.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use clap::Parser;
+/// use rsfq::cli::Args;
+/// use rsfq::cmds::search::search;
+/// use rsfq::provs::ena::ResultType;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let args = Args::parse_from(["rsfq", "search", "--query", "tax_eq(9606)"]);
+///     // INFO: print the matching experiments, then download the RNA-Seq runs
+///     search("tax_eq(9606)", ResultType::ReadExperiment, &[], false, args).await;
+///
+///     let args = Args::parse_from(["rsfq", "search", "--query", "tax_eq(9606)"]);
+///     search(
+///         "tax_eq(9606) AND library_strategy=\"RNA-Seq\"",
+///         ResultType::ReadRun,
+///         &["run_accession".to_string(), "read_count".to_string()],
+///         true,
+///         args,
+///     )
+///     .await;
+/// }
+/// ```
+pub async fn search(
+    query: &str,
+    result: ResultType,
+    fields: &[String],
+    download: bool,
+    mut args: Args,
+) {
+    if download && !result.has_reads() {
+        log::error!(
+            "ERROR: {} results have no FASTQs to download! List their files with --fields submitted_ftp",
+            result
+        );
+        std::process::exit(1);
+    }
+
+    let data = fetch_results(result, query.to_string(), args.attempts, args.sleep)
+        .await
+        .unwrap_or_else(|e| {
+            log::error!("ERROR: {}", e);
+            std::process::exit(1);
+        });
+
+    let accessions = data
+        .iter()
+        .filter_map(|row| row.get(result.key()).cloned())
+        .collect::<Vec<String>>();
+    log::info!("Query matched {} {} results", accessions.len(), result);
+
+    if download {
+        args.accession = Some(AccessionType::List(accessions));
+        get_fastqs(args).await;
+        return;
+    }
+
+    print_results(&data, fields, result.key());
+}
+
+/// Print runs as bare accessions, or as TSV when `fields` are given.
+///
+/// # Arguments
+///
+/// * `data` - The run metadata.
+/// * `fields` - Optional metadata fields to print as TSV columns.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::cmds::search::print_runs;
+/// use std::collections::HashMap;
+///
+/// let data = vec![HashMap::from([("run_accession".to_string(), "SRR123456".to_string())])];
+/// print_runs(&data, &["run_accession".to_string()]);
+/// ```
+pub fn print_runs(data: &[HashMap<String, String>], fields: &[String]) {
+    print_results(data, fields, RUN_ACCESSION);
+}
+
+/// Print results as bare accessions, or as TSV when `fields` are given.
+///
+/// # Arguments
+///
+/// * `data` - The results.
+/// * `fields` - Optional metadata fields to print as TSV columns.
+/// * `key` - The field holding the accession of each result.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::cmds::search::print_results;
+/// use std::collections::HashMap;
+///
+/// let data = vec![HashMap::from([("analysis_accession".to_string(), "ERZ123456".to_string())])];
+/// print_results(&data, &[], "analysis_accession");
+/// ```
+pub fn print_results(data: &[HashMap<String, String>], fields: &[String], key: &str) {
+    if fields.is_empty() {
+        data.iter()
+            .filter_map(|row| row.get(key))
+            .for_each(|accession| println!("{}", accession));
+    } else {
+        println!("{}", fields.join("\t"));
+        for row in data {
+            let row = fields
+                .iter()
+                .map(|field| row.get(field).map(String::as_str).unwrap_or_default())
+                .collect::<Vec<&str>>();
+            println!("{}", row.join("\t"));
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use serde_json::{json, Value};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+    cli::AccessionType,
+    provs::ena::get_run_info,
+    utils::{human_bytes, validate_query},
+};
+
+type Run = HashMap<String, String>;
+
+const STUDY_ACCESSION: &str = "study_accession";
+const SAMPLE_ACCESSION: &str = "sample_accession";
+const EXPERIMENT_ACCESSION: &str = "experiment_accession";
+const RUN_ACCESSION: &str = "run_accession";
+const STUDY_TITLE: &str = "study_title";
+const SAMPLE_ALIAS: &str = "sample_alias";
+const LIBRARY_STRATEGY: &str = "library_strategy";
+const INSTRUMENT_MODEL: &str = "instrument_model";
+const LIBRARY_LAYOUT: &str = "library_layout";
+const READ_COUNT: &str = "read_count";
+const FASTQ_BYTES: &str = "fastq_bytes";
+const UNKNOWN: &str = "-";
+
+/// Runs of an accession, nested by study, sample and experiment
+#[derive(Debug, Clone, Default)]
+pub struct Tree<'a> {
+    pub studies: BTreeMap<String, BTreeMap<String, BTreeMap<String, Vec<&'a Run>>>>,
+}
+
+impl<'a> Tree<'a> {
+    /// Nest runs under their study, sample and experiment.
+    ///
+    /// # Arguments
+    /// * `runs` - The run metadata, as returned by ENA.
+    ///
+    /// # Returns
+    /// A `Tree` instance; runs missing a level are put under `-`.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::cmds::tree::Tree;
+    /// use std::collections::HashMap;
+    /// let runs = vec![HashMap::from([
+    ///     ("study_accession".to_string(), "PRJNA123456".to_string()),
+    ///     ("run_accession".to_string(), "SRR123456".to_string()),
+    /// ])];
+    /// assert_eq!(Tree::from_runs(&runs).studies.len(), 1);
+    /// ```
+    pub fn from_runs(runs: &'a [Run]) -> Self {
+        let mut tree = Tree::default();
+        for run in runs {
+            tree.studies
+                .entry(field(run, STUDY_ACCESSION))
+                .or_default()
+                .entry(field(run, SAMPLE_ACCESSION))
+                .or_default()
+                .entry(field(run, EXPERIMENT_ACCESSION))
+                .or_default()
+                .push(run);
+        }
+        for run in tree.studies.values_mut().flat_map(|samples| {
+            samples
+                .values_mut()
+                .flat_map(|experiments| experiments.values_mut())
+        }) {
+            run.sort_by_key(|run| field(run, RUN_ACCESSION));
+        }
+        tree
+    }
+
+    /// Draw the tree, one node per line.
+    ///
+    /// # Returns
+    /// The tree, without trailing newline.
+    pub fn render(&self) -> String {
+        let mut lines = Vec::new();
+        for (study, samples) in &self.studies {
+            let first = samples
+                .values()
+                .flat_map(|experiments| experiments.values().flatten())
+                .next();
+            lines.push(label(study, first.map(|run| field(run, STUDY_TITLE))));
+
+            for (i, (sample, experiments)) in samples.iter().enumerate() {
+                let last_sample = i + 1 == samples.len();
+                let first = experiments.values().flatten().next();
+                lines.push(format!(
+                    "{}{}",
+                    branch(last_sample),
+                    label(sample, first.map(|run| field(run, SAMPLE_ALIAS)))
+                ));
+
+                let indent = stem(last_sample);
+                for (j, (experiment, runs)) in experiments.iter().enumerate() {
+                    let last_experiment = j + 1 == experiments.len();
+                    let about = runs.first().and_then(|run| {
+                        known(&[field(run, LIBRARY_STRATEGY), field(run, INSTRUMENT_MODEL)])
+                    });
+                    lines.push(format!(
+                        "{}{}{}",
+                        indent,
+                        branch(last_experiment),
+                        label(experiment, about)
+                    ));
+
+                    let indent = format!("{}{}", indent, stem(last_experiment));
+                    for (k, run) in runs.iter().enumerate() {
+                        lines.push(format!(
+                            "{}{}{}",
+                            indent,
+                            branch(k + 1 == runs.len()),
+                            label(&field(run, RUN_ACCESSION), describe(run))
+                        ));
+                    }
+                }
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// Get the tree as nested JSON: studies holding samples, holding
+    /// experiments, holding runs.
+    ///
+    /// # Returns
+    /// The JSON array of studies.
+    pub fn json(&self) -> Value {
+        let studies = self
+            .studies
+            .iter()
+            .map(|(study, samples)| {
+                let samples = samples
+                    .iter()
+                    .map(|(sample, experiments)| {
+                        let experiments = experiments
+                            .iter()
+                            .map(|(experiment, runs)| {
+                                let runs = runs
+                                    .iter()
+                                    .map(|run| {
+                                        json!({
+                                            "accession": field(run, RUN_ACCESSION),
+                                            "library_layout": field(run, LIBRARY_LAYOUT),
+                                            "read_count": field(run, READ_COUNT),
+                                            "fastq_bytes": field(run, FASTQ_BYTES),
+                                        })
+                                    })
+                                    .collect::<Vec<_>>();
+                                json!({ "accession": experiment, "runs": runs })
+                            })
+                            .collect::<Vec<_>>();
+                        json!({ "accession": sample, "experiments": experiments })
+                    })
+                    .collect::<Vec<_>>();
+                json!({ "accession": study, "samples": samples })
+            })
+            .collect::<Vec<_>>();
+        Value::Array(studies)
+    }
+}
+
+/// Print what one or more accessions expand into, as a study → sample →
+/// experiment → run tree (or JSON), without downloading anything.
+///
+/// # Arguments
+///
+/// * `accession` - The accessions to expand.
+/// * `json` - Whether to print JSON instead of a tree.
+/// * `attempts` - The maximum number of metadata attempts.
+/// * `sleep` - The number of seconds to sleep between attempts.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::cli::AccessionType;
+/// use rsfq::cmds::tree::tree;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let accession = AccessionType::Single("PRJNA123456".to_string());
+///     tree(&accession, false, 3, 5).await;
+/// }
+/// ```
+pub async fn tree(accession: &AccessionType, json: bool, attempts: usize, sleep: usize) {
+    let accessions = match accession {
+        AccessionType::Single(accession) => vec![accession.clone()],
+        AccessionType::List(accessions) => accessions.clone(),
+    };
+
+    // INFO: a run listed under several accessions is drawn once
+    let mut seen = HashSet::new();
+    let mut runs = Vec::new();
+    for accession in accessions {
+        let found = get_run_info(validate_query(&accession), attempts, sleep).await;
+        runs.extend(
+            found
+                .into_iter()
+                .filter(|run| seen.insert(field(run, RUN_ACCESSION))),
+        );
+    }
+
+    let tree = Tree::from_runs(&runs);
+    if json {
+        println!("{:#}", tree.json());
+    } else {
+        println!("{}", tree.render());
+    }
+}
+
+/// Get a field of a run, `-` if missing or empty.
+fn field(run: &Run, name: &str) -> String {
+    run.get(name)
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+        .unwrap_or(UNKNOWN)
+        .to_string()
+}
+
+/// Join the known parts of a description.
+fn known(parts: &[String]) -> Option<String> {
+    let parts = parts
+        .iter()
+        .filter(|part| !part.starts_with(UNKNOWN))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
+/// Format a node as its accession followed by what it is, when known.
+fn label(accession: &str, about: Option<String>) -> String {
+    match about.filter(|about| about != UNKNOWN) {
+        Some(about) => format!("{} ({})", accession, about),
+        None => accession.to_string(),
+    }
+}
+
+/// Describe a run by layout, reads and size.
+fn describe(run: &Run) -> Option<String> {
+    let bytes = run
+        .get(FASTQ_BYTES)
+        .map(|value| {
+            value
+                .split(';')
+                .filter_map(|v| v.parse::<u64>().ok())
+                .sum::<u64>()
+        })
+        .filter(|&bytes| bytes > 0)
+        .map(human_bytes)
+        .unwrap_or_else(|| UNKNOWN.to_string());
+    known(&[
+        field(run, LIBRARY_LAYOUT),
+        format!("{} reads", field(run, READ_COUNT)),
+        bytes,
+    ])
+}
+
+/// Get the connector of a node.
+fn branch(last: bool) -> &'static str {
+    if last {
+        "└── "
+    } else {
+        "├── "
+    }
+}
+
+/// Get the indent below a node, for its children.
+fn stem(last: bool) -> &'static str {
+    if last {
+        "    "
+    } else {
+        "│   "
+    }
+}
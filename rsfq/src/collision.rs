@@ -0,0 +1,150 @@
+use once_cell::sync::OnceCell;
+
+use std::{collections::HashSet, sync::Mutex};
+
+static ON_COLLISION: OnceCell<OnCollision> = OnceCell::new();
+
+// INFO: runs whose output name is taken by another run
+static COLLIDING: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+/// What happens when two runs map to the same output file name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnCollision {
+    #[default]
+    Error,
+    Suffix,
+    Overwrite,
+    Skip,
+}
+
+/// Parse a string into an OnCollision
+impl std::str::FromStr for OnCollision {
+    type Err = String;
+
+    /// Parse a string into an OnCollision
+    ///
+    /// # Arguments
+    /// * `s` - The string to parse: `error`, `suffix`, `overwrite` or `skip`.
+    ///
+    /// # Returns
+    /// * `Result<Self, Self::Err>` - The parsed OnCollision.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::collision::OnCollision;
+    /// use std::str::FromStr;
+    /// assert_eq!(OnCollision::from_str("suffix").unwrap(), OnCollision::Suffix);
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "error" => Ok(OnCollision::Error),
+            "suffix" => Ok(OnCollision::Suffix),
+            "overwrite" => Ok(OnCollision::Overwrite),
+            "skip" => Ok(OnCollision::Skip),
+            _ => Err(format!(
+                "Invalid collision policy, expected error, suffix, overwrite or skip: {}",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for OnCollision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OnCollision::Error => write!(f, "error"),
+            OnCollision::Suffix => write!(f, "suffix"),
+            OnCollision::Overwrite => write!(f, "overwrite"),
+            OnCollision::Skip => write!(f, "skip"),
+        }
+    }
+}
+
+/// Set what happens when two runs map to the same output name. Only the
+/// first call has effect.
+///
+/// # Arguments
+/// * `policy` - The policy, from `--on-collision`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::collision::{init_on_collision, OnCollision};
+/// init_on_collision(OnCollision::Suffix);
+/// ```
+pub fn init_on_collision(policy: OnCollision) {
+    let _ = ON_COLLISION.set(policy);
+}
+
+/// Get what happens when two runs map to the same output name.
+///
+/// # Returns
+/// The policy, `error` by default.
+pub fn on_collision() -> OnCollision {
+    ON_COLLISION.get().copied().unwrap_or_default()
+}
+
+/// Mark a run as giving its output name away to another run sharing it,
+/// so its files are not downloaded.
+///
+/// # Arguments
+/// * `run` - The run accession.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::collision::collide;
+/// collide("SRR123457");
+/// ```
+pub fn collide(run: &str) {
+    COLLIDING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(HashSet::new)
+        .insert(run.to_string());
+}
+
+/// Check whether a run gave its output name away to another run.
+///
+/// # Arguments
+/// * `run` - The run accession.
+///
+/// # Returns
+/// `true` if `collide` was called for the run.
+pub fn colliding(run: &str) -> bool {
+    COLLIDING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .is_some_and(|runs| runs.contains(run))
+}
+
+/// Add a numeric suffix to a file name, before its mate and extensions,
+/// e.g. `liver_1.fastq.gz` becomes `liver-2_1.fastq.gz`.
+///
+/// # Arguments
+/// * `name` - The file name.
+/// * `n` - The suffix.
+///
+/// # Returns
+/// The suffixed file name.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::collision::suffixed;
+/// assert_eq!(suffixed("liver_1.fastq.gz", 2), "liver-2_1.fastq.gz");
+/// assert_eq!(suffixed("liver.fastq.gz", 2), "liver-2.fastq.gz");
+/// ```
+pub fn suffixed(name: &str, n: usize) -> String {
+    let (stem, ext) = name.split_once('.').unwrap_or((name, ""));
+    let ext = if ext.is_empty() {
+        String::new()
+    } else {
+        format!(".{}", ext)
+    };
+
+    match stem.rsplit_once('_') {
+        Some((base, mate)) if !mate.is_empty() && mate.chars().all(|c| c.is_ascii_digit()) => {
+            format!("{}-{}_{}{}", base, n, mate, ext)
+        }
+        _ => format!("{}-{}{}", stem, n, ext),
+    }
+}
@@ -1,36 +1,92 @@
 use crate::{
+    audit::{audit, Action, Entry},
+    cellranger::assign_lanes,
+    chunk::{chunk_reads, existing_chunks, split_fastq},
+    claims::{claim, Claim},
     cli::{AccessionType, Args},
+    collision::colliding,
+    crosscheck::cross_validate,
+    dedup::{dedup_runs, link_duplicates},
+    downsample::{fetch_head, target_spots},
+    expand::{filter_runs, pick_runs, sample_runs, slice_runs},
+    export::collect,
+    fasta::{fasta_enabled, fasta_name, to_fasta},
+    hints::{no_fastq_hint, not_found_hint, suggest},
+    html::write_html_report,
+    lock::lock_outdir,
+    merge::merge_runs,
+    multiqc::write_multiqc,
+    net::{download_tuner, fetch, fetch_through, mirrors, offline},
+    perms::{create_dirs, install},
+    plan::{read_plan, run_files, Plan, Planned},
+    plugins::{exchange, fetch_request, plugin_path, PluginKind},
+    progress::{init_batch, run_done, stalled, watch_transfer},
     provs::{
-        ena::get_run_info,
+        aws::download_run as download_from_aws,
+        drs::{access, fetch_blocks, Access},
+        ena::preflight,
+        gcp::download_run as download_from_gcp,
         sra::{download_run as download_from_sra, SRAError},
         Provider,
     },
-    utils::{validate_query, Layout, Retriever},
+    quarantine::quarantine,
+    report::{explain, report, Status},
+    resolve::{hosted_by, mirrored_by_ncbi, resolve},
+    runner::runner,
+    scheduler::{blocking, cpu_slot, summarize_scheduler, transfer_slot},
+    sites::site_retriever,
+    state::{forget, record},
+    submitted::{download_pacbio_bam, download_raw_signal, is_pacbio, pacbio_bams},
+    table::{render_table, terminal_width},
+    timing::{retried, summarize_timings, time, transferred, Phase},
+    utils::{
+        aria2_control, drop_partial, fits_on_disk, human_bytes, part_path, renamed, resume_partial,
+        retriever_chain, run_accession_of, run_dir, segments_control, Checksum, Layout, Retriever,
+    },
+    validate::{check_read_ids, existing_intact, gzip_intact, skip_verify, truncated},
 };
 
-use futures::stream::{self, StreamExt};
-use md5::Context;
+use futures::{
+    future,
+    stream::{self, StreamExt},
+};
+use once_cell::sync::OnceCell;
 use walkdir::WalkDir;
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fmt::Debug,
-    fs::File,
-    io::{BufReader, Read},
+    io::IsTerminal,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
 };
 
 const PAIRED: &str = "PAIRED";
 const SINGLE: &str = "SINGLE";
 const FASTQ_FTP: &str = "fastq_ftp";
 const FASTQ_MD5: &str = "fastq_md5";
+const FASTQ_BYTES: &str = "fastq_bytes";
+const FAILURES_BEFORE_FALLBACK: usize = 2;
+const OUTPUT_TAIL: usize = 5; // lines
 const LIBRARY_LAYOUT: &str = "library_layout";
+const LIBRARY_STRATEGY: &str = "library_strategy";
+const LIBRARY_SELECTION: &str = "library_selection";
+const LIBRARY_SOURCE: &str = "library_source";
 const RUN_ACCESSION: &str = "run_accession";
 const R1: &str = "_1.fastq.gz";
 const R2: &str = "_2.fastq.gz";
-const MB: usize = 1_048_576; // 1 MB
-const BUFFER_SIZE: usize = 10 * MB; // 10 MB
 const QUEUE_SIZE: usize = 50; // 50 requests
+const PREFLIGHT_BATCH: usize = 10; // accessions
+
+/// Exit code used when `--max-runtime` stopped the batch before it finished
+pub const EXIT_TIMED_OUT: i32 = 3;
+
+static DEADLINE: OnceCell<Instant> = OnceCell::new();
+static CUT_SHORT: AtomicBool = AtomicBool::new(false);
 
 const EXTENSIONS: &[&str] = &[
     ".fastq.gz",
@@ -39,8 +95,66 @@ const EXTENSIONS: &[&str] = &[
     "_subreads.fq.gz",
     ".subreads.fastq.gz",
     ".subreads.fq.gz",
+    "_ccs.fastq.gz",
+    ".ccs.fastq.gz",
+    "_hifi_reads.fastq.gz",
+    ".hifi_reads.fastq.gz",
+    "_f1.fq.gz",
+    "_r2.fq.gz",
 ];
 
+/// Set the wall-clock budget of the batch, counted from now.
+///
+/// Once it runs out no new download is started; in-flight ones finish so no
+/// partial file is left behind.
+///
+/// # Arguments
+/// * `max_runtime` - The budget, `None` for no limit.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::core::init_deadline;
+/// use std::time::Duration;
+/// init_deadline(Some(Duration::from_secs(12 * 3600)));
+/// ```
+pub fn init_deadline(max_runtime: Option<Duration>) {
+    if let Some(max_runtime) = max_runtime {
+        let _ = DEADLINE.set(Instant::now() + max_runtime);
+    }
+}
+
+/// Check whether the batch may still start new downloads.
+///
+/// # Returns
+/// `true` while the `--max-runtime` budget is not exhausted.
+pub fn within_runtime() -> bool {
+    match DEADLINE.get() {
+        Some(deadline) if Instant::now() >= *deadline => {
+            if !CUT_SHORT.swap(true, Ordering::SeqCst) {
+                log::warn!("WARNING: --max-runtime reached, not starting new downloads");
+            }
+            false
+        }
+        _ => true,
+    }
+}
+
+/// Check whether the batch was stopped by `--max-runtime`.
+///
+/// # Returns
+/// `true` if some downloads were never started.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::core::{cut_short, EXIT_TIMED_OUT};
+/// if cut_short() {
+///     std::process::exit(EXIT_TIMED_OUT);
+/// }
+/// ```
+pub fn cut_short() -> bool {
+    CUT_SHORT.load(Ordering::SeqCst)
+}
+
 /// Download fastq files for a single accession or a list of accessions
 ///
 /// # Arguments
@@ -54,75 +168,89 @@ const EXTENSIONS: &[&str] = &[
 /// # Examples
 ///
 /// ```rust, no_run
+/// use clap::Parser;
+/// use rsfq::cli::Args;
 /// use rsfq::core::get_fastqs;
-/// use rsfq::cli::{AccessionType, Args};
-/// use rsfq::provs::Provider;
-/// use rsfq::utils::{Layout, Retriever};
 ///
 /// #[tokio::main]
 /// async fn main() {
-///     let args = Args {
-///         accession: AccessionType::Single("SRR123456".to_string()),
-///         outdir: None,
-///         attempts: 3,
-///         sleep: 5,
-///         force: false,
-///         metadata: false,
-///         threads: 4,
-///         group_by_experiment: false,
-///         group_by_sample: false,
-///         prefix: "fastq".to_string(),
-///         nextflow: false,
-///         executor: "local".to_string(),
-///         queue: "null".to_string(),
-///         check_if_downloadable: false,
-///         retriever: Retriever::Aria2c,
-///         queue_size: 10,
-///         layout: Layout::Global,
-///         provider: Provider::ENA,
-///     };
+///     let args = Args::parse_from(["rsfq", "-a", "SRR123456"]);
 ///     get_fastqs(args).await;
 /// }
 /// ```
 pub async fn get_fastqs(args: Args) {
-    match args.accession {
-        AccessionType::Single(accession) => {
-            process_run(
-                accession.clone(),
-                args.outdir,
-                args.attempts,
-                args.sleep,
-                args.force,
-                args.metadata,
-                args.retriever,
-                args.check_if_downloadable,
-                args.provider,
-                args.layout,
-                args.threads,
-            )
-            .await;
+    let started = Instant::now();
+    // INFO: machines sharing claims also share the outdir
+    if !args.no_lock && args.claims_dir.is_none() {
+        lock_outdir(
+            args.outdir
+                .as_deref()
+                .unwrap_or_else(|| Path::new("DOWNLOADS")),
+        );
+    }
+
+    let accessions = match &args.accession {
+        // INFO: a saved plan lists its own accessions
+        None if args.plan_in.is_some() => Vec::new(),
+        None => {
+            log::error!("ERROR: No accession was provided!");
+            std::process::exit(1);
         }
-        AccessionType::List(accessions) => {
-            // INFO: download fastq files for a list of accessions
-            let stream = stream::iter(accessions.into_iter().map(|accession| {
-                process_run(
-                    accession.clone(),
-                    args.outdir.clone(),
-                    args.attempts,
-                    args.sleep,
-                    args.force,
-                    args.metadata,
-                    args.retriever.clone(),
-                    args.check_if_downloadable,
-                    args.provider,
-                    args.layout,
-                    args.threads,
-                )
-            }))
-            .buffer_unordered(QUEUE_SIZE);
+        Some(AccessionType::Single(accession)) => vec![accession.clone()],
+        Some(AccessionType::List(accessions)) => {
+            if !args.no_preflight && accessions.len() >= PREFLIGHT_BATCH {
+                preflight(args.attempts).await;
+            }
+            accessions.clone()
+        }
+    };
 
-            stream.collect::<Vec<_>>().await;
+    if args.claims_dir.is_some() {
+        // INFO: planning would claim the whole list before other machines get a share
+        stream::iter(accessions)
+            .take_while(|_| future::ready(within_runtime()))
+            .map(|accession| process_run(accession, &args))
+            .buffer_unordered(QUEUE_SIZE)
+            .collect::<Vec<_>>()
+            .await;
+    } else {
+        // INFO: every accession is resolved first, so a bad one stops the
+        // batch before anything is downloaded
+        let plan = match &args.plan_in {
+            Some(path) => load_plan(path, &args),
+            None => plan_runs(&accessions, &args).await,
+        };
+        // INFO: lanes follow the plan, not the order accessions resolved in
+        assign_lanes(plan.accessions.iter().flat_map(|planned| &planned.resolved));
+        if !plan.accessions.is_empty() {
+            plan.summarize(&args.provider);
+            plan.validate(&args);
+            if let Some(path) = &args.plan_out {
+                plan.write(path, &args.provider);
+            }
+            init_batch(plan.runs().count(), plan.files(), plan.bytes());
         }
+
+        stream::iter(plan.accessions)
+            .take_while(|_| future::ready(within_runtime()))
+            .map(|planned| fetch_planned(planned, &args))
+            .buffer_unordered(QUEUE_SIZE)
+            .collect::<Vec<_>>()
+            .await;
+    }
+
+    let outdir = args
+        .outdir
+        .as_deref()
+        .unwrap_or_else(|| Path::new("DOWNLOADS"));
+    link_duplicates(outdir);
+    summarize_timings(args.timings.as_deref());
+    summarize_scheduler();
+    if let Some(path) = &args.html_report {
+        write_html_report(path, started.elapsed());
+    }
+    if args.multiqc {
+        write_multiqc(outdir);
     }
 }
 
@@ -131,11 +259,8 @@ pub async fn get_fastqs(args: Args) {
 /// # Arguments
 ///
 /// * `accession` - The accession number of the run to process.
-/// * `outdir` - The output directory to save the downloaded files.
-/// * `attempts` - The number of attempts to make when downloading the files.
-/// * `sleep` - The number of seconds to sleep between attempts.
-/// * `force` - Whether to force the download even if the file already exists.
-/// * `metadata` - Whether to download the metadata for the run.
+/// * `args` - Command line arguments driving the download (outdir, attempts,
+///   sleep, overwrite, metadata, retriever, provider, layout, threads...).
 ///
 /// # Returns
 ///
@@ -144,49 +269,161 @@ pub async fn get_fastqs(args: Args) {
 /// # Examples
 ///
 /// ```rust, no_run
+/// use clap::Parser;
+/// use rsfq::cli::Args;
 /// use rsfq::core::process_run;
-/// use rsfq::provs::Provider;
-/// use rsfq::utils::{Layout, Retriever};
 ///
 /// #[tokio::main]
 /// async fn main() {
-///     process_run(
-///         "ERR123456".to_string(),
-///         None,
-///         3,
-///         5,
-///         false,
-///         false,
-///         Retriever::Aria2c,
-///         false,
-///         Provider::ENA,
-///         Layout::Global,
-///         4,
-///     )
-///     .await;
+///     let args = Args::parse_from(["rsfq", "-a", "ERR123456"]);
+///     process_run("ERR123456".to_string(), &args).await;
 /// }
 /// ```
-pub async fn process_run(
-    accession: String,
-    outdir: Option<PathBuf>,
-    attempts: usize,
-    sleep: usize,
-    force: bool,
-    metadata: bool,
-    retriever: Retriever,
-    check_if_downloadable: bool,
-    provider: Provider,
-    layout: Layout,
-    threads: usize,
-) {
-    let query = validate_query(&accession);
+pub async fn process_run(accession: String, args: &Args) {
+    if let Some(planned) = resolve_run(accession, args).await {
+        assign_lanes(&planned.resolved);
+        fetch_planned(planned, args).await;
+    }
+}
 
-    let data = get_run_info(query, attempts, sleep).await;
+/// Resolve the metadata of the given accessions, a few at a time, into the
+/// plan of the batch.
+///
+/// # Arguments
+///
+/// * `accessions` - The accessions to resolve.
+/// * `args` - Command line arguments driving the download.
+///
+/// # Returns
+///
+/// The plan: the runs of every accession left to download.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use clap::Parser;
+/// use rsfq::cli::Args;
+/// use rsfq::core::plan_runs;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let args = Args::parse_from(["rsfq", "-a", "PRJNA123456"]);
+///     let plan = plan_runs(&["PRJNA123456".to_string()], &args).await;
+///     println!("{} files to download", plan.files());
+/// }
+/// ```
+pub async fn plan_runs(accessions: &[String], args: &Args) -> Plan {
+    let mut resolved = stream::iter(accessions.iter().enumerate())
+        .take_while(|_| future::ready(within_runtime()))
+        .map(|(idx, accession)| async move { (idx, resolve_run(accession.clone(), args).await) })
+        .buffer_unordered(QUEUE_SIZE)
+        .collect::<Vec<_>>()
+        .await;
+
+    // INFO: accessions are downloaded in the order they were given
+    resolved.sort_by_key(|(idx, _)| *idx);
+    Plan {
+        accessions: resolved
+            .into_iter()
+            .filter_map(|(_, planned)| planned)
+            .collect(),
+    }
+}
+
+/// Load a plan written by `--plan-out`, to download exactly the runs it
+/// lists whatever ENA lists now.
+///
+/// # Arguments
+///
+/// * `path` - The plan file, from `--plan-in`.
+/// * `args` - Command line arguments driving the download.
+///
+/// # Returns
+///
+/// The plan.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use clap::Parser;
+/// use rsfq::cli::Args;
+/// use rsfq::core::load_plan;
+/// use std::path::Path;
+///
+/// let args = Args::parse_from(["rsfq", "--plan-in", "plan.json"]);
+/// let plan = load_plan(Path::new("plan.json"), &args);
+/// ```
+pub fn load_plan(path: &Path, args: &Args) -> Plan {
+    Plan {
+        accessions: read_plan(path, &args.provider)
+            .into_iter()
+            .filter_map(|(accession, runs)| {
+                let claim = claim(&accession)?;
+                Some(planned(accession, runs, args, claim))
+            })
+            .collect(),
+    }
+}
 
-    if metadata || check_if_downloadable {
-        if check_if_downloadable {
+/// Resolve the runs of an accession: fetch its metadata, apply the run
+/// filters and drop runs already listed by another accession.
+///
+/// With `--metadata` or `--check-if-downloadable` the metadata is printed
+/// instead and nothing is left to download.
+///
+/// # Arguments
+///
+/// * `accession` - The accession to resolve.
+/// * `args` - Command line arguments driving the download.
+///
+/// # Returns
+///
+/// The runs to download, or `None` if there is nothing to download or
+/// another machine sharing `--claims-dir` has the accession.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use clap::Parser;
+/// use rsfq::cli::Args;
+/// use rsfq::core::resolve_run;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let args = Args::parse_from(["rsfq", "-a", "ERR123456"]);
+///     let planned = resolve_run("ERR123456".to_string(), &args).await;
+/// }
+/// ```
+pub async fn resolve_run(accession: String, args: &Args) -> Option<Planned> {
+    // INFO: another machine sharing --claims-dir is on it
+    let claim = claim(&accession)?;
+
+    let started = Instant::now();
+    let data = match resolve(&accession, args.attempts, args.sleep).await {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("ERROR: {}", e);
+            let hint = not_found_hint(&accession, &args.provider);
+            if let Some(hint) = &hint {
+                suggest(&accession, hint);
+            }
+            // INFO: a missing accession is an answer to --check, not a failure
+            if !args.check_if_downloadable {
+                std::process::exit(1);
+            }
+            Vec::new()
+        }
+    };
+    time(&accession, Phase::Metadata, started.elapsed());
+
+    if args.metadata && args.metadata_out.is_some() {
+        collect(&data);
+    }
+
+    if args.metadata || args.check_if_downloadable {
+        if args.check_if_downloadable {
             let binding = HashMap::new();
-            let run = data.get(0).unwrap_or(&binding);
+            let run = data.first().unwrap_or(&binding);
 
             if run.is_empty() {
                 println!("NOT_FOUND\t{}", accession);
@@ -194,7 +431,10 @@ pub async fn process_run(
                 let binding = String::new();
                 let fastq_ftp = run.get(FASTQ_FTP).unwrap_or(&binding);
 
-                if fastq_ftp.is_empty() {
+                if fastq_ftp.is_empty() && !hosted_by(run, &args.provider).dumps_sra() {
+                    if let Some(hint) = no_fastq_hint(run, &args.provider) {
+                        suggest(&accession, &hint);
+                    }
                     println!("NOT_FOUND\t{}", accession);
                 } else {
                     println!("DOWNLOADABLE\t{}", accession);
@@ -202,90 +442,427 @@ pub async fn process_run(
             }
         } else {
             log::info!("Found {} runs!", data.len());
-            log::info!("Run data: {:#?}", data);
+            // INFO: the raw dump stays for pipes and log files
+            if std::io::stdout().is_terminal() {
+                println!(
+                    "{}",
+                    render_table(&data, &args.metadata_fields, terminal_width())
+                );
+            } else {
+                log::info!("Run data: {:#?}", data);
+            }
         }
-        return;
+        return None;
     }
 
-    if data.len() > 1 {
-        log::warn!("WARNING: More than one run found! Using the first one...");
+    if data.is_empty() {
+        log::error!("ERROR: No data found!");
+        std::process::exit(1);
     }
 
-    let run = data
-        .get(0)
-        .unwrap_or_else(|| {
-            log::error!("ERROR: No data found!");
+    // INFO: studies, samples and experiments expand into runs, even a single one
+    let expanded = data
+        .iter()
+        .any(|run| run.get(RUN_ACCESSION) != Some(&accession));
+    let runs = if expanded {
+        log::info!("{} expands to {} runs", accession, data.len());
+        let data = filter_runs(data, LIBRARY_STRATEGY, &args.strategy);
+        let data = filter_runs(data, LIBRARY_SELECTION, &args.selection);
+        let data = filter_runs(data, LIBRARY_SOURCE, &args.source);
+        if data.is_empty() {
+            log::error!(
+                "ERROR: No runs of {} left after --strategy, --selection and --source!",
+                accession
+            );
             std::process::exit(1);
+        }
+
+        let data = if args.limit.is_some() || args.offset > 0 {
+            slice_runs(data, args.offset, args.limit)
+        } else {
+            data
+        };
+        let data = match args.sample_runs {
+            Some(n) => sample_runs(data, n, args.seed),
+            None => data,
+        };
+        if data.len() < 2 {
+            data
+        } else if args.pick {
+            // INFO: the prompt blocks, keep it off the runtime's workers
+            tokio::task::spawn_blocking(move || pick_runs(data))
+                .await
+                .unwrap_or_else(|e| {
+                    log::error!("ERROR: Run picker failed!: {}", e);
+                    std::process::exit(1);
+                })
+        } else if args.all_runs() {
+            data
+        } else {
+            log::warn!(
+                "WARNING: More than one run found! Using the first one, pass --all-runs to download all {}...",
+                data.len()
+            );
+            data.into_iter().take(1).collect()
+        }
+    } else {
+        data
+    };
+
+    Some(planned(accession, runs, args, claim))
+}
+
+/// Get the plan of an accession from its resolved runs, whether just
+/// fetched or loaded from `--plan-in`.
+fn planned(
+    accession: String,
+    resolved: Vec<HashMap<String, String>>,
+    args: &Args,
+    claim: Claim,
+) -> Planned {
+    if args.metadata_out.is_some() {
+        collect(&resolved);
+    }
+    // INFO: umbrella projects list the same runs as their children
+    let runs = dedup_runs(&accession, resolved.clone());
+
+    Planned {
+        accession,
+        resolved,
+        runs,
+        claim,
+    }
+}
+
+/// Download the runs of a resolved accession, then merge them if asked to.
+///
+/// # Arguments
+///
+/// * `planned` - The resolved accession.
+/// * `args` - Command line arguments driving the download.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use clap::Parser;
+/// use rsfq::cli::Args;
+/// use rsfq::core::{fetch_planned, resolve_run};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let args = Args::parse_from(["rsfq", "-a", "ERR123456"]);
+///     if let Some(planned) = resolve_run("ERR123456".to_string(), &args).await {
+///         fetch_planned(planned, &args).await;
+///     }
+/// }
+/// ```
+pub async fn fetch_planned(planned: Planned, args: &Args) {
+    let Planned {
+        resolved,
+        runs,
+        claim: _claim,
+        ..
+    } = planned;
+
+    stream::iter(runs)
+        .take_while(|_| future::ready(within_runtime()))
+        .map(|run| async move {
+            let files = run_files(&run);
+            let bytes = files.iter().filter_map(|(_, bytes)| *bytes).sum();
+            fetch_run(run, args).await;
+            run_done(files.len(), bytes);
         })
-        .to_owned();
+        .buffer_unordered(QUEUE_SIZE)
+        .collect::<Vec<_>>()
+        .await;
 
-    log::info!("Run data: {:#?}", data);
+    if let Some(merge_by) = args.merge_by() {
+        let outdir = args
+            .outdir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("DOWNLOADS"));
+        let (prefix_ids, force) = (args.prefix_read_ids, args.overwrite());
+        // INFO: merging reads and writes whole files, off the async workers
+        let _cpu = cpu_slot(1).await;
+        if let Err(e) =
+            blocking(move || merge_runs(&resolved, &outdir, merge_by, prefix_ids, force)).await
+        {
+            log::error!("ERROR: Merging runs failed!: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
 
-    match provider {
-        Provider::ENA => {
-            let _ = download_fastq(
-                run.clone(),
-                outdir.clone(),
-                attempts,
-                sleep,
-                force,
-                retriever,
-                layout,
-            )
-            .await;
+/// Download the FASTQ files of a single run through the selected provider.
+///
+/// # Arguments
+///
+/// * `run` - A HashMap containing the run information.
+/// * `args` - Command line arguments driving the download.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use clap::Parser;
+/// use rsfq::cli::Args;
+/// use rsfq::core::fetch_run;
+/// use std::collections::HashMap;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let args = Args::parse_from(["rsfq", "-a", "SRR123456"]);
+///     let run = HashMap::from([
+///         ("run_accession".to_string(), "SRR123456".to_string()),
+///     ]);
+///     fetch_run(run, &args).await;
+/// }
+/// ```
+pub async fn fetch_run(run: HashMap<String, String>, args: &Args) {
+    log::info!("Run data: {:#?}", run);
+
+    match hosted_by(&run, &args.provider) {
+        // INFO: GSA, DRS and provider plugins list fastq_ftp/fastq_md5 like ENA does
+        Provider::ENA | Provider::GSA | Provider::DRS | Provider::Plugin(_) => {
+            let outdir = args
+                .outdir
+                .clone()
+                .unwrap_or_else(|| PathBuf::from("DOWNLOADS"));
+
+            let bam = if args.pacbio_bam && is_pacbio(&run) {
+                download_pacbio_bam(
+                    &run,
+                    &outdir,
+                    args.attempts,
+                    args.sleep,
+                    args.overwrite(),
+                    &args.retriever,
+                )
+                .await
+            } else {
+                if !pacbio_bams(&run).is_empty() {
+                    log::info!(
+                        "{} is a PacBio run with submitted BAMs, use --pacbio-bam to fetch them instead of FASTQ",
+                        run.get(RUN_ACCESSION).map(String::as_str).unwrap_or("-")
+                    );
+                }
+                false
+            };
+
+            if !bam {
+                let failed = download_fastq(
+                    run.clone(),
+                    args.outdir.clone(),
+                    args.attempts,
+                    args.sleep,
+                    args.overwrite(),
+                    &args.retriever,
+                    args.layout,
+                )
+                .await;
+
+                let accession = run.get(RUN_ACCESSION).map(String::as_str).unwrap_or("-");
+                if !failed.is_empty() && mirrored_by_ncbi(&run) {
+                    // INFO: mates from two archives would not share read names,
+                    // so the whole run comes from NCBI
+                    log::warn!(
+                        "WARNING: ENA could not deliver {}, fetching the whole run from NCBI SRA instead",
+                        accession
+                    );
+                    let dir = run_dir(&outdir, accession);
+                    let fetched = run
+                        .get(FASTQ_FTP)
+                        .into_iter()
+                        .flat_map(|ftp| ftp.split(';'))
+                        .filter_map(|url| Path::new(url).file_name())
+                        .map(|name| dir.join(renamed(&name.to_string_lossy())))
+                        .collect::<Vec<_>>();
+                    forget(&outdir, &fetched);
+                    fetch_sra_run(&run, args).await;
+                } else {
+                    for (url, md5) in &failed {
+                        report(accession, Status::Failed, url, md5);
+                    }
+                }
+            }
+
+            if args.raw_signal {
+                download_raw_signal(
+                    &run,
+                    &outdir,
+                    args.attempts,
+                    args.sleep,
+                    args.overwrite(),
+                    &args.retriever,
+                )
+                .await;
+            }
         }
-        Provider::SRA => {
-            let run_accession = run
-                .get(RUN_ACCESSION)
-                .unwrap_or_else(|| {
-                    log::error!("ERROR: No run_accession field found in the run data!");
-                    std::process::exit(1);
-                })
-                .to_string();
+        Provider::SRA | Provider::AWS | Provider::GCP => fetch_sra_run(&run, args).await,
+    }
+}
 
-            let target_outdir = outdir.clone().unwrap_or_else(|| PathBuf::from("DOWNLOADS"));
+/// Fetch a run from NCBI SRA, or its AWS or GCP mirror with `-P aws` or
+/// `-P gcp`, with sra-tools; from ENA if they are missing.
+///
+/// # Arguments
+///
+/// * `run` - The run metadata.
+/// * `args` - Command line arguments.
+async fn fetch_sra_run(run: &HashMap<String, String>, args: &Args) {
+    let run_accession = run
+        .get(RUN_ACCESSION)
+        .unwrap_or_else(|| {
+            log::error!("ERROR: No run_accession field found in the run data!");
+            std::process::exit(1);
+        })
+        .to_string();
+
+    if offline() {
+        log::error!(
+            "ERROR: {} cannot be fetched from SRA with --offline!",
+            run_accession
+        );
+        explain(&run_accession, "-", "not downloaded and --offline is set");
+        report(&run_accession, Status::Failed, "-", "-");
+        return;
+    }
 
-            match download_from_sra(
+    let target_outdir = run_dir(
+        args.outdir
+            .as_deref()
+            .unwrap_or_else(|| Path::new("DOWNLOADS")),
+        &run_accession,
+    );
+
+    let started = Instant::now();
+    let provider = hosted_by(run, &args.provider);
+    let fetched = match provider {
+        Provider::AWS => {
+            download_from_aws(
+                &run_accession,
+                &target_outdir,
+                args.threads,
+                args.attempts,
+                args.sleep,
+                args.overwrite(),
+                args.layout,
+                target_spots(run),
+            )
+            .await
+        }
+        Provider::GCP => {
+            download_from_gcp(
                 &run_accession,
                 &target_outdir,
-                threads,
-                attempts,
-                sleep,
-                force,
-                layout,
+                args.threads,
+                args.attempts,
+                args.sleep,
+                args.overwrite(),
+                args.layout,
+                target_spots(run),
             )
             .await
-            {
-                Ok(paths) => {
-                    log::info!("Downloaded {} via SRA: {:?}", run_accession, paths);
-                }
-                Err(SRAError::MissingTool(tool)) => {
-                    log::warn!(
-                        "{} not found. Falling back to ENA download for {}",
-                        tool,
-                        run_accession
-                    );
-                    let _ = download_fastq(
-                        run.clone(),
-                        outdir,
-                        attempts,
-                        sleep,
-                        force,
-                        retriever,
-                        layout,
-                    )
-                    .await;
-                }
-                Err(err) => {
-                    log::error!(
-                        "ERROR: SRA download failed for {}: {:?}",
-                        run_accession,
-                        err
+        }
+        _ => {
+            download_from_sra(
+                &run_accession,
+                &target_outdir,
+                args.threads,
+                args.attempts,
+                args.sleep,
+                args.overwrite(),
+                args.layout,
+                target_spots(run),
+            )
+            .await
+        }
+    };
+    time(&run_accession, Phase::Transfer, started.elapsed());
+
+    match fetched {
+        Ok(paths) => {
+            log::info!(
+                "Downloaded {} via {}: {:?}",
+                run_accession,
+                provider.to_string().to_uppercase(),
+                paths
+            );
+            // INFO: cut or split files cannot match ENA's counts
+            let status = if !args.cross_validate {
+                Status::Downloaded
+            } else if target_spots(run).is_some() || chunk_reads().is_some() {
+                log::warn!(
+                    "WARNING: {} was downsampled or split, not cross-validating it",
+                    run_accession
+                );
+                Status::Downloaded
+            } else if cross_validate(run, &paths, &target_outdir) {
+                Status::Downloaded
+            } else {
+                for path in &paths {
+                    explain(
+                        &run_accession,
+                        &path.to_string_lossy(),
+                        "read or base counts disagree with ENA",
                     );
-                    std::process::exit(1);
                 }
+                Status::Failed
+            };
+            for path in &paths {
+                transferred(
+                    &run_accession,
+                    path.metadata().map(|m| m.len()).unwrap_or(0),
+                );
+                report(&run_accession, status, &path.to_string_lossy(), "-");
+                audit(&Entry {
+                    action: Action::Downloaded,
+                    url: &run_accession,
+                    file: path,
+                    retriever: "sra-tools",
+                    expected_md5: "-",
+                    observed_md5: None,
+                });
             }
         }
+        Err(SRAError::MissingTool(tool)) => {
+            log::warn!(
+                "{} not found. Falling back to ENA download for {}",
+                tool,
+                run_accession
+            );
+            let failed = download_fastq(
+                run.clone(),
+                args.outdir.clone(),
+                args.attempts,
+                args.sleep,
+                args.overwrite(),
+                &args.retriever,
+                args.layout,
+            )
+            .await;
+            for (url, md5) in &failed {
+                report(&run_accession, Status::Failed, url, md5);
+            }
+        }
+        Err(err) => {
+            log::error!(
+                "ERROR: {} download failed for {}: {:?}",
+                provider.to_string().to_uppercase(),
+                run_accession,
+                err
+            );
+            explain(
+                &run_accession,
+                "-",
+                &format!(
+                    "{} download failed: {:?}",
+                    provider.to_string().to_uppercase(),
+                    err
+                ),
+            );
+            report(&run_accession, Status::Failed, "-", "-");
+            std::process::exit(1);
+        }
     }
 }
 
@@ -301,7 +878,8 @@ pub async fn process_run(
 ///
 /// # Returns
 ///
-/// A Result indicating the success or failure of the download operation.
+/// The files that could not be downloaded from any mirror, as URL and MD5,
+/// left for the caller to report or fetch elsewhere.
 ///
 /// # Example
 ///
@@ -326,7 +904,7 @@ pub async fn process_run(
 ///     let retriever = Retriever::Aria2c;
 ///     let layout = Layout::Global;
 ///
-///     download_fastq(run, outdir, attempts, sleep, force, retriever, layout).await;
+///     download_fastq(run, outdir, attempts, sleep, force, &retriever, layout).await;
 /// }
 /// ```
 pub async fn download_fastq<K: AsRef<Path> + Debug + Send + Sync>(
@@ -335,9 +913,9 @@ pub async fn download_fastq<K: AsRef<Path> + Debug + Send + Sync>(
     attempts: usize,
     sleep: usize,
     force: bool,
-    retriever: Retriever,
+    retriever: &Retriever,
     layout: Layout,
-) {
+) -> Vec<(String, String)> {
     let fastq_ftp = run.get(FASTQ_FTP).unwrap_or_else(|| {
         log::error!("ERROR: No fastq_ftp field found in the run data!");
         std::process::exit(1);
@@ -355,13 +933,24 @@ pub async fn download_fastq<K: AsRef<Path> + Debug + Send + Sync>(
         std::process::exit(1);
     });
 
-    let outdir = outdir
+    let root = outdir
         .as_ref()
         .map(|x| x.as_ref())
         .unwrap_or_else(|| Path::new("DOWNLOADS"));
+    // INFO: the state manifest stays at the root, files may go to a run directory
+    let outdir = &run_dir(root, accession);
+    if let Err(e) = create_dirs(outdir) {
+        log::error!("ERROR: Could not create {}!: {}", outdir.display(), e);
+        std::process::exit(1);
+    }
 
+    let mut failed = Vec::new();
     let ftp_entries = fastq_ftp.split(';').collect::<Vec<&str>>();
     let md5_entries = fastq_md5.split(';');
+    let spots = target_spots(&run);
+
+    // INFO: a run renamed like another one leaves the name to it
+    let skip = colliding(accession);
 
     // INFO: performs strick matching of the number of files, scRNA-Seq will have only one file
     match layout {
@@ -388,7 +977,19 @@ pub async fn download_fastq<K: AsRef<Path> + Debug + Send + Sync>(
         Layout::Global => {}
     }
 
+    let mut bytes_entries = run
+        .get(FASTQ_BYTES)
+        .map(|bytes| {
+            bytes
+                .split(';')
+                .map(|b| b.parse::<u64>().ok())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default()
+        .into_iter();
+
     for (ftp, md5) in ftp_entries.into_iter().zip(md5_entries) {
+        let bytes = bytes_entries.next().flatten();
         let observed = Path::new(ftp)
             .file_name()
             .and_then(|s| s.to_str())
@@ -397,26 +998,23 @@ pub async fn download_fastq<K: AsRef<Path> + Debug + Send + Sync>(
                 std::process::exit(1);
             });
 
-        if library_layout == PAIRED {
-            if !(ftp.ends_with(R1) || ftp.ends_with(R2)) {
-                if !__has_expected_filename(accession, observed, EXTENSIONS) {
-                    log::error!(
-                        "ERROR: Expected {}.fastq.gz/.fq.gz/*subreads.fastq.gz but found {} in the fastq_ftp field",
-                        accession,
-                        observed
-                    );
-                    std::process::exit(1);
-                }
-            }
+        let unexpected = if library_layout == PAIRED {
+            !(ftp.ends_with(R1)
+                || ftp.ends_with(R2)
+                || __has_expected_filename(accession, observed, EXTENSIONS))
         } else if library_layout == SINGLE {
-            if !__has_expected_filename(accession, observed, EXTENSIONS) {
-                log::error!(
-                    "ERROR: Expected {}.fastq.gz/.fq.gz/*subreads.fastq.gz but found {} in the fastq_ftp field",
-                    accession,
-                    observed
-                );
-                std::process::exit(1);
-            }
+            !__has_expected_filename(accession, observed, EXTENSIONS)
+        } else {
+            false
+        };
+
+        if unexpected {
+            log::error!(
+                "ERROR: Expected {}.fastq.gz/.fq.gz/*subreads|ccs|hifi_reads.fastq.gz but found {} in the fastq_ftp field",
+                accession,
+                observed
+            );
+            std::process::exit(1);
         }
 
         if md5.is_empty() {
@@ -424,8 +1022,185 @@ pub async fn download_fastq<K: AsRef<Path> + Debug + Send + Sync>(
             std::process::exit(1);
         }
 
-        let _ = download(ftp, outdir, attempts, sleep, force, md5, retriever).await;
+        if skip {
+            log::warn!(
+                "WARNING: {} is already taken by another run! Skipping download...",
+                renamed(observed)
+            );
+            report(accession, Status::Skipped, ftp, md5);
+            continue;
+        }
+
+        // INFO: a file that no longer matches its size or checksum is fetched again
+        let existing = outdir.join(renamed(observed));
+        if !force && existing.exists() && !existing_intact(&existing, root, bytes, md5).await {
+            if offline() {
+                log::error!(
+                    "ERROR: {} does not match ENA and --offline forbids fetching it again!",
+                    existing.display()
+                );
+                explain(accession, ftp, "does not match ENA and --offline is set");
+                report(accession, Status::Failed, ftp, md5);
+                continue;
+            }
+            if truncated(&existing, root, bytes) && resume_partial(&existing) {
+                log::warn!(
+                    "WARNING: {} looks like an interrupted download, resuming it...",
+                    existing.display()
+                );
+            } else {
+                log::warn!("WARNING: Downloading {} again...", existing.display());
+                let stale = existing.clone();
+                blocking(move || {
+                    quarantine(&stale, "does not match its expected size or checksum")
+                })
+                .await;
+            }
+        }
+        // INFO: with --fasta/--chunk-reads the FASTQ itself is not kept
+        let converted = outdir.join(fasta_name(&renamed(observed)));
+        if !force
+            && !existing.exists()
+            && ((converted != existing && converted.exists())
+                || !existing_chunks(&converted).is_empty())
+        {
+            log::warn!(
+                "WARNING: {} was already converted or split! Skipping download...",
+                existing.display()
+            );
+            report(accession, Status::Skipped, ftp, md5);
+            continue;
+        }
+        let existed = existing.exists();
+        // INFO: the space stays reserved until the file is fetched
+        let _reserved = if existed {
+            None
+        } else {
+            let Some(reserved) = fits_on_disk(outdir, bytes) else {
+                explain(accession, ftp, "not enough disk space");
+                report(accession, Status::Failed, ftp, md5);
+                continue;
+            };
+            Some(reserved)
+        };
+
+        // INFO: a file ENA keeps failing to serve one way often comes through another
+        let (mut fetched, mut streamed_hash) = (None, None);
+        let mirrors = mirrors(ftp);
+        if let Some(spots) = spots.filter(|_| !existed && !offline()) {
+            // INFO: a downsampled file is cut as it streams, the rest is never fetched
+            for attempt in 1..=attempts {
+                match fetch_head(ftp, &existing, spots).await {
+                    Ok(path) => {
+                        fetched = Some(path);
+                        break;
+                    }
+                    Err(e) => log::warn!(
+                        "WARNING: Could not download the head of {} (attempt {}/{})!: {}",
+                        ftp,
+                        attempt,
+                        attempts,
+                        e
+                    ),
+                }
+                if attempt < attempts {
+                    tokio::time::sleep(tokio::time::Duration::from_secs(sleep as u64)).await;
+                }
+            }
+        } else {
+            for (i, url) in mirrors.iter().enumerate() {
+                fetched = download(url, outdir, attempts, sleep, force, md5, bytes, retriever)
+                    .await
+                    .map(|downloaded| {
+                        streamed_hash = downloaded.hash;
+                        downloaded.path
+                    });
+                if fetched.is_some() || existing.exists() || offline() {
+                    break;
+                }
+                if let Some(next) = mirrors.get(i + 1) {
+                    log::warn!("WARNING: {} kept failing, trying {}", url, next);
+                }
+            }
+        }
+
+        // INFO: checking, converting and splitting read whole files, off the async workers
+        let _cpu = match fetched {
+            Some(_) => Some(cpu_slot(1).await),
+            None => None,
+        };
+        let owned = accession.to_string();
+        let fetched = match fetched {
+            Some(path) => Some(blocking(move || (check_read_ids(&path, &owned), path)).await),
+            None => None,
+        };
+
+        // INFO: only checksum-verified files make it into the state manifest
+        match fetched {
+            Some((false, path)) => {
+                let reason = format!("read IDs do not belong to {}", accession);
+                let quarantined = reason.clone();
+                blocking(move || quarantine(&path, &quarantined)).await;
+                explain(accession, ftp, &reason);
+                report(accession, Status::Failed, ftp, md5);
+            }
+            Some((true, path)) => {
+                // INFO: cut or split files no longer match ENA, so their own MD5 is recorded
+                let cut = spots.is_some();
+                let path = if fasta_enabled() {
+                    let fastq = path.clone();
+                    match blocking(move || to_fasta(&fastq)).await {
+                        Ok(fasta) => fasta,
+                        Err(e) => {
+                            log::error!("ERROR: Could not convert {}!: {}", path.display(), e);
+                            explain(accession, ftp, &format!("could not convert: {}", e));
+                            report(accession, Status::Failed, ftp, md5);
+                            continue;
+                        }
+                    }
+                } else {
+                    path
+                };
+                let split = match chunk_reads() {
+                    Some(reads) => {
+                        let fastq = path.clone();
+                        Some(blocking(move || split_fastq(&fastq, reads)).await)
+                    }
+                    None => None,
+                };
+                let files = match split {
+                    Some(Err(e)) => {
+                        log::error!("ERROR: Could not split {}!: {}", path.display(), e);
+                        explain(accession, ftp, &format!("could not split: {}", e));
+                        report(accession, Status::Failed, ftp, md5);
+                        continue;
+                    }
+                    Some(Ok(chunks)) => chunks,
+                    None => vec![path],
+                };
+
+                if !skip_verify() {
+                    let rewritten = cut || fasta_enabled() || chunk_reads().is_some();
+                    for file in files {
+                        let (kept, hash) = if rewritten {
+                            (md5sum(&file).await.unwrap_or_default(), None)
+                        } else {
+                            (md5.to_string(), streamed_hash.clone())
+                        };
+                        // INFO: still under the CPU slot taken for the fetched file
+                        let (root, accession) = (root.to_path_buf(), accession.to_string());
+                        blocking(move || record(&root, &accession, &file, &kept, hash.as_deref()))
+                            .await;
+                    }
+                }
+                report(accession, Status::Downloaded, ftp, md5);
+            }
+            None if existed => report(accession, Status::Skipped, ftp, md5),
+            None => failed.push((ftp.to_string(), md5.to_string())),
+        }
     }
+
+    failed
 }
 
 /// Check if a filename has one of the expected extensions.
@@ -455,6 +1230,16 @@ pub fn __has_expected_filename(accession: &str, observed: &str, extensions: &[&s
     })
 }
 
+/// A file `download` fetched and verified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Downloaded {
+    /// The file, under its final name
+    pub path: PathBuf,
+    /// The local integrity hash set with `--hash`, as `checksum:digest`,
+    /// when it was hashed along with the MD5 in flight
+    pub hash: Option<String>,
+}
+
 /// Download a file from an FTP server and verify its MD5 checksum.
 ///
 /// # Arguments
@@ -465,10 +1250,11 @@ pub fn __has_expected_filename(accession: &str, observed: &str, extensions: &[&s
 /// * `sleep` - The number of seconds to sleep between attempts.
 /// * `force` - Whether to overwrite an existing file.
 /// * `md5` - The expected MD5 checksum of the file.
+/// * `bytes` - The expected size of the file, for progress lines.
 ///
 /// # Returns
 ///
-/// An `Option<PathBuf>` containing the path to the downloaded file, or `None` if the download failed.
+/// An `Option<Downloaded>` containing the path to the downloaded file, or `None` if the download failed.
 ///
 /// # Example
 ///
@@ -484,12 +1270,13 @@ pub fn __has_expected_filename(accession: &str, observed: &str, extensions: &[&s
 ///     let md5 = "md5sum";
 ///     let retriever = Retriever::Aria2c;
 ///
-///     match download(ftp, &outdir, 3, 5, false, md5, retriever).await {
-///         Some(path) => println!("Downloaded file to: {}", path.display()),
+///     match download(ftp, &outdir, 3, 5, false, md5, None, &retriever).await {
+///         Some(downloaded) => println!("Downloaded file to: {}", downloaded.path.display()),
 ///         None => println!("Download failed"),
 ///     }
 /// }
 /// ```
+#[allow(clippy::too_many_arguments)]
 pub async fn download<K: AsRef<Path> + Debug>(
     ftp: &str,
     outdir: K,
@@ -497,10 +1284,11 @@ pub async fn download<K: AsRef<Path> + Debug>(
     sleep: usize,
     force: bool,
     md5: &str,
-    retriever: Retriever,
-) -> Option<PathBuf> {
+    bytes: Option<u64>,
+    retriever: &Retriever,
+) -> Option<Downloaded> {
     let mut attempt = 0;
-    let fastq = outdir.as_ref().join(
+    let fastq = outdir.as_ref().join(renamed(
         Path::new(ftp)
             .file_name()
             .unwrap_or_else(|| {
@@ -512,10 +1300,21 @@ pub async fn download<K: AsRef<Path> + Debug>(
                 log::error!("ERROR: Invalid file name!");
                 std::process::exit(1);
             }),
-    );
+    ));
 
     log::info!("Downloading {} to {}", ftp, fastq.display());
 
+    let retriever = &site_retriever(ftp, retriever);
+    // INFO: aria2c writing straight to the final name leaves a full-size
+    // file next to its control file
+    if fastq.exists() && aria2_control(&fastq).exists() && !force && !offline() {
+        log::warn!(
+            "WARNING: {} is an unfinished aria2c download, resuming it...",
+            fastq.display()
+        );
+        resume_partial(&fastq);
+    }
+
     if fastq.exists() {
         if force {
             log::warn!(
@@ -531,32 +1330,270 @@ pub async fn download<K: AsRef<Path> + Debug>(
         }
     }
 
-    let mut cmd = retriever.materialize(ftp, &fastq);
+    if offline() {
+        log::error!(
+            "ERROR: {} is not downloaded and --offline forbids fetching it!",
+            fastq.display()
+        );
+        return None;
+    }
+
+    // INFO: the file only gets its final name once verified, and is kept
+    // on SIGTERM for the retriever to resume
+    let part = part_path(&fastq);
+    let chain = retriever_chain(retriever);
+    if chain.is_empty() {
+        log::error!("ERROR: No retriever is installed to download {}!", ftp);
+        return None;
+    }
+    match part.metadata().map(|m| m.len()) {
+        Ok(_) if force => drop_partial(&part),
+        // INFO: aria2c allocates the whole file upfront, only its control
+        // file tells which bytes are there
+        Ok(_) if aria2_control(&part).exists() && !matches!(chain[0], Retriever::Aria2c) => {
+            log::warn!(
+                "WARNING: {} was left unfinished by aria2c, {} cannot resume it, starting over",
+                part.display(),
+                chain[0]
+            );
+            drop_partial(&part);
+        }
+        // INFO: segments leave holes, only -T native knows which bytes are there
+        Ok(_) if segments_control(&part).exists() && !matches!(chain[0], Retriever::Native) => {
+            log::warn!(
+                "WARNING: {} was left unfinished in segments, {} cannot resume it, starting over",
+                part.display(),
+                chain[0]
+            );
+            drop_partial(&part);
+        }
+        // INFO: retrievers continue a partial file, the MD5 check covers the whole
+        Ok(partial) if partial > 0 => log::info!(
+            "Found {} of {} from an earlier attempt, resuming",
+            human_bytes(partial),
+            fastq.display()
+        ),
+        _ => {}
+    }
+    let (mut current, mut failures) = (0, 0);
+    let run = run_accession_of(&fastq.file_name().unwrap_or_default().to_string_lossy())
+        .unwrap_or_else(|| ftp.to_string());
+    let mut last_error = String::new();
 
     while max_attempts >= attempt {
-        let output = cmd.output().await.unwrap_or_else(|e| {
-            log::error!("ERROR: Failed to execute command: {}", e);
-            std::process::exit(1);
-        });
+        let retriever = &chain[current];
+        // INFO: DRS access URLs may be signed and short-lived, so each attempt asks anew
+        let access = match access(ftp).await {
+            Ok(access) => access,
+            Err(e) => {
+                log::error!("ERROR: Could not get access to {}: {}", ftp, e);
+                last_error = e;
+                attempt += 1;
+                tokio::time::sleep(tokio::time::Duration::from_secs(sleep as u64)).await;
+                continue;
+            }
+        };
+        // INFO: the transfer slot is held for the transfer only, so waiting
+        // out a retry or verifying never keeps the host busy
+        let slot = transfer_slot(ftp).await;
+        let progress = watch_transfer(&part, bytes);
+        let started = Instant::now();
+        let (mut streamed_md5, mut streamed_hash) = (None, None);
+        // INFO: retriever output goes to the debug log, its end into errors
+        let tail = Mutex::new(VecDeque::with_capacity(OUTPUT_TAIL));
+        let on_line = |line: &str| {
+            log::debug!("{}: {}", retriever, line);
+            if let Some(progress) = &progress {
+                progress.observe(retriever, line);
+            }
+            let mut tail = tail.lock().unwrap_or_else(|e| e.into_inner());
+            if tail.len() == OUTPUT_TAIL {
+                tail.pop_front();
+            }
+            tail.push_back(line.to_string());
+        };
+        let offset = part.metadata().map(|m| m.len()).unwrap_or(0);
+        let transfer = async {
+            let (url, headers) = match &access {
+                Access::Url(url, headers) => (url.as_str(), headers.as_slice()),
+                Access::Htsget(blocks) => {
+                    return match fetch_blocks(blocks, &part).await {
+                        Ok(_) => 0,
+                        Err(e) => {
+                            log::error!("ERROR: htsget download failed for {}: {}", ftp, e);
+                            1
+                        }
+                    };
+                }
+            };
+            // INFO: headers an access URL asks for never reach a command line
+            let retriever = if headers.is_empty() {
+                retriever
+            } else {
+                &Retriever::Native
+            };
+            if let Retriever::Plugin(name) = retriever {
+                let plugin = plugin_path(PluginKind::Retriever, name);
+                match exchange(&plugin, fetch_request(url, &part)).await {
+                    Ok(_) => 0,
+                    Err(e) => {
+                        log::error!("ERROR: Retriever plugin failed for {}: {}", ftp, e);
+                        1
+                    }
+                }
+            } else if let Some(cmd) = retriever.pipe(url, offset) {
+                // INFO: wget and curl print the file, rsfq hashes it on its way to disk
+                match fetch_through(cmd, &part, offset, &on_line).await {
+                    Ok((outcome, fetched)) => {
+                        (streamed_md5, streamed_hash) = (fetched.md5, fetched.hash);
+                        outcome.code.unwrap_or_else(|| {
+                            log::error!("ERROR: No exit code found!");
+                            std::process::exit(1);
+                        })
+                    }
+                    Err(e) => {
+                        log::error!("ERROR: {} failed for {}: {}", retriever, ftp, e);
+                        1
+                    }
+                }
+            } else if let Some(mut cmd) = retriever.materialize(url, &part) {
+                cmd.kill_on_drop(true);
+                let output = runner()
+                    .run_streaming(cmd, &on_line)
+                    .await
+                    .unwrap_or_else(|e| {
+                        log::error!("ERROR: Failed to execute command: {}", e);
+                        std::process::exit(1);
+                    });
 
-        let status = output.status.code().unwrap_or_else(|| {
-            log::error!("ERROR: No exit code found!");
-            std::process::exit(1);
-        });
+                output.code.unwrap_or_else(|| {
+                    log::error!("ERROR: No exit code found!");
+                    std::process::exit(1);
+                })
+            } else {
+                match fetch(url, &part, headers).await {
+                    Ok(fetched) => {
+                        (streamed_md5, streamed_hash) = (fetched.md5, fetched.hash);
+                        0
+                    }
+                    Err(e) => {
+                        log::error!("ERROR: Native download failed for {}: {}", ftp, e);
+                        1
+                    }
+                }
+            }
+        };
+        // INFO: dropping a stalled transfer kills its retriever
+        let mut idle = None;
+        let status = tokio::select! {
+            status = transfer => status,
+            stalled_for = stalled(&part) => {
+                log::error!(
+                    "ERROR: {} made no progress for {}s, stopping {}",
+                    ftp,
+                    stalled_for.as_secs(),
+                    retriever
+                );
+                idle = Some(stalled_for);
+                1
+            }
+        };
+
+        drop(progress);
+        time(&run, Phase::Transfer, started.elapsed());
 
         if status != 0 {
-            log::error!("ERROR: Failed to download {} with status {}", ftp, status);
+            let output = tail
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" | ");
+            log::error!(
+                "ERROR: Failed to download {} with {} (status {}){}",
+                ftp,
+                retriever,
+                status,
+                if output.is_empty() {
+                    String::new()
+                } else {
+                    format!(": {}", output)
+                }
+            );
+            last_error = match idle {
+                Some(idle) => format!("no progress for {}s", idle.as_secs()),
+                None if output.is_empty() => {
+                    format!("{} exited with status {}", retriever, status)
+                }
+                None => format!("{} exited with status {}: {}", retriever, status, output),
+            };
+            audit(&Entry {
+                action: Action::RetrieverFailed,
+                url: ftp,
+                file: &fastq,
+                retriever: &retriever.to_string(),
+                expected_md5: md5,
+                observed_md5: None,
+            });
             attempt += 1;
+            slot.failed();
+            fall_back(&chain, &mut current, &mut failures, &part);
             tokio::time::sleep(tokio::time::Duration::from_secs(sleep as u64)).await;
         } else {
-            if force {
-                log::info!("--force used, skipping MD5sum check for {}", ftp);
-                break;
-            } else {
-                let fq_md5 = md5sum(&fastq).await.unwrap_or_else(|| {
-                    log::error!("ERROR: Failed to calculate MD5sum!");
-                    std::process::exit(1);
+            let size = part.metadata().map(|m| m.len()).unwrap_or(0);
+            slot.finished(size);
+            let _cpu = cpu_slot(1).await;
+            if skip_verify() {
+                // INFO: without MD5 a truncated transfer would go unnoticed
+                let started = Instant::now();
+                let checked = part.clone();
+                let intact = !ftp.ends_with(".gz") || blocking(move || gzip_intact(&checked)).await;
+                time(&run, Phase::Verify, started.elapsed());
+                if !intact {
+                    log::error!(
+                        "ERROR: {} is a truncated or corrupted gzip! Downloading again...",
+                        fastq.display()
+                    );
+                    let corrupt = part.clone();
+                    blocking(move || quarantine(&corrupt, "truncated or corrupted gzip")).await;
+                    last_error = "truncated or corrupted gzip".to_string();
+                    attempt += 1;
+                    fall_back(&chain, &mut current, &mut failures, &part);
+                    if let Some(tuner) = download_tuner() {
+                        tuner.failed();
+                    }
+                    tokio::time::sleep(tokio::time::Duration::from_secs(sleep as u64)).await;
+                    continue;
+                }
+                log::info!(
+                    "--skip-verify used, skipping MD5sum check for {} (downloaded with {})",
+                    ftp,
+                    retriever
+                );
+                transferred(&run, size);
+                let promoted = promote(&part, fastq);
+                audit(&Entry {
+                    action: Action::Downloaded,
+                    url: ftp,
+                    file: promoted.as_deref().unwrap_or(&part),
+                    retriever: &retriever.to_string(),
+                    expected_md5: md5,
+                    observed_md5: None,
                 });
+                retried(&run, attempt);
+                return promoted.map(|path| Downloaded { path, hash: None });
+            } else {
+                let started = Instant::now();
+                // INFO: native, wget and curl transfers were hashed as they were written
+                let fq_md5 = match streamed_md5 {
+                    Some(md5) => md5,
+                    None => md5sum(&part).await.unwrap_or_else(|| {
+                        log::error!("ERROR: Failed to calculate MD5sum!");
+                        std::process::exit(1);
+                    }),
+                };
+                time(&run, Phase::Verify, started.elapsed());
 
                 if fq_md5 != md5 {
                     log::error!(
@@ -565,17 +1602,120 @@ pub async fn download<K: AsRef<Path> + Debug>(
                         md5,
                         fq_md5
                     );
+                    audit(&Entry {
+                        action: Action::ChecksumFailed,
+                        url: ftp,
+                        file: &part,
+                        retriever: &retriever.to_string(),
+                        expected_md5: md5,
+                        observed_md5: Some(&fq_md5),
+                    });
+                    // INFO: a resuming retriever would keep the bad bytes
+                    last_error = format!("MD5 mismatch: expected {}, observed {}", md5, fq_md5);
+                    let (mismatched, reason) = (part.clone(), last_error.clone());
+                    blocking(move || quarantine(&mismatched, &reason)).await;
                     attempt += 1;
+                    fall_back(&chain, &mut current, &mut failures, &part);
+                    if let Some(tuner) = download_tuner() {
+                        tuner.failed();
+                    }
                     tokio::time::sleep(tokio::time::Duration::from_secs(sleep as u64)).await;
                 } else {
-                    log::info!("Downloaded {} successfully!", ftp);
-                    break;
+                    log::info!("Downloaded {} successfully with {}!", ftp, retriever);
+                    transferred(&run, size);
+                    let promoted = promote(&part, fastq);
+                    audit(&Entry {
+                        action: Action::Downloaded,
+                        url: ftp,
+                        file: promoted.as_deref().unwrap_or(&part),
+                        retriever: &retriever.to_string(),
+                        expected_md5: md5,
+                        observed_md5: Some(&fq_md5),
+                    });
+                    retried(&run, attempt);
+                    return promoted.map(|path| Downloaded {
+                        path,
+                        hash: streamed_hash,
+                    });
                 }
             }
         }
     }
 
-    Some(fastq)
+    log::error!("ERROR: Giving up on {} after {} attempts", ftp, attempt);
+    explain(
+        &run,
+        ftp,
+        &format!("gave up after {} attempts, last: {}", attempt, last_error),
+    );
+    audit(&Entry {
+        action: Action::GaveUp,
+        url: ftp,
+        file: &fastq,
+        retriever: &retriever.to_string(),
+        expected_md5: md5,
+        observed_md5: None,
+    });
+    retried(&run, attempt);
+    match part.metadata().map(|m| m.len()) {
+        Ok(0) => drop_partial(&part),
+        Ok(_) => log::info!("Keeping {} to resume it on the next run", part.display()),
+        Err(_) => {}
+    }
+    None
+}
+
+/// Count a failed attempt and move to the next retriever of the chain once
+/// the current one failed `FAILURES_BEFORE_FALLBACK` times in a row,
+/// wrapping around at the end of the chain.
+///
+/// # Arguments
+///
+/// * `chain` - The retrievers to try.
+/// * `current` - The index of the current retriever.
+/// * `failures` - The failures of the current retriever in a row.
+/// * `part` - The staging file, dropped so the next retriever starts clean.
+fn fall_back(chain: &[Retriever], current: &mut usize, failures: &mut usize, part: &Path) {
+    *failures += 1;
+    if chain.len() < 2 || *failures < FAILURES_BEFORE_FALLBACK {
+        return;
+    }
+
+    let next = (*current + 1) % chain.len();
+    log::warn!(
+        "WARNING: {} failed {} times in a row, falling back to {}",
+        chain[*current],
+        failures,
+        chain[next]
+    );
+    drop_partial(part);
+    (*current, *failures) = (next, 0);
+}
+
+/// Give a verified staging file its final name.
+///
+/// # Arguments
+///
+/// * `part` - The staging file.
+/// * `fastq` - The final path.
+///
+/// # Returns
+///
+/// The final path, or `None` if the file could not be renamed.
+fn promote(part: &Path, fastq: PathBuf) -> Option<PathBuf> {
+    match install(part, &fastq) {
+        Ok(()) => Some(fastq),
+        Err(e) => {
+            log::error!(
+                "ERROR: Could not move {} to {}!: {}",
+                part.display(),
+                fastq.display(),
+                e
+            );
+            let _ = std::fs::remove_file(part);
+            None
+        }
+    }
 }
 
 /// Calculate the MD5 checksum of a FASTQ file.
@@ -611,20 +1751,8 @@ pub async fn md5sum<K: AsRef<Path> + Debug>(fastq: &K) -> Option<String> {
         fastq.as_ref().to_path_buf()
     };
 
-    let file = File::open(fastq).ok()?;
-    let mut reader = BufReader::with_capacity(BUFFER_SIZE, file);
-    let mut hasher = Context::new();
-    let mut buffer = vec![0; BUFFER_SIZE];
-
-    loop {
-        let bytes_read = reader.read(&mut buffer).ok()?;
-        if bytes_read == 0 {
-            break;
-        }
-        hasher.consume(&buffer[..bytes_read]);
-    }
-
-    Some(format!("{:x}", hasher.compute()))
+    // INFO: hashing reads the whole file, off the async workers
+    blocking(move || Checksum::Md5.digest(&fastq)).await
 }
 
 /// Check if the provided fastq path is valid and return the absolute path.
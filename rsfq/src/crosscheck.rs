@@ -0,0 +1,205 @@
+use flate2::read::MultiGzDecoder;
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    fasta::{fasta_name, lines_per_record},
+    perms::settle,
+    utils::{renamed, Checksum},
+};
+
+const RUN_ACCESSION: &str = "run_accession";
+const READ_COUNT: &str = "read_count";
+const BASE_COUNT: &str = "base_count";
+const FASTQ_FTP: &str = "fastq_ftp";
+const FASTQ_MD5: &str = "fastq_md5";
+const MATE2_SUFFIX: &str = "_2";
+const CROSSCHECK_SUFFIX: &str = ".crosscheck.tsv";
+const CROSSCHECK_HEADER: &str = "check\tsra\tena\tagree";
+
+/// Reads and bases of a reads file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub reads: u64,
+    pub bases: u64,
+}
+
+/// Count the reads and bases of a gzipped FASTQ or FASTA.
+///
+/// # Arguments
+/// * `path` - The reads file.
+///
+/// # Returns
+/// The counts, or an error if the file could not be read.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::crosscheck::count_reads;
+/// use std::path::Path;
+/// let counts = count_reads(Path::new("DOWNLOADS/SRR123456_1.fastq.gz")).unwrap();
+/// println!("{} reads, {} bases", counts.reads, counts.bases);
+/// ```
+pub fn count_reads(path: &Path) -> std::io::Result<Counts> {
+    let step = lines_per_record(path) as usize;
+    let reader = BufReader::new(MultiGzDecoder::new(File::open(path)?));
+
+    let mut counts = Counts::default();
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        match idx % step {
+            0 => counts.reads += 1,
+            1 => counts.bases += line.trim_end().len() as u64,
+            _ => {}
+        }
+    }
+    Ok(counts)
+}
+
+/// Cross-check the FASTQs of a run fetched from SRA against its ENA
+/// metadata, as ENA's `fastq_md5` does not apply to files fasterq-dump
+/// wrote.
+///
+/// Spots (reads of the single-end and `_1` files) and bases are compared
+/// with ENA's `read_count` and `base_count`; the MD5 of each file is put
+/// next to the one ENA lists for the file of the same name, for the
+/// record. Everything goes to a `<run>.crosscheck.tsv` sidecar.
+///
+/// # Arguments
+/// * `run` - The ENA metadata of the run.
+/// * `files` - The files fasterq-dump produced.
+/// * `outdir` - The directory the sidecar is written to.
+///
+/// # Returns
+/// `true` if the counts agree with ENA, or ENA lists none.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::crosscheck::cross_validate;
+/// use std::collections::HashMap;
+/// use std::path::{Path, PathBuf};
+/// let run = HashMap::from([
+///     ("run_accession".to_string(), "SRR123456".to_string()),
+///     ("read_count".to_string(), "1000".to_string()),
+///     ("base_count".to_string(), "150000".to_string()),
+/// ]);
+/// let files = vec![PathBuf::from("DOWNLOADS/SRR123456.fastq.gz")];
+/// let agree = cross_validate(&run, &files, Path::new("DOWNLOADS"));
+/// ```
+pub fn cross_validate(run: &HashMap<String, String>, files: &[PathBuf], outdir: &Path) -> bool {
+    let accession = run.get(RUN_ACCESSION).map(String::as_str).unwrap_or("-");
+
+    let mut spots = 0;
+    let mut bases = 0;
+    let mut md5s = Vec::new();
+    for file in files {
+        let counts = match count_reads(file) {
+            Ok(counts) => counts,
+            Err(e) => {
+                log::error!(
+                    "ERROR: Could not count the reads of {}!: {}",
+                    file.display(),
+                    e
+                );
+                return false;
+            }
+        };
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        // INFO: a spot is one record of the single-end or _1 file
+        let mate2 = Path::new(&name)
+            .file_stem()
+            .and_then(|stem| Path::new(stem).file_stem())
+            .is_some_and(|stem| stem.to_string_lossy().ends_with(MATE2_SUFFIX));
+        if !mate2 {
+            spots += counts.reads;
+        }
+        bases += counts.bases;
+        md5s.push((name, Checksum::Md5.digest(file).unwrap_or_default()));
+    }
+
+    let ena = ena_md5s(run);
+    let expected = |field: &str| {
+        run.get(field)
+            .and_then(|value| value.split(';').map(|v| v.parse::<u64>().ok()).sum())
+    };
+    let (read_count, base_count) = (expected(READ_COUNT), expected(BASE_COUNT));
+
+    let mut rows = vec![
+        row(READ_COUNT, &spots.to_string(), read_count),
+        row(BASE_COUNT, &bases.to_string(), base_count),
+    ];
+    for (name, md5) in &md5s {
+        let listed = ena.get(name).map(String::as_str).unwrap_or("-");
+        let agree = if listed == "-" {
+            "-"
+        } else if listed == md5 {
+            "yes"
+        } else {
+            "no"
+        };
+        rows.push(format!("md5:{}\t{}\t{}\t{}", name, md5, listed, agree));
+    }
+
+    let sidecar = outdir.join(format!("{}{}", accession, CROSSCHECK_SUFFIX));
+    let content = format!("{}\n{}\n", CROSSCHECK_HEADER, rows.join("\n"));
+    match std::fs::write(&sidecar, content) {
+        Ok(()) => settle(&sidecar),
+        Err(e) => log::warn!("WARNING: Could not write {}!: {}", sidecar.display(), e),
+    }
+
+    let agree = read_count.is_none_or(|count| count == spots)
+        && base_count.is_none_or(|count| count == bases);
+    if agree {
+        log::info!(
+            "{} from SRA agrees with ENA: {} spots, {} bases",
+            accession,
+            spots,
+            bases
+        );
+    } else {
+        log::error!(
+            "ERROR: {} from SRA does not agree with ENA! {} spots and {} bases, ENA lists {} and {}",
+            accession,
+            spots,
+            bases,
+            read_count.map_or("-".to_string(), |c| c.to_string()),
+            base_count.map_or("-".to_string(), |c| c.to_string())
+        );
+    }
+    agree
+}
+
+/// Format a count row of the sidecar.
+fn row(check: &str, sra: &str, ena: Option<u64>) -> String {
+    match ena {
+        Some(ena) => format!(
+            "{}\t{}\t{}\t{}",
+            check,
+            sra,
+            ena,
+            if sra == ena.to_string() { "yes" } else { "no" }
+        ),
+        None => format!("{}\t{}\t-\t-", check, sra),
+    }
+}
+
+/// Get the MD5 ENA lists for each file of a run, keyed by output name.
+fn ena_md5s(run: &HashMap<String, String>) -> HashMap<String, String> {
+    let urls = run.get(FASTQ_FTP).map(String::as_str).unwrap_or_default();
+    let md5s = run.get(FASTQ_MD5).map(String::as_str).unwrap_or_default();
+
+    urls.split(';')
+        .zip(md5s.split(';'))
+        .filter_map(|(url, md5)| {
+            let name = Path::new(url).file_name()?.to_str()?;
+            Some((fasta_name(&renamed(name)), md5.to_string()))
+        })
+        .collect()
+}
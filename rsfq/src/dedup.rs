@@ -0,0 +1,290 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    chunk::chunk_reads,
+    collision::colliding,
+    fasta::fasta_name,
+    perms::{create_dirs, settle},
+    report::{explain, report, Status},
+    state::{record, recorded},
+    utils::{renamed, run_dir},
+};
+
+const RUN_ACCESSION: &str = "run_accession";
+const FASTQ_FTP: &str = "fastq_ftp";
+const FASTQ_MD5: &str = "fastq_md5";
+
+/// A run file holding the same data as a file of another run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Duplicate {
+    pub run: String,
+    pub file: String,
+    pub original_run: String,
+    pub original: String,
+    pub md5: String,
+}
+
+#[derive(Default)]
+struct Seen {
+    // INFO: run accession -> the input it was first listed under
+    runs: HashMap<String, String>,
+    // INFO: fastq_md5 -> the run and file it is downloaded as
+    files: HashMap<String, (String, String)>,
+    duplicates: Vec<Duplicate>,
+}
+
+// INFO: accessions of a list expand concurrently, so the registry is shared
+static SEEN: Mutex<Option<Seen>> = Mutex::new(None);
+
+/// Drop the runs of an input that another input of the batch already
+/// covers, so umbrella projects sharing runs fetch each of them once.
+///
+/// A run listed under an earlier input is dropped outright. A run whose
+/// files all have the `fastq_md5` of files already scheduled (the same
+/// data deposited twice) is dropped too, and its files are linked to the
+/// originals by `link_duplicates` once the batch is done.
+///
+/// # Arguments
+/// * `accession` - The input the runs were expanded from.
+/// * `runs` - The run metadata, as returned by ENA.
+///
+/// # Returns
+/// The runs left to download.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::dedup::dedup_runs;
+/// use std::collections::HashMap;
+/// let runs = vec![HashMap::from([
+///     ("run_accession".to_string(), "SRR123456".to_string()),
+/// ])];
+/// let runs = dedup_runs("PRJNA123456", runs);
+/// ```
+pub fn dedup_runs(
+    accession: &str,
+    runs: Vec<HashMap<String, String>>,
+) -> Vec<HashMap<String, String>> {
+    let mut guard = SEEN.lock().unwrap_or_else(|e| e.into_inner());
+    let seen = guard.get_or_insert_with(Seen::default);
+
+    runs.into_iter()
+        .filter(|run| {
+            let Some(run_accession) = run.get(RUN_ACCESSION) else {
+                return true;
+            };
+
+            if let Some(input) = seen.runs.get(run_accession) {
+                if input != accession {
+                    log::info!(
+                        "{} is also listed under {}, downloading it once",
+                        run_accession,
+                        input
+                    );
+                }
+                return false;
+            }
+            seen.runs
+                .insert(run_accession.to_string(), accession.to_string());
+
+            // INFO: a run giving its name away is not downloaded, so it cannot be linked to
+            if colliding(run_accession) {
+                return true;
+            }
+
+            let files = run_files(run);
+            let originals = files
+                .iter()
+                .map(|(_, md5)| seen.files.get(md5).cloned())
+                .collect::<Option<Vec<(String, String)>>>();
+
+            match originals {
+                Some(originals) if !files.is_empty() => {
+                    log::info!(
+                        "{} holds the same files as {}, linking them instead of downloading",
+                        run_accession,
+                        originals
+                            .iter()
+                            .map(|(_, original)| original.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                    for ((file, md5), (original_run, original)) in files.into_iter().zip(originals)
+                    {
+                        seen.duplicates.push(Duplicate {
+                            run: run_accession.to_string(),
+                            file,
+                            original_run,
+                            original,
+                            md5,
+                        });
+                    }
+                    false
+                }
+                _ => {
+                    for (file, md5) in files {
+                        seen.files
+                            .entry(md5)
+                            .or_insert_with(|| (run_accession.to_string(), file));
+                    }
+                    true
+                }
+            }
+        })
+        .collect()
+}
+
+/// Link the files of runs dropped as duplicates to the files they share
+/// data with, once the originals are downloaded.
+///
+/// Files are hard-linked, falling back to a symlink across filesystems,
+/// and reported as `DUPLICATE`. A duplicate whose original did not make it
+/// is left out with a warning.
+///
+/// # Arguments
+/// * `outdir` - The directory the runs were downloaded to.
+///
+/// # Returns
+/// The duplicates linked.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::dedup::link_duplicates;
+/// use std::path::Path;
+/// let linked = link_duplicates(Path::new("DOWNLOADS"));
+/// ```
+pub fn link_duplicates(outdir: &Path) -> Vec<Duplicate> {
+    let duplicates = SEEN
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_mut()
+        .map(|seen| std::mem::take(&mut seen.duplicates))
+        .unwrap_or_default();
+    if duplicates.is_empty() {
+        return duplicates;
+    }
+
+    if chunk_reads().is_some() {
+        log::warn!(
+            "WARNING: Chunked runs are not linked, {} duplicate files were left out",
+            duplicates.len()
+        );
+        return Vec::new();
+    }
+
+    let mut linked = Vec::new();
+    for duplicate in duplicates {
+        let original =
+            run_dir(outdir, &duplicate.original_run).join(fasta_name(&duplicate.original));
+        let dir = run_dir(outdir, &duplicate.run);
+        let file = dir.join(fasta_name(&duplicate.file));
+
+        if !original.exists() {
+            log::warn!(
+                "WARNING: {} was not downloaded, so {} is missing too",
+                original.display(),
+                file.display()
+            );
+            continue;
+        }
+        if let Err(e) = create_dirs(&dir) {
+            log::error!("ERROR: Could not create {}!: {}", dir.display(), e);
+            continue;
+        }
+
+        match link(&original, &file) {
+            Ok(()) => {
+                log::info!("Linked {} to {}", file.display(), original.display());
+                // INFO: recorded like a download, so it resumes and expires as
+                // one, with the hash of the file it shares
+                let hash = recorded(outdir, &original).and_then(|entry| entry.hash);
+                record(
+                    outdir,
+                    &duplicate.run,
+                    &file,
+                    &duplicate.md5,
+                    hash.as_deref(),
+                );
+                report(
+                    &duplicate.run,
+                    Status::Duplicate,
+                    &file.to_string_lossy(),
+                    &duplicate.md5,
+                );
+                linked.push(duplicate);
+            }
+            Err(e) => {
+                log::error!(
+                    "ERROR: Could not link {} to {}!: {}",
+                    file.display(),
+                    original.display(),
+                    e
+                );
+                explain(
+                    &duplicate.run,
+                    &file.to_string_lossy(),
+                    &format!("could not link to {}: {}", original.display(), e),
+                );
+                report(
+                    &duplicate.run,
+                    Status::Failed,
+                    &file.to_string_lossy(),
+                    &duplicate.md5,
+                );
+            }
+        }
+    }
+
+    linked
+}
+
+/// Get the output name and expected MD5 of each file of a run.
+fn run_files(run: &HashMap<String, String>) -> Vec<(String, String)> {
+    let urls = run.get(FASTQ_FTP).map(String::as_str).unwrap_or_default();
+    let md5s = run.get(FASTQ_MD5).map(String::as_str).unwrap_or_default();
+
+    let files = urls
+        .split(';')
+        .zip(md5s.split(';'))
+        .filter(|(url, md5)| !url.is_empty() && !md5.is_empty())
+        .filter_map(|(url, md5)| {
+            let name = Path::new(url).file_name()?.to_str()?;
+            Some((renamed(name), md5.to_string()))
+        })
+        .collect::<Vec<_>>();
+
+    // INFO: a run listing the same file twice is not a duplicate of itself
+    let unique = files.iter().map(|(_, md5)| md5).collect::<HashSet<_>>();
+    if unique.len() != files.len() {
+        return Vec::new();
+    }
+    files
+}
+
+/// Hard-link a file, or symlink it when hard links are not possible.
+fn link(original: &Path, file: &Path) -> std::io::Result<()> {
+    if file.exists() {
+        std::fs::remove_file(file)?;
+    }
+    if std::fs::hard_link(original, file).is_err() {
+        let target = original
+            .canonicalize()
+            .unwrap_or_else(|_| PathBuf::from(original));
+        symlink(&target, file)?;
+    }
+    settle(file);
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, file: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, file)
+}
+
+#[cfg(not(unix))]
+fn symlink(target: &Path, file: &Path) -> std::io::Result<()> {
+    std::fs::copy(target, file).map(|_| ())
+}
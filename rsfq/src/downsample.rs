@@ -0,0 +1,351 @@
+use flate2::{read, write, write::GzEncoder, Compression};
+use once_cell::sync::OnceCell;
+use reqwest::Method;
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{net::download_request, perms::install, preempt::track_file, utils::part_path};
+
+const RUN_ACCESSION: &str = "run_accession";
+const BASE_COUNT: &str = "base_count";
+const READ_COUNT: &str = "read_count";
+
+// INFO: bases wanted per run, coverage times genome size
+static TARGET_BASES: OnceCell<u64> = OnceCell::new();
+
+/// A sequencing depth, written as `30x` or `30`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coverage(pub f64);
+
+/// Parse a string into a Coverage
+impl std::str::FromStr for Coverage {
+    type Err = String;
+
+    /// Parse a string into a Coverage
+    ///
+    /// # Arguments
+    /// * `s` - The string to parse, e.g. `30x`.
+    ///
+    /// # Returns
+    /// * `Result<Self, Self::Err>` - The parsed Coverage.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::downsample::Coverage;
+    /// use std::str::FromStr;
+    /// assert_eq!(Coverage::from_str("30x").unwrap().0, 30.0);
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim();
+        let value = value
+            .strip_suffix(['x', 'X'])
+            .unwrap_or(value)
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid coverage, expected e.g. 30x: {}", s))?;
+
+        if !value.is_finite() || value <= 0.0 {
+            return Err(format!("Invalid coverage, must be positive: {}", s));
+        }
+
+        Ok(Coverage(value))
+    }
+}
+
+/// A genome size in bases, written as `3100000000`, `3.1g`, `4.6m` or `500k`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenomeSize(pub u64);
+
+/// Parse a string into a GenomeSize
+impl std::str::FromStr for GenomeSize {
+    type Err = String;
+
+    /// Parse a string into a GenomeSize
+    ///
+    /// # Arguments
+    /// * `s` - The string to parse, e.g. `3.1g`.
+    ///
+    /// # Returns
+    /// * `Result<Self, Self::Err>` - The parsed GenomeSize.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::downsample::GenomeSize;
+    /// use std::str::FromStr;
+    /// assert_eq!(GenomeSize::from_str("4.6m").unwrap().0, 4_600_000);
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim().to_lowercase();
+        let (number, scale) = match value.chars().last() {
+            Some('k') => (&value[..value.len() - 1], 1e3),
+            Some('m') => (&value[..value.len() - 1], 1e6),
+            Some('g') => (&value[..value.len() - 1], 1e9),
+            _ => (value.as_str(), 1.0),
+        };
+
+        let bases = number
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid genome size, expected e.g. 3.1g: {}", s))?
+            * scale;
+
+        if !bases.is_finite() || bases < 1.0 {
+            return Err(format!("Invalid genome size, must be positive: {}", s));
+        }
+
+        Ok(GenomeSize(bases.round() as u64))
+    }
+}
+
+/// Set the target coverage of every run. Only the first call has effect.
+///
+/// # Arguments
+/// * `coverage` - The wanted depth, from `--target-coverage`.
+/// * `genome_size` - The genome size, from `--genome-size`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::downsample::{init_downsample, Coverage, GenomeSize};
+/// init_downsample(Some(Coverage(30.0)), Some(GenomeSize(3_100_000_000)));
+/// ```
+pub fn init_downsample(coverage: Option<Coverage>, genome_size: Option<GenomeSize>) {
+    if let (Some(coverage), Some(genome_size)) = (coverage, genome_size) {
+        let bases = (coverage.0 * genome_size.0 as f64).ceil() as u64;
+        log::info!(
+            "Downsampling runs to {}x of a {} bp genome ({} bases)",
+            coverage.0,
+            genome_size.0,
+            bases
+        );
+        let _ = TARGET_BASES.set(bases);
+    }
+}
+
+/// Compute how many spots of a run reach the target coverage.
+///
+/// The average spot length comes from `base_count / read_count`, so both
+/// mates of a paired run count towards the coverage.
+///
+/// # Arguments
+/// * `run` - The run metadata, as returned by ENA.
+///
+/// # Returns
+/// The number of spots to keep, or `None` if downsampling is off, the run
+/// has no base/read counts or it is already below the target.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::downsample::{init_downsample, target_spots, Coverage, GenomeSize};
+/// use std::collections::HashMap;
+/// init_downsample(Some(Coverage(10.0)), Some(GenomeSize(1_000)));
+/// let run = HashMap::from([
+///     ("base_count".to_string(), "100000".to_string()),
+///     ("read_count".to_string(), "500".to_string()),
+/// ]);
+/// assert_eq!(target_spots(&run), Some(50));
+/// ```
+pub fn target_spots(run: &HashMap<String, String>) -> Option<u64> {
+    let target = *TARGET_BASES.get()?;
+    let accession = run.get(RUN_ACCESSION).map(String::as_str).unwrap_or("-");
+    let count = |field: &str| {
+        run.get(field)
+            .map(|value| {
+                value
+                    .split(';')
+                    .filter_map(|v| v.parse::<u64>().ok())
+                    .sum::<u64>()
+            })
+            .filter(|count| *count > 0)
+    };
+
+    let (Some(bases), Some(spots)) = (count(BASE_COUNT), count(READ_COUNT)) else {
+        log::warn!(
+            "WARNING: No base/read counts for {}, downloading it in full",
+            accession
+        );
+        return None;
+    };
+
+    if bases <= target {
+        log::info!(
+            "{} has {} bases, below the target of {}; keeping every read",
+            accession,
+            bases,
+            target
+        );
+        return None;
+    }
+
+    let keep = (spots as f64 * target as f64 / bases as f64).ceil() as u64;
+    log::info!(
+        "Keeping {} of {} spots of {} to reach the target coverage",
+        keep,
+        spots,
+        accession
+    );
+    Some(keep.max(1))
+}
+
+/// Cut a gzipped FASTQ down to its first `spots` records, in place.
+///
+/// This is the head of the file, not a random subsample: reads are stored
+/// in sequencing order, so the kept ones come from the first tiles or the
+/// first flowcell lanes. Keeping the same number from `_1` and `_2` keeps
+/// mates paired.
+///
+/// # Arguments
+/// * `fastq` - The gzipped FASTQ.
+/// * `spots` - The number of records to keep.
+///
+/// # Returns
+/// `true` if the file was cut, `false` if it had no more than `spots` records.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::downsample::truncate_fastq;
+/// use std::path::Path;
+/// truncate_fastq(Path::new("DOWNLOADS/SRR123456_1.fastq.gz"), 1_000_000).unwrap();
+/// ```
+pub fn truncate_fastq(fastq: &Path, spots: u64) -> std::io::Result<bool> {
+    let staging = part_path(fastq);
+    let _partial = track_file(&staging);
+
+    let reader = BufReader::new(read::MultiGzDecoder::new(File::open(fastq)?));
+    let mut writer = GzEncoder::new(
+        BufWriter::new(File::create(&staging)?),
+        Compression::default(),
+    );
+
+    let mut lines = reader.lines();
+    let mut kept = 0;
+    while kept < spots * 4 {
+        match lines.next() {
+            Some(line) => {
+                writer.write_all(line?.as_bytes())?;
+                writer.write_all(b"\n")?;
+                kept += 1;
+            }
+            None => break,
+        }
+    }
+
+    if lines.next().is_none() {
+        drop(writer);
+        std::fs::remove_file(&staging)?;
+        return Ok(false);
+    }
+
+    writer.finish()?.flush()?;
+    install(&staging, fastq)?;
+    log::info!("Downsampled {} to {} spots", fastq.display(), spots);
+
+    Ok(true)
+}
+
+/// Download the first `spots` records of a gzipped FASTQ, decompressing it
+/// as it streams and closing the connection once they are written, so the
+/// rest of the file is never fetched.
+///
+/// Like `truncate_fastq`, this keeps the head of the file, not a random
+/// subsample.
+///
+/// # Arguments
+/// * `url` - The URL (with or without scheme) of the gzipped FASTQ.
+/// * `fastq` - The file to write.
+/// * `spots` - The number of records to keep.
+///
+/// # Returns
+/// The file, or why the transfer failed.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::downsample::fetch_head;
+/// use std::path::Path;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let url = "ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR123456/SRR123456_1.fastq.gz";
+///     fetch_head(url, Path::new("DOWNLOADS/SRR123456_1.fastq.gz"), 1_000_000)
+///         .await
+///         .unwrap();
+/// }
+/// ```
+pub async fn fetch_head(url: &str, fastq: &Path, spots: u64) -> Result<PathBuf, String> {
+    let staging = part_path(fastq);
+    // INFO: a cut stream cannot be resumed, SIGTERM removes it
+    let _partial = track_file(&staging);
+
+    let mut response = download_request(Method::GET, url, &[])
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?;
+
+    let head = Head {
+        writer: GzEncoder::new(
+            BufWriter::new(File::create(&staging).map_err(|e| e.to_string())?),
+            Compression::default(),
+        ),
+        lines: spots * 4,
+    };
+    let mut decoder = write::MultiGzDecoder::new(head);
+    while decoder.get_ref().lines > 0 {
+        match response.chunk().await.map_err(|e| e.to_string())? {
+            Some(chunk) => decoder.write_all(&chunk).map_err(|e| e.to_string())?,
+            None => break,
+        }
+    }
+    drop(response);
+
+    // INFO: a cut stream is left mid-member on purpose, only a whole one is checked
+    if decoder.get_ref().lines > 0 {
+        decoder.try_finish().map_err(|e| e.to_string())?;
+    }
+    let head = decoder.get_mut();
+    head.writer
+        .try_finish()
+        .and_then(|_| head.writer.get_mut().flush())
+        .map_err(|e| e.to_string())?;
+    drop(decoder);
+
+    install(&staging, fastq).map_err(|e| e.to_string())?;
+    log::info!(
+        "Downloaded the first {} spots of {} to {}",
+        spots,
+        url,
+        fastq.display()
+    );
+
+    Ok(fastq.to_path_buf())
+}
+
+/// Decompressed FASTQ lines, recompressed until enough are written and
+/// dropped after
+struct Head {
+    writer: GzEncoder<BufWriter<File>>,
+    lines: u64,
+}
+
+impl Write for Head {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut end = 0;
+        for line in buf.split_inclusive(|byte| *byte == b'\n') {
+            if self.lines == 0 {
+                break;
+            }
+            end += line.len();
+            if line.ends_with(b"\n") {
+                self.lines -= 1;
+            }
+        }
+        self.writer.write_all(&buf[..end])?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
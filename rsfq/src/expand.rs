@@ -0,0 +1,371 @@
+use dialoguer::MultiSelect;
+use rand::{rngs::StdRng, seq::IndexedRandom, SeedableRng};
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::IsTerminal,
+    sync::Mutex,
+};
+
+use crate::{
+    cli::Args,
+    provs::ena::{get_run_count, get_run_info, get_tax_id},
+    utils::{confirm, human_bytes, Layout},
+};
+
+const RUN_ACCESSION: &str = "run_accession";
+const LIBRARY_LAYOUT: &str = "library_layout";
+const FASTQ_BYTES: &str = "fastq_bytes";
+const EXPERIMENT_TITLE: &str = "experiment_title";
+const TITLE_WIDTH: usize = 60;
+const LARGE_EXPANSION: usize = 500; // runs
+
+// INFO: concurrent expansions must not prompt the user at the same time
+static PICKER: Mutex<()> = Mutex::new(());
+
+/// Build the ENA portal query matching every run of a taxon and its descendants.
+///
+/// # Arguments
+///
+/// * `tax_id` - The NCBI taxonomy id.
+/// * `layout` - The requested library layout, used as a filter.
+///
+/// # Returns
+///
+/// The portal query string.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::expand::organism_query;
+/// use rsfq::utils::Layout;
+///
+/// assert_eq!(
+///     organism_query("10090", Layout::Paired),
+///     "tax_tree(10090) AND library_layout=PAIRED"
+/// );
+/// ```
+pub fn organism_query(tax_id: &str, layout: Layout) -> String {
+    let mut query = format!("tax_tree({})", tax_id);
+
+    match layout {
+        Layout::Single => query.push_str(" AND library_layout=SINGLE"),
+        Layout::Paired => query.push_str(" AND library_layout=PAIRED"),
+        Layout::Global => {}
+    }
+
+    query
+}
+
+/// Expand an organism name into all matching public run accessions.
+///
+/// Asks for confirmation when the expansion is large, unless `--yes` was given.
+///
+/// # Arguments
+///
+/// * `organism` - The scientific name, e.g. `Mus musculus`.
+/// * `args` - Command line arguments (filters, attempts, sleep, yes).
+///
+/// # Returns
+///
+/// A vector with the matching run accessions.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use clap::Parser;
+/// use rsfq::cli::Args;
+/// use rsfq::expand::expand_organism;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let args = Args::parse_from(["rsfq", "--organism", "Mus musculus", "--yes"]);
+///     let runs = expand_organism("Mus musculus", &args).await;
+///     println!("{} runs", runs.len());
+/// }
+/// ```
+pub async fn expand_organism(organism: &str, args: &Args) -> Vec<String> {
+    let tax_id = get_tax_id(organism).await.unwrap_or_else(|| {
+        log::error!("ERROR: Could not resolve {} to a taxonomy id!", organism);
+        std::process::exit(1);
+    });
+    log::info!("Resolved {} to tax_id {}", organism, tax_id);
+
+    let query = organism_query(&tax_id, args.layout);
+    let count = get_run_count(&query).await.unwrap_or_else(|| {
+        log::error!("ERROR: Could not count the runs for {}!", organism);
+        std::process::exit(1);
+    });
+
+    if count == 0 {
+        log::error!("ERROR: No public runs found for {}!", organism);
+        std::process::exit(1);
+    }
+
+    log::info!("Found {} runs for {} ({})", count, organism, query);
+    if count > LARGE_EXPANSION
+        && !args.yes
+        && !confirm(&format!(
+            "{} matches {} runs. Download all of them?",
+            organism, count
+        ))
+    {
+        log::error!("ERROR: Aborted by user! Re-run with --yes to skip this prompt");
+        std::process::exit(1);
+    }
+
+    get_run_info(query, args.attempts, args.sleep)
+        .await
+        .into_iter()
+        .filter_map(|mut run| run.remove(RUN_ACCESSION))
+        .collect()
+}
+
+/// Let the user cherry-pick runs from an expansion with a terminal multi-select.
+///
+/// Falls back to keeping every run when stdin/stdout is not a TTY.
+///
+/// # Arguments
+///
+/// * `runs` - The expanded runs, as returned by ENA.
+///
+/// # Returns
+///
+/// The runs selected by the user.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::expand::pick_runs;
+/// use std::collections::HashMap;
+///
+/// let runs = vec![HashMap::from([("run_accession".to_string(), "SRR123456".to_string())])];
+/// let picked = pick_runs(runs);
+/// ```
+pub fn pick_runs(runs: Vec<HashMap<String, String>>) -> Vec<HashMap<String, String>> {
+    if !std::io::stdout().is_terminal() || !std::io::stdin().is_terminal() {
+        log::warn!(
+            "WARNING: Not running in a terminal, keeping all {} runs",
+            runs.len()
+        );
+        return runs;
+    }
+
+    let _guard = PICKER.lock().unwrap_or_else(|e| e.into_inner());
+    let items = runs.iter().map(describe_run).collect::<Vec<String>>();
+
+    let picked = MultiSelect::new()
+        .with_prompt("Select runs to download (space to toggle, enter to confirm)")
+        .items(&items)
+        .interact_opt()
+        .unwrap_or_else(|e| {
+            log::error!("ERROR: Run picker failed!: {}", e);
+            std::process::exit(1);
+        })
+        .unwrap_or_else(|| {
+            log::error!("ERROR: Run selection aborted by user!");
+            std::process::exit(1);
+        })
+        .into_iter()
+        .collect::<HashSet<usize>>();
+
+    log::info!("Selected {} of {} runs", picked.len(), runs.len());
+    runs.into_iter()
+        .enumerate()
+        .filter(|(idx, _)| picked.contains(idx))
+        .map(|(_, run)| run)
+        .collect()
+}
+
+/// Describe a run in a single picker line: accession, layout, size and title.
+///
+/// # Arguments
+///
+/// * `run` - The run metadata.
+///
+/// # Returns
+///
+/// A fixed-width line describing the run.
+fn describe_run(run: &HashMap<String, String>) -> String {
+    let field = |key: &str| run.get(key).map(String::as_str).unwrap_or("-");
+    let bytes = run
+        .get(FASTQ_BYTES)
+        .map(|bytes| {
+            bytes
+                .split(';')
+                .filter_map(|b| b.parse::<u64>().ok())
+                .sum::<u64>()
+        })
+        .unwrap_or_default();
+    let title = field(EXPERIMENT_TITLE)
+        .chars()
+        .take(TITLE_WIDTH)
+        .collect::<String>();
+
+    format!(
+        "{:<12} {:<7} {:>9}  {}",
+        field(RUN_ACCESSION),
+        field(LIBRARY_LAYOUT),
+        human_bytes(bytes),
+        title
+    )
+}
+
+/// Randomly sample runs from an expanded accession.
+///
+/// Runs are sorted by accession before sampling, so the same seed always
+/// selects the same runs for the same study.
+///
+/// # Arguments
+///
+/// * `runs` - The expanded runs, as returned by ENA.
+/// * `n` - The number of runs to keep.
+/// * `seed` - The random seed, `None` for a fresh one.
+///
+/// # Returns
+///
+/// At most `n` runs, in accession order.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::expand::sample_runs;
+/// use std::collections::HashMap;
+///
+/// let runs = vec![HashMap::from([("run_accession".to_string(), "SRR123456".to_string())])];
+/// let sampled = sample_runs(runs, 1, Some(42));
+/// ```
+pub fn sample_runs(
+    mut runs: Vec<HashMap<String, String>>,
+    n: usize,
+    seed: Option<u64>,
+) -> Vec<HashMap<String, String>> {
+    if n >= runs.len() {
+        return runs;
+    }
+
+    runs.sort_by(|a, b| a.get(RUN_ACCESSION).cmp(&b.get(RUN_ACCESSION)));
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    };
+
+    let mut sampled = runs
+        .choose_multiple(&mut rng, n)
+        .cloned()
+        .collect::<Vec<_>>();
+    sampled.sort_by(|a, b| a.get(RUN_ACCESSION).cmp(&b.get(RUN_ACCESSION)));
+
+    log::info!("Sampled {} of {} runs", sampled.len(), runs.len());
+    sampled
+}
+
+/// Keep a deterministic slice of the runs of an expanded accession.
+///
+/// Runs are sorted by accession first, so consecutive invocations with
+/// increasing offsets cover a study without overlap.
+///
+/// # Arguments
+///
+/// * `runs` - The expanded runs, as returned by ENA.
+/// * `offset` - The number of runs to skip.
+/// * `limit` - The maximum number of runs to keep, `None` for all.
+///
+/// # Returns
+///
+/// The selected runs, in accession order.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::expand::slice_runs;
+/// use std::collections::HashMap;
+///
+/// let runs = vec![HashMap::from([("run_accession".to_string(), "SRR123456".to_string())])];
+/// let slice = slice_runs(runs, 0, Some(100));
+/// ```
+pub fn slice_runs(
+    mut runs: Vec<HashMap<String, String>>,
+    offset: usize,
+    limit: Option<usize>,
+) -> Vec<HashMap<String, String>> {
+    let total = runs.len();
+    runs.sort_by(|a, b| a.get(RUN_ACCESSION).cmp(&b.get(RUN_ACCESSION)));
+
+    let slice = runs
+        .into_iter()
+        .skip(offset)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect::<Vec<_>>();
+
+    log::info!(
+        "Keeping runs {}..{} of {}",
+        offset.min(total),
+        offset.min(total) + slice.len(),
+        total
+    );
+    slice
+}
+
+/// Keep the runs of an expanded accession whose metadata field has one of
+/// the wanted values, compared case-insensitively.
+///
+/// # Arguments
+///
+/// * `runs` - The expanded runs, as returned by ENA.
+/// * `field` - The ENA field to filter on, e.g. `library_strategy`.
+/// * `wanted` - The accepted values; an empty list keeps every run.
+///
+/// # Returns
+///
+/// The runs with an accepted value, in their original order.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::expand::filter_runs;
+/// use std::collections::HashMap;
+///
+/// let runs = vec![HashMap::from([("library_strategy".to_string(), "RNA-Seq".to_string())])];
+/// let kept = filter_runs(runs, "library_strategy", &["rna-seq".to_string()]);
+/// assert_eq!(kept.len(), 1);
+/// ```
+pub fn filter_runs(
+    runs: Vec<HashMap<String, String>>,
+    field: &str,
+    wanted: &[String],
+) -> Vec<HashMap<String, String>> {
+    if wanted.is_empty() {
+        return runs;
+    }
+
+    let total = runs.len();
+    let kept = runs
+        .into_iter()
+        .filter(|run| {
+            run.get(field).is_some_and(|value| {
+                wanted
+                    .iter()
+                    .any(|w| w.trim().eq_ignore_ascii_case(value.trim()))
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if kept.is_empty() {
+        log::warn!(
+            "WARNING: None of the {} runs has {} in {}",
+            total,
+            field,
+            wanted.join(",")
+        );
+    } else {
+        log::info!(
+            "Keeping {} of {} runs with {} in {}",
+            kept.len(),
+            total,
+            field,
+            wanted.join(",")
+        );
+    }
+    kept
+}
@@ -0,0 +1,198 @@
+use parquet::{
+    basic::Compression,
+    data_type::{ByteArray, ByteArrayType},
+    errors::ParquetError,
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+const RUN_ACCESSION: &str = "run_accession";
+const PARQUET_EXT: &str = "parquet";
+
+// INFO: runs are collected from concurrent process_run calls
+static COLLECTED: Mutex<Vec<HashMap<String, String>>> = Mutex::new(Vec::new());
+
+/// Keep run metadata for the export written at the end of the batch.
+///
+/// # Arguments
+/// * `rows` - The run metadata, as returned by ENA.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::export::collect;
+/// use std::collections::HashMap;
+/// collect(&[HashMap::from([("run_accession".to_string(), "SRR123456".to_string())])]);
+/// ```
+pub fn collect(rows: &[HashMap<String, String>]) {
+    COLLECTED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .extend_from_slice(rows);
+}
+
+/// Write the collected run metadata, as Parquet for `.parquet` files and
+/// as TSV otherwise.
+///
+/// # Arguments
+/// * `path` - The output file.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::export::write_metadata;
+/// use std::path::Path;
+/// write_metadata(Path::new("metadata.parquet"));
+/// ```
+pub fn write_metadata(path: &Path) {
+    let rows = COLLECTED.lock().unwrap_or_else(|e| e.into_inner());
+
+    let written = if path.extension().is_some_and(|ext| ext == PARQUET_EXT) {
+        write_parquet(&rows, path).map_err(|e| e.to_string())
+    } else {
+        write_tsv(&rows, path).map_err(|e| e.to_string())
+    };
+
+    match written {
+        Ok(()) => log::info!(
+            "Wrote metadata of {} runs to {}",
+            rows.len(),
+            path.display()
+        ),
+        Err(e) => {
+            log::error!(
+                "ERROR: Could not write metadata to {}!: {}",
+                path.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Get the columns of a metadata table: `run_accession` first, then every
+/// other field in alphabetical order.
+///
+/// # Arguments
+/// * `rows` - The run metadata.
+///
+/// # Returns
+/// The column names.
+fn columns(rows: &[HashMap<String, String>]) -> Vec<String> {
+    let fields = rows
+        .iter()
+        .flat_map(|row| row.keys())
+        .filter(|key| *key != RUN_ACCESSION)
+        .cloned()
+        .collect::<BTreeSet<String>>();
+
+    std::iter::once(RUN_ACCESSION.to_string())
+        .chain(fields)
+        .collect()
+}
+
+/// Write run metadata as a TSV file, missing fields left empty.
+///
+/// # Arguments
+/// * `rows` - The run metadata.
+/// * `path` - The output file.
+///
+/// # Returns
+/// An `io::Result<()>`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::export::write_tsv;
+/// use std::collections::HashMap;
+/// use std::path::Path;
+/// let rows = vec![HashMap::from([("run_accession".to_string(), "SRR123456".to_string())])];
+/// write_tsv(&rows, Path::new("metadata.tsv")).unwrap();
+/// ```
+pub fn write_tsv(rows: &[HashMap<String, String>], path: &Path) -> std::io::Result<()> {
+    let columns = columns(rows);
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writeln!(writer, "{}", columns.join("\t"))?;
+    for row in rows {
+        let line = columns
+            .iter()
+            .map(|column| row.get(column).map(String::as_str).unwrap_or_default())
+            .collect::<Vec<&str>>()
+            .join("\t");
+        writeln!(writer, "{}", line)?;
+    }
+
+    writer.flush()
+}
+
+/// Write run metadata as a Parquet file with one nullable UTF-8 column per field.
+///
+/// # Arguments
+/// * `rows` - The run metadata.
+/// * `path` - The output file.
+///
+/// # Returns
+/// A `Result<(), ParquetError>`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::export::write_parquet;
+/// use std::collections::HashMap;
+/// use std::path::Path;
+/// let rows = vec![HashMap::from([("run_accession".to_string(), "SRR123456".to_string())])];
+/// write_parquet(&rows, Path::new("metadata.parquet")).unwrap();
+/// ```
+pub fn write_parquet(rows: &[HashMap<String, String>], path: &Path) -> Result<(), ParquetError> {
+    let columns = columns(rows);
+    let schema = format!(
+        "message run {{ {} }}",
+        columns
+            .iter()
+            .map(|column| format!("OPTIONAL BYTE_ARRAY {} (UTF8);", column))
+            .collect::<Vec<String>>()
+            .join(" ")
+    );
+
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = SerializedFileWriter::new(
+        File::create(path)?,
+        Arc::new(parse_message_type(&schema)?),
+        Arc::new(properties),
+    )?;
+
+    let mut group = writer.next_row_group()?;
+    for column in &columns {
+        let Some(mut writer) = group.next_column()? else {
+            break;
+        };
+
+        let mut values = Vec::with_capacity(rows.len());
+        let mut levels = Vec::with_capacity(rows.len());
+        for row in rows {
+            match row.get(column) {
+                Some(value) => {
+                    values.push(ByteArray::from(value.as_str()));
+                    levels.push(1);
+                }
+                None => levels.push(0),
+            }
+        }
+
+        writer
+            .typed::<ByteArrayType>()
+            .write_batch(&values, Some(&levels), None)?;
+        writer.close()?;
+    }
+    group.close()?;
+    writer.close()?;
+
+    Ok(())
+}
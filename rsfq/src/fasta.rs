@@ -0,0 +1,134 @@
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use once_cell::sync::OnceCell;
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{perms::install, preempt::track_file, utils::part_path};
+
+const FASTQ_SUFFIXES: &[&str] = &[".fastq.gz", ".fq.gz"];
+pub const FASTA_SUFFIX: &str = ".fasta.gz";
+
+static FASTA: OnceCell<bool> = OnceCell::new();
+
+/// Write FASTA instead of FASTQ. Only the first call has effect.
+///
+/// # Arguments
+/// * `enabled` - Whether `--fasta` was given.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::fasta::init_fasta;
+/// init_fasta(true);
+/// ```
+pub fn init_fasta(enabled: bool) {
+    let _ = FASTA.set(enabled);
+}
+
+/// Check whether `--fasta` is on.
+///
+/// # Returns
+/// `true` if reads are written as FASTA.
+pub fn fasta_enabled() -> bool {
+    FASTA.get().copied().unwrap_or(false)
+}
+
+/// Get the name a FASTQ is saved under, `.fasta.gz` instead of
+/// `.fastq.gz`/`.fq.gz` with `--fasta`.
+///
+/// # Arguments
+/// * `name` - The FASTQ file name.
+///
+/// # Returns
+/// The output file name.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::fasta::{fasta_name, init_fasta};
+/// init_fasta(true);
+/// assert_eq!(fasta_name("SRR123456_1.fastq.gz"), "SRR123456_1.fasta.gz");
+/// ```
+pub fn fasta_name(name: &str) -> String {
+    if !fasta_enabled() {
+        return name.to_string();
+    }
+
+    FASTQ_SUFFIXES
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .map(|stem| format!("{}{}", stem, FASTA_SUFFIX))
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// Get the number of lines per record of a gzipped reads file.
+///
+/// # Arguments
+/// * `path` - The reads file.
+///
+/// # Returns
+/// 2 for FASTA, 4 for FASTQ.
+pub fn lines_per_record(path: &Path) -> u64 {
+    if path.to_string_lossy().ends_with(FASTA_SUFFIX) {
+        2
+    } else {
+        4
+    }
+}
+
+/// Rewrite a gzipped FASTQ as a gzipped FASTA, dropping the qualities, and
+/// remove the FASTQ.
+///
+/// # Arguments
+/// * `fastq` - The gzipped FASTQ.
+///
+/// # Returns
+/// The path of the FASTA, named by `fasta_name`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::fasta::{init_fasta, to_fasta};
+/// use std::path::Path;
+/// init_fasta(true);
+/// let fasta = to_fasta(Path::new("DOWNLOADS/SRR123456_1.fastq.gz")).unwrap();
+/// ```
+pub fn to_fasta(fastq: &Path) -> std::io::Result<PathBuf> {
+    let name = fastq
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let fasta = fastq.with_file_name(fasta_name(&name));
+    if fasta == fastq {
+        return Ok(fasta);
+    }
+    let part = part_path(&fasta);
+    let _partial = track_file(&part);
+
+    let reader = BufReader::new(MultiGzDecoder::new(File::open(fastq)?));
+    let mut writer = GzEncoder::new(BufWriter::new(File::create(&part)?), Compression::default());
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        match idx % 4 {
+            0 => {
+                writer.write_all(b">")?;
+                writer.write_all(line.strip_prefix('@').unwrap_or(&line).as_bytes())?;
+                writer.write_all(b"\n")?;
+            }
+            1 => {
+                writer.write_all(line.as_bytes())?;
+                writer.write_all(b"\n")?;
+            }
+            _ => {}
+        }
+    }
+
+    writer.finish()?.flush()?;
+    install(&part, &fasta)?;
+    std::fs::remove_file(fastq)?;
+    log::info!("Converted {} to {}", fastq.display(), fasta.display());
+
+    Ok(fasta)
+}
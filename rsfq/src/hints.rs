@@ -0,0 +1,128 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use crate::{
+    provs::{gsa::is_gsa_accession, Provider},
+    submitted::{is_pacbio, pacbio_bams},
+    utils::run_accession_of,
+};
+
+const FASTQ_FTP: &str = "fastq_ftp";
+const SRA_FTP: &str = "sra_ftp";
+const SUBMITTED_FTP: &str = "submitted_ftp";
+
+// INFO: accessions of a list are resolved concurrently
+static HINTS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Suggest what to try when an accession is not found.
+///
+/// # Arguments
+/// * `accession` - The accession that was not found.
+/// * `provider` - The provider it was looked up in.
+///
+/// # Returns
+/// The next step to take, if there is an obvious one.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::hints::not_found_hint;
+/// use rsfq::provs::Provider;
+/// assert!(not_found_hint("SRR123456", &Provider::ENA).unwrap().contains("prefetch"));
+/// assert!(not_found_hint("CRR000001", &Provider::ENA).unwrap().contains("-P gsa"));
+/// ```
+pub fn not_found_hint(accession: &str, provider: &Provider) -> Option<String> {
+    if is_gsa_accession(accession) && !matches!(provider, Provider::GSA) {
+        return Some(format!(
+            "{} is a GSA accession, retry with -P gsa",
+            accession
+        ));
+    }
+
+    // INFO: ENA mirrors new SRA runs with a delay, and only ENA resolves runs
+    let run = run_accession_of(accession).is_some_and(|run| run == accession);
+    if run && accession.starts_with("SRR") && !matches!(provider, Provider::Plugin(_)) {
+        return Some(format!(
+            "{} may not be mirrored by ENA yet, try again later or fetch it from NCBI with prefetch {}",
+            accession, accession
+        ));
+    }
+
+    None
+}
+
+/// Suggest how to get the reads of a run that lists no FASTQs.
+///
+/// # Arguments
+/// * `run` - The run metadata.
+/// * `provider` - The provider the run was looked up in.
+///
+/// # Returns
+/// The next step to take, if the run lists other files.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::hints::no_fastq_hint;
+/// use rsfq::provs::Provider;
+/// use std::collections::HashMap;
+///
+/// let run = HashMap::from([
+///     ("run_accession".to_string(), "SRR123456".to_string()),
+///     ("sra_ftp".to_string(), "ftp.sra.ebi.ac.uk/vol1/srr/SRR123/SRR123456".to_string()),
+/// ]);
+/// assert!(no_fastq_hint(&run, &Provider::ENA).unwrap().contains("-P sra"));
+/// ```
+pub fn no_fastq_hint(run: &HashMap<String, String>, provider: &Provider) -> Option<String> {
+    let listed = |field: &str| run.get(field).is_some_and(|value| !value.is_empty());
+    if listed(FASTQ_FTP) {
+        return None;
+    }
+
+    if is_pacbio(run) && !pacbio_bams(run).is_empty() {
+        Some("ENA has no FASTQs but lists PacBio BAMs, use --pacbio-bam".to_string())
+    } else if listed(SRA_FTP) && !provider.dumps_sra() {
+        Some("ENA has no FASTQs but lists an SRA file, retry with -P sra".to_string())
+    } else if listed(SUBMITTED_FTP) {
+        Some(
+            "ENA has no FASTQs but lists submitted files, see them with rsfq search --fields submitted_ftp"
+                .to_string(),
+        )
+    } else {
+        None
+    }
+}
+
+/// Log a suggestion for an accession and keep it for the JSON outputs.
+///
+/// # Arguments
+/// * `accession` - The accession the suggestion is about.
+/// * `hint` - What to try next.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::hints::suggest;
+/// suggest("SRR123456", "SRR123456 may not be mirrored by ENA yet, retry with -P sra");
+/// ```
+pub fn suggest(accession: &str, hint: &str) {
+    log::info!("HINT: {}", hint);
+    HINTS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(accession.to_string(), hint.to_string());
+}
+
+/// Get the suggestion made for an accession, if any.
+///
+/// # Arguments
+/// * `accession` - The accession.
+///
+/// # Returns
+/// The hint given to `suggest`, if any.
+pub fn hint(accession: &str) -> Option<String> {
+    HINTS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(accession)
+        .cloned()
+}
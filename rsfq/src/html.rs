@@ -0,0 +1,263 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    perms::settle,
+    report::{reason, records, Record, Status},
+    timing::{timings, Timings},
+    utils::human_bytes,
+};
+
+const CHART_WIDTH: f64 = 600.0; // pixels
+const BAR_HEIGHT: usize = 18; // pixels
+const LABEL_WIDTH: usize = 140; // pixels
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+h1{font-size:1.6em}h2{font-size:1.2em;margin-top:2em}\
+table{border-collapse:collapse;font-size:.9em}\
+th,td{border:1px solid #ccc;padding:.3em .6em;text-align:left}\
+th{background:#f0f0f0}td.num{text-align:right}\
+tr.failed td{background:#fde8e8}\
+.muted{color:#777}svg text{font-size:12px;font-family:sans-serif}";
+
+/// Outcome of the files of one accession
+#[derive(Debug, Clone, Default)]
+struct Outcome {
+    downloaded: usize,
+    skipped: usize,
+    duplicate: usize,
+    failed: usize,
+}
+
+impl Outcome {
+    fn add(&mut self, status: Status) {
+        match status {
+            Status::Downloaded => self.downloaded += 1,
+            Status::Skipped => self.skipped += 1,
+            Status::Duplicate => self.duplicate += 1,
+            Status::Failed => self.failed += 1,
+        }
+    }
+
+    fn files(&self) -> usize {
+        self.downloaded + self.skipped + self.duplicate + self.failed
+    }
+}
+
+/// Write a self-contained HTML report of the batch, to share with people
+/// who will not read logs: summary stats, a per-accession table, the reason
+/// of every failure and a throughput chart.
+///
+/// The page has no scripts or external assets, so it can be mailed or
+/// opened offline.
+///
+/// # Arguments
+/// * `path` - The HTML file, from `--html-report`.
+/// * `elapsed` - How long the batch took.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::html::write_html_report;
+/// use std::path::Path;
+/// use std::time::Duration;
+/// write_html_report(Path::new("rsfq.html"), Duration::from_secs(120));
+/// ```
+pub fn write_html_report(path: &Path, elapsed: Duration) {
+    let records = records();
+    let timings = timings();
+
+    let mut outcomes: BTreeMap<String, Outcome> = BTreeMap::new();
+    let mut total = Outcome::default();
+    for record in &records {
+        outcomes
+            .entry(record.run.clone())
+            .or_default()
+            .add(record.status);
+        total.add(record.status);
+    }
+    let accessions = outcomes
+        .keys()
+        .chain(timings.keys())
+        .cloned()
+        .collect::<BTreeSet<String>>();
+
+    let mut spent = Timings::default();
+    timings.values().for_each(|timing| spent.add(timing));
+
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>rsfq batch report</title>\n<style>{}</style>\n</head>\n<body>\n\
+         <h1>rsfq batch report</h1>\n<p class=\"muted\">Generated {} by rsfq {}</p>\n",
+        STYLE,
+        humantime::format_rfc3339_seconds(SystemTime::now()),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    html.push_str("<h2>Summary</h2>\n<table>\n");
+    let stats = [
+        ("Accessions", accessions.len().to_string()),
+        ("Files", total.files().to_string()),
+        ("Downloaded", total.downloaded.to_string()),
+        ("Skipped (already there)", total.skipped.to_string()),
+        ("Linked duplicates", total.duplicate.to_string()),
+        ("Failed", total.failed.to_string()),
+        ("Downloaded size", human_bytes(spent.bytes)),
+        ("Elapsed", format!("{:.3?}", elapsed)),
+        ("Metadata time", format!("{:.3?}", spent.metadata)),
+        ("Transfer time", format!("{:.3?}", spent.transfer)),
+        ("Verification time", format!("{:.3?}", spent.verify)),
+        ("Retries", spent.retries.to_string()),
+        ("Mean throughput", rate(spent.throughput())),
+    ];
+    for (name, value) in stats {
+        let _ = writeln!(
+            html,
+            "<tr><th>{}</th><td class=\"num\">{}</td></tr>",
+            name,
+            escape(&value)
+        );
+    }
+    html.push_str("</table>\n");
+
+    html.push_str(&chart(&timings));
+
+    html.push_str(
+        "<h2>Accessions</h2>\n<table>\n<tr><th>accession</th><th>files</th>\
+         <th>downloaded</th><th>skipped</th><th>duplicate</th><th>failed</th>\
+         <th>size</th><th>metadata</th><th>transfer</th><th>verify</th>\
+         <th>retries</th><th>throughput</th></tr>\n",
+    );
+    for accession in &accessions {
+        let outcome = outcomes.get(accession).cloned().unwrap_or_default();
+        let timing = timings.get(accession).copied().unwrap_or_default();
+        let _ = writeln!(
+            html,
+            "<tr{}><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+             <td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+             <td class=\"num\">{}</td><td class=\"num\">{:.3?}</td><td class=\"num\">{:.3?}</td>\
+             <td class=\"num\">{:.3?}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>",
+            if outcome.failed > 0 {
+                " class=\"failed\""
+            } else {
+                ""
+            },
+            escape(accession),
+            outcome.files(),
+            outcome.downloaded,
+            outcome.skipped,
+            outcome.duplicate,
+            outcome.failed,
+            human_bytes(timing.bytes),
+            timing.metadata,
+            timing.transfer,
+            timing.verify,
+            timing.retries,
+            rate(timing.throughput())
+        );
+    }
+    html.push_str("</table>\n");
+
+    html.push_str(&failures(&records));
+    html.push_str("</body>\n</html>\n");
+
+    match std::fs::write(path, html) {
+        Ok(()) => {
+            settle(path);
+            log::info!("HTML report written to {}", path.display());
+        }
+        Err(e) => log::warn!("WARNING: Could not write {}!: {}", path.display(), e),
+    }
+}
+
+/// Render the failed files and why they failed.
+fn failures(records: &[Record]) -> String {
+    let failed = records
+        .iter()
+        .filter(|record| record.status == Status::Failed)
+        .collect::<Vec<_>>();
+
+    let mut html = String::from("<h2>Failures</h2>\n");
+    if failed.is_empty() {
+        html.push_str("<p>No file failed.</p>\n");
+        return html;
+    }
+
+    html.push_str("<table>\n<tr><th>accession</th><th>file</th><th>reason</th></tr>\n");
+    for record in failed {
+        let _ = writeln!(
+            html,
+            "<tr class=\"failed\"><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&record.run),
+            escape(&record.file),
+            escape(&reason(record).unwrap_or_else(|| "see the log".to_string()))
+        );
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// Render the throughput of each accession as an inline SVG bar chart.
+fn chart(timings: &BTreeMap<String, Timings>) -> String {
+    let rates = timings
+        .iter()
+        .filter_map(|(accession, timing)| Some((accession, timing.throughput()?)))
+        .collect::<Vec<_>>();
+
+    let mut html = String::from("<h2>Throughput</h2>\n");
+    let Some(max) = rates.iter().map(|(_, rate)| *rate).reduce(f64::max) else {
+        html.push_str("<p>Nothing was transferred.</p>\n");
+        return html;
+    };
+
+    let height = rates.len() * (BAR_HEIGHT + 4);
+    let width = LABEL_WIDTH + CHART_WIDTH as usize + 100;
+    let _ = writeln!(
+        html,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+        width, height
+    );
+    for (idx, (accession, rate)) in rates.iter().enumerate() {
+        let y = idx * (BAR_HEIGHT + 4);
+        let bar = (rate / max * CHART_WIDTH).max(1.0);
+        let _ = writeln!(
+            html,
+            "<text x=\"0\" y=\"{}\">{}</text>\
+             <rect x=\"{}\" y=\"{}\" width=\"{:.1}\" height=\"{}\" fill=\"#4a90d9\"/>\
+             <text x=\"{:.1}\" y=\"{}\">{}</text>",
+            y + BAR_HEIGHT - 4,
+            escape(accession),
+            LABEL_WIDTH,
+            y,
+            bar,
+            BAR_HEIGHT,
+            LABEL_WIDTH as f64 + bar + 6.0,
+            y + BAR_HEIGHT - 4,
+            rate_of(*rate)
+        );
+    }
+    html.push_str("</svg>\n");
+    html
+}
+
+/// Format a transfer rate, `-` if unknown.
+fn rate(rate: Option<f64>) -> String {
+    rate.map(rate_of).unwrap_or_else(|| "-".to_string())
+}
+
+/// Format a transfer rate.
+fn rate_of(rate: f64) -> String {
+    format!("{}/s", human_bytes(rate as u64))
+}
+
+/// Escape text for HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
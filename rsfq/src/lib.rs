@@ -1,5 +1,41 @@
+pub mod audit;
+pub mod cellranger;
+pub mod chunk;
+pub mod claims;
 pub mod cli;
+pub mod cmds;
+pub mod collision;
 pub mod core;
+pub mod crosscheck;
+pub mod dedup;
+pub mod downsample;
+pub mod expand;
+pub mod export;
+pub mod fasta;
+pub mod hints;
+pub mod html;
+pub mod lock;
+pub mod merge;
+pub mod multiqc;
+pub mod net;
 pub mod nf;
+pub mod perms;
+pub mod plan;
+pub mod plugins;
+pub mod preempt;
+pub mod progress;
 pub mod provs;
+pub mod quarantine;
+pub mod report;
+pub mod resolve;
+pub mod runner;
+pub mod runtable;
+pub mod scheduler;
+pub mod sites;
+pub mod state;
+pub mod store;
+pub mod submitted;
+pub mod table;
+pub mod timing;
 pub mod utils;
+pub mod validate;
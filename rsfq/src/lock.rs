@@ -0,0 +1,107 @@
+use once_cell::sync::OnceCell;
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, Write},
+    path::Path,
+};
+
+use crate::perms::{create_dirs, settle};
+
+pub const LOCK_FILE: &str = ".rsfq.lock";
+
+// INFO: the lock lives as long as the file stays open, i.e. the whole process
+static LOCK: OnceCell<File> = OnceCell::new();
+
+/// Take an advisory lock on an output directory, so two rsfq invocations
+/// writing to the same place do not race on the same files. Exits if
+/// another process already holds it.
+///
+/// The lock is released by the OS when the process exits, even when killed,
+/// so a leftover `.rsfq.lock` never blocks a later run.
+///
+/// # Arguments
+/// * `outdir` - The output directory, created if missing.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::lock::lock_outdir;
+/// use std::path::Path;
+/// lock_outdir(Path::new("DOWNLOADS"));
+/// ```
+pub fn lock_outdir(outdir: &Path) {
+    if LOCK.get().is_some() {
+        return;
+    }
+
+    create_dirs(outdir).unwrap_or_else(|e| {
+        log::error!("ERROR: Failed to create output directory!: {}", e);
+        std::process::exit(1);
+    });
+
+    let path = outdir.join(LOCK_FILE);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .unwrap_or_else(|e| {
+            log::error!("ERROR: Could not open lock file {}!: {}", path.display(), e);
+            std::process::exit(1);
+        });
+    settle(&path);
+
+    match try_lock(&file) {
+        Ok(true) => {}
+        Ok(false) => {
+            let mut holder = String::new();
+            let _ = file.read_to_string(&mut holder);
+            log::error!(
+                "ERROR: Another rsfq (pid {}) is writing to {}! Wait for it to finish or pass --no-lock",
+                holder.trim(),
+                outdir.display()
+            );
+            std::process::exit(1);
+        }
+        Err(e) => {
+            log::warn!(
+                "WARNING: Could not lock {}, running unlocked!: {}",
+                path.display(),
+                e
+            );
+            return;
+        }
+    }
+
+    // INFO: the pid is only informative, the lock itself is what counts
+    let _ = file
+        .set_len(0)
+        .and_then(|()| file.rewind())
+        .and_then(|()| write!(file, "{}", std::process::id()));
+
+    let _ = LOCK.set(file);
+}
+
+/// Try to take an exclusive lock on a file without blocking.
+#[cfg(unix)]
+fn try_lock(file: &File) -> std::io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: flock only acts on the descriptor, which outlives the call
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+
+    let e = std::io::Error::last_os_error();
+    if e.kind() == std::io::ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(e)
+    }
+}
+
+#[cfg(not(unix))]
+fn try_lock(_file: &File) -> std::io::Result<bool> {
+    Ok(true)
+}
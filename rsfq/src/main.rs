@@ -32,16 +32,62 @@
 ///
 use std::path::PathBuf;
 
-use clap::{self, Parser};
-use log::{info, Level};
-use simple_logger::init_with_level;
-use tokio;
+use clap::{self, parser::ValueSource, CommandFactory, FromArgMatches};
+use log::{info, LevelFilter};
+use simple_logger::SimpleLogger;
 
 use rsfq::{
-    cli::Args,
-    core::get_fastqs,
-    nf::distribute,
-    utils::{__clean_nf_dirs, __move_to_root},
+    audit::init_audit,
+    cellranger::init_cellranger,
+    chunk::init_chunks,
+    claims::init_claims,
+    cli::{AccessionType, Args, Commands, MetaCommands},
+    cmds::{
+        audit::{audit_dir, AuditStatus},
+        bench::bench,
+        diff::diff,
+        expire::expire,
+        meta::query,
+        plugins::list_plugins,
+        prune::prune,
+        report::summarize,
+        search::search,
+        tree::tree,
+    },
+    collision::init_on_collision,
+    core::{cut_short, get_fastqs, init_deadline, EXIT_TIMED_OUT},
+    downsample::init_downsample,
+    expand::expand_organism,
+    export::write_metadata,
+    fasta::init_fasta,
+    lock::lock_outdir,
+    net::{
+        forget_secrets, init_client, init_connections, init_datahub, init_download_tuner,
+        init_headers, init_host_limits, init_netrc, init_offline, init_proxy, init_retry,
+        init_transport,
+    },
+    nf::{distribute, pending},
+    perms::init_permissions,
+    plugins::init_plugins,
+    preempt::init_preemption,
+    progress::{init_progress, init_stall_timeout},
+    provs::{
+        drs::init_drs,
+        ena::{init_metadata_cache, init_metadata_file},
+        gsa::init_gsa,
+        Provider,
+    },
+    report::{init_report, REPORT_EXT, REPORT_FILE},
+    resolve::init_cross_archive,
+    scheduler::init_scheduler,
+    sites::init_site_retrievers,
+    state::{init_hash, init_retain, merge_nested},
+    store::{default_db, init_store},
+    utils::{
+        __clean_nf_dirs, __concat, __move_to_root, init_aria2c_connections, init_fallback,
+        init_per_run_dirs, init_protocol, init_rename_map, init_retriever_args, Retriever,
+    },
+    validate::{init_read_id_check, init_skip_verify, init_verify_existing},
 };
 
 const NF_LOG: &str = ".nextflow.log";
@@ -50,63 +96,260 @@ const NF_HISTORY: &str = ".nextflow";
 #[tokio::main]
 async fn main() {
     let start = std::time::Instant::now();
-    init_with_level(Level::Info).unwrap_or_else(|e| {
-        panic!("Failed to initialize logger: {}", e);
-    });
+    // INFO: rsfq's own debug lines are there for --verbose, dependencies stay at info
+    SimpleLogger::new()
+        .with_level(LevelFilter::Info)
+        .with_module_level(env!("CARGO_CRATE_NAME"), LevelFilter::Debug)
+        .init()
+        .unwrap_or_else(|e| {
+            panic!("Failed to initialize logger: {}", e);
+        });
+    log::set_max_level(LevelFilter::Info);
 
-    let args: Args = Args::parse();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // INFO: check creates the output directory, modes must be known by then
+    init_permissions(args.chmod, args.dir_mode, args.group);
     args.check();
+    if args.verbose {
+        log::set_max_level(LevelFilter::Debug);
+    }
+    init_preemption();
+    init_host_limits(&args.host_limits);
+    init_datahub(args.datahub.clone());
+    init_netrc(args.credentials.as_ref());
+    init_headers(&args.headers, &args.header_hosts);
+    init_proxy(args.proxy.clone());
+    init_client(args.connect_timeout, args.request_timeout);
+    init_retry(args.attempts, args.sleep);
+    init_connections(args.connections_per_file);
+    init_download_tuner(args.auto_concurrency);
+    init_scheduler(args.cpu_slots);
+    init_hash(args.hash);
+    init_retain(args.retain.map(Into::into));
+    init_read_id_check(args.check_read_ids);
+    init_verify_existing(args.verify_existing);
+    init_skip_verify(args.skip_verify());
+    init_cellranger(args.cellranger_names);
+    init_downsample(args.target_coverage, args.genome_size);
+    init_chunks(args.chunk_reads);
+    init_fasta(args.fasta);
+    init_per_run_dirs(args.per_run_dirs);
+    init_fallback(if args.no_fallback {
+        vec![]
+    } else {
+        args.fallback.clone()
+    });
+    init_retriever_args(&args.retriever, args.retriever_args.clone());
+    init_protocol(args.protocol);
+    init_transport(args.transport);
+    init_aria2c_connections(args.aria2c_connections);
+    // INFO: a retriever given with -T beats the site defaults saved by rsfq bench
+    if matches.value_source("retriever") == Some(ValueSource::DefaultValue) {
+        init_site_retrievers();
+    }
+    init_claims(args.claims_dir.clone(), args.claim_timeout.into());
+    init_on_collision(args.on_collision);
+    init_rename_map(args.rename_map.as_ref(), args.cellranger_names);
+    init_metadata_file(args.metadata_file.as_ref());
+    init_offline(args.offline);
+    if args.offline && args.metadata_file.is_none() {
+        init_metadata_cache(&args.db.clone().unwrap_or_else(default_db));
+    }
+    init_gsa(matches!(args.provider, Provider::GSA));
+    init_drs(matches!(args.provider, Provider::DRS));
+    init_cross_archive(
+        (matches!(args.provider, Provider::ENA) || args.provider.dumps_sra())
+            && !args.no_cross_archive
+            && args.metadata_file.is_none(),
+    );
+    init_plugins(
+        args.plugins_dir.clone(),
+        match &args.provider {
+            Provider::Plugin(name) => Some(name),
+            _ => None,
+        },
+        match &args.retriever {
+            Retriever::Plugin(name) => Some(name),
+            _ => None,
+        },
+    );
+    if args.store {
+        init_store(args.db.clone().unwrap_or_else(default_db));
+    }
+    init_deadline(args.max_runtime.map(Into::into));
+    if !args.nextflow {
+        init_report(args.report.clone());
+    }
+    init_audit(args.audit_log.clone());
+    init_progress(args.progress_interval);
+    init_stall_timeout(args.timeout);
 
-    if args.nextflow {
-        match args.accession {
-            rsfq::cli::AccessionType::Single(_) => {
-                log::error!("ERROR: Nextflow mode can only accept a list of accessions!");
+    if let Some(organism) = args.organism.clone() {
+        let runs = expand_organism(&organism, &args).await;
+        args.accession = Some(AccessionType::List(runs));
+    }
+
+    match args.command.clone() {
+        Some(Commands::Search {
+            query,
+            result,
+            fields,
+            download,
+        }) => {
+            log::info!("INFO: Running in search mode...");
+            search(&query, result, &fields, download, args).await;
+        }
+        Some(Commands::Diff { accession, outdir }) => {
+            log::info!("INFO: Running in diff mode...");
+            let diffs = diff(&accession, &outdir, args.attempts, args.sleep).await;
+            if !diffs.is_empty() {
                 std::process::exit(1);
             }
-            rsfq::cli::AccessionType::List(accessions) => {
-                let outdir = args.outdir.unwrap_or(PathBuf::from("DOWNLOADS"));
-
-                log::info!("INFO: Running in Nextflow mode...");
-                distribute(
-                    accessions,
-                    args.executor,
-                    args.attempts,
-                    &outdir,
-                    args.threads,
-                    args.queue,
-                    args.sleep,
-                    args.retriever,
-                    args.queue_size,
-                    args.provider,
-                );
+        }
+        Some(Commands::Prune {
+            accession,
+            outdir,
+            dry_run,
+            yes,
+        }) => {
+            log::info!("INFO: Running in prune mode...");
+            prune(
+                &accession,
+                &outdir,
+                dry_run,
+                yes || args.yes,
+                args.attempts,
+                args.sleep,
+            )
+            .await;
+        }
+        Some(Commands::Expire {
+            outdir,
+            dry_run,
+            yes,
+        }) => {
+            log::info!("INFO: Running in expire mode...");
+            expire(&outdir, dry_run, yes || args.yes);
+        }
+        Some(Commands::Audit { dir }) => {
+            log::info!("INFO: Running in audit mode...");
+            let results = audit_dir(&dir, args.attempts, args.sleep).await;
+            if results
+                .iter()
+                .any(|(status, ..)| *status == AuditStatus::Fail)
+            {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Report { accession }) => {
+            log::info!("INFO: Running in report mode...");
+            summarize(&accession, args.attempts, args.sleep).await;
+        }
+        Some(Commands::Tree { accession, json }) => {
+            log::info!("INFO: Running in tree mode...");
+            tree(&accession, json, args.attempts, args.sleep).await;
+        }
+        Some(Commands::Bench {
+            accession,
+            timeout,
+            save,
+        }) => {
+            log::info!("INFO: Running in bench mode...");
+            bench(&accession, timeout, save, args.attempts, args.sleep).await;
+        }
+        Some(Commands::Plugins) => list_plugins(),
+        Some(Commands::Meta {
+            command:
+                MetaCommands::Query {
+                    filter,
+                    fields,
+                    download,
+                },
+        }) => {
+            log::info!("INFO: Running in meta query mode...");
+            query(&filter, &fields, download, args).await;
+        }
+        None if args.nextflow => run_nextflow(&args).await,
+        None => {
+            log::info!("INFO: Running in local mode...");
+            let metadata_out = args.metadata_out.clone();
+            get_fastqs(args).await;
+
+            if let Some(path) = metadata_out {
+                write_metadata(&path);
+            }
 
-                log::info!("INFO: Cleaning and joining output files...");
-                std::fs::remove_file(NF_LOG).unwrap_or_else(|e| {
-                    log::error!("ERROR: Could not remove Nextflow log files!: {}", e);
-                    std::process::exit(1);
-                });
-                std::fs::remove_dir_all(NF_HISTORY).unwrap_or_else(|e| {
-                    log::error!("ERROR: Could not remove Nextflow history!: {}", e);
-                    std::process::exit(1);
-                });
-
-                // INFO: moving/joining output files
-                // INFO: here is also the place to use --group-by [not implemented yet]
-                __move_to_root(&outdir);
-
-                // LOGS.iter().for_each(|log| {
-                //     let file = format!("{}.{}", "rsfq", log);
-                //     __concat(&outdir, log, &file);
-                // });
-
-                __clean_nf_dirs(&outdir);
+            if cut_short() {
+                log::warn!(
+                    "WARNING: Stopped after --max-runtime, run the same command again to resume"
+                );
+                info!("Elapsed time: {:.3?}", start.elapsed());
+                std::process::exit(EXIT_TIMED_OUT);
             }
         }
-    } else {
-        log::info!("INFO: Running in local mode...");
-        get_fastqs(args).await;
     }
 
+    forget_secrets();
     let elapsed = start.elapsed();
     info!("Elapsed time: {:.3?}", elapsed);
 }
+
+/// Distribute the accessions through Nextflow and gather the outputs.
+///
+/// # Arguments
+///
+/// * `args` - Command line arguments.
+async fn run_nextflow(args: &Args) {
+    match &args.accession {
+        Some(AccessionType::Single(_)) | None => {
+            log::error!("ERROR: Nextflow mode can only accept a list of accessions!");
+            std::process::exit(1);
+        }
+        Some(AccessionType::List(accessions)) => {
+            let outdir = args.outdir.clone().unwrap_or(PathBuf::from("DOWNLOADS"));
+
+            log::info!("INFO: Running in Nextflow mode...");
+            if !args.no_lock && args.claims_dir.is_none() {
+                lock_outdir(&outdir);
+            }
+            let accessions = pending(accessions.clone(), &outdir, args.overwrite());
+            if accessions.is_empty() {
+                log::info!("All accessions are already downloaded and verified, nothing to do!");
+                return;
+            }
+            distribute(accessions, &outdir, args).await;
+            if args.nf_generate {
+                return;
+            }
+
+            log::info!("INFO: Cleaning and joining output files...");
+            if args.keep_nf_logs {
+                log::info!("Keeping Nextflow logs in {} and {}", NF_LOG, NF_HISTORY);
+            } else {
+                // INFO: a leftover log is not worth failing a finished batch over
+                if let Err(e) = std::fs::remove_file(NF_LOG) {
+                    log::warn!("WARNING: Could not remove Nextflow log files!: {}", e);
+                }
+                if let Err(e) = std::fs::remove_dir_all(NF_HISTORY) {
+                    log::warn!("WARNING: Could not remove Nextflow history!: {}", e);
+                }
+            }
+
+            // INFO: moving/joining output files
+            // INFO: here is also the place to use --group-by [not implemented yet]
+            __move_to_root(&outdir);
+            merge_nested(&outdir);
+
+            let report = args
+                .report
+                .as_ref()
+                .map(|path| path.to_string_lossy().to_string())
+                .unwrap_or(REPORT_FILE.to_string());
+            __concat(&outdir, REPORT_EXT, &report);
+            log::info!("Batch report written to {}", outdir.join(&report).display());
+
+            __clean_nf_dirs(&outdir);
+        }
+    }
+}
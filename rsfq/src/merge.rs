@@ -0,0 +1,317 @@
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    chunk::chunk_reads,
+    fasta::{fasta_name, lines_per_record},
+    perms::{install, settle},
+    preempt::track_file,
+    state::record,
+    utils::{part_path, renamed, run_dir, Checksum},
+};
+
+type Run = HashMap<String, String>;
+
+const RUN_ACCESSION: &str = "run_accession";
+const SAMPLE_ACCESSION: &str = "sample_accession";
+const EXPERIMENT_ACCESSION: &str = "experiment_accession";
+const LIBRARY_NAME: &str = "library_name";
+const INSTRUMENT_PLATFORM: &str = "instrument_platform";
+const READ_COUNT: &str = "read_count";
+const MATES: &[&str] = &["", "_1", "_2"];
+const LANES_SUFFIX: &str = ".lanes.tsv";
+const LANES_HEADER: &str =
+    "lane\trun_accession\tlibrary_name\tsample_accession\tinstrument_platform\tread_count";
+
+/// What runs are merged by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeBy {
+    Sample,
+    Experiment,
+    Library,
+}
+
+impl MergeBy {
+    /// Get the ENA field runs are grouped by.
+    ///
+    /// # Returns
+    /// The field name, e.g. `library_name`.
+    pub fn field(&self) -> &'static str {
+        match self {
+            MergeBy::Sample => SAMPLE_ACCESSION,
+            MergeBy::Experiment => EXPERIMENT_ACCESSION,
+            MergeBy::Library => LIBRARY_NAME,
+        }
+    }
+}
+
+/// Parse a string into a MergeBy
+impl std::str::FromStr for MergeBy {
+    type Err = String;
+
+    /// Parse a string into a MergeBy
+    ///
+    /// # Arguments
+    /// * `s` - The string to parse: `sample`, `experiment` or `library`.
+    ///
+    /// # Returns
+    /// * `Result<Self, Self::Err>` - The parsed MergeBy.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::merge::MergeBy;
+    /// use std::str::FromStr;
+    /// assert_eq!(MergeBy::from_str("library").unwrap(), MergeBy::Library);
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "sample" => Ok(MergeBy::Sample),
+            "experiment" => Ok(MergeBy::Experiment),
+            "library" => Ok(MergeBy::Library),
+            _ => Err(format!(
+                "Invalid merge key, expected sample, experiment or library: {}",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for MergeBy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeBy::Sample => write!(f, "sample"),
+            MergeBy::Experiment => write!(f, "experiment"),
+            MergeBy::Library => write!(f, "library"),
+        }
+    }
+}
+
+/// Merge the FASTQs of runs sharing a sample, experiment or library into
+/// one file per mate, `<group>.fastq.gz`, `<group>_1.fastq.gz` and
+/// `<group>_2.fastq.gz`, next to a `<group>.lanes.tsv` sidecar listing the
+/// runs merged in as lanes.
+///
+/// Runs are concatenated in accession order, so `_1` and `_2` stay in sync
+/// and lane `N` of the sidecar is the `N`th run of the file; a group whose
+/// runs do not all have both mates is not merged. The run files are kept,
+/// so an interrupted batch resumes as usual.
+///
+/// # Arguments
+/// * `runs` - The run metadata, as returned by ENA.
+/// * `outdir` - The directory the runs were downloaded to.
+/// * `merge_by` - What runs are grouped by.
+/// * `prefix_ids` - Whether to prefix read IDs with their run accession.
+/// * `force` - Whether to merge again over existing merged files.
+///
+/// # Returns
+/// `Ok(())` once every group is merged or skipped, or the groups that could
+/// not be merged; the others are merged all the same.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::merge::{merge_runs, MergeBy};
+/// use std::collections::HashMap;
+/// use std::path::Path;
+/// let runs = vec![HashMap::from([
+///     ("run_accession".to_string(), "SRR123456".to_string()),
+///     ("library_name".to_string(), "lib1".to_string()),
+/// ])];
+/// merge_runs(&runs, Path::new("DOWNLOADS"), MergeBy::Library, true, false).unwrap();
+/// ```
+pub fn merge_runs(
+    runs: &[HashMap<String, String>],
+    outdir: &Path,
+    merge_by: MergeBy,
+    prefix_ids: bool,
+    force: bool,
+) -> Result<(), String> {
+    if chunk_reads().is_some() {
+        log::warn!("WARNING: Chunked runs are not merged, drop --chunk-reads to merge them");
+        return Ok(());
+    }
+
+    let field = merge_by.field();
+    let mut groups: BTreeMap<String, Vec<(&str, &Run)>> = BTreeMap::new();
+    for run in runs {
+        match (run.get(field), run.get(RUN_ACCESSION)) {
+            (Some(key), Some(accession)) if !key.trim().is_empty() => groups
+                .entry(file_key(key))
+                .or_default()
+                .push((accession, run)),
+            _ => log::warn!(
+                "WARNING: {} has no {}, leaving it unmerged",
+                run.get(RUN_ACCESSION).map(String::as_str).unwrap_or("-"),
+                field
+            ),
+        }
+    }
+
+    let mut failed = Vec::new();
+    for (key, mut members) in groups {
+        members.sort_by_key(|(accession, _)| *accession);
+
+        let inputs = MATES
+            .iter()
+            .map(|mate| {
+                members
+                    .iter()
+                    .map(|(accession, _)| {
+                        let name = renamed(&format!("{}{}.fastq.gz", accession, mate));
+                        let dir = run_dir(outdir, accession);
+                        (accession.to_string(), dir.join(fasta_name(&name)))
+                    })
+                    .filter(|(_, path)| path.exists())
+                    .collect::<Vec<(String, PathBuf)>>()
+            })
+            .collect::<Vec<_>>();
+
+        let runs_of = |idx: usize| inputs[idx].iter().map(|(acc, _)| acc).collect::<Vec<_>>();
+        if runs_of(1) != runs_of(2) {
+            log::error!(
+                "ERROR: Runs of {} do not all have both mates, not merging them!",
+                key
+            );
+            failed.push(key);
+            continue;
+        }
+
+        let mut merged_any = false;
+        for (mate, files) in MATES.iter().zip(&inputs) {
+            if files.is_empty() {
+                continue;
+            }
+
+            let merged = outdir.join(fasta_name(&format!("{}{}.fastq.gz", key, mate)));
+            if merged.exists() && !force {
+                log::warn!(
+                    "WARNING: File {} already exists! Skipping merge...",
+                    merged.display()
+                );
+                continue;
+            }
+
+            let part = part_path(&merged);
+            match merge_files(files, &part, prefix_ids).and_then(|()| install(&part, &merged)) {
+                Ok(()) => {
+                    let md5 = Checksum::Md5.digest(&merged).unwrap_or_default();
+                    record(outdir, &key, &merged, &md5, None);
+                    log::info!("Merged {} runs into {}", files.len(), merged.display());
+                    merged_any = true;
+                }
+                Err(e) => {
+                    log::error!("ERROR: Could not merge into {}!: {}", merged.display(), e);
+                    let _ = std::fs::remove_file(&part);
+                    failed.push(format!("{}{}", key, mate));
+                }
+            }
+        }
+
+        if merged_any {
+            let lanes = members
+                .iter()
+                .filter(|(accession, _)| {
+                    inputs
+                        .iter()
+                        .flatten()
+                        .any(|(merged, _)| merged == accession)
+                })
+                .map(|(_, run)| *run)
+                .collect::<Vec<_>>();
+            write_lanes(&outdir.join(format!("{}{}", key, LANES_SUFFIX)), &lanes);
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("could not merge {}", failed.join(", ")))
+    }
+}
+
+/// Write the lanes sidecar of a merged group, one row per run in merge order.
+fn write_lanes(path: &Path, runs: &[&HashMap<String, String>]) {
+    let field = |run: &HashMap<String, String>, name: &str| {
+        run.get(name)
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| "-".to_string())
+    };
+
+    let mut content = format!("{}\n", LANES_HEADER);
+    for (idx, run) in runs.iter().enumerate() {
+        content.push_str(&format!(
+            "L{:03}\t{}\t{}\t{}\t{}\t{}\n",
+            idx + 1,
+            field(run, RUN_ACCESSION),
+            field(run, LIBRARY_NAME),
+            field(run, SAMPLE_ACCESSION),
+            field(run, INSTRUMENT_PLATFORM),
+            field(run, READ_COUNT)
+        ));
+    }
+
+    match std::fs::write(path, content) {
+        Ok(()) => settle(path),
+        Err(e) => log::warn!("WARNING: Could not write {}!: {}", path.display(), e),
+    }
+}
+
+/// Turn a group key into a file name, as library names may hold spaces or
+/// slashes.
+fn file_key(key: &str) -> String {
+    key.trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Concatenate gzipped reads files, optionally prefixing read IDs with the
+/// run accession (`@SRR123456:<id>`) so IDs of different runs cannot collide.
+///
+/// Every ID is prefixed, even one that already starts with an accession,
+/// since the file may hold another run's reads under its name.
+fn merge_files(
+    files: &[(String, PathBuf)],
+    merged: &Path,
+    prefix_ids: bool,
+) -> std::io::Result<()> {
+    let _partial = track_file(merged);
+    let mut output = BufWriter::new(File::create(merged)?);
+
+    // INFO: concatenated gzip members are a valid gzip, no need to recompress
+    if !prefix_ids {
+        for (_, file) in files {
+            std::io::copy(&mut File::open(file)?, &mut output)?;
+        }
+        return output.flush();
+    }
+
+    let mut writer = GzEncoder::new(output, Compression::default());
+    for (accession, file) in files {
+        let lines = BufReader::new(MultiGzDecoder::new(File::open(file)?)).lines();
+        let step = lines_per_record(file) as usize;
+
+        for (idx, line) in lines.enumerate() {
+            let line = line?;
+            match line.split_at_checked(1) {
+                Some((marker, id)) if idx % step == 0 => {
+                    writeln!(writer, "{}{}:{}", marker, accession, id)?;
+                }
+                _ => writeln!(writer, "{}", line)?,
+            }
+        }
+    }
+    writer.finish()?.flush()
+}
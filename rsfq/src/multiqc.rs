@@ -0,0 +1,117 @@
+use serde_json::{json, Map, Value};
+
+use std::{collections::BTreeMap, path::Path};
+
+use crate::{
+    fasta::fasta_name,
+    perms::settle,
+    report::{records, Status},
+    utils::{renamed, run_dir},
+    validate::skip_verify,
+};
+
+pub const MULTIQC_FILE: &str = "rsfq_mqc.json";
+
+/// Files, size and verification of one run
+#[derive(Debug, Clone, Default)]
+struct Run {
+    files: usize,
+    downloaded: usize,
+    skipped: usize,
+    failed: usize,
+    bytes: u64,
+}
+
+impl Run {
+    /// Get the verification status: `failed` if any file failed,
+    /// `unverified` with `--skip-verify`, `verified` otherwise.
+    fn status(&self) -> &'static str {
+        if self.failed > 0 {
+            "failed"
+        } else if skip_verify() {
+            "unverified"
+        } else {
+            "verified"
+        }
+    }
+}
+
+/// Write the runs of the batch as MultiQC custom content, so a MultiQC run
+/// over the output directory shows them as an `rsfq` table: files, size and
+/// verification status per run.
+///
+/// # Arguments
+/// * `outdir` - The directory the runs were downloaded to; the
+///   `rsfq_mqc.json` is written there.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::multiqc::write_multiqc;
+/// use std::path::Path;
+/// write_multiqc(Path::new("DOWNLOADS"));
+/// ```
+pub fn write_multiqc(outdir: &Path) {
+    let mut runs: BTreeMap<String, Run> = BTreeMap::new();
+    for record in records() {
+        let run = runs.entry(record.run.clone()).or_default();
+        run.files += 1;
+        match record.status {
+            Status::Downloaded => run.downloaded += 1,
+            Status::Skipped | Status::Duplicate => run.skipped += 1,
+            Status::Failed => run.failed += 1,
+        }
+
+        // INFO: sizes are read off disk, so files kept from earlier batches count too
+        let file = run_dir(outdir, &record.run).join(fasta_name(&renamed(&record.file)));
+        run.bytes += std::fs::metadata(file).map(|m| m.len()).unwrap_or(0);
+    }
+    if runs.is_empty() {
+        return;
+    }
+
+    let data = runs
+        .iter()
+        .map(|(accession, run)| {
+            (
+                accession.clone(),
+                json!({
+                    "files": run.files,
+                    "downloaded": run.downloaded,
+                    "skipped": run.skipped,
+                    "failed": run.failed,
+                    "size_mb": run.bytes as f64 / 1e6,
+                    "status": run.status(),
+                }),
+            )
+        })
+        .collect::<Map<String, Value>>();
+
+    let content = json!({
+        "id": "rsfq",
+        "section_name": "rsfq",
+        "description": "Runs downloaded by rsfq, with their size and whether their checksums were verified.",
+        "plot_type": "table",
+        "pconfig": {
+            "id": "rsfq_table",
+            "title": "rsfq: downloaded runs",
+        },
+        "headers": {
+            "files": { "title": "Files", "format": "{:,.0f}" },
+            "downloaded": { "title": "Downloaded", "format": "{:,.0f}" },
+            "skipped": { "title": "Skipped", "description": "Already there or linked", "format": "{:,.0f}" },
+            "failed": { "title": "Failed", "format": "{:,.0f}" },
+            "size_mb": { "title": "Size", "suffix": " MB", "format": "{:,.1f}" },
+            "status": { "title": "Verification" },
+        },
+        "data": data,
+    });
+
+    let path = outdir.join(MULTIQC_FILE);
+    match std::fs::write(&path, format!("{:#}\n", content)) {
+        Ok(()) => {
+            settle(&path);
+            log::info!("MultiQC summary written to {}", path.display());
+        }
+        Err(e) => log::warn!("WARNING: Could not write {}!: {}", path.display(), e),
+    }
+}
@@ -0,0 +1,2236 @@
+use once_cell::sync::{Lazy, OnceCell};
+use reqwest::{
+    header::{ACCEPT_RANGES, CONTENT_LENGTH, RANGE, RETRY_AFTER},
+    Client, Method, NoProxy, Proxy, RequestBuilder, Response, StatusCode,
+};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt, BufWriter},
+    process::Command,
+    sync::{Notify, OwnedSemaphorePermit, Semaphore, SemaphorePermit},
+};
+
+use crate::runner::{runner, Outcome};
+use crate::scheduler::blocking;
+use crate::state::integrity_hash;
+use crate::utils::{
+    drop_partial, human_bytes, part_path, preallocate, segments_control, with_protocol, Checksum,
+    Hasher,
+};
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+
+// INFO: ENA throttles clients opening too many parallel FTP sessions,
+// cloud mirrors are left unbounded unless the user says otherwise
+const DEFAULT_HOST_LIMITS: &[(&str, usize)] = &[("ftp.sra.ebi.ac.uk", 4), ("ftp.ebi.ac.uk", 4)];
+
+const EBI_DOMAIN: &str = "ebi.ac.uk";
+const DATAHUB_PREFIX: &str = "dcc_";
+const DATAHUB_PASSWORD_ENV: &str = "RSFQ_DATAHUB_PASSWORD";
+const NETRC_FILE: &str = ".netrc";
+// INFO: the variables wget, curl, aria2c and prefetch read their proxy from
+const PROXY_VARS: &[&str] = &["http_proxy", "https_proxy", "ftp_proxy"];
+const METADATA_CONCURRENCY: usize = 50; // 50 requests
+pub const CONNECT_TIMEOUT: u64 = 30; // 30 seconds
+pub const REQUEST_TIMEOUT: u64 = 300; // 5 minutes
+const POOL_IDLE_TIMEOUT: u64 = 90; // 90 seconds
+const TCP_KEEPALIVE: u64 = 60; // 60 seconds
+pub const CONNECTIONS_PER_FILE: usize = 4; // as aria2c -x4
+const MIN_SEGMENT: u64 = 8 * 1024 * 1024; // 8 MiB
+const SEGMENT_CHECKPOINT: u64 = 8 * 1024 * 1024; // 8 MiB flushed per range between checkpoints
+const TUNER_START: usize = 2;
+const THROUGHPUT_GAIN: f64 = 1.05; // grow while throughput improves by 5%
+const THROUGHPUT_LOSS: f64 = 0.8; // back off when it drops by 20%
+const DECREASE_COOLDOWN: Duration = Duration::from_secs(10);
+const MAX_RETRY_AFTER: u64 = 600; // 10 minutes
+const MAX_BACKOFF_SHIFT: usize = 6; // 64x the first pause
+
+static HOST_LIMITER: OnceCell<HostLimiter> = OnceCell::new();
+static DATAHUB: OnceCell<Credentials> = OnceCell::new();
+static NETRC: OnceCell<(PathBuf, Netrc)> = OnceCell::new();
+// INFO: credentials files written for retrievers, by file name
+static SECRETS: Lazy<Mutex<HashMap<String, PathBuf>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static HEADERS: OnceCell<(Vec<Header>, Vec<String>)> = OnceCell::new();
+static PROXY: OnceCell<Option<String>> = OnceCell::new();
+static TRANSPORT: OnceCell<Option<Transport>> = OnceCell::new();
+static CLIENT: OnceCell<Client> = OnceCell::new();
+static DOWNLOAD_CLIENT: OnceCell<Client> = OnceCell::new();
+static CONNECTIONS: OnceCell<usize> = OnceCell::new();
+static DOWNLOAD_TUNER: OnceCell<DownloadTuner> = OnceCell::new();
+static OFFLINE: OnceCell<bool> = OnceCell::new();
+static RETRY: OnceCell<RetryPolicy> = OnceCell::new();
+static METADATA_LIMIT: Lazy<AdaptiveLimit> = Lazy::new(|| AdaptiveLimit::new(METADATA_CONCURRENCY));
+
+/// A cap on the number of simultaneous transfers against a single host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostLimit {
+    pub host: String,
+    pub limit: usize,
+}
+
+/// Parse a string into a HostLimit
+impl std::str::FromStr for HostLimit {
+    type Err = String;
+
+    /// Parse a string into a HostLimit
+    ///
+    /// # Arguments
+    /// * `s` - The string to parse, formatted as `HOST=N`.
+    ///
+    /// # Returns
+    /// * `Result<Self, Self::Err>` - The parsed HostLimit.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::net::HostLimit;
+    /// use std::str::FromStr;
+    /// let limit = HostLimit::from_str("ftp.sra.ebi.ac.uk=2").unwrap();
+    /// assert_eq!(limit.limit, 2);
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (host, limit) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid host limit, expected HOST=N: {}", s))?;
+
+        let host = host.trim().to_lowercase();
+        if host.is_empty() {
+            return Err(format!("Invalid host limit, empty host: {}", s));
+        }
+
+        let limit = limit
+            .trim()
+            .parse::<usize>()
+            .map_err(|e| format!("Invalid host limit {}: {}", s, e))?;
+        if limit == 0 {
+            return Err(format!("Invalid host limit, must be greater than 0: {}", s));
+        }
+
+        Ok(HostLimit { host, limit })
+    }
+}
+
+/// Per-host transfer slots shared by every download in the process
+#[derive(Debug, Default)]
+pub struct HostLimiter {
+    slots: HashMap<String, Arc<Semaphore>>,
+}
+
+impl HostLimiter {
+    /// Build a limiter from the default caps overridden by user-provided ones.
+    ///
+    /// # Arguments
+    /// * `limits` - User-provided per-host caps.
+    ///
+    /// # Returns
+    /// A `HostLimiter` instance.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::net::{HostLimit, HostLimiter};
+    /// use std::str::FromStr;
+    ///
+    /// let limiter = HostLimiter::new(&[HostLimit::from_str("s3.amazonaws.com=32").unwrap()]);
+    /// ```
+    pub fn new(limits: &[HostLimit]) -> Self {
+        let mut caps = DEFAULT_HOST_LIMITS
+            .iter()
+            .map(|(host, limit)| (host.to_string(), *limit))
+            .collect::<HashMap<String, usize>>();
+
+        for limit in limits {
+            caps.insert(limit.host.clone(), limit.limit);
+        }
+
+        let slots = caps
+            .into_iter()
+            .map(|(host, limit)| (host, Arc::new(Semaphore::new(limit))))
+            .collect();
+
+        HostLimiter { slots }
+    }
+
+    /// Wait for a free transfer slot for the host serving `url`.
+    ///
+    /// # Arguments
+    /// * `url` - The URL (with or without scheme) about to be downloaded.
+    ///
+    /// # Returns
+    /// An `Option<OwnedSemaphorePermit>` holding the slot until dropped, or
+    /// `None` if the host is not capped.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::net::HostLimiter;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let limiter = HostLimiter::new(&[]);
+    ///     let _permit = limiter.acquire("ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR123456.fastq.gz").await;
+    /// }
+    /// ```
+    pub async fn acquire(&self, url: &str) -> Option<OwnedSemaphorePermit> {
+        let host = host_of(url);
+        let slot = self.slots.get(&host)?.clone();
+
+        if slot.available_permits() == 0 {
+            log::info!("Waiting for a free transfer slot on {}...", host);
+        }
+
+        slot.acquire_owned().await.ok()
+    }
+}
+
+/// Initialize the process-wide host limiter. Only the first call has effect.
+///
+/// # Arguments
+/// * `limits` - User-provided per-host caps.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::init_host_limits;
+/// init_host_limits(&[]);
+/// ```
+pub fn init_host_limits(limits: &[HostLimit]) {
+    let _ = HOST_LIMITER.set(HostLimiter::new(limits));
+}
+
+/// Wait for a free transfer slot for the host serving `url`.
+///
+/// Falls back to the default caps if `init_host_limits` was never called.
+///
+/// # Arguments
+/// * `url` - The URL (with or without scheme) about to be downloaded.
+///
+/// # Returns
+/// An `Option<OwnedSemaphorePermit>` holding the slot until dropped.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::acquire_host_slot;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let _permit = acquire_host_slot("ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR123456.fastq.gz").await;
+/// }
+/// ```
+pub async fn acquire_host_slot(url: &str) -> Option<OwnedSemaphorePermit> {
+    HOST_LIMITER
+        .get_or_init(|| HostLimiter::new(&[]))
+        .acquire(url)
+        .await
+}
+
+/// Extract the lowercase host from a URL that may lack a scheme.
+///
+/// # Arguments
+/// * `url` - The URL to parse, e.g. `ftp.sra.ebi.ac.uk/vol1/...` or `https://host:443/path`.
+///
+/// # Returns
+/// The host name without port or credentials.
+///
+/// # Examples
+/// ```rust
+/// use rsfq::net::host_of;
+/// assert_eq!(host_of("ftp.sra.ebi.ac.uk/vol1/fastq"), "ftp.sra.ebi.ac.uk");
+/// assert_eq!(host_of("https://user:pw@Example.org:8443/a"), "example.org");
+/// ```
+pub fn host_of(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split('/').next().unwrap_or_default();
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = authority.split(':').next().unwrap_or_default();
+
+    host.to_lowercase()
+}
+
+/// Username/password pair used to authenticate against a remote host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub user: String,
+    pub password: String,
+}
+
+/// Initialize ENA data hub credentials. Only the first call has effect.
+///
+/// The data hub password is read from `RSFQ_DATAHUB_PASSWORD`, so it never
+/// shows up in the process list or shell history.
+///
+/// # Arguments
+/// * `datahub` - The data hub name, e.g. `dcc_abcd`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::init_datahub;
+/// std::env::set_var("RSFQ_DATAHUB_PASSWORD", "secret");
+/// init_datahub(Some("dcc_abcd".to_string()));
+/// ```
+pub fn init_datahub(datahub: Option<String>) {
+    let Some(user) = datahub else {
+        return;
+    };
+
+    if !user.starts_with(DATAHUB_PREFIX) {
+        log::error!(
+            "ERROR: Data hub names start with {}, found {}",
+            DATAHUB_PREFIX,
+            user
+        );
+        std::process::exit(1);
+    }
+
+    let password = std::env::var(DATAHUB_PASSWORD_ENV).unwrap_or_else(|_| {
+        log::error!(
+            "ERROR: --datahub requires the data hub password in ${}",
+            DATAHUB_PASSWORD_ENV
+        );
+        std::process::exit(1);
+    });
+
+    let _ = DATAHUB.set(Credentials { user, password });
+}
+
+/// Get the ENA data hub credentials, if any.
+///
+/// # Returns
+/// An `Option<&Credentials>` with the data hub user and password.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::datahub;
+/// if let Some(hub) = datahub() {
+///     println!("Using data hub {}", hub.user);
+/// }
+/// ```
+pub fn datahub() -> Option<&'static Credentials> {
+    DATAHUB.get()
+}
+
+/// Check whether a host belongs to EBI, `ebi.ac.uk` or one of its
+/// subdomains.
+///
+/// # Arguments
+/// * `host` - The host name, as given by `host_of`.
+///
+/// # Returns
+/// `true` for EBI hosts, `false` for look-alikes such as `evilebi.ac.uk`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::is_ebi_host;
+/// assert!(is_ebi_host("ftp.sra.ebi.ac.uk"));
+/// assert!(!is_ebi_host("evilebi.ac.uk"));
+/// ```
+pub fn is_ebi_host(host: &str) -> bool {
+    on_domain(host, EBI_DOMAIN)
+}
+
+/// Check whether a host is `domain` itself or one of its subdomains.
+fn on_domain(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|subdomain| subdomain.ends_with('.'))
+}
+
+/// Get the credentials that apply to the host serving `url`.
+///
+/// # Arguments
+/// * `url` - The URL (with or without scheme) about to be requested.
+///
+/// # Returns
+/// An `Option<Credentials>` if the host requires authentication.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::credentials_for;
+/// let creds = credentials_for("ftp.dcc-private.ebi.ac.uk/vol1/run/ERR123/ERR123456.fastq.gz");
+/// ```
+pub fn credentials_for(url: &str) -> Option<Credentials> {
+    let host = host_of(url);
+
+    // INFO: data hub credentials are only ever sent to EBI hosts
+    match datahub() {
+        Some(hub) if is_ebi_host(&host) => Some(hub.clone()),
+        _ => NETRC.get().and_then(|(_, netrc)| netrc.get(&host)).cloned(),
+    }
+}
+
+/// Credentials parsed from a netrc file
+#[derive(Debug, Default, Clone)]
+pub struct Netrc {
+    machines: HashMap<String, Credentials>,
+}
+
+impl Netrc {
+    /// Parse the contents of a netrc file.
+    ///
+    /// Supports `machine`, `default`, `login`, `password`, `account` and
+    /// `macdef` tokens; macros are skipped. The `default` entry is dropped,
+    /// credentials only go to the hosts listed by name.
+    ///
+    /// # Arguments
+    /// * `content` - The netrc contents.
+    ///
+    /// # Returns
+    /// A `Netrc` instance.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use rsfq::net::Netrc;
+    /// let netrc = Netrc::parse("machine ftp.example.org login me password pw");
+    /// assert!(netrc.get("ftp.example.org").is_some());
+    /// ```
+    pub fn parse(content: &str) -> Self {
+        let mut netrc = Netrc::default();
+        let mut machine: Option<Option<String>> = None;
+        let (mut user, mut password) = (None, None);
+
+        let mut flush = |machine: Option<Option<String>>,
+                         user: &mut Option<String>,
+                         password: &mut Option<String>| {
+            if let Some(machine) = machine {
+                let creds = Credentials {
+                    user: user.take().unwrap_or_default(),
+                    password: password.take().unwrap_or_default(),
+                };
+                if let Some(host) = machine {
+                    netrc.machines.entry(host.to_lowercase()).or_insert(creds);
+                }
+            }
+        };
+
+        let mut lines = content.lines();
+        while let Some(line) = lines.next() {
+            let mut tokens = line.split_whitespace();
+            while let Some(token) = tokens.next() {
+                match token {
+                    "machine" => {
+                        flush(machine.take(), &mut user, &mut password);
+                        machine = Some(tokens.next().map(|t| t.to_string()));
+                    }
+                    "default" => {
+                        flush(machine.take(), &mut user, &mut password);
+                        machine = Some(None);
+                    }
+                    "login" => user = tokens.next().map(|t| t.to_string()),
+                    "password" => password = tokens.next().map(|t| t.to_string()),
+                    "account" => {
+                        tokens.next();
+                    }
+                    "macdef" => {
+                        // INFO: macro bodies run until the next empty line
+                        for line in lines.by_ref() {
+                            if line.trim().is_empty() {
+                                break;
+                            }
+                        }
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        flush(machine, &mut user, &mut password);
+
+        netrc
+    }
+
+    /// Get the credentials listed for a host.
+    ///
+    /// # Arguments
+    /// * `host` - The host name.
+    ///
+    /// # Returns
+    /// An `Option<&Credentials>` for the host, `None` for hosts only the
+    /// `default` entry would cover.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::net::Netrc;
+    /// let netrc = Netrc::parse("default login anonymous password me@example.org");
+    /// assert!(netrc.get("ftp.sra.ebi.ac.uk").is_none());
+    /// ```
+    pub fn get(&self, host: &str) -> Option<&Credentials> {
+        self.machines.get(&host.to_lowercase())
+    }
+}
+
+/// Load netrc credentials. Only the first call has effect.
+///
+/// An explicit `file` must exist; otherwise `~/.netrc` is read if present.
+///
+/// # Arguments
+/// * `file` - An optional path to a netrc-formatted credentials file.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::init_netrc;
+/// use std::path::PathBuf;
+/// init_netrc(Some(&PathBuf::from("/path/to/credentials")));
+/// ```
+pub fn init_netrc(file: Option<&PathBuf>) {
+    let path = match file {
+        Some(file) => {
+            if !file.exists() {
+                log::error!("ERROR: Credentials file {} not found!", file.display());
+                std::process::exit(1);
+            }
+            file.clone()
+        }
+        None => match std::env::var_os("HOME") {
+            Some(home) => Path::new(&home).join(NETRC_FILE),
+            None => return,
+        },
+    };
+
+    if !path.exists() {
+        return;
+    }
+
+    let content = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        log::error!(
+            "ERROR: Could not read credentials from {}: {}",
+            path.display(),
+            e
+        );
+        std::process::exit(1);
+    });
+
+    log::info!("Using credentials from {}", path.display());
+    let _ = NETRC.set((path, Netrc::parse(&content)));
+}
+
+/// Get a netrc file holding the credentials for the host serving `url`,
+/// for retrievers to read them by path instead of from their arguments,
+/// where any user could see them with `ps`.
+///
+/// Netrc credentials come from the user's own file; data hub ones are
+/// written to a file only the user can read.
+///
+/// # Arguments
+/// * `url` - The URL (with or without scheme) about to be requested.
+///
+/// # Returns
+/// The netrc file, `None` if the host needs no credentials or the file
+/// could not be written.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::netrc_for;
+/// let netrc = netrc_for("ftp.dcc-private.ebi.ac.uk/vol1/run/ERR123/ERR123456.fastq.gz");
+/// ```
+pub fn netrc_for(url: &str) -> Option<PathBuf> {
+    let host = host_of(url);
+    match datahub() {
+        Some(hub) if is_ebi_host(&host) => secret_file(
+            &format!("{}.netrc", host),
+            &format!(
+                "machine {} login {} password {}\n",
+                host, hub.user, hub.password
+            ),
+        ),
+        _ => NETRC
+            .get()
+            .filter(|(_, netrc)| netrc.get(&host).is_some())
+            .map(|(path, _)| path.clone()),
+    }
+}
+
+/// Get a wgetrc file holding the credentials for the host serving `url`,
+/// as wget has no option to read a netrc file by path.
+///
+/// # Arguments
+/// * `url` - The URL (with or without scheme) about to be requested.
+///
+/// # Returns
+/// The wgetrc file, only readable by the user, `None` if the host needs no
+/// credentials or the file could not be written.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::wgetrc_for;
+/// let wgetrc = wgetrc_for("ftp.dcc-private.ebi.ac.uk/vol1/run/ERR123/ERR123456.fastq.gz");
+/// ```
+pub fn wgetrc_for(url: &str) -> Option<PathBuf> {
+    let host = host_of(url);
+    let creds = credentials_for(url)?;
+    secret_file(
+        &format!("{}.wgetrc", host),
+        &format!("user = {}\npassword = {}\n", creds.user, creds.password),
+    )
+}
+
+/// Write a file only the user can read to the private directory of this
+/// process, once per name.
+fn secret_file(name: &str, content: &str) -> Option<PathBuf> {
+    let mut secrets = SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(path) = secrets.get(name) {
+        return Some(path.clone());
+    }
+
+    let path = secrets_dir().join(name);
+    let written = private_dir(&secrets_dir()).and_then(|()| {
+        let mut options = OpenOptions::new();
+        options.create(true).write(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(&path)?.write_all(content.as_bytes())
+    });
+    match written {
+        Ok(()) => {
+            secrets.insert(name.to_string(), path.clone());
+            Some(path)
+        }
+        Err(e) => {
+            log::error!(
+                "ERROR: Could not write credentials to {}: {}",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Get the directory credentials files of this process are written to.
+fn secrets_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("rsfq-{}", std::process::id()))
+}
+
+/// Create a directory only the user can enter.
+fn private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+/// Remove the credentials files written for retrievers.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::forget_secrets;
+/// forget_secrets();
+/// ```
+pub fn forget_secrets() {
+    let mut secrets = SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+    if !secrets.is_empty() {
+        let _ = std::fs::remove_dir_all(secrets_dir());
+        secrets.clear();
+    }
+}
+
+/// Give a URL the `https` scheme, whatever it was listed with, so
+/// credentials sent along are never in cleartext.
+///
+/// # Arguments
+/// * `url` - The URL, with or without scheme.
+///
+/// # Returns
+/// The URL over HTTPS.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::tls_url;
+/// assert_eq!(
+///     tls_url("ftp://ftp.dcc-private.ebi.ac.uk/vol1/ERR123456.fastq.gz"),
+///     "https://ftp.dcc-private.ebi.ac.uk/vol1/ERR123456.fastq.gz"
+/// );
+/// ```
+pub fn tls_url(url: &str) -> String {
+    format!(
+        "https://{}",
+        url.split_once("://").map_or(url, |(_, rest)| rest)
+    )
+}
+
+/// An extra HTTP header sent with metadata and download requests, only to
+/// the hosts it is scoped to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// The header name, e.g. `Authorization`
+    pub name: String,
+    /// The header value, sent as given
+    pub value: String,
+}
+/// Parse a string into a Header
+impl std::str::FromStr for Header {
+    type Err = String;
+
+    /// Parse a string into a Header
+    ///
+    /// # Arguments
+    /// * `s` - The string to parse, formatted as `Name: value`.
+    ///
+    /// # Returns
+    /// * `Result<Self, Self::Err>` - The parsed Header.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::net::Header;
+    /// use std::str::FromStr;
+    /// let header = Header::from_str("Authorization: Bearer abc").unwrap();
+    /// assert_eq!(header.value, "Bearer abc");
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid header, expected 'Name: value': {}", s))?;
+
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("Invalid header name: {}", s));
+        }
+
+        Ok(Header {
+            name: name.to_string(),
+            value: value.trim().to_string(),
+        })
+    }
+}
+
+/// Display the `Header` as `Name: value`.
+impl std::fmt::Display for Header {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.name, self.value)
+    }
+}
+
+/// Initialize the extra HTTP headers and the hosts they are sent to. Only
+/// the first call has effect.
+///
+/// Headers often carry tokens, so they are never sent to every host: by
+/// default only EBI hosts get them, or the hosts given with `--header-host`
+/// (and their subdomains) instead.
+///
+/// # Arguments
+/// * `headers` - The headers to send.
+/// * `hosts` - The hosts to send them to, empty for EBI hosts only.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::{init_headers, Header};
+/// use std::str::FromStr;
+/// init_headers(
+///     &[Header::from_str("Authorization: Bearer abc").unwrap()],
+///     &["data.example.org".to_string()],
+/// );
+/// ```
+pub fn init_headers(headers: &[Header], hosts: &[String]) {
+    let hosts = hosts.iter().map(|host| host.to_lowercase()).collect();
+    let _ = HEADERS.set((headers.to_vec(), hosts));
+}
+
+/// Get the extra HTTP headers to send to the host serving `url`.
+///
+/// # Arguments
+/// * `url` - The URL (with or without scheme) about to be requested.
+///
+/// # Returns
+/// A slice of `Header`, empty if none were configured or the host is not
+/// one they are scoped to.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::headers_for;
+/// for header in headers_for("https://www.ebi.ac.uk/ena/portal/api/search") {
+///     println!("{}", header);
+/// }
+/// ```
+pub fn headers_for(url: &str) -> &'static [Header] {
+    let Some((headers, hosts)) = HEADERS.get() else {
+        return &[];
+    };
+
+    let host = host_of(url);
+    let scoped = match hosts.is_empty() {
+        true => is_ebi_host(&host),
+        false => hosts.iter().any(|domain| on_domain(&host, domain)),
+    };
+    match scoped {
+        true => headers.as_slice(),
+        false => &[],
+    }
+}
+
+/// Route metadata requests and downloads through a proxy, overriding the
+/// `http_proxy`, `https_proxy` and `ftp_proxy` variables. Only the first
+/// call has effect, and it has to come before `init_client`.
+///
+/// Without an override both the HTTP client and the spawned retrievers
+/// follow those variables on their own, and `no_proxy` is honored either
+/// way.
+///
+/// # Arguments
+/// * `proxy` - The proxy URL, from `--proxy`; `None` to keep the environment.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::init_proxy;
+/// init_proxy(Some("http://proxy.cluster.local:3128".to_string()));
+/// ```
+pub fn init_proxy(proxy: Option<String>) {
+    if let Some(proxy) = &proxy {
+        if let Err(e) = Proxy::all(proxy) {
+            log::error!("ERROR: Invalid proxy {}: {}", proxy, e);
+            std::process::exit(1);
+        }
+    }
+    let _ = PROXY.set(proxy);
+}
+
+/// Get the environment a spawned tool needs to go through the `--proxy`
+/// override.
+///
+/// # Returns
+/// The variables to set, empty without an override.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::proxy_env;
+/// use tokio::process::Command;
+///
+/// let mut cmd = Command::new("wget");
+/// cmd.envs(proxy_env());
+/// ```
+pub fn proxy_env() -> Vec<(&'static str, &'static str)> {
+    match PROXY.get() {
+        Some(Some(proxy)) => PROXY_VARS
+            .iter()
+            .map(|var| (*var, proxy.as_str()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Initialize the shared HTTP client. Only the first call has effect.
+///
+/// Connections are pooled and kept alive, so a batch of metadata queries
+/// reuses a handful of TLS sessions instead of opening one per request.
+///
+/// # Arguments
+/// * `connect_timeout` - Seconds to wait for a connection to be established.
+/// * `request_timeout` - Seconds to wait for a whole request, 0 to disable.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::init_client;
+/// init_client(30, 300);
+/// ```
+pub fn init_client(connect_timeout: u64, request_timeout: u64) {
+    let _ = CLIENT.set(build_client(connect_timeout, request_timeout));
+    // INFO: a whole FASTQ can take far longer than any metadata request
+    let _ = DOWNLOAD_CLIENT.set(build_client(connect_timeout, 0));
+}
+
+/// Get the shared HTTP client, built with the default timeouts if
+/// `init_client` was never called.
+///
+/// # Returns
+/// A reference to the shared `Client`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::client;
+/// let request = client().get("https://www.ebi.ac.uk/ena/portal/api/results");
+/// ```
+pub fn client() -> &'static Client {
+    CLIENT.get_or_init(|| build_client(CONNECT_TIMEOUT, REQUEST_TIMEOUT))
+}
+
+/// Get the HTTP client the native retriever downloads with: the shared
+/// client's settings, without a timeout on the whole request.
+///
+/// # Returns
+/// A reference to the download `Client`.
+pub fn download_client() -> &'static Client {
+    DOWNLOAD_CLIENT.get_or_init(|| build_client(CONNECT_TIMEOUT, 0))
+}
+
+/// Get the HTTPS URL of a file listed with an FTP or scheme-less URL.
+///
+/// ENA and NCBI serve their FTP trees over HTTPS from the same hosts, so
+/// the native retriever never needs to speak FTP.
+///
+/// # Arguments
+/// * `url` - The URL, e.g. `ftp.sra.ebi.ac.uk/vol1/fastq/...`.
+///
+/// # Returns
+/// The URL to request.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::https_url;
+/// assert_eq!(
+///     https_url("ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR123456.fastq.gz"),
+///     "https://ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR123456.fastq.gz"
+/// );
+/// ```
+pub fn https_url(url: &str) -> String {
+    match url.split_once("://") {
+        Some(("ftp", rest)) => format!("https://{}", rest),
+        Some(_) => url.to_string(),
+        None => format!("https://{}", url),
+    }
+}
+
+/// How files ENA serves are reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Ftp,
+    Https,
+}
+
+/// Parse a string into a `Transport`
+impl std::str::FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ftp" => Ok(Transport::Ftp),
+            "https" => Ok(Transport::Https),
+            _ => Err(format!("Invalid transport: {}, expected ftp or https", s)),
+        }
+    }
+}
+
+/// Display the `Transport` as its URL scheme.
+impl std::fmt::Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transport::Ftp => write!(f, "ftp"),
+            Transport::Https => write!(f, "https"),
+        }
+    }
+}
+
+/// Set how files ENA serves are reached. Only the first call has effect.
+///
+/// # Arguments
+/// * `transport` - The transport, from `--transport`; `None` to fetch files
+///   as listed and fall back to HTTPS.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::{init_transport, Transport};
+/// init_transport(Some(Transport::Https));
+/// ```
+pub fn init_transport(transport: Option<Transport>) {
+    let _ = TRANSPORT.set(transport);
+}
+
+/// Rewrite the URL of a file ENA serves to the `--transport` chosen, before
+/// any retriever sees it.
+///
+/// # Arguments
+/// * `url` - The URL, with or without scheme.
+///
+/// # Returns
+/// The URL, untouched for other hosts or without `--transport`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::{init_transport, transported, Transport};
+/// init_transport(Some(Transport::Https));
+/// assert_eq!(
+///     transported("ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR123456/SRR123456.fastq.gz"),
+///     "https://ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR123456/SRR123456.fastq.gz"
+/// );
+/// ```
+pub fn transported(url: &str) -> String {
+    match TRANSPORT.get().copied().flatten() {
+        Some(transport) if is_ebi_host(&host_of(url)) => {
+            let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+            format!("{}://{}", transport, rest)
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// Get the URLs a file can be fetched from, in the order to try them: as
+/// listed, then over HTTPS for files ENA serves, since its FTP tree is
+/// served over HTTPS from the same hosts. With `--transport` only the
+/// chosen one is tried.
+///
+/// # Arguments
+/// * `url` - The URL (with or without scheme) the file is listed with.
+///
+/// # Returns
+/// The URLs, the listed one first.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::mirrors;
+/// let urls = mirrors("ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/006/SRR1234566/SRR1234566_1.fastq.gz");
+/// assert_eq!(urls[1], "https://ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/006/SRR1234566/SRR1234566_1.fastq.gz");
+/// ```
+pub fn mirrors(url: &str) -> Vec<String> {
+    let transported = transported(url);
+    if transported != url {
+        return vec![transported];
+    }
+
+    let mut mirrors = vec![url.to_string()];
+    if is_ebi_host(&host_of(url)) {
+        let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+        let https = format!("https://{}", rest);
+        if with_protocol(url) != https {
+            mirrors.push(https);
+        }
+    }
+
+    mirrors
+}
+
+/// Set how many connections the native retriever opens per file. Only the
+/// first call has effect.
+///
+/// # Arguments
+/// * `connections` - The connections, from `--connections-per-file`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::init_connections;
+/// init_connections(8);
+/// ```
+pub fn init_connections(connections: usize) {
+    let _ = CONNECTIONS.set(connections.max(1));
+}
+
+/// Get how many connections the native retriever opens per file.
+///
+/// # Returns
+/// The value given to `init_connections`, `CONNECTIONS_PER_FILE` by default.
+pub fn connections() -> usize {
+    CONNECTIONS.get().copied().unwrap_or(CONNECTIONS_PER_FILE)
+}
+
+/// A file downloaded by the native retriever
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fetched {
+    /// The size of the file
+    pub bytes: u64,
+    /// The MD5 of the file, hashed as it streamed to disk; `None` when
+    /// nothing was left to download past a resumed file
+    pub md5: Option<String>,
+    /// The local integrity hash set with `--hash`, as `checksum:digest`,
+    /// hashed along with the MD5
+    pub hash: Option<String>,
+}
+
+/// The MD5 a download is verified with and the local integrity hash the
+/// state manifest records, fed together so the file is read once
+struct Digests {
+    md5: Hasher,
+    local: Option<(Checksum, Hasher)>,
+}
+
+impl Digests {
+    fn new() -> Self {
+        Digests {
+            md5: Hasher::new(Checksum::Md5),
+            local: integrity_hash().map(|checksum| (checksum, Hasher::new(checksum))),
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        self.md5.update(chunk);
+        if let Some((_, hasher)) = &mut self.local {
+            hasher.update(chunk);
+        }
+    }
+
+    /// Finish both digests into what `Fetched` holds.
+    fn finish(self, bytes: u64) -> Fetched {
+        Fetched {
+            bytes,
+            md5: Some(self.md5.finish()),
+            hash: self
+                .local
+                .map(|(checksum, hasher)| format!("{}:{}", checksum, hasher.finish())),
+        }
+    }
+}
+
+/// Download a file with the shared HTTP client, streaming it to disk, as
+/// `-T native` does where no external downloader is installed.
+///
+/// A fresh file large enough is split into `connections()` ranges fetched
+/// in parallel, their progress kept in a `.segments` control file until the
+/// file is whole. Any other non-empty `output` is resumed with a Range request;
+/// servers ignoring the range send the whole file, which then replaces it.
+/// The host's credentials and the extra headers are sent like the external
+/// retrievers send them, along with the headers given for this file alone,
+/// e.g. those a DRS access URL asks for. Retries are left to the caller.
+///
+/// A single stream is hashed as it is written, and segments as soon as the
+/// bytes before them are in, so verifying never reads the whole file again
+/// once the transfer ends; the bytes already there when resuming are hashed
+/// on a blocking thread.
+///
+/// # Arguments
+/// * `url` - The URL (with or without scheme) to download.
+/// * `output` - The file to write, or to resume.
+/// * `headers` - Headers to send for this file only, on every request.
+///
+/// # Returns
+/// The file once the transfer ends, or why it failed.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::fetch;
+/// use std::path::Path;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let url = "ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR123456/SRR123456.fastq.gz";
+///     let fetched = fetch(url, Path::new("SRR123456.fastq.gz.part"), &[]).await.unwrap();
+///     println!("{} bytes, MD5 {:?}", fetched.bytes, fetched.md5);
+/// }
+/// ```
+pub async fn fetch(url: &str, output: &Path, headers: &[Header]) -> Result<Fetched, String> {
+    let mut offset = output.metadata().map(|m| m.len()).unwrap_or(0);
+
+    // INFO: a file with holes is only ever resumed range by range
+    if segments_control(output).exists() {
+        match Segments::load(output) {
+            Some(segments) if offset > 0 => {
+                return fetch_segments(url, output, headers, segments).await
+            }
+            _ => {
+                log::warn!(
+                    "WARNING: Cannot resume the segments of {}, starting over",
+                    output.display()
+                );
+                drop_partial(output);
+                offset = 0;
+            }
+        }
+    }
+
+    if offset == 0 && connections() > 1 {
+        if let Some(size) = ranged_size(url, headers).await {
+            let segments = connections().min((size / MIN_SEGMENT) as usize);
+            if segments > 1 {
+                let segments = Segments::new(output, size, segments);
+                return fetch_segments(url, output, headers, segments).await;
+            }
+        }
+    }
+
+    let mut request = download_request(Method::GET, url, headers);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    }
+
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    // INFO: nothing past the end, the MD5 check tells whether it is whole
+    if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        return Ok(Fetched {
+            bytes: offset,
+            md5: None,
+            hash: None,
+        });
+    }
+    let mut response = response.error_for_status().map_err(|e| e.to_string())?;
+
+    let resumed = offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+    if resumed {
+        log::info!("Resuming {} from byte {}", url, offset);
+    }
+    let start = if resumed { offset } else { 0 };
+    let expected = response.content_length().map(|length| start + length);
+
+    // INFO: the bytes already on disk are hashed once, the rest as they arrive
+    let mut digests = if resumed {
+        let prefix = output.to_path_buf();
+        blocking(move || hash_prefix(&prefix, offset))
+            .await
+            .map_err(|e| format!("{}: {}", output.display(), e))?
+    } else {
+        Digests::new()
+    };
+
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(output)
+        .map_err(|e| format!("{}: {}", output.display(), e))?;
+    if let Some(expected) = expected {
+        preallocate(&file, expected).map_err(|e| format!("{}: {}", output.display(), e))?;
+    }
+    let mut writer = BufWriter::new(tokio::fs::File::from_std(file));
+    let mut written = start;
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        writer
+            .write_all(&chunk)
+            .await
+            .map_err(|e| format!("{}: {}", output.display(), e))?;
+        digests.update(&chunk);
+        written += chunk.len() as u64;
+    }
+    writer
+        .flush()
+        .await
+        .map_err(|e| format!("{}: {}", output.display(), e))?;
+
+    match expected {
+        Some(expected) if expected != written => Err(format!(
+            "transfer ended after {} of {} bytes",
+            written, expected
+        )),
+        _ => Ok(digests.finish(written)),
+    }
+}
+
+/// Download a file with an external retriever printing it to stdout, as
+/// `-T wget` and `-T curl` do, appending it to `output` and hashing it as
+/// it arrives so verifying it does not read the file again.
+///
+/// # Arguments
+/// * `cmd` - The retriever command, from `Retriever::pipe`.
+/// * `output` - The file to write, holding the `offset` bytes `cmd` skips.
+/// * `offset` - The bytes already downloaded, hashed on a blocking thread first.
+/// * `on_line` - Called with every line the retriever prints to stderr.
+///
+/// # Returns
+/// How the retriever exited and the file it wrote, or why it could not run.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::fetch_through;
+/// use rsfq::utils::Retriever;
+/// use std::path::Path;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let url = "https://ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR123456/SRR123456.fastq.gz";
+///     let cmd = Retriever::Curl.pipe(url, 0).unwrap();
+///     let output = Path::new("SRR123456.fastq.gz.part");
+///     let (outcome, fetched) = fetch_through(cmd, output, 0, &|line| println!("{}", line))
+///         .await
+///         .unwrap();
+///     println!("{:?}: {} bytes, MD5 {:?}", outcome.code, fetched.bytes, fetched.md5);
+/// }
+/// ```
+pub async fn fetch_through(
+    mut cmd: Command,
+    output: &Path,
+    offset: u64,
+    on_line: &(dyn Fn(&str) + Send + Sync),
+) -> std::io::Result<(Outcome, Fetched)> {
+    let digests = if offset > 0 {
+        let prefix = output.to_path_buf();
+        blocking(move || hash_prefix(&prefix, offset)).await?
+    } else {
+        Digests::new()
+    };
+
+    let file = OpenOptions::new().create(true).append(true).open(output)?;
+    // INFO: nothing past the bytes the retriever was told to skip
+    file.set_len(offset)?;
+    let mut sink = Hashing {
+        writer: BufWriter::new(tokio::fs::File::from_std(file)),
+        digests,
+        written: offset,
+    };
+
+    // INFO: dropping a stalled transfer kills the retriever
+    cmd.kill_on_drop(true);
+    let outcome = runner().run_piped(cmd, &mut sink, on_line).await?;
+    sink.flush().await?;
+
+    Ok((outcome, sink.digests.finish(sink.written)))
+}
+
+/// A writer hashing the bytes it passes on
+struct Hashing<W> {
+    writer: W,
+    digests: Digests,
+    written: u64,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for Hashing<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        let written = ready!(Pin::new(&mut this.writer).poll_write(cx, buf))?;
+        this.digests.update(&buf[..written]);
+        this.written += written as u64;
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
+
+/// Hash the first `bytes` of a file, returning the digests to feed the rest.
+fn hash_prefix(path: &Path, bytes: u64) -> std::io::Result<Digests> {
+    hash_range(path, 0, bytes, Digests::new())
+}
+
+/// Feed the bytes `start..end` of a file to the digests, returning them to
+/// feed the rest.
+fn hash_range(path: &Path, start: u64, end: u64, mut digests: Digests) -> std::io::Result<Digests> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut reader = BufReader::new(file).take(end - start);
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        match reader.read(&mut buffer)? {
+            0 => return Ok(digests),
+            read => digests.update(&buffer[..read]),
+        }
+    }
+}
+
+/// Start a download request, with the host's credentials, the extra
+/// headers and those given for this file.
+pub(crate) fn download_request(method: Method, url: &str, headers: &[Header]) -> RequestBuilder {
+    let mut request = match credentials_for(url) {
+        Some(creds) => download_client()
+            .request(method, tls_url(url))
+            .basic_auth(creds.user, Some(creds.password)),
+        None => download_client().request(method, https_url(&with_protocol(url))),
+    };
+    for header in headers_for(url).iter().chain(headers) {
+        request = request.header(&header.name, &header.value);
+    }
+    request
+}
+
+/// Get the size of a file whose server accepts range requests.
+async fn ranged_size(url: &str, headers: &[Header]) -> Option<u64> {
+    let response = download_request(Method::HEAD, url, headers)
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?;
+    let ranges = response
+        .headers()
+        .get(ACCEPT_RANGES)
+        .is_some_and(|value| value.as_bytes() == b"bytes");
+    // INFO: the body of a HEAD response is empty, its length is in the header
+    let size = response
+        .headers()
+        .get(CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse::<u64>()
+        .ok()?;
+
+    ranges.then_some(size)
+}
+
+/// How far each range of a segmented download got, kept in the control
+/// file next to its staging file. The file itself has holes, so its length
+/// says nothing about what is there.
+struct Segments {
+    control: PathBuf,
+    ranges: Vec<(u64, u64)>,
+    done: Vec<AtomicU64>,
+    progressed: Notify,
+    finished: bool,
+}
+
+impl Segments {
+    /// Split `size` bytes into `segments` ranges, none of them started.
+    fn new(output: &Path, size: u64, segments: usize) -> Self {
+        let length = size.div_ceil(segments as u64);
+        let ranges = (0..segments as u64)
+            .map(|i| (i * length, ((i + 1) * length).min(size)))
+            .filter(|(start, end)| start < end)
+            .collect::<Vec<_>>();
+
+        Segments {
+            control: segments_control(output),
+            done: ranges.iter().map(|_| AtomicU64::new(0)).collect(),
+            ranges,
+            progressed: Notify::new(),
+            finished: false,
+        }
+    }
+
+    /// Read the control file of an interrupted segmented download, one
+    /// `START END DONE` line per range.
+    fn load(output: &Path) -> Option<Self> {
+        let control = segments_control(output);
+        let content = std::fs::read_to_string(&control).ok()?;
+
+        let mut ranges = Vec::new();
+        let mut done = Vec::new();
+        for line in content.lines() {
+            let mut fields = line.split_whitespace().map(|field| field.parse::<u64>());
+            let (Some(Ok(start)), Some(Ok(end)), Some(Ok(received)), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return None;
+            };
+            if start >= end || received > end - start {
+                return None;
+            }
+            ranges.push((start, end));
+            done.push(AtomicU64::new(received));
+        }
+
+        (!ranges.is_empty()).then_some(Segments {
+            control,
+            ranges,
+            done,
+            progressed: Notify::new(),
+            finished: false,
+        })
+    }
+
+    /// The size of the whole file.
+    fn size(&self) -> u64 {
+        self.ranges.last().map(|(_, end)| *end).unwrap_or(0)
+    }
+
+    /// The bytes flushed without a gap from the start of the file.
+    fn complete(&self) -> u64 {
+        let mut complete = 0;
+        for ((start, end), done) in self.ranges.iter().zip(&self.done) {
+            let received = done.load(Ordering::Relaxed);
+            complete = start + received;
+            if received < end - start {
+                break;
+            }
+        }
+        complete
+    }
+
+    /// The control file content, as `load` reads it.
+    fn render(&self) -> String {
+        self.ranges
+            .iter()
+            .zip(&self.done)
+            .map(|((start, end), done)| {
+                format!("{} {} {}\n", start, end, done.load(Ordering::Relaxed))
+            })
+            .collect()
+    }
+
+    /// Write the control file, through a staging name so a crash never
+    /// leaves half of it.
+    fn save(&self) -> std::io::Result<()> {
+        let staging = part_path(&self.control);
+        std::fs::write(&staging, self.render())?;
+        std::fs::rename(staging, &self.control)
+    }
+
+    /// Write the control file from async code, once range `idx` has
+    /// flushed its bytes. Each range stages its own copy, so two
+    /// checkpoints never write the same file.
+    async fn checkpoint(&self, idx: usize) -> std::io::Result<()> {
+        let mut staging = part_path(&self.control).into_os_string();
+        staging.push(idx.to_string());
+        tokio::fs::write(&staging, self.render()).await?;
+        tokio::fs::rename(staging, &self.control).await
+    }
+}
+
+impl Drop for Segments {
+    fn drop(&mut self) {
+        // INFO: a failed, stalled or stopped transfer keeps its progress
+        if self.finished {
+            let _ = std::fs::remove_file(&self.control);
+        } else {
+            let _ = self.save();
+        }
+    }
+}
+
+/// Download a file as ranges over as many connections.
+///
+/// Each range counts its bytes once flushed, and checkpoints them in the
+/// control file, so however the transfer ends (failed, dropped on a stall
+/// or killed) the next attempt resumes every range where it stopped. The
+/// file is hashed in order as its ranges complete.
+async fn fetch_segments(
+    url: &str,
+    output: &Path,
+    headers: &[Header],
+    mut segments: Segments,
+) -> Result<Fetched, String> {
+    let size = segments.size();
+    let resumed = segments
+        .done
+        .iter()
+        .any(|done| done.load(Ordering::Relaxed) > 0);
+    if resumed {
+        log::info!(
+            "Resuming {} over {} connections",
+            url,
+            segments.ranges.len()
+        );
+    } else {
+        // INFO: the control file comes first, a file with holes is never left without it
+        segments
+            .save()
+            .map_err(|e| format!("{}: {}", segments.control.display(), e))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(output)
+        .and_then(|file| preallocate(&file, size))
+        .map_err(|e| format!("{}: {}", output.display(), e))?;
+
+    let (_, digests) = futures::future::try_join(
+        futures::future::try_join_all(
+            (0..segments.ranges.len()).map(|idx| fetch_range(url, output, headers, &segments, idx)),
+        ),
+        hash_segments(output, &segments),
+    )
+    .await?;
+
+    segments.finished = true;
+    log::info!(
+        "Downloaded {} over {} connections",
+        url,
+        segments.ranges.len()
+    );
+    Ok(digests.finish(size))
+}
+
+/// Hash a segmented download in order while its ranges arrive, reading
+/// back each stretch once the bytes before it are all flushed, so the MD5
+/// is ready when the last range is.
+async fn hash_segments(output: &Path, segments: &Segments) -> Result<Digests, String> {
+    let mut digests = Digests::new();
+    let mut hashed = 0;
+    while hashed < segments.size() {
+        let complete = segments.complete();
+        if complete == hashed {
+            segments.progressed.notified().await;
+            continue;
+        }
+
+        let path = output.to_path_buf();
+        digests = blocking(move || hash_range(&path, hashed, complete, digests))
+            .await
+            .map_err(|e| format!("{}: {}", output.display(), e))?;
+        hashed = complete;
+    }
+    Ok(digests)
+}
+
+/// Download what is missing of range `idx` into the same bytes of
+/// `output`, counting them in its progress once they are flushed.
+async fn fetch_range(
+    url: &str,
+    output: &Path,
+    headers: &[Header],
+    segments: &Segments,
+    idx: usize,
+) -> Result<(), String> {
+    let ((start, end), done) = (segments.ranges[idx], &segments.done[idx]);
+    let from = start + done.load(Ordering::Relaxed);
+    if from == end {
+        return Ok(());
+    }
+
+    let mut response = download_request(Method::GET, url, headers)
+        .header(RANGE, format!("bytes={}-{}", from, end - 1))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(format!("{} ignored the range {}-{}", url, from, end - 1));
+    }
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(output)
+        .map_err(|e| format!("{}: {}", output.display(), e))?;
+    file.seek(SeekFrom::Start(from))
+        .map_err(|e| format!("{}: {}", output.display(), e))?;
+    let mut writer = BufWriter::new(tokio::fs::File::from_std(file));
+    let mut unflushed = 0;
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        writer
+            .write_all(&chunk)
+            .await
+            .map_err(|e| format!("{}: {}", output.display(), e))?;
+        unflushed += chunk.len() as u64;
+        if unflushed >= SEGMENT_CHECKPOINT {
+            writer
+                .flush()
+                .await
+                .map_err(|e| format!("{}: {}", output.display(), e))?;
+            done.fetch_add(std::mem::take(&mut unflushed), Ordering::Relaxed);
+            segments.progressed.notify_one();
+            segments
+                .checkpoint(idx)
+                .await
+                .map_err(|e| format!("{}: {}", segments.control.display(), e))?;
+        }
+    }
+    writer
+        .flush()
+        .await
+        .map_err(|e| format!("{}: {}", output.display(), e))?;
+    done.fetch_add(unflushed, Ordering::Relaxed);
+    segments.progressed.notify_one();
+
+    match done.load(Ordering::Relaxed) {
+        received if received == end - start => Ok(()),
+        received => Err(format!(
+            "range {}-{} ended after {} of {} bytes",
+            start,
+            end - 1,
+            received,
+            end - start
+        )),
+    }
+}
+
+/// Build an HTTP client with connection pooling and the given timeouts.
+///
+/// # Arguments
+/// * `connect_timeout` - Seconds to wait for a connection to be established.
+/// * `request_timeout` - Seconds to wait for a whole request, 0 to disable.
+fn build_client(connect_timeout: u64, request_timeout: u64) -> Client {
+    let mut builder = Client::builder()
+        .user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        ))
+        .connect_timeout(Duration::from_secs(connect_timeout))
+        .pool_idle_timeout(Duration::from_secs(POOL_IDLE_TIMEOUT))
+        .pool_max_idle_per_host(METADATA_CONCURRENCY)
+        .tcp_keepalive(Duration::from_secs(TCP_KEEPALIVE));
+
+    if request_timeout > 0 {
+        builder = builder.timeout(Duration::from_secs(request_timeout));
+    }
+    // INFO: reqwest reads the proxy variables by itself otherwise
+    if let Some(Some(proxy)) = PROXY.get() {
+        if let Ok(proxy) = Proxy::all(proxy) {
+            builder = builder.proxy(proxy.no_proxy(NoProxy::from_env()));
+        }
+    }
+
+    builder.build().unwrap_or_else(|e| {
+        log::error!("ERROR: Could not build the HTTP client!: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// A concurrency limit that shrinks when the server throttles us and slowly
+/// grows back on success
+#[derive(Debug)]
+pub struct AdaptiveLimit {
+    slots: Semaphore,
+    capacity: AtomicUsize,
+    max: usize,
+}
+
+impl AdaptiveLimit {
+    /// Create a new limit starting (and capped) at `max` concurrent requests.
+    ///
+    /// # Arguments
+    /// * `max` - The maximum number of concurrent requests.
+    ///
+    /// # Returns
+    /// An `AdaptiveLimit` instance.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::net::AdaptiveLimit;
+    /// let limit = AdaptiveLimit::new(10);
+    /// ```
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        AdaptiveLimit {
+            slots: Semaphore::new(max),
+            capacity: AtomicUsize::new(max),
+            max,
+        }
+    }
+
+    /// Wait for a free request slot.
+    ///
+    /// # Returns
+    /// A `SemaphorePermit` to hand back through `success` or `throttled`.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::net::AdaptiveLimit;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let limit = AdaptiveLimit::new(10);
+    ///     let permit = limit.acquire().await;
+    ///     limit.success(permit);
+    /// }
+    /// ```
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        self.slots.acquire().await.unwrap_or_else(|e| {
+            log::error!("ERROR: Request slots were closed!: {}", e);
+            std::process::exit(1);
+        })
+    }
+
+    /// Release a slot after a successful request, growing the limit by one.
+    ///
+    /// # Arguments
+    /// * `permit` - The permit obtained from `acquire`.
+    pub fn success(&self, permit: SemaphorePermit<'_>) {
+        drop(permit);
+
+        let grown = self
+            .capacity
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |c| {
+                (c < self.max).then_some(c + 1)
+            });
+        if grown.is_ok() {
+            self.slots.add_permits(1);
+        }
+    }
+
+    /// Release a slot after a throttled request, shrinking the limit by one.
+    ///
+    /// # Arguments
+    /// * `permit` - The permit obtained from `acquire`.
+    pub fn throttled(&self, permit: SemaphorePermit<'_>) {
+        // INFO: never go below a single in-flight request
+        let shrunk = self
+            .capacity
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |c| {
+                (c > 1).then_some(c - 1)
+            });
+
+        match shrunk {
+            Ok(previous) => {
+                permit.forget();
+                log::warn!(
+                    "WARNING: Server is throttling requests, lowering concurrency to {}",
+                    previous - 1
+                );
+            }
+            Err(_) => drop(permit),
+        }
+    }
+
+    /// Get the current number of allowed concurrent requests.
+    ///
+    /// # Returns
+    /// The current capacity.
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::SeqCst)
+    }
+}
+
+/// Get the process-wide adaptive limit for ENA metadata requests.
+///
+/// # Returns
+/// A reference to the shared `AdaptiveLimit`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::metadata_limit;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let permit = metadata_limit().acquire().await;
+///     metadata_limit().success(permit);
+/// }
+/// ```
+pub fn metadata_limit() -> &'static AdaptiveLimit {
+    &METADATA_LIMIT
+}
+
+/// Observed transfers since the last change of concurrency
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    bytes: u64,
+    finished: usize,
+    rate: f64,
+    decreased: Option<Instant>,
+}
+
+/// A download concurrency tuned AIMD-style: it grows by one while the
+/// aggregate throughput keeps improving and halves on errors or when the
+/// throughput drops
+#[derive(Debug)]
+pub struct DownloadTuner {
+    slots: Semaphore,
+    capacity: AtomicUsize,
+    // INFO: permits to forget as they come back, after a decrease
+    debt: AtomicUsize,
+    max: usize,
+    window: Mutex<Window>,
+}
+
+impl DownloadTuner {
+    /// Create a tuner starting at `start` concurrent downloads, capped at `max`.
+    ///
+    /// # Arguments
+    /// * `start` - The initial concurrency.
+    /// * `max` - The maximum concurrency.
+    ///
+    /// # Returns
+    /// A `DownloadTuner` instance.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::net::DownloadTuner;
+    /// let tuner = DownloadTuner::new(2, 16);
+    /// ```
+    pub fn new(start: usize, max: usize) -> Self {
+        let max = max.max(1);
+        let start = start.clamp(1, max);
+        DownloadTuner {
+            slots: Semaphore::new(start),
+            capacity: AtomicUsize::new(start),
+            debt: AtomicUsize::new(0),
+            max,
+            window: Mutex::new(Window {
+                started: Instant::now(),
+                bytes: 0,
+                finished: 0,
+                rate: 0.0,
+                decreased: None,
+            }),
+        }
+    }
+
+    /// Wait for a free download slot.
+    ///
+    /// # Returns
+    /// A `SemaphorePermit` to hand back through `finished` or `release`.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::net::DownloadTuner;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let tuner = DownloadTuner::new(2, 16);
+    ///     let permit = tuner.acquire().await;
+    ///     tuner.finished(permit, 1_048_576);
+    /// }
+    /// ```
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        self.slots.acquire().await.unwrap_or_else(|e| {
+            log::error!("ERROR: Download slots were closed!: {}", e);
+            std::process::exit(1);
+        })
+    }
+
+    /// Hand back a slot without counting a transfer.
+    ///
+    /// # Arguments
+    /// * `permit` - The permit obtained from `acquire`.
+    pub fn release(&self, permit: SemaphorePermit<'_>) {
+        let owed = self
+            .debt
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |d| d.checked_sub(1));
+        match owed {
+            Ok(_) => permit.forget(),
+            Err(_) => drop(permit),
+        }
+    }
+
+    /// Hand back a slot after a completed download and adjust the
+    /// concurrency once every slot finished a transfer at the current level.
+    ///
+    /// # Arguments
+    /// * `permit` - The permit obtained from `acquire`.
+    /// * `bytes` - The size of the downloaded file.
+    pub fn finished(&self, permit: SemaphorePermit<'_>, bytes: u64) {
+        self.release(permit);
+
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        window.bytes += bytes;
+        window.finished += 1;
+        if window.finished < self.capacity() {
+            return;
+        }
+
+        let seconds = window.started.elapsed().as_secs_f64().max(f64::EPSILON);
+        let rate = window.bytes as f64 / seconds;
+        let previous = window.rate;
+        window.started = Instant::now();
+        window.bytes = 0;
+        window.finished = 0;
+        window.rate = rate;
+
+        if rate >= previous * THROUGHPUT_GAIN {
+            self.grow(rate);
+        } else if rate < previous * THROUGHPUT_LOSS {
+            window.decreased = Some(Instant::now());
+            self.shrink("Throughput dropped");
+        }
+    }
+
+    /// Count a failed transfer, halving the concurrency at most once per
+    /// window so a burst of errors does not collapse it to one.
+    pub fn failed(&self) {
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        if window
+            .decreased
+            .is_some_and(|at| at.elapsed() < DECREASE_COOLDOWN)
+        {
+            return;
+        }
+        window.started = Instant::now();
+        window.bytes = 0;
+        window.finished = 0;
+        window.decreased = Some(Instant::now());
+
+        self.shrink("Downloads are failing");
+    }
+
+    /// Get the current number of allowed concurrent downloads.
+    ///
+    /// # Returns
+    /// The current capacity.
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::SeqCst)
+    }
+
+    fn grow(&self, rate: f64) {
+        let grown = self
+            .capacity
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |c| {
+                (c < self.max).then_some(c + 1)
+            });
+        let Ok(previous) = grown else {
+            return;
+        };
+
+        // INFO: a permit still owed cancels out with the new one
+        let owed = self
+            .debt
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |d| d.checked_sub(1));
+        if owed.is_err() {
+            self.slots.add_permits(1);
+        }
+        log::info!(
+            "Throughput at {}/s, raising concurrency to {}",
+            human_bytes(rate as u64),
+            previous + 1
+        );
+    }
+
+    fn shrink(&self, reason: &str) {
+        let shrunk = self
+            .capacity
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |c| {
+                (c > 1).then_some(c.div_ceil(2))
+            });
+        if let Ok(previous) = shrunk {
+            let current = previous.div_ceil(2);
+            self.debt.fetch_add(previous - current, Ordering::SeqCst);
+            log::warn!("WARNING: {}, lowering concurrency to {}", reason, current);
+        }
+    }
+}
+
+/// Tune the number of concurrent downloads from observed throughput and
+/// errors. Only the first call has effect.
+///
+/// # Arguments
+/// * `max` - The maximum concurrency, from `--max-concurrency`; `None` leaves
+///   downloads untuned.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::init_download_tuner;
+/// init_download_tuner(Some(16));
+/// ```
+pub fn init_download_tuner(max: Option<usize>) {
+    if let Some(max) = max {
+        log::info!(
+            "Tuning download concurrency between 1 and {}, starting at {}",
+            max,
+            TUNER_START.min(max)
+        );
+        let _ = DOWNLOAD_TUNER.set(DownloadTuner::new(TUNER_START, max));
+    }
+}
+
+/// Get the process-wide download tuner.
+///
+/// # Returns
+/// The tuner, or `None` if downloads are not tuned.
+pub fn download_tuner() -> Option<&'static DownloadTuner> {
+    DOWNLOAD_TUNER.get()
+}
+
+/// Forbid all network access, so rsfq works from local files only. Only
+/// the first call has effect.
+///
+/// # Arguments
+/// * `offline` - Whether `--offline` was given.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::init_offline;
+/// init_offline(true);
+/// ```
+pub fn init_offline(offline: bool) {
+    if offline {
+        log::info!("Running offline, nothing will be fetched");
+    }
+    let _ = OFFLINE.set(offline);
+}
+
+/// Check whether network access is forbidden.
+///
+/// # Returns
+/// `true` with `--offline`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::offline;
+/// if offline() {
+///     println!("working from local files only");
+/// }
+/// ```
+pub fn offline() -> bool {
+    OFFLINE.get().copied().unwrap_or(false)
+}
+
+/// Parse a `Retry-After` header value, given either in seconds or as an
+/// HTTP date, capped at `MAX_RETRY_AFTER` so a server cannot stall the batch.
+///
+/// # Arguments
+/// * `value` - The raw header value.
+///
+/// # Returns
+/// An `Option<u64>` with the number of seconds to wait, zero for a date
+/// already past.
+///
+/// # Examples
+/// ```rust
+/// use rsfq::net::parse_retry_after;
+/// assert_eq!(parse_retry_after("120"), Some(120));
+/// assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(0));
+/// assert_eq!(parse_retry_after("86400"), Some(600));
+/// ```
+pub fn parse_retry_after(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let seconds = match value.parse::<u64>() {
+        Ok(seconds) => seconds,
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()?
+            .duration_since(std::time::SystemTime::now())
+            .map_or(0, |wait| wait.as_secs()),
+    };
+
+    Some(seconds.min(MAX_RETRY_AFTER))
+}
+
+/// How metadata requests are retried: transport failures and throttling or
+/// gateway answers (`429`, `502`, `503`, `504`) are tried again after a
+/// pause that doubles each time, unless the server names its own with
+/// `Retry-After`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub attempts: usize,
+    /// Seconds of the first pause
+    pub sleep: u64,
+}
+
+impl RetryPolicy {
+    /// Create a policy from `--max-attempts` and `--sleep`.
+    ///
+    /// # Arguments
+    /// * `attempts` - Retries after the first attempt.
+    /// * `sleep` - Seconds of the first pause.
+    ///
+    /// # Returns
+    /// A `RetryPolicy` instance.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::net::RetryPolicy;
+    /// let policy = RetryPolicy::new(3, 5);
+    /// ```
+    pub fn new(attempts: usize, sleep: usize) -> Self {
+        RetryPolicy {
+            attempts,
+            sleep: sleep as u64,
+        }
+    }
+
+    /// Check whether an answer is worth asking again for.
+    ///
+    /// # Arguments
+    /// * `status` - The status the server answered.
+    ///
+    /// # Returns
+    /// `true` for throttling and gateway errors.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use reqwest::StatusCode;
+    /// use rsfq::net::RetryPolicy;
+    /// assert!(RetryPolicy::retryable(StatusCode::TOO_MANY_REQUESTS));
+    /// assert!(!RetryPolicy::retryable(StatusCode::NOT_FOUND));
+    /// ```
+    pub fn retryable(status: StatusCode) -> bool {
+        matches!(
+            status,
+            StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+        )
+    }
+
+    /// Get the pause before a retry.
+    ///
+    /// # Arguments
+    /// * `retry` - The retry about to be made, from 1.
+    /// * `retry_after` - The seconds the server asked to wait, if any.
+    ///
+    /// # Returns
+    /// The server's wait, else the first pause doubled per earlier retry.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::net::RetryPolicy;
+    /// use std::time::Duration;
+    /// let policy = RetryPolicy::new(3, 5);
+    /// assert_eq!(policy.wait(3, None), Duration::from_secs(20));
+    /// assert_eq!(policy.wait(3, Some(7)), Duration::from_secs(7));
+    /// ```
+    pub fn wait(&self, retry: usize, retry_after: Option<u64>) -> Duration {
+        let backoff = self.sleep << retry.saturating_sub(1).min(MAX_BACKOFF_SHIFT);
+        Duration::from_secs(retry_after.unwrap_or(backoff))
+    }
+}
+
+/// Set the retry policy of requests made outside an accession's own
+/// attempts, e.g. while downloading. Only the first call has effect.
+///
+/// # Arguments
+/// * `attempts` - Retries after the first attempt, from `--max-attempts`.
+/// * `sleep` - Seconds of the first pause, from `--sleep`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::init_retry;
+/// init_retry(3, 5);
+/// ```
+pub fn init_retry(attempts: usize, sleep: usize) {
+    let _ = RETRY.set(RetryPolicy::new(attempts, sleep));
+}
+
+/// Get the retry policy set with `init_retry`, three quick retries if it
+/// was never called.
+///
+/// # Returns
+/// The `RetryPolicy`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::retry_policy;
+/// println!("{} retries", retry_policy().attempts);
+/// ```
+pub fn retry_policy() -> RetryPolicy {
+    RETRY
+        .get()
+        .copied()
+        .unwrap_or_else(|| RetryPolicy::new(3, 5))
+}
+
+/// Send a request under a retry policy.
+///
+/// Answers that are not retryable are returned whatever their status, for
+/// the caller to check; requests whose body cannot be replayed are sent
+/// once.
+///
+/// # Arguments
+/// * `request` - The request, built on the shared client.
+/// * `policy` - How failures are retried.
+///
+/// # Returns
+/// The response, or why the last attempt failed.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::net::{client, send_with_retry, RetryPolicy};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let request = client().get("https://www.ebi.ac.uk/ena/portal/api/results");
+///     let response = send_with_retry(request, RetryPolicy::new(3, 5)).await.unwrap();
+///     println!("{}", response.status());
+/// }
+/// ```
+pub async fn send_with_retry(
+    request: RequestBuilder,
+    policy: RetryPolicy,
+) -> Result<Response, String> {
+    let mut retry = 0;
+    loop {
+        let Some(attempt) = request.try_clone() else {
+            return request.send().await.map_err(|e| e.to_string());
+        };
+
+        let (reason, retry_after) = match attempt.send().await {
+            Ok(response) if RetryPolicy::retryable(response.status()) => {
+                let retry_after = response
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(parse_retry_after);
+                (format!("status {}", response.status()), retry_after)
+            }
+            Ok(response) => return Ok(response),
+            Err(e) if e.is_builder() || e.is_redirect() => return Err(e.to_string()),
+            Err(e) => (e.to_string(), None),
+        };
+
+        retry += 1;
+        if retry > policy.attempts {
+            return Err(format!("{} after {} attempts", reason, retry));
+        }
+
+        let wait = policy.wait(retry, retry_after);
+        log::warn!(
+            "WARNING: Request failed ({}), retrying in {}s [{}/{}]",
+            reason,
+            wait.as_secs(),
+            retry,
+            policy.attempts
+        );
+        tokio::time::sleep(wait).await;
+    }
+}
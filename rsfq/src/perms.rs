@@ -0,0 +1,270 @@
+use once_cell::sync::OnceCell;
+
+use std::path::Path;
+
+static FILE_MODE: OnceCell<Mode> = OnceCell::new();
+static DIR_MODE: OnceCell<Mode> = OnceCell::new();
+static GROUP: OnceCell<Group> = OnceCell::new();
+
+// INFO: files created in a setgid directory inherit its group
+const SETGID: u32 = 0o2000;
+
+/// Unix permission bits, written in octal as `0644` or `644`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode(pub u32);
+
+/// Parse a string into a Mode
+impl std::str::FromStr for Mode {
+    type Err = String;
+
+    /// Parse a string into a Mode
+    ///
+    /// # Arguments
+    /// * `s` - The string to parse, in octal, e.g. `0644`.
+    ///
+    /// # Returns
+    /// * `Result<Self, Self::Err>` - The parsed Mode.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::perms::Mode;
+    /// use std::str::FromStr;
+    /// assert_eq!(Mode::from_str("0644").unwrap().0, 0o644);
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.trim();
+        let digits = digits.strip_prefix("0o").unwrap_or(digits);
+
+        match u32::from_str_radix(digits, 8) {
+            Ok(mode) if mode <= 0o7777 => Ok(Mode(mode)),
+            _ => Err(format!("Invalid mode, expected octal like 0644: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for Mode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04o}", self.0)
+    }
+}
+
+/// A Unix group, given by name or numeric id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Group(pub u32);
+
+/// Parse a string into a Group
+impl std::str::FromStr for Group {
+    type Err = String;
+
+    /// Parse a string into a Group
+    ///
+    /// # Arguments
+    /// * `s` - The group name or id, e.g. `lab`.
+    ///
+    /// # Returns
+    /// * `Result<Self, Self::Err>` - The Group, holding its id.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::perms::Group;
+    /// use std::str::FromStr;
+    /// assert_eq!(Group::from_str("0").unwrap().0, 0);
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim();
+        if let Ok(gid) = name.parse::<u32>() {
+            return Ok(Group(gid));
+        }
+
+        group_id(name)
+            .map(Group)
+            .ok_or_else(|| format!("Unknown group: {}", s))
+    }
+}
+
+impl std::fmt::Display for Group {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Set the permissions and group given to created files and directories.
+/// Only the first call has effect.
+///
+/// # Arguments
+/// * `file` - The mode of created files, from `--chmod`.
+/// * `dir` - The mode of created directories, from `--dir-mode`.
+/// * `group` - The group owning created files and directories, from `--group`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::perms::{init_permissions, Mode};
+/// init_permissions(Some(Mode(0o644)), Some(Mode(0o755)), None);
+/// ```
+pub fn init_permissions(file: Option<Mode>, dir: Option<Mode>, group: Option<Group>) {
+    if let Some(mode) = file {
+        let _ = FILE_MODE.set(mode);
+    }
+    if let Some(mode) = dir {
+        let _ = DIR_MODE.set(mode);
+    }
+    if let Some(group) = group {
+        let _ = GROUP.set(group);
+    }
+}
+
+/// Apply the configured group and mode to a created file or directory.
+///
+/// Directories given to a group also get the setgid bit, so files other
+/// tools write into them keep the group. Failures are only warned about,
+/// the data itself is fine.
+///
+/// # Arguments
+/// * `path` - The file or directory.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::perms::settle;
+/// use std::path::Path;
+/// settle(Path::new("DOWNLOADS/SRR123456_1.fastq.gz"));
+/// ```
+pub fn settle(path: &Path) {
+    let is_dir = path.is_dir();
+    let mut mode = if is_dir {
+        DIR_MODE.get().copied()
+    } else {
+        FILE_MODE.get().copied()
+    };
+
+    // INFO: chown may clear the setgid bit, so the mode goes last
+    if let Some(group) = GROUP.get() {
+        if let Err(e) = set_group(path, *group) {
+            log::warn!(
+                "WARNING: Could not give {} to group {}!: {}",
+                path.display(),
+                group,
+                e
+            );
+        }
+
+        if is_dir {
+            let current = mode.or_else(|| current_mode(path)).unwrap_or(Mode(0o755));
+            mode = Some(Mode(current.0 | SETGID));
+        }
+    }
+
+    if let Some(mode) = mode {
+        if let Err(e) = set_mode(path, mode) {
+            log::warn!(
+                "WARNING: Could not set mode {} on {}!: {}",
+                mode,
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Give a completed staging file its final name and permissions.
+///
+/// # Arguments
+/// * `part` - The staging file.
+/// * `path` - The final path.
+///
+/// # Returns
+/// An error if the file could not be renamed.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::perms::install;
+/// use std::path::Path;
+/// install(
+///     Path::new("DOWNLOADS/SRR123456_1.fastq.gz.part"),
+///     Path::new("DOWNLOADS/SRR123456_1.fastq.gz"),
+/// ).unwrap();
+/// ```
+pub fn install(part: &Path, path: &Path) -> std::io::Result<()> {
+    std::fs::rename(part, path)?;
+    settle(path);
+    Ok(())
+}
+
+/// Create a directory and its missing parents, giving each one that did
+/// not exist the configured mode.
+///
+/// # Arguments
+/// * `dir` - The directory.
+///
+/// # Returns
+/// An error if the directory could not be created.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::perms::create_dirs;
+/// use std::path::Path;
+/// create_dirs(Path::new("DOWNLOADS/SRR123456")).unwrap();
+/// ```
+pub fn create_dirs(dir: &Path) -> std::io::Result<()> {
+    let missing = dir
+        .ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+        .map(Path::to_path_buf)
+        .collect::<Vec<_>>();
+
+    std::fs::create_dir_all(dir)?;
+    for created in missing.iter().rev() {
+        settle(created);
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: Mode) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode.0))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: Mode) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn current_mode(path: &Path) -> Option<Mode> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(path).ok()?.permissions().mode();
+    Some(Mode(mode & 0o7777))
+}
+
+#[cfg(not(unix))]
+fn current_mode(_path: &Path) -> Option<Mode> {
+    None
+}
+
+#[cfg(unix)]
+fn set_group(path: &Path, group: Group) -> std::io::Result<()> {
+    std::os::unix::fs::chown(path, None, Some(group.0))
+}
+
+#[cfg(not(unix))]
+fn set_group(_path: &Path, _group: Group) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Look up the id of a group by name.
+#[cfg(unix)]
+fn group_id(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    // SAFETY: getgrnam reads a NUL-terminated name; the returned entry is
+    // only read before any other group lookup
+    unsafe {
+        let entry = libc::getgrnam(name.as_ptr());
+        (!entry.is_null()).then(|| (*entry).gr_gid)
+    }
+}
+
+#[cfg(not(unix))]
+fn group_id(_name: &str) -> Option<u32> {
+    None
+}
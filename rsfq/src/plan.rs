@@ -0,0 +1,350 @@
+use serde_json::{json, Value};
+
+use std::{collections::HashMap, path::Path, time::SystemTime};
+
+use crate::{
+    claims::Claim,
+    cli::Args,
+    hints::{no_fastq_hint, suggest},
+    perms::settle,
+    provs::Provider,
+    resolve::hosted_by,
+    submitted::is_pacbio,
+    utils::{fits_on_disk, human_bytes, renamed, run_dir, Layout},
+};
+
+const FASTQ_FTP: &str = "fastq_ftp";
+const FASTQ_MD5: &str = "fastq_md5";
+const FASTQ_BYTES: &str = "fastq_bytes";
+const RUN_ACCESSION: &str = "run_accession";
+const PLAN_VERSION: u64 = 1;
+
+/// An accession whose metadata is resolved: the runs left after filters
+/// and deduplication, ready to be downloaded
+pub struct Planned {
+    pub accession: String,
+    /// Every run left after filters, as written to `--plan-out`
+    pub resolved: Vec<HashMap<String, String>>,
+    /// The runs to download, without those listed by another accession
+    pub runs: Vec<HashMap<String, String>>,
+    /// Held until the accession is done, so no other machine takes it
+    pub claim: Claim,
+}
+
+/// Every accession of a batch, resolved before any transfer starts
+#[derive(Default)]
+pub struct Plan {
+    pub accessions: Vec<Planned>,
+}
+
+impl Plan {
+    /// Get every run of the plan.
+    ///
+    /// # Returns
+    /// The runs, accession by accession.
+    pub fn runs(&self) -> impl Iterator<Item = &HashMap<String, String>> {
+        self.accessions
+            .iter()
+            .flat_map(|planned| planned.runs.iter())
+    }
+
+    /// Get the number of files of the plan.
+    ///
+    /// # Returns
+    /// The number of files listed by the runs.
+    pub fn files(&self) -> usize {
+        self.runs().map(|run| run_files(run).len()).sum()
+    }
+
+    /// Get the size of the files of the plan, as far as ENA lists it.
+    ///
+    /// # Returns
+    /// The size in bytes; files of unknown size count as 0.
+    pub fn bytes(&self) -> u64 {
+        self.runs()
+            .flat_map(run_files)
+            .filter_map(|(_, bytes)| bytes)
+            .sum()
+    }
+
+    /// Get the size of the files of the plan not in the output directory yet.
+    ///
+    /// # Arguments
+    /// * `outdir` - The output directory.
+    ///
+    /// # Returns
+    /// The size in bytes still to transfer.
+    pub fn pending_bytes(&self, outdir: &Path) -> u64 {
+        self.runs()
+            .flat_map(|run| {
+                let dir = run_dir(outdir, run_accession(run));
+                run_files(run)
+                    .into_iter()
+                    .filter(move |(name, _)| !dir.join(renamed(name)).exists())
+            })
+            .filter_map(|(_, bytes)| bytes)
+            .sum()
+    }
+
+    /// Check the plan before anything is downloaded: every run must list
+    /// the files and checksums its download needs, in the number `--layout`
+    /// asks for, and the files still missing must fit on disk. Problems are
+    /// all logged, then the batch stops.
+    ///
+    /// # Arguments
+    /// * `args` - Command line arguments driving the download.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use clap::Parser;
+    /// use rsfq::cli::Args;
+    /// use rsfq::plan::Plan;
+    ///
+    /// let args = Args::parse_from(["rsfq", "-a", "SRR123456"]);
+    /// Plan::default().validate(&args);
+    /// ```
+    pub fn validate(&self, args: &Args) {
+        let mut problems = 0;
+
+        // INFO: SRA and submitted BAMs do not go through fastq_ftp
+        for run in self
+            .runs()
+            .filter(|run| !hosted_by(run, &args.provider).dumps_sra())
+        {
+            if args.pacbio_bam && is_pacbio(run) {
+                continue;
+            }
+            let accession = run_accession(run);
+            let files = run_files(run);
+            let md5s = run
+                .get(FASTQ_MD5)
+                .map(|md5| md5.split(';').filter(|md5| !md5.is_empty()).count())
+                .unwrap_or(0);
+
+            let problem = if files.is_empty() {
+                Some("lists no FASTQ files".to_string())
+            } else if md5s < files.len() {
+                Some(format!(
+                    "lists {} checksums for {} files",
+                    md5s,
+                    files.len()
+                ))
+            } else {
+                match args.layout {
+                    Layout::Single if files.len() != 1 => Some(format!(
+                        "has {} files but --layout single expects 1",
+                        files.len()
+                    )),
+                    Layout::Paired if files.len() != 2 => Some(format!(
+                        "has {} files but --layout paired expects 2",
+                        files.len()
+                    )),
+                    _ => None,
+                }
+            };
+            if let Some(problem) = problem {
+                log::error!("ERROR: {} {}!", accession, problem);
+                if let Some(hint) = no_fastq_hint(run, &args.provider) {
+                    suggest(accession, &hint);
+                }
+                problems += 1;
+            }
+        }
+
+        let outdir = args
+            .outdir
+            .as_deref()
+            .unwrap_or_else(|| Path::new("DOWNLOADS"));
+        if fits_on_disk(outdir, Some(self.pending_bytes(outdir))).is_none() {
+            problems += 1;
+        }
+
+        if problems > 0 {
+            log::error!(
+                "ERROR: The plan has {} problems, nothing was downloaded",
+                problems
+            );
+            std::process::exit(1);
+        }
+    }
+
+    /// Log what the batch is about to download.
+    ///
+    /// # Arguments
+    /// * `provider` - Where the runs are downloaded from.
+    pub fn summarize(&self, provider: &Provider) {
+        log::info!(
+            "Plan: {} accessions, {} runs, {} files ({}) from {}",
+            self.accessions.len(),
+            self.runs().count(),
+            self.files(),
+            human_bytes(self.bytes()),
+            provider
+        );
+    }
+
+    /// Write the plan to a JSON file that `--plan-in` downloads verbatim:
+    /// every accession with the metadata of its runs.
+    ///
+    /// # Arguments
+    /// * `path` - The plan file, from `--plan-out`.
+    /// * `provider` - Where the runs are downloaded from.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::plan::Plan;
+    /// use rsfq::provs::Provider;
+    /// use std::path::Path;
+    /// Plan::default().write(Path::new("plan.json"), &Provider::ENA);
+    /// ```
+    pub fn write(&self, path: &Path, provider: &Provider) {
+        let plan = json!({
+            "version": PLAN_VERSION,
+            "created": humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            "provider": provider.to_string(),
+            "accessions": self
+                .accessions
+                .iter()
+                .map(|planned| json!({
+                    "accession": planned.accession,
+                    "runs": planned.resolved,
+                }))
+                .collect::<Vec<Value>>(),
+        });
+
+        match std::fs::write(path, format!("{:#}\n", plan)) {
+            Ok(()) => {
+                settle(path);
+                log::info!("Plan written to {}", path.display());
+            }
+            Err(e) => {
+                log::error!("ERROR: Could not write plan {}!: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Read a plan written by `--plan-out`.
+///
+/// # Arguments
+/// * `path` - The plan file, from `--plan-in`.
+/// * `provider` - Where the runs are downloaded from; a plan resolved for
+///   another provider is warned about.
+///
+/// # Returns
+/// Every accession of the plan with the metadata of its runs, in order.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::plan::read_plan;
+/// use rsfq::provs::Provider;
+/// use std::path::Path;
+/// let accessions = read_plan(Path::new("plan.json"), &Provider::ENA);
+/// ```
+pub fn read_plan(path: &Path, provider: &Provider) -> Vec<(String, Vec<HashMap<String, String>>)> {
+    let plan = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|content| serde_json::from_str::<Value>(&content).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            log::error!("ERROR: Could not read plan {}!: {}", path.display(), e);
+            std::process::exit(1);
+        });
+
+    if plan["version"].as_u64() != Some(PLAN_VERSION) {
+        log::error!(
+            "ERROR: {} is not a plan written by --plan-out (version {})!",
+            path.display(),
+            PLAN_VERSION
+        );
+        std::process::exit(1);
+    }
+    if let Some(planned) = plan["provider"]
+        .as_str()
+        .filter(|p| *p != provider.to_string())
+    {
+        log::warn!(
+            "WARNING: {} was resolved for {}, downloading it from {}",
+            path.display(),
+            planned,
+            provider
+        );
+    }
+
+    let accessions = plan["accessions"]
+        .as_array()
+        .map(|accessions| {
+            accessions
+                .iter()
+                .filter_map(|planned| {
+                    let accession = planned["accession"].as_str()?.to_string();
+                    let runs = serde_json::from_value(planned["runs"].clone()).ok()?;
+                    Some((accession, runs))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if accessions.len() != plan["accessions"].as_array().map_or(0, Vec::len) {
+        log::error!("ERROR: {} has malformed accessions!", path.display());
+        std::process::exit(1);
+    }
+
+    log::info!(
+        "Loaded a plan of {} accessions from {}, resolved {}",
+        accessions.len(),
+        path.display(),
+        plan["created"].as_str().unwrap_or("-")
+    );
+    accessions
+}
+
+/// Get the run accession of a run, `-` if missing.
+fn run_accession(run: &HashMap<String, String>) -> &str {
+    run.get(RUN_ACCESSION).map(String::as_str).unwrap_or("-")
+}
+
+/// Get the file names of a run with their size, as listed by ENA.
+///
+/// # Arguments
+/// * `run` - The run metadata.
+///
+/// # Returns
+/// The name and size, if listed, of every file in `fastq_ftp`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::plan::run_files;
+/// use std::collections::HashMap;
+///
+/// let run = HashMap::from([
+///     ("fastq_ftp".to_string(), "ftp.sra.ebi.ac.uk/vol1/fastq/SRR123456.fastq.gz".to_string()),
+///     ("fastq_bytes".to_string(), "1000".to_string()),
+/// ]);
+/// assert_eq!(run_files(&run), vec![("SRR123456.fastq.gz".to_string(), Some(1000))]);
+/// ```
+pub fn run_files(run: &HashMap<String, String>) -> Vec<(String, Option<u64>)> {
+    let mut bytes = run
+        .get(FASTQ_BYTES)
+        .map(|bytes| {
+            bytes
+                .split(';')
+                .map(|b| b.parse::<u64>().ok())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default()
+        .into_iter();
+
+    run.get(FASTQ_FTP)
+        .map(String::as_str)
+        .unwrap_or_default()
+        .split(';')
+        .filter(|ftp| !ftp.is_empty())
+        .map(|ftp| {
+            let name = Path::new(ftp)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            (name, bytes.next().flatten())
+        })
+        .collect()
+}
@@ -0,0 +1,399 @@
+use once_cell::sync::OnceCell;
+use serde_json::{json, Map, Value};
+use tokio::process::Command;
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    net::{credentials_for, headers_for, proxy_env},
+    runner::runner,
+};
+
+pub const PLUGIN_PREFIX: &str = "plugin:";
+const PROTOCOL_VERSION: u64 = 1;
+const PLUGINS_DIR: &str = ".rsfq/plugins";
+const EXECUTABLE_PREFIX: &str = "rsfq-";
+
+static PLUGINS: OnceCell<PathBuf> = OnceCell::new();
+static PROVIDER: OnceCell<PathBuf> = OnceCell::new();
+
+/// The kinds of plugin rsfq can run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginKind {
+    Provider,
+    Retriever,
+}
+
+impl std::fmt::Display for PluginKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PluginKind::Provider => write!(f, "provider"),
+            PluginKind::Retriever => write!(f, "retriever"),
+        }
+    }
+}
+
+/// A plugin executable found in the plugins directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plugin {
+    pub kind: PluginKind,
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// Get the default plugins directory, `~/.rsfq/plugins`.
+///
+/// # Returns
+/// The plugins directory.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::plugins::default_plugins_dir;
+/// println!("{}", default_plugins_dir().display());
+/// ```
+pub fn default_plugins_dir() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(PLUGINS_DIR)
+}
+
+/// Set the plugins directory and resolve the plugins chosen with
+/// `-P plugin:<name>` and `-T plugin:<name>`, exiting if one is missing.
+/// Only the first call has effect.
+///
+/// # Arguments
+/// * `dir` - The plugins directory, `~/.rsfq/plugins` if `None`.
+/// * `provider` - The name of the provider plugin, if any.
+/// * `retriever` - The name of the retriever plugin, if any.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::plugins::init_plugins;
+/// init_plugins(None, Some("archive"), None);
+/// ```
+pub fn init_plugins(dir: Option<PathBuf>, provider: Option<&str>, retriever: Option<&str>) {
+    let _ = PLUGINS.set(dir.unwrap_or_else(default_plugins_dir));
+
+    if let Some(name) = provider {
+        let _ = PROVIDER.set(plugin_path(PluginKind::Provider, name));
+    }
+    if let Some(name) = retriever {
+        plugin_path(PluginKind::Retriever, name);
+    }
+}
+
+/// Find the executable of a plugin, exiting if it is not installed.
+///
+/// A plugin named `<name>` of kind `<kind>` is an executable called
+/// `rsfq-<kind>-<name>` inside the plugins directory.
+///
+/// # Arguments
+/// * `kind` - The kind of plugin.
+/// * `name` - The plugin name.
+///
+/// # Returns
+/// The path to the plugin executable.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::plugins::{plugin_path, PluginKind};
+/// let path = plugin_path(PluginKind::Retriever, "irods");
+/// ```
+pub fn plugin_path(kind: PluginKind, name: &str) -> PathBuf {
+    let path = plugins_dir().join(format!("{}{}-{}", EXECUTABLE_PREFIX, kind, name));
+
+    if !is_executable(&path) {
+        log::error!(
+            "ERROR: No {} plugin named {} found at {}!",
+            kind,
+            name,
+            path.display()
+        );
+        std::process::exit(1);
+    }
+
+    path
+}
+
+/// List the plugins installed in the plugins directory.
+///
+/// # Returns
+/// The plugins, sorted by kind and name.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::plugins::discover;
+/// for plugin in discover() {
+///     println!("{}\t{}\t{}", plugin.kind, plugin.name, plugin.path.display());
+/// }
+/// ```
+pub fn discover() -> Vec<Plugin> {
+    let Ok(entries) = std::fs::read_dir(plugins_dir()) else {
+        return vec![];
+    };
+
+    let mut plugins = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| is_executable(path))
+        .filter_map(|path| {
+            let file = path.file_name()?.to_str()?;
+            let rest = file.strip_prefix(EXECUTABLE_PREFIX)?;
+            let (kind, name) = rest.split_once('-')?;
+            let kind = match kind {
+                "provider" => PluginKind::Provider,
+                "retriever" => PluginKind::Retriever,
+                _ => return None,
+            };
+
+            Some(Plugin {
+                kind,
+                name: name.to_string(),
+                path: path.clone(),
+            })
+        })
+        .collect::<Vec<Plugin>>();
+
+    plugins.sort_by_key(|plugin| (plugin.kind.to_string(), plugin.name.clone()));
+    plugins
+}
+
+/// Whether run metadata comes from a provider plugin instead of ENA.
+///
+/// # Returns
+/// The provider plugin executable, if any.
+pub fn provider_plugin() -> Option<&'static PathBuf> {
+    PROVIDER.get()
+}
+
+/// Run one request/response exchange with a plugin.
+///
+/// The request is written to the plugin's stdin as a single JSON object and
+/// stdin is closed; the plugin answers with a single JSON object on stdout
+/// and exits 0. An answer with an `"error"` string, a non-zero exit or an
+/// unparseable answer is an error. Whatever the plugin writes to stderr is
+/// passed through to the log.
+///
+/// # Arguments
+/// * `plugin` - The plugin executable.
+/// * `request` - The request; `protocol` is added if missing.
+///
+/// # Returns
+/// The plugin's answer, or an error message.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::plugins::exchange;
+/// use serde_json::json;
+/// use std::path::Path;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let plugin = Path::new("/home/user/.rsfq/plugins/rsfq-provider-archive");
+///     let answer = exchange(plugin, json!({"action": "runs", "query": "PRJ1"})).await;
+/// }
+/// ```
+pub async fn exchange(plugin: &Path, mut request: Value) -> Result<Value, String> {
+    if let Some(object) = request.as_object_mut() {
+        object
+            .entry("protocol")
+            .or_insert_with(|| json!(PROTOCOL_VERSION));
+    }
+
+    let mut cmd = Command::new(plugin);
+    cmd.envs(proxy_env());
+    let output = runner()
+        .run_with_input(cmd, request.to_string().into_bytes())
+        .await
+        .map_err(|e| format!("could not run {}: {}", plugin.display(), e))?;
+
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        log::info!("[{}] {}", plugin.display(), line);
+    }
+
+    let answer = serde_json::from_slice::<Value>(&output.stdout).map_err(|e| {
+        format!(
+            "{} exited with {} and an invalid answer: {}",
+            plugin.display(),
+            exit_status(output.code),
+            e
+        )
+    })?;
+
+    if let Some(error) = answer.get("error").and_then(Value::as_str) {
+        return Err(error.to_string());
+    }
+    if !output.success() {
+        return Err(format!(
+            "{} exited with {}",
+            plugin.display(),
+            exit_status(output.code)
+        ));
+    }
+
+    Ok(answer)
+}
+
+/// Describe how a program exited, like `ExitStatus` does.
+fn exit_status(code: Option<i32>) -> String {
+    match code {
+        Some(code) => format!("exit status: {}", code),
+        None => "a signal".to_string(),
+    }
+}
+
+/// Get run metadata from a provider plugin.
+///
+/// The plugin gets `{"protocol": 1, "action": "runs", "query": <ENA query>}`
+/// and answers `{"runs": [{<field>: <value>, ...}, ...]}` using ENA field
+/// names, so `fastq_ftp`/`fastq_md5` drive the download as usual. Non-string
+/// values are kept as their JSON text.
+///
+/// # Arguments
+/// * `plugin` - The provider plugin executable.
+/// * `query` - The ENA query.
+/// * `max_attempts` - The maximum number of attempts.
+/// * `sleep` - The number of seconds to sleep between attempts.
+///
+/// # Returns
+/// The runs found; exits if there are none.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::plugins::plugin_runs;
+/// use std::path::Path;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let plugin = Path::new("/home/user/.rsfq/plugins/rsfq-provider-archive");
+///     let runs = plugin_runs(plugin, "run_accession=SRR123456", 3, 5).await;
+/// }
+/// ```
+pub async fn plugin_runs(
+    plugin: &Path,
+    query: &str,
+    max_attempts: usize,
+    sleep: usize,
+) -> Vec<HashMap<String, String>> {
+    let request = json!({"action": "runs", "query": query});
+
+    let mut attempts = 0;
+    while max_attempts >= attempts {
+        match exchange(plugin, request.clone()).await {
+            Ok(answer) => {
+                let runs = answer
+                    .get("runs")
+                    .and_then(Value::as_array)
+                    .map(|runs| {
+                        runs.iter()
+                            .filter_map(Value::as_object)
+                            .map(as_fields)
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+
+                if runs.is_empty() {
+                    log::error!("ERROR: No data found by {} for {}", plugin.display(), query);
+                    std::process::exit(1);
+                }
+
+                log::info!("Total runs found: {}", runs.len());
+                return runs;
+            }
+            Err(e) => {
+                attempts += 1;
+                log::error!(
+                    "ERROR: Provider plugin failed: {}. Attempts til now {} for query {}",
+                    e,
+                    attempts,
+                    query
+                );
+                tokio::time::sleep(tokio::time::Duration::from_secs(sleep as u64)).await;
+            }
+        }
+    }
+
+    log::error!(
+        "ERROR: No data found after {} attempts for {}",
+        max_attempts,
+        query
+    );
+    std::process::exit(1);
+}
+
+/// Build the request a retriever plugin gets for one file.
+///
+/// The plugin gets `{"protocol": 1, "action": "fetch", "url": ..., "output":
+/// ..., "headers": [...], "credentials": {"user": ..., "password": ...}}`,
+/// must write the file to `output` and answer `{}` (or `{"error": ...}`).
+/// Checksums are verified by rsfq afterwards.
+///
+/// # Arguments
+/// * `url` - The file URL, as listed in `fastq_ftp`.
+/// * `output` - The path the file must be written to.
+///
+/// # Returns
+/// The request.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::plugins::fetch_request;
+/// use std::path::Path;
+/// let request = fetch_request("ftp.sra.ebi.ac.uk/vol1/SRR123456.fastq.gz", Path::new("SRR123456.fastq.gz"));
+/// ```
+pub fn fetch_request(url: &str, output: &Path) -> Value {
+    let credentials = credentials_for(url)
+        .map(|creds| json!({"user": creds.user, "password": creds.password}))
+        .unwrap_or(Value::Null);
+
+    json!({
+        "action": "fetch",
+        "url": url,
+        "output": output,
+        "headers": headers_for(url)
+            .iter()
+            .map(|header| format!("{}: {}", header.name, header.value))
+            .collect::<Vec<String>>(),
+        "credentials": credentials,
+    })
+}
+
+/// Get the plugins directory set with `init_plugins`.
+fn plugins_dir() -> PathBuf {
+    PLUGINS.get().cloned().unwrap_or_else(default_plugins_dir)
+}
+
+/// Turn a JSON run into ENA-style string fields.
+fn as_fields(run: &Map<String, Value>) -> HashMap<String, String> {
+    run.iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(field, value)| {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            (field.clone(), value)
+        })
+        .collect()
+}
+
+/// Whether a path is a file the current user may execute.
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = path.metadata() else {
+        return false;
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
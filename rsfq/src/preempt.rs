@@ -0,0 +1,178 @@
+use crate::net::forget_secrets;
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Exit code used when a SIGTERM (e.g. SLURM preemption) stopped the batch,
+/// the conventional `128 + SIGTERM`
+pub const EXIT_PREEMPTED: i32 = 143;
+
+// INFO: child processes and files being written, cleaned up on SIGTERM
+static CHILDREN: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+static IN_FLIGHT: Mutex<Option<HashSet<PathBuf>>> = Mutex::new(None);
+
+/// A child process or file registered for cleanup on SIGTERM, unregistered
+/// when dropped
+pub struct Tracked {
+    pid: Option<u32>,
+    file: Option<PathBuf>,
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        if let Some(pid) = self.pid {
+            let mut children = CHILDREN.lock().unwrap_or_else(|e| e.into_inner());
+            children.retain(|child| *child != pid);
+        }
+        if let Some(file) = &self.file {
+            if let Some(files) = IN_FLIGHT.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+                files.remove(file);
+            }
+        }
+    }
+}
+
+/// Register a child process, so it is terminated if rsfq gets SIGTERM.
+///
+/// # Arguments
+/// * `pid` - The child's process id, as given by `Child::id`.
+///
+/// # Returns
+/// A guard that unregisters the child when dropped.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::preempt::track_child;
+/// use tokio::process::Command;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut child = Command::new("sleep").arg("60").spawn().unwrap();
+///     let _tracked = track_child(child.id());
+///     child.wait().await.unwrap();
+/// }
+/// ```
+pub fn track_child(pid: Option<u32>) -> Tracked {
+    if let Some(pid) = pid {
+        CHILDREN.lock().unwrap_or_else(|e| e.into_inner()).push(pid);
+    }
+
+    Tracked { pid, file: None }
+}
+
+/// Register a file that is being written, so it is removed if rsfq gets
+/// SIGTERM before it is complete and verified.
+///
+/// Only for files that cannot be resumed, such as pigz or fasterq-dump
+/// outputs; `.part` downloads are left for the next run to continue.
+///
+/// # Arguments
+/// * `file` - The file being written.
+///
+/// # Returns
+/// A guard that unregisters the file when dropped.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::preempt::track_file;
+/// use std::path::Path;
+/// let _tracked = track_file(Path::new("DOWNLOADS/SRR123456_1.fastq"));
+/// ```
+pub fn track_file(file: &Path) -> Tracked {
+    IN_FLIGHT
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(HashSet::new)
+        .insert(file.to_path_buf());
+
+    Tracked {
+        pid: None,
+        file: Some(file.to_path_buf()),
+    }
+}
+
+/// Handle SIGTERM so a preempted job can be requeued and resume the batch.
+///
+/// On SIGTERM, running retrievers and converters are terminated, files that
+/// were still being written and cannot be resumed are removed (completed
+/// files are already in the state file) and rsfq exits with
+/// `EXIT_PREEMPTED`. Rerunning the same command then skips what was
+/// verified, resumes the `.part` downloads and fetches the rest.
+///
+/// Must be called from within the tokio runtime.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::preempt::init_preemption;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     init_preemption();
+/// }
+/// ```
+#[cfg(unix)]
+pub fn init_preemption() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            log::warn!("WARNING: Could not install a SIGTERM handler!: {}", e);
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        terminate.recv().await;
+        checkpoint();
+        std::process::exit(EXIT_PREEMPTED);
+    });
+}
+
+/// SIGTERM is not available here, so there is nothing to handle.
+#[cfg(not(unix))]
+pub fn init_preemption() {}
+
+/// Terminate the tracked children and remove the tracked files.
+#[cfg(unix)]
+fn checkpoint() {
+    match std::env::var("SLURM_JOB_ID") {
+        Ok(job) => log::warn!(
+            "WARNING: SIGTERM received in SLURM job {}, checkpointing before preemption...",
+            job
+        ),
+        Err(_) => log::warn!("WARNING: SIGTERM received, checkpointing..."),
+    }
+
+    for pid in CHILDREN.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+        // SAFETY: kill only sends a signal; a stale pid at worst fails with ESRCH
+        unsafe {
+            libc::kill(*pid as libc::pid_t, libc::SIGTERM);
+        }
+    }
+
+    let files = IN_FLIGHT
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+        .unwrap_or_default();
+    for file in files.iter().filter(|file| file.exists()) {
+        match std::fs::remove_file(file) {
+            Ok(()) => log::info!("Removed partial file {}", file.display()),
+            Err(e) => log::warn!(
+                "WARNING: Could not remove partial file {}!: {}",
+                file.display(),
+                e
+            ),
+        }
+    }
+
+    forget_secrets();
+    log::warn!(
+        "WARNING: Exiting with code {}; rerun the same command to resume",
+        EXIT_PREEMPTED
+    );
+}
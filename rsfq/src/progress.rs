@@ -0,0 +1,387 @@
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+use tokio::task::JoinHandle;
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::utils::{human_bytes, Retriever, PART_SUFFIX};
+
+pub const PROGRESS_INTERVAL: u64 = 30; // 30 seconds
+const STALL_POLL: Duration = Duration::from_secs(5);
+
+static INTERVAL: OnceCell<u64> = OnceCell::new();
+static STALL_TIMEOUT: OnceCell<Option<Duration>> = OnceCell::new();
+
+// INFO: [#2089b0 400KiB/33MiB(1%) CN:1 DL:115KiB ETA:4m51s]
+static ARIA2C_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\[#\w+ ([0-9.]+[KMGT]?i?B)/([0-9.]+[KMGT]?i?B)")
+        .unwrap_or_else(|e| panic!("Failed to compile ARIA2C_RE regex: {}", e))
+});
+// INFO: Length: 2403 (2.3K) [application/gzip], then dot lines ending in a percentage
+static WGET_LENGTH_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^Length: ([0-9]+)")
+        .unwrap_or_else(|e| panic!("Failed to compile WGET_LENGTH_RE regex: {}", e))
+});
+static WGET_DOTS_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\s*[0-9]+[KMG]\s[. ]+\s([0-9]{1,3})%")
+        .unwrap_or_else(|e| panic!("Failed to compile WGET_DOTS_RE regex: {}", e))
+});
+// INFO: % Total % Received % Xferd ..., e.g. " 42 1024M 42  430M 0 0 ..."
+static CURL_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\s*[0-9]{1,3}\s+([0-9.]+[kMGT]?)\s+[0-9]{1,3}\s+([0-9.]+[kMGT]?)\s")
+        .unwrap_or_else(|e| panic!("Failed to compile CURL_RE regex: {}", e))
+});
+// INFO: curl counts a resumed transfer from where it picks up
+static CURL_RESUME_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"Resuming transfer from byte position ([0-9]+)")
+        .unwrap_or_else(|e| panic!("Failed to compile CURL_RESUME_RE regex: {}", e))
+});
+
+// INFO: planned runs, files and bytes of the batch, then those done so far
+static BATCH: OnceCell<(usize, usize, u64)> = OnceCell::new();
+static DONE: Mutex<(usize, usize, u64)> = Mutex::new((0, 0, 0));
+
+/// Set how often transfers log their progress. Only the first call has effect.
+///
+/// # Arguments
+/// * `secs` - Seconds between progress lines, from `--progress-interval`;
+///   `0` turns them off.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::progress::init_progress;
+/// init_progress(60);
+/// ```
+pub fn init_progress(secs: u64) {
+    let _ = INTERVAL.set(secs);
+}
+
+/// Get the seconds between progress lines.
+fn interval() -> u64 {
+    *INTERVAL.get().unwrap_or(&PROGRESS_INTERVAL)
+}
+
+/// Bytes done and expected, as a retriever reports them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Reported {
+    done: Option<u64>,
+    total: Option<u64>,
+    offset: u64,
+}
+
+/// A running progress logger; stops when dropped
+pub struct Progress {
+    handle: JoinHandle<()>,
+    reported: Arc<Mutex<Reported>>,
+}
+
+impl Progress {
+    /// Take the progress a retriever prints over the size of the file it
+    /// writes, which says nothing for aria2c since it allocates the whole
+    /// file upfront.
+    ///
+    /// Understands the aria2c readout, wget's dot progress and curl's
+    /// progress meter; other lines are ignored.
+    ///
+    /// # Arguments
+    /// * `retriever` - The retriever that printed the line.
+    /// * `line` - A line of its output.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::progress::watch_transfer;
+    /// use rsfq::utils::Retriever;
+    /// use std::path::Path;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let progress = watch_transfer(Path::new("SRR123456_1.fastq.gz.part"), None).unwrap();
+    ///     progress.observe(&Retriever::Aria2c, "[#2089b0 400KiB/33MiB(1%) CN:1 DL:115KiB ETA:4m51s]");
+    /// }
+    /// ```
+    pub fn observe(&self, retriever: &Retriever, line: &str) {
+        let mut reported = self.reported.lock().unwrap_or_else(|e| e.into_inner());
+        match retriever {
+            Retriever::Aria2c => {
+                if let Some(caps) = ARIA2C_RE.captures(line) {
+                    reported.done = parse_size(&caps[1]);
+                    reported.total = parse_size(&caps[2]).filter(|&total| total > 0);
+                }
+            }
+            Retriever::Wget => {
+                if let Some(caps) = WGET_LENGTH_RE.captures(line) {
+                    reported.total = caps[1].parse().ok();
+                } else if let (Some(caps), Some(total)) =
+                    (WGET_DOTS_RE.captures(line), reported.total)
+                {
+                    let percent = caps[1].parse::<u64>().unwrap_or(0).min(100);
+                    reported.done = Some(total * percent / 100);
+                }
+            }
+            Retriever::Curl => {
+                if let Some(caps) = CURL_RESUME_RE.captures(line) {
+                    reported.offset = caps[1].parse().unwrap_or(0);
+                } else if let Some(caps) = CURL_RE.captures(line) {
+                    let offset = reported.offset;
+                    reported.total = parse_size(&caps[1])
+                        .filter(|&total| total > 0)
+                        .map(|total| total + offset);
+                    reported.done = parse_size(&caps[2]).map(|done| done + offset);
+                }
+            }
+            Retriever::Native | Retriever::Plugin(_) => {}
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Parse a size as retrievers print it: `2403`, `1.5M`, `400KiB` or `33MiB`.
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim_end_matches('B').trim_end_matches('i');
+    let (number, unit) = match size.char_indices().last()? {
+        (i, c) if c.is_ascii_alphabetic() => (&size[..i], c.to_ascii_uppercase()),
+        _ => (size, ' '),
+    };
+    let scale: u64 = match unit {
+        ' ' => 1,
+        'K' => 1 << 10,
+        'M' => 1 << 20,
+        'G' => 1 << 30,
+        'T' => 1 << 40,
+        _ => return None,
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .map(|number| (number * scale as f64) as u64)
+}
+
+/// Log the progress of a transfer every `--progress-interval` seconds until
+/// the returned guard is dropped: bytes so far, the rate over the last
+/// interval and, when the size is known, the share done and an ETA from the
+/// average rate.
+///
+/// Progress is what the retriever reports through `Progress::observe` or,
+/// until it reports any, the size of the file being written, so it reads
+/// the same for every retriever. Transfers done within one interval log
+/// nothing.
+///
+/// # Arguments
+/// * `part` - The file the retriever writes to.
+/// * `bytes` - The expected size of the file, if known.
+///
+/// # Returns
+/// The guard, or `None` if progress lines are off.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::progress::watch_transfer;
+/// use std::path::Path;
+/// let _progress = watch_transfer(
+///     Path::new("DOWNLOADS/SRR123456_1.fastq.gz.part"),
+///     Some(1_000_000_000),
+/// );
+/// ```
+pub fn watch_transfer(part: &Path, bytes: Option<u64>) -> Option<Progress> {
+    let secs = interval();
+    if secs == 0 {
+        return None;
+    }
+
+    let part = PathBuf::from(part);
+    let reported = Arc::new(Mutex::new(Reported::default()));
+    let observed = reported.clone();
+    let handle = tokio::spawn(async move {
+        let observed = move || *observed.lock().unwrap_or_else(|e| e.into_inner());
+        let done_of = |part: &Path| observed().done.unwrap_or_else(|| size_of(part));
+        let name = part
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let name = name.strip_suffix(PART_SUFFIX).unwrap_or(&name).to_string();
+        let start = Instant::now();
+        let mut ticker = tokio::time::interval(Duration::from_secs(secs));
+        // INFO: the first tick fires right away
+        ticker.tick().await;
+
+        let mut last = (start, done_of(&part));
+        loop {
+            ticker.tick().await;
+            let now = Instant::now();
+            let done = done_of(&part);
+            let rate = rate(done.saturating_sub(last.1), now - last.0);
+            last = (now, done);
+
+            let total = bytes.or(observed().total).filter(|&total| total > 0);
+            match total {
+                Some(total) => {
+                    let average = rate_of(done, now - start);
+                    let eta = match average {
+                        Some(average) if total > done => humantime::format_duration(
+                            Duration::from_secs(((total - done) as f64 / average) as u64),
+                        )
+                        .to_string(),
+                        _ => "-".to_string(),
+                    };
+                    log::info!(
+                        "{}: {} of {} ({:.1}%) at {}/s, ETA {}",
+                        name,
+                        human_bytes(done),
+                        human_bytes(total),
+                        done as f64 * 100.0 / total as f64,
+                        human_bytes(rate as u64),
+                        eta
+                    );
+                }
+                None => log::info!(
+                    "{}: {} at {}/s",
+                    name,
+                    human_bytes(done),
+                    human_bytes(rate as u64)
+                ),
+            }
+        }
+    });
+
+    Some(Progress { handle, reported })
+}
+
+/// Set how long a transfer may go without progress before it is stopped.
+/// Only the first call has effect.
+///
+/// # Arguments
+/// * `minutes` - Minutes without progress, from `--timeout`; `None` waits
+///   forever.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::progress::init_stall_timeout;
+/// init_stall_timeout(Some(10));
+/// ```
+pub fn init_stall_timeout(minutes: Option<u64>) {
+    let _ = STALL_TIMEOUT.set(minutes.map(|minutes| Duration::from_secs(minutes * 60)));
+}
+
+/// Wait until a transfer stalls: the file it writes to neither grows nor
+/// is modified for `--timeout` minutes.
+///
+/// Modification times count as progress, since aria2c allocates the whole
+/// file upfront and only fills it in.
+///
+/// # Arguments
+/// * `part` - The file the retriever writes to.
+///
+/// # Returns
+/// How long the transfer went without progress; never returns without
+/// `--timeout`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::progress::stalled;
+/// use std::path::Path;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let idle = stalled(Path::new("DOWNLOADS/SRR123456_1.fastq.gz.part")).await;
+///     println!("No progress for {:?}", idle);
+/// }
+/// ```
+pub async fn stalled(part: &Path) -> Duration {
+    let Some(timeout) = STALL_TIMEOUT.get().copied().flatten() else {
+        return std::future::pending().await;
+    };
+
+    let mut last = (footprint(part), Instant::now());
+    loop {
+        tokio::time::sleep(STALL_POLL.min(timeout)).await;
+        let now = footprint(part);
+        if now != last.0 {
+            last = (now, Instant::now());
+        } else if last.1.elapsed() >= timeout {
+            return last.1.elapsed();
+        }
+    }
+}
+
+/// Get the size and modification time of a file being written.
+fn footprint(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// Set the totals of the batch, known once its plan is resolved, so every
+/// finished run logs how far the batch is. Only the first call has effect.
+///
+/// # Arguments
+/// * `runs` - The number of runs planned.
+/// * `files` - The number of files planned.
+/// * `bytes` - The size of the files planned.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::progress::init_batch;
+/// init_batch(12, 24, 48_000_000_000);
+/// ```
+pub fn init_batch(runs: usize, files: usize, bytes: u64) {
+    let _ = BATCH.set((runs, files, bytes));
+}
+
+/// Count a run of the batch as done and log how far the batch is.
+///
+/// # Arguments
+/// * `files` - The number of files of the run.
+/// * `bytes` - The size of the files of the run.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::progress::run_done;
+/// run_done(2, 4_000_000_000);
+/// ```
+pub fn run_done(files: usize, bytes: u64) {
+    let Some(&(total_runs, total_files, total_bytes)) = BATCH.get() else {
+        return;
+    };
+
+    let (runs, files, bytes) = {
+        let mut done = DONE.lock().unwrap_or_else(|e| e.into_inner());
+        *done = (done.0 + 1, done.1 + files, done.2 + bytes);
+        *done
+    };
+    if interval() == 0 {
+        return;
+    }
+    log::info!(
+        "Batch: {} of {} runs, {} of {} files ({} of {})",
+        runs,
+        total_runs,
+        files,
+        total_files,
+        human_bytes(bytes),
+        human_bytes(total_bytes)
+    );
+}
+
+/// Get the size of a file being written, 0 until it shows up.
+fn size_of(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// Get the bytes per second over an elapsed time.
+fn rate(bytes: u64, elapsed: Duration) -> f64 {
+    rate_of(bytes, elapsed).unwrap_or(0.0)
+}
+
+/// Get the bytes per second over an elapsed time, if anything moved.
+fn rate_of(bytes: u64, elapsed: Duration) -> Option<f64> {
+    let secs = elapsed.as_secs_f64();
+    (bytes > 0 && secs > 0.0).then(|| bytes as f64 / secs)
+}
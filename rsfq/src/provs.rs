@@ -1,11 +1,46 @@
+pub mod aws;
+pub mod drs;
 pub mod ena;
+pub mod gcp;
+pub mod gsa;
+pub mod sdl;
 pub mod sra;
 
+use crate::plugins::PLUGIN_PREFIX;
+
 /// Enum representing the providers
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum Provider {
     ENA,
     SRA,
+    /// The Genome Sequence Archive of the China NGDC
+    GSA,
+    /// The SRA Open Data bucket on AWS, `s3://sra-pub-run-odp`
+    AWS,
+    /// The SRA mirror on Google Cloud Storage
+    GCP,
+    /// Objects of a GA4GH DRS server, fetched over https or htsget
+    DRS,
+    /// An external provider plugin, given as `plugin:<name>`
+    Plugin(String),
+}
+
+impl Provider {
+    /// Check whether the provider serves `.sra` objects, dumped to FASTQ
+    /// with sra-tools, rather than FASTQs listed in `fastq_ftp`.
+    ///
+    /// # Returns
+    /// `true` for NCBI SRA and its cloud mirrors.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::provs::Provider;
+    /// assert!(Provider::AWS.dumps_sra());
+    /// assert!(!Provider::ENA.dumps_sra());
+    /// ```
+    pub fn dumps_sra(&self) -> bool {
+        matches!(self, Provider::SRA | Provider::AWS | Provider::GCP)
+    }
 }
 
 /// Parse a string into a Provider
@@ -30,6 +65,13 @@ impl std::str::FromStr for Provider {
         match s {
             "ena" => Ok(Provider::ENA),
             "sra" => Ok(Provider::SRA),
+            "gsa" => Ok(Provider::GSA),
+            "aws" => Ok(Provider::AWS),
+            "gcp" => Ok(Provider::GCP),
+            "drs" => Ok(Provider::DRS),
+            _ if s.starts_with(PLUGIN_PREFIX) && s.len() > PLUGIN_PREFIX.len() => {
+                Ok(Provider::Plugin(s[PLUGIN_PREFIX.len()..].to_string()))
+            }
             _ => Err(format!("Invalid provider: {}", s)),
         }
     }
@@ -55,6 +97,11 @@ impl std::fmt::Display for Provider {
         match self {
             Provider::ENA => write!(f, "ena"),
             Provider::SRA => write!(f, "sra"),
+            Provider::GSA => write!(f, "gsa"),
+            Provider::AWS => write!(f, "aws"),
+            Provider::GCP => write!(f, "gcp"),
+            Provider::DRS => write!(f, "drs"),
+            Provider::Plugin(name) => write!(f, "{}{}", PLUGIN_PREFIX, name),
         }
     }
 }
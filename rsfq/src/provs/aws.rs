@@ -0,0 +1,191 @@
+use once_cell::sync::Lazy;
+use tokio::process::Command;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::net::{fetch, proxy_env};
+use crate::perms::install;
+use crate::provs::sra::{
+    dump_run, ensure_tools, existing_run, run_with_retry, SRAError, FASTERQ_DUMP,
+};
+use crate::runner::runner;
+use crate::scheduler::transfer_slot;
+use crate::utils::{part_path, Layout};
+
+const AWS: &str = "aws";
+const ODP_BUCKET: &str = "sra-pub-run-odp";
+const ODP_HTTPS: &str = "https://sra-pub-run-odp.s3.amazonaws.com";
+const CREDENTIALS_ENV: &[&str] = &["AWS_ACCESS_KEY_ID", "AWS_PROFILE"];
+const CREDENTIALS_FILE: &str = ".aws/credentials";
+
+// INFO: checked once, the answer does not change during a batch
+static CREDENTIALS: Lazy<bool> = Lazy::new(|| {
+    CREDENTIALS_ENV
+        .iter()
+        .any(|var| std::env::var_os(var).is_some_and(|value| !value.is_empty()))
+        || std::env::var_os("HOME")
+            .is_some_and(|home| PathBuf::from(home).join(CREDENTIALS_FILE).exists())
+});
+
+/// Get the S3 key of a run in the SRA Open Data bucket.
+///
+/// # Arguments
+/// * `accession` - The run accession.
+///
+/// # Returns
+/// The key, e.g. `sra/SRR123456/SRR123456`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::aws::odp_key;
+/// assert_eq!(odp_key("SRR123456"), "sra/SRR123456/SRR123456");
+/// ```
+pub fn odp_key(accession: &str) -> String {
+    format!("sra/{}/{}", accession, accession)
+}
+
+/// Download FASTQs for a run from the SRA Open Data bucket on AWS
+/// (`s3://sra-pub-run-odp`), as chosen with `-P aws`.
+///
+/// The `.sra` object is fetched with the aws CLI when it is installed,
+/// signed with the credentials it finds or anonymously otherwise, and over
+/// plain HTTPS when it is not. It is then dumped like `-P sra` does, so
+/// only fasterq-dump and pigz are needed.
+///
+/// # Arguments
+///
+/// * `accession` - The SRA run accession to download.
+/// * `outdir` - The directory to download the FASTQs to.
+/// * `threads` - The number of threads to use for dumping.
+/// * `attempts` - The number of attempts to make for each download.
+/// * `sleep` - The number of seconds to sleep between attempts.
+/// * `force` - Whether to force downloading of existing files.
+/// * `layout` - The layout of the run.
+/// * `spots` - The number of spots to keep, `None` for every spot.
+///
+/// # Returns
+///
+/// A vector of paths to the downloaded FASTQs.
+///
+/// # Example
+///
+/// ```no_run
+/// use rsfq::provs::aws::download_run;
+/// use rsfq::utils::Layout;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     download_run("SRR123456", "DOWNLOADS", 4, 3, 5, false, Layout::Paired, None)
+///         .await
+///         .unwrap();
+/// }
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub async fn download_run<K: AsRef<Path>>(
+    accession: &str,
+    outdir: K,
+    threads: usize,
+    attempts: usize,
+    sleep: usize,
+    force: bool,
+    layout: Layout,
+    spots: Option<u64>,
+) -> Result<Vec<PathBuf>, SRAError> {
+    let toolkit = ensure_tools(&[FASTERQ_DUMP]).await?;
+
+    let outdir = outdir.as_ref();
+    if let Some(existing) = existing_run(accession, outdir, force, layout)? {
+        return Ok(existing);
+    }
+
+    // INFO: staged under .part, so an interrupted object is never dumped;
+    // kept on SIGTERM for the next run to resume
+    let sra = outdir.join(format!("{}.sra", accession));
+    let part = part_path(&sra);
+
+    let slot = transfer_slot(ODP_HTTPS).await;
+    let fetched = if runner().installed(AWS) {
+        run_with_retry(|| aws_cp(accession, &part), attempts, sleep, AWS).await
+    } else {
+        if *CREDENTIALS {
+            log::warn!(
+                "WARNING: AWS credentials are set but the aws CLI is not installed, fetching {} anonymously over HTTPS",
+                accession
+            );
+        }
+        fetch_https(accession, &part, attempts, sleep).await
+    };
+    match fetched {
+        Ok(()) => slot.finished(part.metadata().map(|m| m.len()).unwrap_or(0)),
+        Err(e) => {
+            slot.failed();
+            return Err(e);
+        }
+    }
+    install(&part, &sra)?;
+
+    dump_run(
+        accession, outdir, toolkit, threads, attempts, sleep, layout, spots,
+    )
+    .await
+}
+
+/// Build the aws CLI command copying the `.sra` object of a run.
+///
+/// # Arguments
+///
+/// * `accession` - The run accession.
+/// * `output` - Where to write the object.
+fn aws_cp(accession: &str, output: &Path) -> Command {
+    let mut cmd = Command::new(AWS);
+    cmd.arg("s3")
+        .arg("cp")
+        .arg("--only-show-errors")
+        .arg(format!("s3://{}/{}", ODP_BUCKET, odp_key(accession)))
+        .arg(output)
+        .envs(proxy_env());
+    // INFO: the bucket is public, unsigned requests need no account
+    if !*CREDENTIALS {
+        cmd.arg("--no-sign-request");
+    }
+    cmd
+}
+
+/// Fetch the `.sra` object of a run anonymously over HTTPS, resuming what an
+/// earlier attempt left.
+///
+/// # Arguments
+///
+/// * `accession` - The run accession.
+/// * `output` - Where to write the object.
+/// * `attempts` - The number of attempts to make.
+/// * `sleep` - The number of seconds to sleep between attempts.
+async fn fetch_https(
+    accession: &str,
+    output: &Path,
+    attempts: usize,
+    sleep: usize,
+) -> Result<(), SRAError> {
+    let url = format!("{}/{}", ODP_HTTPS, odp_key(accession));
+    let mut current_attempt = 0;
+    loop {
+        current_attempt += 1;
+        match fetch(&url, output, &[]).await {
+            Ok(_) => return Ok(()),
+            Err(e) if current_attempt >= attempts => {
+                log::error!("ERROR: Could not fetch {}: {}", url, e);
+                return Err(SRAError::CommandFailed { tool: AWS, code: 1 });
+            }
+            Err(e) => {
+                log::warn!(
+                    "WARNING: Fetching {} failed ({}), retrying in {}s",
+                    url,
+                    e,
+                    sleep
+                );
+                tokio::time::sleep(Duration::from_secs(sleep as u64)).await;
+            }
+        }
+    }
+}
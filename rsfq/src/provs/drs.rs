@@ -0,0 +1,523 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use once_cell::sync::OnceCell;
+use serde_json::Value;
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+use crate::net::{client, download_client, retry_policy, send_with_retry, Header, RetryPolicy};
+
+const DRS_SCHEME: &str = "drs://";
+const DRS_OBJECTS: &str = "ga4gh/drs/v1/objects";
+const HTTPS_ACCESS: &str = "https";
+const HTSGET_ACCESS: &str = "htsget";
+const MD5_TYPE: &str = "md5";
+const DATA_PREFIX: &str = "data:";
+const R1: &str = "_1.fastq.gz";
+const R2: &str = "_2.fastq.gz";
+const PAIRED: &str = "PAIRED";
+
+static DRS: OnceCell<bool> = OnceCell::new();
+
+/// Resolve runs from GA4GH DRS objects instead of ENA, as chosen with
+/// `-P drs`. Only the first call has effect.
+///
+/// # Arguments
+/// * `enabled` - Whether `-P drs` was given.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::drs::init_drs;
+/// init_drs(true);
+/// ```
+pub fn init_drs(enabled: bool) {
+    let _ = DRS.set(enabled);
+}
+
+/// Check whether runs come from GA4GH DRS objects.
+///
+/// # Returns
+/// `true` if `-P drs` was given.
+pub fn drs() -> bool {
+    DRS.get().copied().unwrap_or(false)
+}
+
+/// Check whether a string is a hostname-based DRS URI, `drs://<host>/<id>`.
+///
+/// # Arguments
+/// * `uri` - The string to check.
+///
+/// # Returns
+/// `true` for DRS URIs, also those naming a file after the id as the
+/// downloaded files are listed.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::drs::is_drs_uri;
+/// assert!(is_drs_uri("drs://drs.example.org/314159"));
+/// assert!(!is_drs_uri("SRR123456"));
+/// ```
+pub fn is_drs_uri(uri: &str) -> bool {
+    split_uri(uri).is_some()
+}
+
+/// Split a DRS URI into its host, object id and file name, if any.
+fn split_uri(uri: &str) -> Option<(&str, &str, Option<&str>)> {
+    let rest = uri.strip_prefix(DRS_SCHEME)?;
+    let (host, rest) = rest.split_once('/')?;
+    let (id, name) = match rest.split_once('/') {
+        Some((id, name)) => (id, Some(name).filter(|name| !name.is_empty())),
+        None => (rest, None),
+    };
+    (!host.is_empty() && !id.is_empty()).then_some((host, id, name))
+}
+
+/// Get the DRS API URL of an object.
+///
+/// # Arguments
+/// * `uri` - The DRS URI of the object.
+///
+/// # Returns
+/// The URL, `None` if `uri` is not a DRS URI.
+///
+/// # Examples
+/// ```rust
+/// use rsfq::provs::drs::object_url;
+/// assert_eq!(
+///     object_url("drs://drs.example.org/314159/SRR123456_1.fastq.gz").unwrap(),
+///     "https://drs.example.org/ga4gh/drs/v1/objects/314159"
+/// );
+/// ```
+pub fn object_url(uri: &str) -> Option<String> {
+    let (host, id, _) = split_uri(uri)?;
+    Some(format!("https://{}/{}/{}", host, DRS_OBJECTS, id))
+}
+
+/// Get the runs of DRS objects, shaped like ENA `read_run` results so they
+/// download like any other run.
+///
+/// An object is one run: a blob is its only file, a bundle has its blobs
+/// as files. Files are listed as `drs://<host>/<id>/<name>`, so they are
+/// named after the DRS object and their access URL, which may be signed
+/// and short-lived, is only asked for when they are downloaded.
+///
+/// # Arguments
+/// * `query` - The query built by `validate_query` for a DRS URI.
+/// * `max_attempts` - The maximum number of attempts to make when retrieving data.
+/// * `sleep` - The number of seconds to sleep between attempts.
+///
+/// # Returns
+/// The runs, or why none were found.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::drs::drs_runs;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let runs = drs_runs("run_accession=drs://drs.example.org/314159".to_string(), 3, 5).await;
+/// }
+/// ```
+pub async fn drs_runs(
+    query: String,
+    max_attempts: usize,
+    sleep: usize,
+) -> Result<Vec<HashMap<String, String>>, String> {
+    let Some(uri) = query
+        .split(|c: char| c.is_whitespace() || matches!(c, '=' | '(' | ')'))
+        .find(|token| is_drs_uri(token))
+        .map(str::to_string)
+    else {
+        return Err(format!("{} has no DRS URI", query));
+    };
+
+    match fetch_drs_run(&uri, RetryPolicy::new(max_attempts, sleep)).await {
+        Ok(run) => {
+            log::info!("Total runs found: 1");
+            Ok(vec![run])
+        }
+        Err(e) => Err(format!("DRS request failed for {}: {}", query, e)),
+    }
+}
+
+/// Get a DRS object, with the contents of bundles expanded.
+async fn get_object(uri: &str, policy: RetryPolicy) -> Result<Value, String> {
+    let url = object_url(uri).ok_or_else(|| format!("{} is not a DRS URI", uri))?;
+    get_json(&format!("{}?expand=true", url), &HashMap::new(), policy).await
+}
+
+/// GET a JSON document, sending the headers given.
+async fn get_json(
+    url: &str,
+    headers: &HashMap<String, String>,
+    policy: RetryPolicy,
+) -> Result<Value, String> {
+    let mut request = client().get(url);
+    for (name, value) in headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    let answer = send_with_retry(request, policy)
+        .await
+        .and_then(|response| response.error_for_status().map_err(|e| e.to_string()))
+        .map_err(|e| format!("{}: {}", url, e))?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    serde_json::from_str(&answer).map_err(|e| format!("{}: {}", url, e))
+}
+
+/// Fetch the run of a DRS object, failing on bundles that reach an object
+/// twice, as a bundle containing itself would forever.
+async fn fetch_drs_run(uri: &str, policy: RetryPolicy) -> Result<HashMap<String, String>, String> {
+    let (_, id, _) = split_uri(uri).ok_or_else(|| format!("{} is not a DRS URI", uri))?;
+
+    let mut blobs = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![uri.to_string()];
+    while let Some(uri) = pending.pop() {
+        if !visited.insert(object_url(&uri).unwrap_or_else(|| uri.clone())) {
+            return Err(format!("bundle {} lists {} more than once", id, uri));
+        }
+        let object = get_object(&uri, policy).await?;
+        let (host, _, _) = split_uri(&uri).ok_or_else(|| format!("{} is not a DRS URI", uri))?;
+        let Some(contents) = object["contents"].as_array().filter(|c| !c.is_empty()) else {
+            blobs.push(drs_file(&object, host)?);
+            continue;
+        };
+        // INFO: an expanded bundle lists its children, not their checksums
+        for content in contents {
+            let child = match content["drs_uri"].as_array().and_then(|uris| uris.first()) {
+                Some(child) => child.as_str().unwrap_or_default().to_string(),
+                None => format!(
+                    "{}{}/{}",
+                    DRS_SCHEME,
+                    host,
+                    content["id"].as_str().unwrap_or_default()
+                ),
+            };
+            pending.push(child);
+        }
+    }
+
+    // INFO: by file name, so mates come in order whatever their ids
+    blobs.sort_by(|a, b| a.0.rsplit('/').next().cmp(&b.0.rsplit('/').next()));
+
+    Ok(as_ena_run(id, &blobs))
+}
+
+/// Read the locator, MD5 and size of a DRS blob.
+fn drs_file(blob: &Value, host: &str) -> Result<(String, String, Option<u64>), String> {
+    let id = blob["id"]
+        .as_str()
+        .ok_or_else(|| "DRS object without id".to_string())?;
+    let name = blob["name"]
+        .as_str()
+        .filter(|name| !name.is_empty())
+        .unwrap_or(id);
+    let md5 = blob["checksums"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|checksum| {
+            checksum["type"].as_str().map(str::to_lowercase).as_deref() == Some(MD5_TYPE)
+        })
+        .and_then(|checksum| checksum["checksum"].as_str())
+        .ok_or_else(|| format!("{} has no MD5 checksum", id))?;
+    if access_methods(blob).is_empty() {
+        return Err(format!("{} has no https or htsget access method", id));
+    }
+
+    Ok((
+        format!("{}{}/{}/{}", DRS_SCHEME, host, id, name),
+        md5.to_lowercase(),
+        blob["size"].as_u64(),
+    ))
+}
+
+/// Shape the files of a DRS object like an ENA run.
+///
+/// # Arguments
+/// * `accession` - The id of the object, used as run accession.
+/// * `files` - The locator, MD5 and size of each file, sorted.
+///
+/// # Returns
+/// The run; `PAIRED` when its files are `_1`/`_2` mates, without layout
+/// otherwise, so their names are not checked against the run.
+///
+/// # Examples
+/// ```rust
+/// use rsfq::provs::drs::as_ena_run;
+///
+/// let files = [
+///     ("drs://drs.example.org/1/S1_1.fastq.gz".to_string(), "abc".to_string(), Some(10)),
+///     ("drs://drs.example.org/2/S1_2.fastq.gz".to_string(), "def".to_string(), Some(12)),
+/// ];
+/// let run = as_ena_run("314159", &files);
+/// assert_eq!(run["library_layout"], "PAIRED");
+/// assert_eq!(run["fastq_md5"], "abc;def");
+/// ```
+pub fn as_ena_run(
+    accession: &str,
+    files: &[(String, String, Option<u64>)],
+) -> HashMap<String, String> {
+    let paired = files.len() == 2 && files[0].0.ends_with(R1) && files[1].0.ends_with(R2);
+    let join = |field: fn(&(String, String, Option<u64>)) -> String| {
+        files.iter().map(field).collect::<Vec<_>>().join(";")
+    };
+
+    HashMap::from([
+        ("run_accession".to_string(), accession.to_string()),
+        ("fastq_ftp".to_string(), join(|file| file.0.clone())),
+        ("fastq_md5".to_string(), join(|file| file.1.clone())),
+        (
+            "fastq_bytes".to_string(),
+            join(|file| file.2.map(|bytes| bytes.to_string()).unwrap_or_default()),
+        ),
+        (
+            "library_layout".to_string(),
+            if paired { PAIRED } else { "" }.to_string(),
+        ),
+    ])
+}
+
+/// The access methods of a DRS object rsfq can use, https ones first.
+fn access_methods(object: &Value) -> Vec<&Value> {
+    let methods = object["access_methods"].as_array().into_iter().flatten();
+    let (https, htsget): (Vec<&Value>, Vec<&Value>) = methods
+        .filter(|method| {
+            matches!(
+                method["type"].as_str(),
+                Some(HTTPS_ACCESS) | Some(HTSGET_ACCESS)
+            )
+        })
+        .partition(|method| method["type"].as_str() == Some(HTTPS_ACCESS));
+
+    https.into_iter().chain(htsget).collect()
+}
+
+/// A block of an htsget ticket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    /// URL of the block, or a `data:` URI holding it
+    pub url: String,
+    /// Headers to send along
+    pub headers: HashMap<String, String>,
+}
+
+/// How the bytes of a file are fetched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Access {
+    /// A URL to download, with the headers it must be requested with;
+    /// only the native retriever sends them
+    Url(String, Vec<Header>),
+    /// The blocks of an htsget ticket, joined in order
+    Htsget(Vec<Block>),
+}
+
+/// Ask how to fetch a file, as the download of each attempt starts.
+///
+/// Files listed with a DRS locator get an access URL from the DRS server,
+/// or the ticket of its htsget server; any other file is fetched from the
+/// URL it is listed with.
+///
+/// # Arguments
+/// * `url` - The URL or DRS locator the file is listed with.
+///
+/// # Returns
+/// How to fetch the file, or why the DRS or htsget server could not tell.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::drs::{access, Access};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let url = "ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR123456/SRR123456.fastq.gz";
+///     assert_eq!(access(url).await.unwrap(), Access::Url(url.to_string(), vec![]));
+/// }
+/// ```
+pub async fn access(url: &str) -> Result<Access, String> {
+    if !is_drs_uri(url) {
+        return Ok(Access::Url(url.to_string(), vec![]));
+    }
+
+    let policy = retry_policy();
+    let object = get_object(url, policy).await?;
+    let Some(method) = access_methods(&object).into_iter().next() else {
+        return Err(format!("{} has no https or htsget access method", url));
+    };
+
+    // INFO: without a URL, the object is fetched through its access id
+    let access_url = match method["access_url"]["url"].as_str() {
+        Some(_) => method["access_url"].clone(),
+        None => {
+            let access_id = method["access_id"]
+                .as_str()
+                .ok_or_else(|| format!("{} lists neither access URL nor access id", url))?;
+            let object_url = object_url(url).unwrap_or_default();
+            get_json(
+                &format!("{}/access/{}", object_url, access_id),
+                &HashMap::new(),
+                policy,
+            )
+            .await?
+        }
+    };
+    let target = access_url["url"]
+        .as_str()
+        .ok_or_else(|| format!("{} has an access method without URL", url))?
+        .to_string();
+    let headers = headers_of(&access_url["headers"]);
+
+    if method["type"].as_str() == Some(HTSGET_ACCESS) {
+        let ticket = get_json(&target, &headers, policy).await?;
+        return Ok(Access::Htsget(parse_ticket(&ticket)?));
+    }
+    let headers = headers
+        .into_iter()
+        .map(|(name, value)| Header { name, value })
+        .collect();
+    Ok(Access::Url(target, headers))
+}
+
+/// Read the headers of an access URL or ticket block, listed either as an
+/// object or as `Name: value` strings.
+fn headers_of(headers: &Value) -> HashMap<String, String> {
+    match headers {
+        Value::Object(headers) => headers
+            .iter()
+            .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+            .collect(),
+        Value::Array(headers) => headers
+            .iter()
+            .filter_map(|header| header.as_str()?.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// Read the blocks out of an htsget ticket.
+///
+/// # Arguments
+/// * `ticket` - The JSON ticket of an htsget `reads` request.
+///
+/// # Returns
+/// The blocks, in the order they are joined, or why the ticket is unusable.
+///
+/// # Examples
+/// ```rust
+/// use rsfq::provs::drs::parse_ticket;
+///
+/// let ticket = serde_json::json!({"htsget": {"format": "BAM", "urls": [
+///     {"url": "data:application/vnd.ga4gh.bam;base64,QkFNAQ==", "class": "header"},
+///     {"url": "https://htsget.example.org/blocks/1", "headers": {"Range": "bytes=0-1023"}}
+/// ]}});
+/// let blocks = parse_ticket(&ticket).unwrap();
+/// assert_eq!(blocks[1].headers["Range"], "bytes=0-1023");
+/// ```
+pub fn parse_ticket(ticket: &Value) -> Result<Vec<Block>, String> {
+    let blocks = ticket["htsget"]["urls"]
+        .as_array()
+        .ok_or_else(|| "htsget ticket without urls".to_string())?
+        .iter()
+        .map(|block| {
+            Ok(Block {
+                url: block["url"]
+                    .as_str()
+                    .ok_or_else(|| "htsget block without url".to_string())?
+                    .to_string(),
+                headers: headers_of(&block["headers"]),
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if blocks.is_empty() {
+        return Err("htsget ticket without blocks".to_string());
+    }
+    Ok(blocks)
+}
+
+/// Join the blocks of an htsget ticket into a file, from scratch.
+///
+/// # Arguments
+/// * `blocks` - The blocks of the ticket.
+/// * `output` - The file to write.
+///
+/// # Returns
+/// The size of the file, or why a block could not be fetched.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::drs::{fetch_blocks, Block};
+/// use std::collections::HashMap;
+/// use std::path::Path;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let blocks = [Block {
+///         url: "data:application/vnd.ga4gh.bam;base64,QkFNAQ==".to_string(),
+///         headers: HashMap::new(),
+///     }];
+///     fetch_blocks(&blocks, Path::new("NA12878.bam.part")).await.unwrap();
+/// }
+/// ```
+pub async fn fetch_blocks(blocks: &[Block], output: &Path) -> Result<u64, String> {
+    // INFO: ticket blocks may be re-cut between attempts, so nothing is resumed
+    let file = tokio::fs::File::create(output)
+        .await
+        .map_err(|e| format!("{}: {}", output.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    let mut written = 0;
+
+    for block in blocks {
+        if let Some(data) = block.url.strip_prefix(DATA_PREFIX) {
+            let bytes = decode_data(data)?;
+            writer
+                .write_all(&bytes)
+                .await
+                .map_err(|e| format!("{}: {}", output.display(), e))?;
+            written += bytes.len() as u64;
+            continue;
+        }
+
+        // INFO: blocks may be large, so only the connection is timed out
+        let mut request = download_client().get(&block.url);
+        for (name, value) in &block.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        let mut response = send_with_retry(request, retry_policy())
+            .await
+            .and_then(|response| response.error_for_status().map_err(|e| e.to_string()))
+            .map_err(|e| format!("{}: {}", block.url, e))?;
+        while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+            writer
+                .write_all(&chunk)
+                .await
+                .map_err(|e| format!("{}: {}", output.display(), e))?;
+            written += chunk.len() as u64;
+        }
+    }
+    writer
+        .flush()
+        .await
+        .map_err(|e| format!("{}: {}", output.display(), e))?;
+
+    Ok(written)
+}
+
+/// Decode the payload of a `data:` URI, without its scheme.
+fn decode_data(data: &str) -> Result<Vec<u8>, String> {
+    let (meta, payload) = data
+        .split_once(',')
+        .ok_or_else(|| "malformed data URI".to_string())?;
+    if meta.ends_with(";base64") {
+        STANDARD
+            .decode(payload)
+            .map_err(|e| format!("malformed data URI: {}", e))
+    } else {
+        Ok(payload.as_bytes().to_vec())
+    }
+}
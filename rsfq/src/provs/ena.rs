@@ -1,11 +1,129 @@
-use reqwest::Client;
-use std::collections::HashMap;
+use once_cell::sync::OnceCell;
+use reqwest::{header::RETRY_AFTER, RequestBuilder};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
-const ENA_URL: &str = "https://www.ebi.ac.uk/ena/portal/api/search?result=read_run&format=tsv";
+use crate::net::{
+    client, credentials_for, datahub, headers_for, metadata_limit, offline, parse_retry_after,
+    retry_policy, send_with_retry, RetryPolicy,
+};
+use crate::plugins::{plugin_runs, provider_plugin};
+use crate::provs::drs::{drs, drs_runs};
+use crate::provs::gsa::{gsa, gsa_runs};
+use crate::scheduler::{Busy, Resource};
+use crate::store::{save, Store};
+
+const ENA_SEARCH_URL: &str = "https://www.ebi.ac.uk/ena/portal/api/search";
+const MAX_GET_URL: usize = 2048; // characters, proxies and servers commonly cut longer URLs
+const ENA_COUNT_URL: &str = "https://www.ebi.ac.uk/ena/portal/api/count?result=read_run";
+const ENA_TAXONOMY_URL: &str = "https://www.ebi.ac.uk/ena/taxonomy/rest/scientific-name";
+const DATAHUB_PARAMS: &str = "dataPortal=pathogen&dccDataOnly=true";
+const RUN_ACCESSION: &str = "run_accession";
+const EXPERIMENT_ACCESSION: &str = "experiment_accession";
+const STUDY_ACCESSION: &str = "study_accession";
+const ANALYSIS_ACCESSION: &str = "analysis_accession";
+const ENA_RESULTS_URL: &str =
+    "https://www.ebi.ac.uk/ena/portal/api/results?dataPortal=ena&format=json";
+const ENA_PROBE_URL: &str = "https://www.ebi.ac.uk/ena/portal/api/search?result=read_run&fields=run_accession&limit=1&format=tsv&query=tax_eq(9606)";
+const MAINTENANCE_PAUSE: u64 = 300; // 5 minutes
+const MAX_PAUSE_SHIFT: usize = 2; // 20 minutes
+
+static METADATA_FILE: OnceCell<Vec<HashMap<String, String>>> = OnceCell::new();
+static METADATA_CACHE: OnceCell<(PathBuf, Vec<HashMap<String, String>>)> = OnceCell::new();
+
+/// ENA portal result types rsfq can query
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResultType {
+    /// Sequencing runs, with their FASTQs
+    #[default]
+    ReadRun,
+    /// Experiments, grouping the runs of one library
+    ReadExperiment,
+    /// Studies holding raw reads
+    ReadStudy,
+    /// Analyses (assemblies, alignments, variant calls...), with their submitted files
+    Analysis,
+}
+
+impl ResultType {
+    /// Get the field that holds the accession of each result.
+    ///
+    /// # Returns
+    /// The field name, e.g. `experiment_accession`.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::provs::ena::ResultType;
+    /// assert_eq!(ResultType::ReadExperiment.key(), "experiment_accession");
+    /// ```
+    pub fn key(&self) -> &'static str {
+        match self {
+            ResultType::ReadRun => RUN_ACCESSION,
+            ResultType::ReadExperiment => EXPERIMENT_ACCESSION,
+            ResultType::ReadStudy => STUDY_ACCESSION,
+            ResultType::Analysis => ANALYSIS_ACCESSION,
+        }
+    }
+
+    /// Whether the results expand into runs that can be downloaded as FASTQ.
+    ///
+    /// # Returns
+    /// `false` for analyses.
+    pub fn has_reads(&self) -> bool {
+        !matches!(self, ResultType::Analysis)
+    }
+}
+
+/// Parse a string into a ResultType
+impl std::str::FromStr for ResultType {
+    type Err = String;
+
+    /// Parse a string into a ResultType
+    ///
+    /// # Arguments
+    /// * `s` - The ENA result name: `read_run`, `read_experiment`, `read_study` or `analysis`.
+    ///
+    /// # Returns
+    /// * `Result<Self, Self::Err>` - The parsed ResultType.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::provs::ena::ResultType;
+    /// use std::str::FromStr;
+    /// assert_eq!(ResultType::from_str("analysis").unwrap(), ResultType::Analysis);
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "read_run" => Ok(ResultType::ReadRun),
+            "read_experiment" => Ok(ResultType::ReadExperiment),
+            "read_study" => Ok(ResultType::ReadStudy),
+            "analysis" => Ok(ResultType::Analysis),
+            _ => Err(format!(
+                "Invalid result type, expected read_run, read_experiment, read_study or analysis: {}",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ResultType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResultType::ReadRun => write!(f, "read_run"),
+            ResultType::ReadExperiment => write!(f, "read_experiment"),
+            ResultType::ReadStudy => write!(f, "read_study"),
+            ResultType::Analysis => write!(f, "analysis"),
+        }
+    }
+}
 
 pub enum ENAServerResponse {
     Success(Vec<HashMap<String, String>>),
     Error(u16, String),
+    Throttled(u16, Option<u64>),
 }
 
 /// Get run information from ENA.
@@ -40,17 +158,206 @@ pub async fn get_run_info(
     max_attempts: usize,
     sleep: usize,
 ) -> Vec<HashMap<String, String>> {
+    match fetch_run_info(query, max_attempts, sleep).await {
+        Ok(result) => result,
+        Err(e) => {
+            log::error!("ERROR: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Get run information from ENA, without exiting when nothing is found.
+///
+/// # Arguments
+///
+/// * `query` - The query to search for.
+/// * `max_attempts` - The maximum number of attempts to make when retrieving data.
+/// * `sleep` - The number of seconds to sleep between attempts.
+///
+/// # Returns
+///
+/// The run information, or why none was found.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::provs::ena::fetch_run_info;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     match fetch_run_info("SRR123456".to_string(), 3, 5).await {
+///         Ok(runs) => println!("{} runs", runs.len()),
+///         Err(e) => eprintln!("{}", e),
+///     }
+/// }
+/// ```
+pub async fn fetch_run_info(
+    query: String,
+    max_attempts: usize,
+    sleep: usize,
+) -> Result<Vec<HashMap<String, String>>, String> {
+    // INFO: --metadata-file replaces the API entirely
+    if let Some(rows) = METADATA_FILE.get() {
+        let result = query_report(rows, &query);
+        if result.is_empty() {
+            return Err(format!("No data found in the metadata file for {}", query));
+        }
+        log::info!("Total runs found: {}", result.len());
+        return Ok(result);
+    }
+
+    // INFO: and --offline, answered from the local store
+    if let Some((db, rows)) = METADATA_CACHE.get() {
+        let result = query_report(rows, &query);
+        if result.is_empty() {
+            return Err(format!(
+                "{} is not in the local metadata cache {}! Fetch it once online with --store, or pass --metadata-file",
+                query,
+                db.display()
+            ));
+        }
+        log::info!("Total runs found: {}", result.len());
+        return Ok(result);
+    }
+
+    // INFO: so does a provider plugin
+    if let Some(plugin) = provider_plugin() {
+        return Ok(plugin_runs(plugin, &query, max_attempts, sleep).await);
+    }
+    if gsa() {
+        return gsa_runs(query, max_attempts, sleep).await;
+    }
+    if drs() {
+        return drs_runs(query, max_attempts, sleep).await;
+    }
+
+    query_portal(ResultType::ReadRun, &query, max_attempts, sleep).await
+}
+
+/// Get results of any type from ENA, e.g. the experiments or analyses of
+/// a study.
+///
+/// Only runs can be answered by `--metadata-file`, `--offline` or a
+/// provider plugin; other result types always ask the portal.
+///
+/// # Arguments
+///
+/// * `result` - The result type to query.
+/// * `query` - The query to search for.
+/// * `max_attempts` - The maximum number of attempts to make when retrieving data.
+/// * `sleep` - The number of seconds to sleep between attempts.
+///
+/// # Returns
+///
+/// The results, or why none were found.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::provs::ena::{fetch_results, ResultType};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let query = "study_accession=PRJEB1234".to_string();
+///     match fetch_results(ResultType::Analysis, query, 3, 5).await {
+///         Ok(analyses) => println!("{} analyses", analyses.len()),
+///         Err(e) => eprintln!("{}", e),
+///     }
+/// }
+/// ```
+pub async fn fetch_results(
+    result: ResultType,
+    query: String,
+    max_attempts: usize,
+    sleep: usize,
+) -> Result<Vec<HashMap<String, String>>, String> {
+    if result == ResultType::ReadRun {
+        return fetch_run_info(query, max_attempts, sleep).await;
+    }
+
+    if METADATA_FILE.get().is_some() || METADATA_CACHE.get().is_some() {
+        return Err(format!(
+            "{} results are not in local metadata, only read_run results are",
+            result
+        ));
+    }
+    if provider_plugin().is_some() {
+        return Err(format!(
+            "{} results cannot come from a provider plugin, only read_run results can",
+            result
+        ));
+    }
+    if gsa() {
+        return Err(format!(
+            "{} results cannot come from GSA, only read_run results can",
+            result
+        ));
+    }
+    if drs() {
+        return Err(format!(
+            "{} results cannot come from DRS, only read_run results can",
+            result
+        ));
+    }
+
+    query_portal(result, &query, max_attempts, sleep).await
+}
+
+/// Query the ENA portal, retrying failures and waiting out throttling.
+///
+/// Retries follow the shared `RetryPolicy`, but are made here rather than
+/// by `send_with_retry`: every throttled answer has to shrink the metadata
+/// concurrency limit, and a report that does not parse is retried too.
+async fn query_portal(
+    result_type: ResultType,
+    query: &String,
+    max_attempts: usize,
+    sleep: usize,
+) -> Result<Vec<HashMap<String, String>>, String> {
+    let policy = RetryPolicy::new(max_attempts, sleep);
     let mut attempts = 0;
+    let mut throttled = 0;
     let mut result = vec![];
     while max_attempts >= attempts {
-        let ena_data = get_ena_metadata(&query).await;
+        let limit = metadata_limit();
+        let started = Instant::now();
+        let permit = limit.acquire().await;
+        let busy = Busy::new(Resource::Metadata, started.elapsed());
+        let ena_data = get_ena_results(result_type, query).await;
+        drop(busy);
         match ena_data {
             ENAServerResponse::Success(data) => {
-                log::info!("Total runs found: {}", data.len());
+                limit.success(permit);
+                log::info!("Total {} results found: {}", result_type, data.len());
+                // INFO: the store holds runs only
+                if result_type == ResultType::ReadRun {
+                    save(&data);
+                }
                 result.extend(data);
                 break;
             }
+            ENAServerResponse::Throttled(status, retry_after) => {
+                limit.throttled(permit);
+
+                // INFO: throttling windows have their own budget, so they do not
+                // consume the attempts meant for real failures
+                throttled += 1;
+                if throttled > max_attempts {
+                    attempts += 1;
+                }
+
+                let wait = policy.wait(throttled, retry_after);
+                log::warn!(
+                    "WARNING: ENA answered {} for query {}, retrying in {}s",
+                    status,
+                    query,
+                    wait.as_secs()
+                );
+                tokio::time::sleep(wait).await;
+            }
             ENAServerResponse::Error(status, message) => {
+                drop(permit);
                 attempts += 1;
                 log::error!(
                     "ERROR: Request failed with status {}: {}. Attempts til now {} for query {}",
@@ -59,25 +366,112 @@ pub async fn get_run_info(
                     attempts,
                     query
                 );
-                tokio::time::sleep(tokio::time::Duration::from_secs(sleep as u64)).await;
+                tokio::time::sleep(policy.wait(attempts, None)).await;
             }
         }
     }
 
     if result.is_empty() {
-        log::error!(
-            "ERROR: No data found after {} attempts for {}",
-            max_attempts,
-            query
-        );
-        std::process::exit(1);
+        Err(format!(
+            "No data found after {} attempts for {}",
+            max_attempts, query
+        ))
     } else {
-        result
+        Ok(result)
+    }
+}
+
+/// Check that the ENA portal answers before a large batch, pausing while
+/// it is down or in a maintenance window instead of letting every
+/// accession burn its attempts at once.
+///
+/// The portal is up when its results endpoint answers and a one-run test
+/// query returns a real report (maintenance pages are often served as
+/// `200`). Pauses start at 5 minutes and double up to 20; exits once
+/// `max_attempts` pauses did not help. Batches that do not query ENA
+/// (`--metadata-file`, `--offline`, provider plugins) are not checked.
+///
+/// # Arguments
+///
+/// * `max_attempts` - The maximum number of pauses.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::provs::ena::preflight;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     preflight(3).await;
+/// }
+/// ```
+pub async fn preflight(max_attempts: usize) {
+    if METADATA_FILE.get().is_some()
+        || METADATA_CACHE.get().is_some()
+        || provider_plugin().is_some()
+        || gsa()
+        || drs()
+    {
+        return;
     }
+
+    let mut pauses = 0;
+    loop {
+        let Err(reason) = ena_health().await else {
+            if pauses > 0 {
+                log::info!("ENA is back, starting the batch");
+            }
+            return;
+        };
+
+        if pauses >= max_attempts {
+            log::error!(
+                "ERROR: ENA is still unavailable after {} checks ({})! Try again later",
+                pauses + 1,
+                reason
+            );
+            std::process::exit(1);
+        }
+
+        let wait = MAINTENANCE_PAUSE << pauses.min(MAX_PAUSE_SHIFT);
+        pauses += 1;
+        log::warn!(
+            "WARNING: ENA looks down or in maintenance ({}), pausing {}s before starting the batch [{}/{}]",
+            reason,
+            wait,
+            pauses,
+            max_attempts
+        );
+        tokio::time::sleep(tokio::time::Duration::from_secs(wait)).await;
+    }
+}
+
+/// Probe the ENA portal once.
+async fn ena_health() -> Result<(), String> {
+    for url in [ENA_RESULTS_URL, ENA_PROBE_URL] {
+        let response = authorize(client().get(url), url)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("status {}", status));
+        }
+
+        if url == ENA_PROBE_URL {
+            let text = response.text().await.map_err(|e| e.to_string())?;
+            if !text.starts_with(RUN_ACCESSION) {
+                return Err("the test query did not return a report".to_string());
+            }
+        }
+    }
+    Ok(())
 }
 
 /// Get metadata from ENA.
 ///
+/// Queries that would make the URL too long are sent as a POST form instead.
+///
 /// # Arguments
 ///
 /// * `query` - The query to search for.
@@ -97,46 +491,87 @@ pub async fn get_run_info(
 ///     match get_ena_metadata(&query).await {
 ///         ENAServerResponse::Success(data) => println!("Metadata entries: {}", data.len()),
 ///         ENAServerResponse::Error(_, message) => println!("Failed: {}", message),
+///         ENAServerResponse::Throttled(status, _) => println!("Throttled: {}", status),
 ///     }
 /// }
 /// ```
 pub async fn get_ena_metadata(query: &String) -> ENAServerResponse {
-    let client = Client::new();
-    let url = format!(r#"{}&query="{}"&fields=all"#, ENA_URL, query);
+    get_ena_results(ResultType::ReadRun, query).await
+}
+
+/// Get results of any type from ENA.
+///
+/// Queries that would make the URL too long are sent as a POST form instead.
+///
+/// # Arguments
+///
+/// * `result` - The result type to query.
+/// * `query` - The query to search for.
+///
+/// # Returns
+///
+/// A `ENAServerResponse` containing the results.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::provs::ena::{get_ena_results, ENAServerResponse, ResultType};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let query = "study_accession=PRJEB1234".to_string();
+///     if let ENAServerResponse::Success(data) = get_ena_results(ResultType::ReadExperiment, &query).await {
+///         println!("Experiments: {}", data.len());
+///     }
+/// }
+/// ```
+pub async fn get_ena_results(result: ResultType, query: &String) -> ENAServerResponse {
+    let result = result.to_string();
+    let mut url = format!(
+        r#"{}?result={}&format=tsv&query="{}"&fields=all"#,
+        ENA_SEARCH_URL, result, query
+    );
+
+    // INFO: data hub runs are only visible to authenticated portal queries
+    if datahub().is_some() {
+        url = format!("{}&{}", url, DATAHUB_PARAMS);
+    }
     log::debug!("Request URL: {}", url);
 
-    let response = client
-        .get(&url)
-        .header("Content-type", "application/x-www-form-urlencoded")
-        .send()
-        .await;
+    // INFO: long queries (big IN lists, complex filters) go as a form body
+    let request = if url.len() > MAX_GET_URL {
+        log::debug!("Query is {} characters long, using POST", url.len());
+        let mut form = vec![
+            ("result", result.as_str()),
+            ("format", "tsv"),
+            ("query", query.as_str()),
+            ("fields", "all"),
+        ];
+        if datahub().is_some() {
+            form.extend(
+                DATAHUB_PARAMS
+                    .split('&')
+                    .filter_map(|param| param.split_once('=')),
+            );
+        }
+        authorize(client().post(ENA_SEARCH_URL).form(&form), ENA_SEARCH_URL)
+    } else {
+        authorize(
+            client()
+                .get(&url)
+                .header("Content-type", "application/x-www-form-urlencoded"),
+            &url,
+        )
+    };
+
+    let response = request.send().await;
 
     match response {
         Ok(resp) if resp.status().is_success() => {
             let text = resp.text().await.unwrap_or_default();
             log::debug!("Response text: {}", text);
 
-            let mut lines = text.lines();
-
-            if let Some(header_line) = lines.next() {
-                let headers: Vec<&str> = header_line.split('\t').collect();
-                let data: Vec<HashMap<String, String>> = lines
-                    .filter(|line| !line.is_empty())
-                    .map(|line| {
-                        headers
-                            .iter()
-                            .zip(line.split('\t'))
-                            .filter_map(|(key, value)| {
-                                if value.is_empty() {
-                                    None
-                                } else {
-                                    Some((key.to_string(), value.to_string()))
-                                }
-                            })
-                            .collect()
-                    })
-                    .collect();
-
+            if let Some(data) = parse_report(&text) {
                 if data.is_empty() {
                     log::warn!(
                         "ERROR: Query was successful, but received an empty response for query {}",
@@ -162,6 +597,15 @@ pub async fn get_ena_metadata(query: &String) -> ENAServerResponse {
                 )
             }
         }
+        Ok(resp) if RetryPolicy::retryable(resp.status()) => {
+            let status = resp.status().as_u16();
+            let retry_after = resp
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+            ENAServerResponse::Throttled(status, retry_after)
+        }
         Ok(resp) => {
             let status = resp.status().as_u16();
             let text = resp.text().await.unwrap_or_default();
@@ -174,3 +618,274 @@ pub async fn get_ena_metadata(query: &String) -> ENAServerResponse {
         }
     }
 }
+
+/// Parse a TSV file report as returned by the ENA portal API.
+///
+/// # Arguments
+///
+/// * `text` - The report, header line included.
+///
+/// # Returns
+///
+/// One map per row, empty values left out, or `None` if there is no header.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::provs::ena::parse_report;
+///
+/// let rows = parse_report("run_accession\tfastq_md5\nSRR123456\tabc\n").unwrap();
+/// assert_eq!(rows[0]["run_accession"], "SRR123456");
+/// ```
+pub fn parse_report(text: &str) -> Option<Vec<HashMap<String, String>>> {
+    let mut lines = text.lines();
+    let headers: Vec<&str> = lines.next()?.split('\t').collect();
+
+    Some(
+        lines
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                headers
+                    .iter()
+                    .zip(line.split('\t'))
+                    .filter_map(|(key, value)| {
+                        if value.is_empty() {
+                            None
+                        } else {
+                            Some((key.to_string(), value.to_string()))
+                        }
+                    })
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+/// Use a previously saved ENA file report instead of the portal API.
+/// Only the first call has effect.
+///
+/// # Arguments
+///
+/// * `file` - The TSV report, e.g. saved with `--metadata` or from the ENA browser.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::provs::ena::init_metadata_file;
+/// use std::path::PathBuf;
+///
+/// init_metadata_file(Some(&PathBuf::from("report.tsv")));
+/// ```
+pub fn init_metadata_file(file: Option<&PathBuf>) {
+    let Some(file) = file else {
+        return;
+    };
+
+    let rows = std::fs::read_to_string(file)
+        .ok()
+        .and_then(|text| parse_report(&text))
+        .unwrap_or_else(|| {
+            log::error!("ERROR: Could not read metadata file {}!", file.display());
+            std::process::exit(1);
+        });
+
+    log::info!("Loaded {} runs from {}", rows.len(), file.display());
+    let _ = METADATA_FILE.set(rows);
+}
+
+/// Answer every metadata query from the local store filled by `--store`,
+/// for `--offline`. Only the first call has effect.
+///
+/// # Arguments
+///
+/// * `db` - The metadata store, from `--db`.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::provs::ena::init_metadata_cache;
+/// use rsfq::store::default_db;
+///
+/// init_metadata_cache(&default_db());
+/// ```
+pub fn init_metadata_cache(db: &Path) {
+    if !db.exists() {
+        log::error!(
+            "ERROR: No local metadata cache at {}! Fetch the metadata once online with --store, or pass --metadata-file",
+            db.display()
+        );
+        std::process::exit(1);
+    }
+
+    let rows = Store::open(db)
+        .and_then(|store| store.query("1"))
+        .unwrap_or_else(|e| {
+            log::error!(
+                "ERROR: Could not read the local metadata cache {}!: {}",
+                db.display(),
+                e
+            );
+            std::process::exit(1);
+        });
+
+    log::info!("Loaded {} cached runs from {}", rows.len(), db.display());
+    let _ = METADATA_CACHE.set((db.to_path_buf(), rows));
+}
+
+/// Answer a portal query from the saved file report.
+///
+/// Only the `field=value [OR field=value]` queries built by `validate_query`
+/// are supported; a row matches if any of the pairs does.
+///
+/// # Arguments
+///
+/// * `rows` - The saved report.
+/// * `query` - The portal query.
+///
+/// # Returns
+///
+/// The matching rows.
+fn query_report(rows: &[HashMap<String, String>], query: &str) -> Vec<HashMap<String, String>> {
+    let pairs = query
+        .split(" OR ")
+        .filter_map(|term| {
+            term.trim_matches(|c: char| c == '(' || c == ')' || c.is_whitespace())
+                .split_once('=')
+        })
+        .collect::<Vec<_>>();
+
+    rows.iter()
+        .filter(|row| {
+            pairs
+                .iter()
+                .any(|(field, value)| row.get(*field).is_some_and(|v| v == value))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Attach credentials and user-provided headers to an ENA request.
+///
+/// # Arguments
+///
+/// * `request` - The request to decorate.
+/// * `url` - The URL the request targets.
+///
+/// # Returns
+///
+/// The decorated `RequestBuilder`.
+fn authorize(mut request: RequestBuilder, url: &str) -> RequestBuilder {
+    if let Some(creds) = credentials_for(url) {
+        request = request.basic_auth(&creds.user, Some(&creds.password));
+    }
+    for header in headers_for(url) {
+        request = request.header(&header.name, &header.value);
+    }
+    request
+}
+
+/// Count the runs matching a portal query without fetching them.
+///
+/// # Arguments
+///
+/// * `query` - The query to count.
+///
+/// # Returns
+///
+/// An `Option<usize>` with the number of matching runs, or `None` if the
+/// request failed.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::provs::ena::get_run_count;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let count = get_run_count("tax_eq(10090)").await;
+///     println!("Runs: {:?}", count);
+/// }
+/// ```
+pub async fn get_run_count(query: &str) -> Option<usize> {
+    if offline() {
+        log::error!("ERROR: Cannot count the runs of {} with --offline!", query);
+        return None;
+    }
+
+    let mut url = format!(r#"{}&query="{}""#, ENA_COUNT_URL, query);
+    if datahub().is_some() {
+        url = format!("{}&{}", url, DATAHUB_PARAMS);
+    }
+    log::debug!("Request URL: {}", url);
+
+    let response = send_with_retry(authorize(client().get(&url), &url), retry_policy())
+        .await
+        .ok()?;
+    if !response.status().is_success() {
+        log::error!(
+            "ERROR: Count request failed with status {}",
+            response.status()
+        );
+        return None;
+    }
+
+    // INFO: the count endpoint answers a bare number, optionally after a header
+    let text = response.text().await.ok()?;
+    text.lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .and_then(|line| line.trim().parse::<usize>().ok())
+}
+
+/// Resolve a scientific name into an NCBI taxonomy id via the ENA taxonomy API.
+///
+/// # Arguments
+///
+/// * `organism` - The scientific name, e.g. `Mus musculus`.
+///
+/// # Returns
+///
+/// An `Option<String>` with the tax id, or `None` if the name is unknown.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::provs::ena::get_tax_id;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let tax_id = get_tax_id("Mus musculus").await;
+///     assert_eq!(tax_id.as_deref(), Some("10090"));
+/// }
+/// ```
+pub async fn get_tax_id(organism: &str) -> Option<String> {
+    if offline() {
+        log::error!("ERROR: Cannot resolve {} with --offline!", organism);
+        return None;
+    }
+
+    let url = format!("{}/{}", ENA_TAXONOMY_URL, organism.trim());
+    log::debug!("Request URL: {}", url);
+
+    let response = send_with_retry(authorize(client().get(&url), &url), retry_policy())
+        .await
+        .ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+
+    let text = response.text().await.ok()?;
+    let taxa = serde_json::from_str::<Vec<serde_json::Value>>(&text).ok()?;
+    if taxa.len() > 1 {
+        log::warn!(
+            "WARNING: {} matches {} taxa, using the first one",
+            organism,
+            taxa.len()
+        );
+    }
+
+    taxa.first()
+        .and_then(|taxon| taxon.get("taxId"))
+        .and_then(|id| id.as_str())
+        .map(|id| id.to_string())
+}
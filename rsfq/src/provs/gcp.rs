@@ -0,0 +1,149 @@
+use tokio::process::Command;
+
+use std::path::{Path, PathBuf};
+
+use crate::net::proxy_env;
+use crate::perms::install;
+use crate::provs::sdl::{best, fetch_located, locate};
+use crate::provs::sra::{dump_run, ensure_tools, existing_run, SRAError, FASTERQ_DUMP};
+use crate::runner::runner;
+use crate::scheduler::transfer_slot;
+use crate::utils::{part_path, Layout};
+
+const GCLOUD: &str = "gcloud";
+const GSUTIL: &str = "gsutil";
+const GCS_SERVICE: &str = "gs";
+const GCS_LOCATION: &str = "gs.US";
+const GCS_HTTPS: &str = "https://storage.googleapis.com/";
+
+/// Download FASTQs for a run from the SRA mirror on Google Cloud Storage, as
+/// chosen with `-P gcp`.
+///
+/// The bucket holding a run is looked up with the NCBI SRA Data Locator.
+/// The `.sra` object is fetched with `gcloud storage` or `gsutil` when one
+/// is installed, so the VM's credentials are used, and over plain HTTPS
+/// otherwise. It is checked against the MD5 the locator gives, then dumped
+/// like `-P sra` does.
+///
+/// # Arguments
+///
+/// * `accession` - The SRA run accession to download.
+/// * `outdir` - The directory to download the FASTQs to.
+/// * `threads` - The number of threads to use for dumping.
+/// * `attempts` - The number of attempts to make for each download.
+/// * `sleep` - The number of seconds to sleep between attempts.
+/// * `force` - Whether to force downloading of existing files.
+/// * `layout` - The layout of the run.
+/// * `spots` - The number of spots to keep, `None` for every spot.
+///
+/// # Returns
+///
+/// A vector of paths to the downloaded FASTQs.
+///
+/// # Example
+///
+/// ```no_run
+/// use rsfq::provs::gcp::download_run;
+/// use rsfq::utils::Layout;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     download_run("SRR123456", "DOWNLOADS", 4, 3, 5, false, Layout::Paired, None)
+///         .await
+///         .unwrap();
+/// }
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub async fn download_run<K: AsRef<Path>>(
+    accession: &str,
+    outdir: K,
+    threads: usize,
+    attempts: usize,
+    sleep: usize,
+    force: bool,
+    layout: Layout,
+    spots: Option<u64>,
+) -> Result<Vec<PathBuf>, SRAError> {
+    let toolkit = ensure_tools(&[FASTERQ_DUMP]).await?;
+
+    let outdir = outdir.as_ref();
+    if let Some(existing) = existing_run(accession, outdir, force, layout)? {
+        return Ok(existing);
+    }
+
+    let copies = locate(accession, Some(GCS_LOCATION))
+        .await
+        .map_err(|e| SRAError::Unavailable(format!("{}: {}", accession, e)))?;
+    let located = best(&copies, &[GCS_SERVICE])
+        .ok_or_else(|| SRAError::Unavailable(format!("{} has no copy on GCS", accession)))?;
+
+    // INFO: staged under .part, so an interrupted object is never dumped;
+    // kept on SIGTERM for the next run to resume
+    let sra = outdir.join(format!("{}.sra", accession));
+    let part = part_path(&sra);
+
+    let slot = transfer_slot(&located.link).await;
+    let copy = || gs_uri(&located.link).and_then(|uri| gcs_cp(&uri, &part));
+    match fetch_located(located, &part, attempts, sleep, copy).await {
+        Ok(()) => slot.finished(part.metadata().map(|m| m.len()).unwrap_or(0)),
+        Err(e) => {
+            slot.failed();
+            return Err(e);
+        }
+    }
+    install(&part, &sra)?;
+
+    dump_run(
+        accession, outdir, toolkit, threads, attempts, sleep, layout, spots,
+    )
+    .await
+}
+
+/// Get the `gs://` URI of an object linked over HTTPS.
+///
+/// # Arguments
+/// * `link` - The HTTPS link, e.g. `https://storage.googleapis.com/<bucket>/<key>`.
+///
+/// # Returns
+/// The URI, `None` for links that are not plain GCS objects, such as
+/// signed ones.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::gcp::gs_uri;
+/// assert_eq!(
+///     gs_uri("https://storage.googleapis.com/sra-pub-run-1/SRR123456/SRR123456.1").unwrap(),
+///     "gs://sra-pub-run-1/SRR123456/SRR123456.1"
+/// );
+/// ```
+pub fn gs_uri(link: &str) -> Option<String> {
+    link.strip_prefix(GCS_HTTPS)
+        .filter(|object| !object.contains('?'))
+        .map(|object| format!("gs://{}", object))
+}
+
+/// Build the command copying a GCS object with whichever Google Cloud CLI
+/// is installed.
+///
+/// # Arguments
+/// * `uri` - The `gs://` URI of the object.
+/// * `output` - Where to write the object.
+///
+/// # Returns
+/// The command, `None` if neither `gcloud` nor `gsutil` is installed.
+fn gcs_cp(uri: &str, output: &Path) -> Option<Command> {
+    let mut cmd = if runner().installed(GCLOUD) {
+        let mut cmd = Command::new(GCLOUD);
+        cmd.arg("storage").arg("cp").arg("--no-user-output-enabled");
+        cmd
+    } else if runner().installed(GSUTIL) {
+        let mut cmd = Command::new(GSUTIL);
+        cmd.arg("-q").arg("cp");
+        cmd
+    } else {
+        return None;
+    };
+    cmd.arg(uri).arg(output).envs(proxy_env());
+
+    Some(cmd)
+}
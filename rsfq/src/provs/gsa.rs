@@ -0,0 +1,277 @@
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+
+use std::collections::HashMap;
+
+use crate::net::{client, send_with_retry, RetryPolicy};
+use crate::runtable::{as_ena_run, read_runinfo};
+use crate::utils::run_accession_of;
+
+const GSA_RUN_INFO_URL: &str = "https://ngdc.cncb.ac.cn/gsa/search/getRunInfo";
+const GSA_DOWNLOAD_URL: &str = "download.cncb.ac.cn/gsa";
+const MD5SUM_FILE: &str = "md5sum.txt";
+const SUBMISSION_COLUMNS: &[&str] = &["Submission", "SRAStudy"];
+
+static GSA: OnceCell<bool> = OnceCell::new();
+
+static GSA_ACCESSION_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(PRJCA|SAMC|CRA|CRX|CRR)[0-9]{6,}$")
+        .unwrap_or_else(|e| panic!("Failed to compile GSA_ACCESSION_RE regex: {}", e))
+});
+static SUBMISSION_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^CRA[0-9]{6,}$")
+        .unwrap_or_else(|e| panic!("Failed to compile SUBMISSION_RE regex: {}", e))
+});
+
+/// Resolve run metadata from the Genome Sequence Archive (NGDC) instead of
+/// ENA, as chosen with `-P gsa`. Only the first call has effect.
+///
+/// # Arguments
+/// * `enabled` - Whether `-P gsa` was given.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::gsa::init_gsa;
+/// init_gsa(true);
+/// ```
+pub fn init_gsa(enabled: bool) {
+    let _ = GSA.set(enabled);
+}
+
+/// Check whether run metadata comes from the Genome Sequence Archive.
+///
+/// # Returns
+/// `true` if `-P gsa` was given.
+pub fn gsa() -> bool {
+    GSA.get().copied().unwrap_or(false)
+}
+
+/// Check whether an accession belongs to the Genome Sequence Archive.
+///
+/// # Arguments
+/// * `accession` - The accession, e.g. `CRA000001` or `CRR000001`.
+///
+/// # Returns
+/// `true` for GSA projects, samples, submissions, experiments and runs.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::gsa::is_gsa_accession;
+/// assert!(is_gsa_accession("PRJCA000001"));
+/// assert!(!is_gsa_accession("SRR123456"));
+/// ```
+pub fn is_gsa_accession(accession: &str) -> bool {
+    GSA_ACCESSION_RE.is_match(accession)
+}
+
+/// Get the runs of a GSA accession, shaped like ENA `read_run` results so
+/// they download like any other run.
+///
+/// Run metadata comes from the NGDC RunInfo export and files with their
+/// checksums from the `md5sum.txt` of each submission (CRA).
+///
+/// # Arguments
+/// * `query` - The query built by `validate_query` for a GSA accession.
+/// * `max_attempts` - The maximum number of attempts to make when retrieving data.
+/// * `sleep` - The number of seconds to sleep between attempts.
+///
+/// # Returns
+/// The runs, or why none were found.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::gsa::gsa_runs;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let runs = gsa_runs("run_accession=CRR000001".to_string(), 3, 5).await;
+/// }
+/// ```
+pub async fn gsa_runs(
+    query: String,
+    max_attempts: usize,
+    sleep: usize,
+) -> Result<Vec<HashMap<String, String>>, String> {
+    // INFO: the accession is the only GSA-looking token of the query
+    let Some(accession) = query
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .find(|token| is_gsa_accession(token))
+        .map(str::to_string)
+    else {
+        return Err(format!("{} has no GSA accession", query));
+    };
+
+    match fetch_gsa_runs(&accession, RetryPolicy::new(max_attempts, sleep)).await {
+        Ok(runs) if !runs.is_empty() => {
+            log::info!("Total runs found: {}", runs.len());
+            Ok(runs)
+        }
+        Ok(_) => Err(format!("No data found in GSA for {}", accession)),
+        Err(e) => Err(format!("GSA request failed for {}: {}", query, e)),
+    }
+}
+
+/// Fetch the runs of a GSA accession.
+async fn fetch_gsa_runs(
+    accession: &str,
+    policy: RetryPolicy,
+) -> Result<Vec<HashMap<String, String>>, String> {
+    let request = client()
+        .post(GSA_RUN_INFO_URL)
+        .form(&[("searchTerm", accession)]);
+    let run_info = send_with_retry(request, policy)
+        .await?
+        .error_for_status()
+        .map_err(|e| e.to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut runs = parse_run_info(&run_info, accession)?;
+
+    let mut md5sums: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    for run in runs.iter_mut() {
+        let Some(submission) = run.get("secondary_study_accession").cloned() else {
+            continue;
+        };
+        if !md5sums.contains_key(&submission) {
+            let url = format!(
+                "https://{}/{}/{}",
+                GSA_DOWNLOAD_URL, submission, MD5SUM_FILE
+            );
+            let content = send_with_retry(client().get(&url), policy)
+                .await
+                .and_then(|response| response.error_for_status().map_err(|e| e.to_string()))
+                .map_err(|e| format!("{}: {}", url, e))?
+                .text()
+                .await
+                .map_err(|e| e.to_string())?;
+            md5sums.insert(submission.clone(), parse_md5sum(&content, &submission));
+        }
+        add_files(run, &md5sums[&submission]);
+    }
+
+    Ok(runs)
+}
+
+/// Parse an NGDC RunInfo export into ENA-shaped runs, without files.
+///
+/// # Arguments
+/// * `content` - The comma-separated RunInfo export.
+/// * `accession` - The accession searched; rows not listing it are dropped.
+///
+/// # Returns
+/// The runs, with the submission (CRA) as `secondary_study_accession`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::gsa::parse_run_info;
+///
+/// let content = "Run,Experiment,BioSample,BioProject,Submission,LibraryLayout\n\
+///     CRR000001,CRX000001,SAMC000001,PRJCA000001,CRA000001,PAIRED\n";
+/// let runs = parse_run_info(content, "CRA000001").unwrap();
+/// assert_eq!(runs[0]["run_accession"], "CRR000001");
+/// assert_eq!(runs[0]["secondary_study_accession"], "CRA000001");
+/// ```
+pub fn parse_run_info(
+    content: &str,
+    accession: &str,
+) -> Result<Vec<HashMap<String, String>>, String> {
+    let runs = read_runinfo(content)?
+        .iter()
+        .filter(|row| row.values().any(|value| value == accession))
+        .map(|row| {
+            let mut run = as_ena_run(row);
+            // INFO: GSA files live under the submission, not the project
+            match SUBMISSION_COLUMNS
+                .iter()
+                .filter_map(|column| row.get(*column))
+                .find(|value| SUBMISSION_RE.is_match(value))
+            {
+                Some(submission) => {
+                    run.insert("secondary_study_accession".to_string(), submission.clone());
+                }
+                None => {
+                    run.remove("secondary_study_accession");
+                }
+            }
+            run
+        })
+        .collect();
+
+    Ok(runs)
+}
+
+/// Parse the `md5sum.txt` of a GSA submission.
+///
+/// # Arguments
+/// * `content` - Lines of `<md5>  <path>`.
+/// * `submission` - The submission (CRA) the file belongs to.
+///
+/// # Returns
+/// The checksum and path, relative to the submission directory, of every
+/// file, sorted by path.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::gsa::parse_md5sum;
+///
+/// let content = "d41d8cd98f00b204e9800998ecf8427e  CRR000001/CRR000001_f1.fq.gz\n";
+/// let files = parse_md5sum(content, "CRA000001");
+/// assert_eq!(files[0].1, "CRR000001/CRR000001_f1.fq.gz");
+/// ```
+pub fn parse_md5sum(content: &str, submission: &str) -> Vec<(String, String)> {
+    let mut files = content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let md5 = fields.next()?;
+            let path = fields
+                .last()?
+                .trim_start_matches('*')
+                .trim_start_matches("./");
+            let path = path
+                .strip_prefix(&format!("{}/", submission))
+                .unwrap_or(path);
+
+            // INFO: files are kept under a directory named after their run
+            let name = path.rsplit('/').next().unwrap_or(path);
+            let run = run_accession_of(name)?;
+            let path = if path.contains('/') {
+                path.to_string()
+            } else {
+                format!("{}/{}", run, path)
+            };
+            Some((md5.to_lowercase(), path))
+        })
+        .collect::<Vec<_>>();
+
+    files.sort_by(|a, b| a.1.cmp(&b.1));
+    files
+}
+
+/// Fill in `fastq_ftp` and `fastq_md5` of a GSA run from the checksums of
+/// its submission.
+fn add_files(run: &mut HashMap<String, String>, files: &[(String, String)]) {
+    let (Some(accession), Some(submission)) = (
+        run.get("run_accession").cloned(),
+        run.get("secondary_study_accession").cloned(),
+    ) else {
+        return;
+    };
+
+    let (urls, md5s): (Vec<String>, Vec<String>) = files
+        .iter()
+        .filter(|(_, path)| path.starts_with(&format!("{}/", accession)))
+        .filter(|(_, path)| path.ends_with(".fq.gz") || path.ends_with(".fastq.gz"))
+        .map(|(md5, path)| {
+            (
+                format!("{}/{}/{}", GSA_DOWNLOAD_URL, submission, path),
+                md5.clone(),
+            )
+        })
+        .unzip();
+
+    run.insert("fastq_ftp".to_string(), urls.join(";"));
+    run.insert("fastq_md5".to_string(), md5s.join(";"));
+}
@@ -0,0 +1,213 @@
+use serde_json::Value;
+use tokio::process::Command;
+
+use std::path::Path;
+use std::time::Duration;
+
+use crate::net::{client, fetch, retry_policy, send_with_retry};
+use crate::provs::sra::SRAError;
+use crate::runner::runner;
+use crate::utils::{drop_partial, Checksum};
+
+const SDL_URL: &str = "https://locate.ncbi.nlm.nih.gov/sdl/2/retrieve";
+// INFO: the normalized run, what prefetch fetches by default
+const SRA_TYPE: &str = "sra";
+
+/// A copy of the `.sra` object of a run, as the NCBI SRA Data Locator
+/// reports it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Located {
+    /// HTTPS link to the object
+    pub link: String,
+    /// Where it is stored: `ncbi`, `s3` or `gs`
+    pub service: String,
+    /// Region of the copy, empty if not given
+    pub region: String,
+    /// MD5 of the object, if given
+    pub md5: Option<String>,
+    /// Size of the object in bytes, if given
+    pub size: Option<u64>,
+}
+
+/// Ask the NCBI SRA Data Locator where the `.sra` object of a run is.
+///
+/// # Arguments
+/// * `accession` - The run accession.
+/// * `location` - Where the download runs from, e.g. `gs.US` or
+///   `s3.us-east-1`; copies there are listed first. `None` outside a cloud.
+///
+/// # Returns
+/// The copies of the object, or why the locator could not be asked.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::sdl::locate;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let copies = locate("SRR123456", Some("gs.US")).await.unwrap();
+/// }
+/// ```
+pub async fn locate(accession: &str, location: Option<&str>) -> Result<Vec<Located>, String> {
+    let mut form = vec![("acc", accession), ("accept-alternate-locations", "yes")];
+    if let Some(location) = location {
+        form.push(("location", location));
+    }
+
+    let answer = send_with_retry(client().post(SDL_URL).form(&form), retry_policy())
+        .await?
+        .error_for_status()
+        .map_err(|e| e.to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    serde_json::from_str::<Value>(&answer)
+        .map(|answer| parse_locations(&answer))
+        .map_err(|e| e.to_string())
+}
+
+/// Read the copies of the `.sra` objects out of an SRA Data Locator answer.
+///
+/// # Arguments
+/// * `answer` - The JSON answer of `/sdl/2/retrieve`.
+///
+/// # Returns
+/// The copies, in the order the locator lists them.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::sdl::parse_locations;
+///
+/// let answer = serde_json::json!({"result": [{"files": [{
+///     "type": "sra", "md5": "d41d8cd98f00b204e9800998ecf8427e", "size": 0,
+///     "locations": [{"link": "https://storage.googleapis.com/sra-pub-run-1/SRR123456/SRR123456.1",
+///                    "service": "gs", "region": "us-east1"}]
+/// }]}]});
+/// assert_eq!(parse_locations(&answer)[0].service, "gs");
+/// ```
+pub fn parse_locations(answer: &Value) -> Vec<Located> {
+    answer["result"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|bundle| bundle["files"].as_array().cloned().unwrap_or_default())
+        .filter(|file| file["type"].as_str() == Some(SRA_TYPE))
+        .flat_map(|file| {
+            let md5 = file["md5"].as_str().map(str::to_lowercase);
+            let size = file["size"].as_u64();
+            file["locations"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(move |location| {
+                    Some(Located {
+                        link: location["link"].as_str()?.to_string(),
+                        service: location["service"].as_str().unwrap_or_default().to_string(),
+                        region: location["region"].as_str().unwrap_or_default().to_string(),
+                        md5: md5.clone(),
+                        size,
+                    })
+                })
+        })
+        .collect()
+}
+
+/// Pick the copy to fetch, going through the services in order of preference.
+///
+/// # Arguments
+/// * `copies` - The copies the locator gave.
+/// * `services` - The services to take copies from, preferred first.
+///
+/// # Returns
+/// The first copy on the most preferred service, `None` if no copy is on
+/// any of them.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::sdl::{best, Located};
+///
+/// let copy = |service: &str| Located {
+///     link: format!("https://{}.example/SRR123456", service),
+///     service: service.to_string(),
+///     region: String::new(),
+///     md5: None,
+///     size: None,
+/// };
+/// let copies = [copy("gs"), copy("ncbi")];
+/// assert_eq!(best(&copies, &["ncbi", "gs"]).unwrap().service, "ncbi");
+/// assert!(best(&copies, &["s3"]).is_none());
+/// ```
+pub fn best<'a>(copies: &'a [Located], services: &[&str]) -> Option<&'a Located> {
+    services
+        .iter()
+        .find_map(|service| copies.iter().find(|copy| copy.service == *service))
+}
+
+/// Fetch a located `.sra` object, retrying until it matches its MD5.
+///
+/// # Arguments
+/// * `located` - The copy to fetch.
+/// * `output` - Where to write the object.
+/// * `attempts` - The number of attempts to make.
+/// * `sleep` - The number of seconds to sleep between attempts.
+/// * `copy` - Builds the command copying the object with a cloud CLI, `None`
+///   to fetch its link over HTTPS instead.
+pub(crate) async fn fetch_located<F>(
+    located: &Located,
+    output: &Path,
+    attempts: usize,
+    sleep: usize,
+    copy: F,
+) -> Result<(), SRAError>
+where
+    F: Fn() -> Option<Command>,
+{
+    let mut current_attempt = 0;
+    loop {
+        current_attempt += 1;
+        let fetched = match copy() {
+            Some(cmd) => match runner().run(cmd).await {
+                Ok(outcome) if outcome.success() => Ok(()),
+                Ok(outcome) => Err(format!(
+                    "copy exited with status {}",
+                    outcome.code.unwrap_or(-1)
+                )),
+                Err(e) => Err(e.to_string()),
+            },
+            None => fetch(&located.link, output, &[]).await.map(|_| ()),
+        };
+        let fetched = fetched.and_then(|()| match &located.md5 {
+            Some(md5) => match Checksum::Md5.digest(output) {
+                Some(observed) if observed == *md5 => Ok(()),
+                observed => {
+                    // INFO: resuming would keep the bad bytes
+                    drop_partial(output);
+                    Err(format!(
+                        "MD5 mismatch: expected {}, observed {}",
+                        md5,
+                        observed.unwrap_or_default()
+                    ))
+                }
+            },
+            None => Ok(()),
+        });
+
+        match fetched {
+            Ok(()) => return Ok(()),
+            Err(e) if current_attempt >= attempts => {
+                return Err(SRAError::Unavailable(format!("{}: {}", located.link, e)));
+            }
+            Err(e) => {
+                log::warn!(
+                    "WARNING: Fetching {} failed ({}), retrying in {}s",
+                    located.link,
+                    e,
+                    sleep
+                );
+                tokio::time::sleep(Duration::from_secs(sleep as u64)).await;
+            }
+        }
+    }
+}
@@ -1,22 +1,97 @@
-use crate::utils::Layout;
+use crate::chunk::{chunk_reads, existing_chunks, split_fastq};
+use crate::fasta::{fasta_enabled, fasta_name, to_fasta};
+use crate::net::proxy_env;
+use crate::perms::{create_dirs, install};
+use crate::preempt::{track_file, Tracked};
+use crate::provs::sdl::{best, fetch_located, locate};
+use crate::runner::{runner, Spawned};
+use crate::scheduler::{blocking, cpu_slot, transfer_slot};
+use crate::utils::{part_path, renamed, Layout, PART_SUFFIX};
+use futures::future::try_join_all;
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+use std::io::{BufRead, Write};
 use std::path::{Path, PathBuf};
+use std::process::Stdio;
 use std::time::Duration;
 use tokio::process::Command;
-use which::which;
 
 const PREFETCH: &str = "prefetch";
-const FASTERQ_DUMP: &str = "fasterq-dump";
+pub(crate) const FASTERQ_DUMP: &str = "fasterq-dump";
+const FASTQ_DUMP: &str = "fastq-dump";
 const PIGZ: &str = "pigz";
+// INFO: prefetch downloads from NCBI, so --host-limit for this host caps it too
+const NCBI_HOST: &str = "https://sra-downloadb.be-md.ncbi.nlm.nih.gov";
+// INFO: every service serves its public copies over plain HTTPS
+const SDL_SERVICES: &[&str] = &["ncbi", "s3", "gs"];
+
+// INFO: oldest toolkit whose prefetch/fasterq-dump understand the flags used here
+const MIN_SRA_TOOLS: ToolVersion = ToolVersion(2, 9, 6);
+// INFO: fasterq-dump releases before this one have no --mem
+const MEM_SINCE: ToolVersion = ToolVersion(2, 10, 0);
+// INFO: fasterq-dump releases before this one cannot set the quality defline
+const STREAM_SINCE: ToolVersion = ToolVersion(2, 10, 0);
+const RECOMMENDED_SRA_TOOLS: ToolVersion = ToolVersion(3, 0, 0);
+
+// INFO: the quality line carries spot and mate, so reads can be split while streaming
+const STREAM_QUAL_DEFLINE: &str = "+$si:$ri";
+const STREAM_BUFFER: usize = 1 << 20; // 1 MiB
+
+static VERSION_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(\d+)\.(\d+)\.(\d+)").unwrap());
+static TOOLKIT: OnceCell<ToolVersion> = OnceCell::new();
+
+/// A `major.minor.patch` sra-tools release
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ToolVersion(pub u32, pub u32, pub u32);
+
+impl ToolVersion {
+    /// Parse the output of `<tool> --version`, e.g. `fasterq-dump : 3.0.10`.
+    ///
+    /// # Arguments
+    /// * `output` - The version output.
+    ///
+    /// # Returns
+    /// The first `major.minor.patch` found, if any.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use rsfq::provs::sra::ToolVersion;
+    /// assert_eq!(
+    ///     ToolVersion::parse("\nfasterq-dump : 3.0.10\n"),
+    ///     Some(ToolVersion(3, 0, 10))
+    /// );
+    /// ```
+    pub fn parse(output: &str) -> Option<Self> {
+        let caps = VERSION_RE.captures(output)?;
+        let part = |idx: usize| caps.get(idx)?.as_str().parse::<u32>().ok();
+        Some(ToolVersion(part(1)?, part(2)?, part(3)?))
+    }
+}
+
+impl std::fmt::Display for ToolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
 
 /// Errors that can occur while downloading runs from SRA.
 #[derive(Debug)]
 pub enum SRAError {
     MissingTool(&'static str),
-    CommandFailed { tool: &'static str, code: i32 },
+    UnsupportedVersion {
+        tool: &'static str,
+        found: ToolVersion,
+        minimum: ToolVersion,
+    },
+    CommandFailed {
+        tool: &'static str,
+        code: i32,
+    },
     NotFound(&'static str),
     Io(std::io::Error),
     NoFastqProduced(String),
     LayoutMismatch(String),
+    Unavailable(String),
 }
 
 impl From<std::io::Error> for SRAError {
@@ -25,20 +100,106 @@ impl From<std::io::Error> for SRAError {
     }
 }
 
-/// Ensure all SRA command line tools are available in PATH.
+/// Ensure the SRA command line tools a run needs are available in PATH and
+/// recent enough, along with pigz.
+///
+/// The toolkit version is detected once per process from `<tool> --version`
+/// of each tool; the oldest is the one used. An unparseable version is
+/// assumed to be supported.
+///
+/// # Arguments
+///
+/// * `tools` - The sra-tools binaries needed.
 ///
 /// # Returns
 ///
-/// A `Result` with an `SRAError` if any of the tools are not available.
-fn ensure_tools() -> Result<(), SRAError> {
-    for tool in [PREFETCH, FASTERQ_DUMP, PIGZ] {
-        which(tool).map_err(|_| SRAError::MissingTool(tool))?;
+/// The detected toolkit version, or an `SRAError` if any of the tools are not
+/// available or older than the supported minimum.
+pub(crate) async fn ensure_tools(tools: &[&'static str]) -> Result<ToolVersion, SRAError> {
+    for tool in tools.iter().copied().chain([PIGZ]) {
+        if !runner().installed(tool) {
+            return Err(SRAError::MissingTool(tool));
+        }
     }
-    Ok(())
+
+    if let Some(version) = TOOLKIT.get() {
+        return Ok(*version);
+    }
+
+    let mut toolkit = None;
+    for &tool in tools {
+        let Some(version) = tool_version(tool).await else {
+            log::warn!(
+                "WARNING: Could not detect the {} version, assuming it is supported",
+                tool
+            );
+            continue;
+        };
+
+        log::info!("Found {} {}", tool, version);
+        if version < MIN_SRA_TOOLS {
+            log::error!(
+                "ERROR: {} {} is too old, rsfq needs sra-tools >= {}. Please upgrade sra-tools!",
+                tool,
+                version,
+                MIN_SRA_TOOLS
+            );
+            return Err(SRAError::UnsupportedVersion {
+                tool,
+                found: version,
+                minimum: MIN_SRA_TOOLS,
+            });
+        }
+
+        toolkit = Some(toolkit.map_or(version, |older: ToolVersion| older.min(version)));
+    }
+
+    // INFO: an undetectable toolkit gets every flag, as before version checks
+    let version = toolkit.unwrap_or(RECOMMENDED_SRA_TOOLS);
+    if version < RECOMMENDED_SRA_TOOLS {
+        log::warn!(
+            "WARNING: sra-tools {} is supported but outdated, consider upgrading to >= {}",
+            version,
+            RECOMMENDED_SRA_TOOLS
+        );
+    }
+    if version < MEM_SINCE {
+        log::warn!(
+            "WARNING: {} {} has no --mem, running without it",
+            FASTERQ_DUMP,
+            version
+        );
+    }
+
+    Ok(*TOOLKIT.get_or_init(|| version))
+}
+
+/// Get the version of an sra-tools binary.
+///
+/// # Arguments
+///
+/// * `tool` - The binary name.
+///
+/// # Returns
+///
+/// The version, if `<tool> --version` runs and prints one.
+async fn tool_version(tool: &str) -> Option<ToolVersion> {
+    let mut cmd = Command::new(tool);
+    cmd.arg("--version")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let output = runner().run(cmd).await.ok()?;
+
+    ToolVersion::parse(&String::from_utf8_lossy(&output.stdout))
+        .or_else(|| ToolVersion::parse(&String::from_utf8_lossy(&output.stderr)))
 }
 
 /// Download FASTQs for a run accession via SRA.
 ///
+/// The `.sra` object is fetched over HTTPS from the copy the NCBI SRA Data
+/// Locator points to, preferring NCBI over the AWS and GCP mirrors. Only
+/// when no copy can be fetched that way is prefetch used.
+///
 /// # Arguments
 ///
 /// * `accession` - The SRA run accession to download.
@@ -48,6 +209,7 @@ fn ensure_tools() -> Result<(), SRAError> {
 /// * `sleep` - The number of seconds to sleep between attempts.
 /// * `force` - Whether to force downloading of existing files.
 /// * `layout` - The layout of the run.
+/// * `spots` - The number of spots to keep, `None` for every spot.
 ///
 /// # Returns
 ///
@@ -57,21 +219,26 @@ fn ensure_tools() -> Result<(), SRAError> {
 ///
 /// ```no_run
 /// use rsfq::provs::sra::download_run;
-/// use rsfq::provs::sra::Layout;
+/// use rsfq::utils::Layout;
 ///
-/// let outdir = "~/Downloads/SRA";
-/// let layout = Layout::Paired;
+/// #[tokio::main]
+/// async fn main() {
+///     let outdir = "~/Downloads/SRA";
+///     let layout = Layout::Paired;
 ///
-/// download_run(
-///     "SRR123456",
-///     outdir,
-///     4,
-///     3,
-///     5,
-///     false,
-///     layout,
-/// ).await.unwrap();
+///     download_run(
+///         "SRR123456",
+///         outdir,
+///         4,
+///         3,
+///         5,
+///         false,
+///         layout,
+///         None,
+///     ).await.unwrap();
+/// }
 /// ```
+#[allow(clippy::too_many_arguments)]
 pub async fn download_run<K: AsRef<Path>>(
     accession: &str,
     outdir: K,
@@ -80,26 +247,33 @@ pub async fn download_run<K: AsRef<Path>>(
     sleep: usize,
     force: bool,
     layout: Layout,
+    spots: Option<u64>,
 ) -> Result<Vec<PathBuf>, SRAError> {
-    ensure_tools()?;
+    let toolkit = ensure_tools(&[FASTERQ_DUMP]).await?;
 
     let outdir = outdir.as_ref();
-    std::fs::create_dir_all(outdir)?;
-
-    let gz_paths = gz_candidates(accession, outdir);
-    if !force && layout_satisfied(layout, outdir, accession) {
-        log::info!(
-            "Skipping download for {} because FASTQ files already exist",
-            accession
-        );
-        return Ok(existing_paths(&gz_paths));
+    if let Some(existing) = existing_run(accession, outdir, force, layout)? {
+        return Ok(existing);
     }
 
-    if force {
-        remove_existing(&gz_paths)?;
+    let sra = outdir.join(format!("{}.sra", accession));
+    match fetch_from_sdl(accession, &sra, attempts, sleep).await {
+        Ok(()) => {
+            return dump_run(
+                accession, outdir, toolkit, threads, attempts, sleep, layout, spots,
+            )
+            .await
+        }
+        Err(e) => log::warn!(
+            "WARNING: Could not fetch {} through the SRA Data Locator ({:?}), falling back to prefetch",
+            accession,
+            e
+        ),
     }
 
-    run_with_retry(
+    ensure_tools(&[PREFETCH]).await?;
+    let slot = transfer_slot(NCBI_HOST).await;
+    let prefetched = run_with_retry(
         || {
             let mut cmd = Command::new(PREFETCH);
             cmd.arg(accession)
@@ -107,6 +281,7 @@ pub async fn download_run<K: AsRef<Path>>(
                 .arg("10T")
                 .arg("-o")
                 .arg(format!("{}.sra", accession))
+                .envs(proxy_env())
                 .current_dir(outdir);
             cmd
         },
@@ -114,41 +289,545 @@ pub async fn download_run<K: AsRef<Path>>(
         sleep,
         PREFETCH,
     )
-    .await?;
+    .await;
+    match prefetched {
+        Ok(()) => slot.finished(std::fs::metadata(&sra).map(|m| m.len()).unwrap_or(0)),
+        Err(e) => {
+            slot.failed();
+            return Err(e);
+        }
+    }
 
-    run_with_retry(
-        || {
-            let mut cmd = Command::new(FASTERQ_DUMP);
-            cmd.arg(accession)
-                .arg("--split-3")
-                .arg("--mem")
-                .arg("1G")
-                .arg("--threads")
-                .arg(threads.max(1).to_string())
-                .current_dir(outdir);
-            cmd
-        },
-        attempts,
-        sleep,
-        FASTERQ_DUMP,
+    dump_run(
+        accession, outdir, toolkit, threads, attempts, sleep, layout, spots,
     )
-    .await?;
+    .await
+}
 
-    let produced = compress_fastqs(accession, outdir, threads).await?;
+/// Fetch the `.sra` object of a run from the copy the SRA Data Locator
+/// prefers, staged under `.part`.
+///
+/// # Arguments
+///
+/// * `accession` - The SRA run accession.
+/// * `sra` - Where to write the object.
+/// * `attempts` - The number of attempts to make.
+/// * `sleep` - The number of seconds to sleep between attempts.
+async fn fetch_from_sdl(
+    accession: &str,
+    sra: &Path,
+    attempts: usize,
+    sleep: usize,
+) -> Result<(), SRAError> {
+    let copies = locate(accession, None)
+        .await
+        .map_err(|e| SRAError::Unavailable(format!("{}: {}", accession, e)))?;
+    let located = best(&copies, SDL_SERVICES)
+        .ok_or_else(|| SRAError::Unavailable(format!("{} has no located copy", accession)))?;
+    log::info!(
+        "Fetching {} from {} ({})",
+        accession,
+        located.service,
+        located.link
+    );
+
+    // INFO: kept on SIGTERM, the next run resumes it
+    let part = part_path(sra);
+    let slot = transfer_slot(&located.link).await;
+    match fetch_located(located, &part, attempts, sleep, || None).await {
+        Ok(()) => slot.finished(part.metadata().map(|m| m.len()).unwrap_or(0)),
+        Err(e) => {
+            slot.failed();
+            return Err(e);
+        }
+    }
+    install(&part, sra)?;
+
+    Ok(())
+}
+
+/// Get the FASTQs of a run that are already there, clearing them first
+/// with `force`.
+///
+/// # Arguments
+///
+/// * `accession` - The SRA run accession to download.
+/// * `outdir` - The directory to download the FASTQs to.
+/// * `force` - Whether to force downloading of existing files.
+/// * `layout` - The layout of the run.
+///
+/// # Returns
+///
+/// The existing FASTQs if they satisfy the layout, `None` if the run has
+/// to be downloaded.
+pub(crate) fn existing_run(
+    accession: &str,
+    outdir: &Path,
+    force: bool,
+    layout: Layout,
+) -> Result<Option<Vec<PathBuf>>, SRAError> {
+    create_dirs(outdir)?;
+
+    let gz_paths = gz_candidates(accession, outdir);
+    if !force && layout_satisfied(layout, outdir, accession) {
+        log::info!(
+            "Skipping download for {} because FASTQ files already exist",
+            accession
+        );
+        return Ok(Some(existing_paths(&gz_paths)));
+    }
+
+    if force {
+        remove_existing(&gz_paths)?;
+    }
+
+    Ok(None)
+}
+
+/// Turn the `<accession>.sra` of a run in `outdir` into compressed FASTQs,
+/// removing it afterwards.
+///
+/// # Arguments
+///
+/// * `accession` - The SRA run accession to download.
+/// * `outdir` - The directory holding the `.sra` file.
+/// * `toolkit` - The sra-tools version, from `ensure_tools`.
+/// * `threads` - The number of threads to use for dumping.
+/// * `attempts` - The number of attempts to make.
+/// * `sleep` - The number of seconds to sleep between attempts.
+/// * `layout` - The layout of the run.
+/// * `spots` - The number of spots to keep, `None` for every spot.
+///
+/// # Returns
+///
+/// A vector of paths to the FASTQs.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn dump_run(
+    accession: &str,
+    outdir: &Path,
+    toolkit: ToolVersion,
+    threads: usize,
+    attempts: usize,
+    sleep: usize,
+    layout: Layout,
+    spots: Option<u64>,
+) -> Result<Vec<PathBuf>, SRAError> {
+    let gz_paths = gz_candidates(accession, outdir);
+
+    // INFO: dumping and compressing are bound by local CPUs, not the network
+    let _cpu = cpu_slot(threads).await;
+    let produced = if toolkit >= STREAM_SINCE {
+        stream_fastqs(accession, outdir, threads, attempts, sleep, spots).await?
+    } else {
+        // INFO: a half-dumped FASTQ cannot be resumed, SIGTERM removes it
+        let _raws = raw_candidates(accession, outdir)
+            .iter()
+            .map(|raw| track_file(raw))
+            .collect::<Vec<Tracked>>();
+        // INFO: fasterq-dump cannot stop early, fastq-dump -X dumps only the spots kept
+        let tool = if spots.is_some() {
+            FASTQ_DUMP
+        } else {
+            FASTERQ_DUMP
+        };
+        run_with_retry(
+            || {
+                let mut cmd = Command::new(tool);
+                cmd.arg(accession).arg("--split-3");
+                if let Some(spots) = spots {
+                    cmd.arg("-X").arg(spots.to_string()).arg("-O").arg(".");
+                } else {
+                    if toolkit >= MEM_SINCE {
+                        cmd.arg("--mem").arg("1G");
+                    }
+                    cmd.arg("--threads").arg(threads.max(1).to_string());
+                }
+                cmd.current_dir(outdir);
+                cmd
+            },
+            attempts,
+            sleep,
+            tool,
+        )
+        .await?;
+
+        let produced = compress_fastqs(accession, outdir, threads).await?;
+        if fasta_enabled() {
+            blocking(move || {
+                produced
+                    .iter()
+                    .map(|fastq| to_fasta(fastq))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .await?
+        } else {
+            produced
+        }
+    };
     cleanup_sra(accession, outdir)?;
 
     if !layout_satisfied(layout, outdir, accession) {
         return Err(SRAError::LayoutMismatch(accession.to_string()));
     }
 
-    Ok(if produced.is_empty() {
+    let produced = if produced.is_empty() {
         existing_paths(&gz_paths)
     } else {
         produced
-    })
+    };
+
+    match chunk_reads() {
+        Some(reads) => {
+            let chunks = blocking(move || {
+                let mut chunks = Vec::new();
+                for fastq in produced.iter().filter(|fastq| fastq.exists()) {
+                    chunks.extend(split_fastq(fastq, reads)?);
+                }
+                Ok::<_, std::io::Error>(chunks)
+            })
+            .await?;
+            Ok(chunks)
+        }
+        None => Ok(produced),
+    }
 }
 
-/// Compress FASTQs for a run accession via SRA.
+/// Convert a prefetched run straight into compressed FASTQs, with no
+/// uncompressed FASTQ ever written to disk.
+///
+/// fasterq-dump streams every read to stdout (`--split-spot --stdout`) and
+/// the reads are split like `--split-3` would: both mates of a spot go to
+/// `_1`/`_2`, lone reads to the single-end file. Each output is compressed
+/// by its own pigz as the reads arrive.
+///
+/// # Arguments
+///
+/// * `accession` - The SRA run accession to download.
+/// * `outdir` - The directory to download the FASTQs to.
+/// * `threads` - The number of threads to use for downloading.
+/// * `attempts` - The number of attempts to make.
+/// * `sleep` - The number of seconds to sleep between attempts.
+/// * `spots` - The number of spots to keep, `None` for every spot.
+///
+/// # Returns
+///
+/// A vector of paths to the compressed FASTQs.
+async fn stream_fastqs(
+    accession: &str,
+    outdir: &Path,
+    threads: usize,
+    attempts: usize,
+    sleep: usize,
+    spots: Option<u64>,
+) -> Result<Vec<PathBuf>, SRAError> {
+    let mut current_attempt = 0;
+    loop {
+        current_attempt += 1;
+        match stream_once(accession, outdir, threads, spots).await {
+            Ok(produced) => return Ok(produced),
+            Err(SRAError::NotFound(tool)) => return Err(SRAError::NotFound(tool)),
+            Err(err) => {
+                remove_existing(&gz_candidates(accession, outdir))?;
+                if current_attempt >= attempts {
+                    return Err(err);
+                }
+
+                log::warn!(
+                    "WARNING: Streaming {} failed ({:?}), retrying in {}s",
+                    accession,
+                    err,
+                    sleep
+                );
+                tokio::time::sleep(Duration::from_secs(sleep as u64)).await;
+            }
+        }
+    }
+}
+
+/// A FASTQ record as streamed by fasterq-dump with `STREAM_QUAL_DEFLINE`
+struct Record {
+    header: String,
+    seq: String,
+    qual: String,
+    spot: String,
+    mate: String,
+}
+
+/// A pigz process compressing one output FASTQ
+struct Compressor {
+    child: Spawned,
+    stdin: std::io::BufWriter<Box<dyn Write + Send>>,
+    path: PathBuf,
+    _file: Tracked,
+}
+
+impl Compressor {
+    /// Start compressing into `path`.
+    fn start(path: PathBuf, threads: usize) -> Result<Self, SRAError> {
+        // INFO: written under a staging name until pigz is done
+        let output = std::fs::File::create(part_path(&path))?;
+        let _file = track_file(&part_path(&path));
+
+        let mut cmd = Command::new(PIGZ);
+        cmd.arg("-p").arg(threads.max(1).to_string()).arg("-c");
+        let mut child = runner().spawn(cmd, Some(output))?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| std::io::Error::other("pigz has no stdin"))?;
+
+        Ok(Compressor {
+            child,
+            stdin: std::io::BufWriter::with_capacity(STREAM_BUFFER, stdin),
+            path,
+            _file,
+        })
+    }
+
+    /// Write a record, with a bare `+` as quality line, or as FASTA with `--fasta`.
+    fn write(&mut self, record: &Record) -> Result<(), SRAError> {
+        if fasta_enabled() {
+            let header = record.header.strip_prefix('@').unwrap_or(&record.header);
+            writeln!(self.stdin, ">{}\n{}", header, record.seq)?;
+            return Ok(());
+        }
+
+        writeln!(
+            self.stdin,
+            "{}\n{}\n+\n{}",
+            record.header, record.seq, record.qual
+        )?;
+        Ok(())
+    }
+
+    /// Close the input and wait for the compressed file.
+    fn finish(self) -> Result<Staged, SRAError> {
+        let Compressor {
+            mut child,
+            stdin,
+            path,
+            _file,
+        } = self;
+        drop(stdin.into_inner().map_err(|e| e.into_error())?);
+
+        let outcome = child.wait()?;
+        if !outcome.success() {
+            return Err(SRAError::CommandFailed {
+                tool: PIGZ,
+                code: outcome.code.unwrap_or(-1),
+            });
+        }
+
+        Ok(Staged { path, _file })
+    }
+}
+
+/// A compressed FASTQ still under its staging name, installed once the
+/// whole dump is known to have succeeded.
+struct Staged {
+    path: PathBuf,
+    _file: Tracked,
+}
+
+impl Staged {
+    /// Give the FASTQ its final name.
+    fn install(self) -> Result<PathBuf, SRAError> {
+        install(&part_path(&self.path), &self.path)?;
+        Ok(self.path)
+    }
+}
+
+/// Stream a run through fasterq-dump and pigz once.
+///
+/// Reads are split and copied on a blocking thread, in large buffered
+/// chunks, so the runtime's workers stay free for transfers.
+///
+/// # Arguments
+///
+/// * `accession` - The SRA run accession to download.
+/// * `outdir` - The directory to download the FASTQs to.
+/// * `threads` - The number of threads to use for downloading.
+/// * `spots` - The number of spots to keep; fasterq-dump is stopped once
+///   they are written.
+///
+/// # Returns
+///
+/// A vector of paths to the compressed FASTQs.
+async fn stream_once(
+    accession: &str,
+    outdir: &Path,
+    threads: usize,
+    spots: Option<u64>,
+) -> Result<Vec<PathBuf>, SRAError> {
+    let (accession, outdir) = (accession.to_string(), outdir.to_path_buf());
+    // INFO: runs within the cpu_slot(threads) dump_run holds for the whole dump
+    blocking(move || split_stream(&accession, &outdir, threads, spots)).await
+}
+
+/// Run fasterq-dump and split its reads into one pigz per output.
+fn split_stream(
+    accession: &str,
+    outdir: &Path,
+    threads: usize,
+    spots: Option<u64>,
+) -> Result<Vec<PathBuf>, SRAError> {
+    let mut cmd = Command::new(FASTERQ_DUMP);
+    cmd.arg(accession)
+        .arg("--split-spot")
+        .arg("--stdout")
+        .arg("--skip-technical")
+        .arg("--qual-defline")
+        .arg(STREAM_QUAL_DEFLINE)
+        .arg("--threads")
+        .arg(threads.max(1).to_string())
+        .current_dir(outdir);
+    let mut child = runner().spawn(cmd, None)?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| std::io::Error::other("fasterq-dump has no stdout"))?;
+    let mut reader = std::io::BufReader::with_capacity(STREAM_BUFFER, stdout);
+
+    // INFO: at most two outputs are busy at once (mates, or the single-end
+    // file), so they split the thread budget instead of each taking it all
+    let pigz_threads = (threads / 2).max(1);
+
+    // INFO: same order as gz_candidates, single-end first
+    let [single, r1, r2] = gz_candidates(accession, outdir);
+    let mut outputs: [(PathBuf, Option<Compressor>); 3] = [(single, None), (r1, None), (r2, None)];
+
+    let mut pending: Option<Record> = None;
+    let mut written = 0;
+    let mut truncated = false;
+    while let Some(record) = read_record(&mut reader)? {
+        if spots.is_some_and(|spots| written >= spots) {
+            // INFO: a mate still waiting for its pair is dropped with the rest
+            pending = None;
+            truncated = true;
+            break;
+        }
+
+        let (first, second) = match pending.take() {
+            Some(mate1) if mate1.spot == record.spot && record.mate == "2" => {
+                (Some((1, mate1)), Some((2, record)))
+            }
+            Some(lone) if record.mate == "1" => {
+                pending = Some(record);
+                (Some((0, lone)), None)
+            }
+            Some(lone) => (Some((0, lone)), Some((0, record))),
+            None if record.mate == "1" => {
+                pending = Some(record);
+                (None, None)
+            }
+            None => (Some((0, record)), None),
+        };
+
+        written += match (&first, &second) {
+            (Some((1, _)), Some(_)) => 1,
+            _ => first.is_some() as u64 + second.is_some() as u64,
+        };
+        for (idx, record) in first.into_iter().chain(second) {
+            let (path, compressor) = &mut outputs[idx];
+            if compressor.is_none() {
+                *compressor = Some(Compressor::start(path.clone(), pigz_threads)?);
+            }
+            if let Some(compressor) = compressor {
+                compressor.write(&record)?;
+            }
+        }
+    }
+    if let Some(lone) = pending {
+        let (path, compressor) = &mut outputs[0];
+        if compressor.is_none() {
+            *compressor = Some(Compressor::start(path.clone(), pigz_threads)?);
+        }
+        if let Some(compressor) = compressor {
+            compressor.write(&lone)?;
+        }
+    }
+
+    if truncated {
+        log::info!("Stopping {} after {} spots", accession, written);
+        child.kill()?;
+    }
+    drop(reader);
+    let outcome = child.wait()?;
+    let mut staged = Vec::new();
+    for (_, compressor) in outputs {
+        if let Some(compressor) = compressor {
+            staged.push(compressor.finish()?);
+        }
+    }
+
+    match outcome.code {
+        _ if truncated => {}
+        Some(0) => {}
+        Some(3) => return Err(SRAError::NotFound(FASTERQ_DUMP)),
+        code => {
+            return Err(SRAError::CommandFailed {
+                tool: FASTERQ_DUMP,
+                code: code.unwrap_or(-1),
+            })
+        }
+    }
+
+    if staged.is_empty() {
+        return Err(SRAError::NoFastqProduced(accession.to_string()));
+    }
+    // INFO: only a dump that succeeded as a whole takes the final names
+    staged.into_iter().map(Staged::install).collect()
+}
+
+/// Read the next FASTQ record from fasterq-dump.
+///
+/// # Arguments
+///
+/// * `reader` - The fasterq-dump output.
+///
+/// # Returns
+///
+/// The record, `None` at the end of the stream, or an error on a truncated
+/// record or a quality line without spot and mate.
+fn read_record<R: BufRead>(reader: &mut R) -> Result<Option<Record>, SRAError> {
+    let mut lines = [String::new(), String::new(), String::new(), String::new()];
+    for (idx, line) in lines.iter_mut().enumerate() {
+        if reader.read_line(line)? == 0 {
+            if idx == 0 {
+                return Ok(None);
+            }
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "truncated FASTQ record from fasterq-dump",
+            )
+            .into());
+        }
+        line.truncate(line.trim_end_matches(['\r', '\n']).len());
+    }
+
+    let [header, seq, plus, qual] = lines;
+    let (spot, mate) = plus
+        .strip_prefix('+')
+        .and_then(|defline| defline.rsplit_once(':'))
+        .map(|(spot, mate)| (spot.to_string(), mate.to_string()))
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unexpected quality line from fasterq-dump: {}", plus),
+            )
+        })?;
+
+    Ok(Some(Record {
+        header,
+        seq,
+        qual,
+        spot,
+        mate,
+    }))
+}
+
+/// Compress FASTQs for a run accession via SRA. Mates are compressed
+/// concurrently, each pigz getting an equal share of `threads`.
 ///
 /// # Arguments
 ///
@@ -162,12 +841,10 @@ pub async fn download_run<K: AsRef<Path>>(
 ///
 /// # Example
 ///
-/// ```no_run
+/// ```ignore
 /// use rsfq::provs::sra::compress_fastqs;
-/// use rsfq::provs::sra::Layout;
 ///
 /// let outdir = "~/Downloads/SRA";
-/// let layout = Layout::Paired;
 ///
 /// compress_fastqs(
 ///     "SRR123456",
@@ -180,40 +857,62 @@ async fn compress_fastqs(
     outdir: &Path,
     threads: usize,
 ) -> Result<Vec<PathBuf>, SRAError> {
-    let cpus = threads.max(1).to_string();
-    let raw_candidates = raw_candidates(accession, outdir);
-    let mut produced = Vec::new();
-
-    for raw in raw_candidates {
-        if raw.exists() {
-            let gz = PathBuf::from(format!("{}.gz", raw.to_string_lossy()));
-
-            run_with_retry(
-                || {
-                    let mut cmd = Command::new(PIGZ);
-                    cmd.arg("--force")
-                        .arg("-p")
-                        .arg(&cpus)
-                        .arg("-n")
-                        .arg(&raw)
-                        .current_dir(outdir);
-                    cmd
-                },
-                1,
-                0,
-                PIGZ,
-            )
-            .await?;
-
-            produced.push(gz);
-        }
+    let raws = raw_candidates(accession, outdir)
+        .into_iter()
+        .filter(|raw| raw.exists())
+        .collect::<Vec<PathBuf>>();
+    if raws.is_empty() {
+        return Err(SRAError::NoFastqProduced(accession.to_string()));
     }
 
-    if produced.is_empty() {
-        Err(SRAError::NoFastqProduced(accession.to_string()))
-    } else {
-        Ok(produced)
-    }
+    // INFO: mates are compressed side by side, splitting the thread budget
+    let cpus = (threads.max(1) / raws.len()).max(1).to_string();
+    try_join_all(raws.iter().map(|raw| compress_fastq(raw, outdir, &cpus))).await
+}
+
+/// Compress a single dumped FASTQ with pigz, applying `--rename-map`.
+///
+/// # Arguments
+///
+/// * `raw` - The dumped FASTQ.
+/// * `outdir` - The directory pigz runs in.
+/// * `cpus` - The number of pigz threads.
+///
+/// # Returns
+///
+/// The path to the compressed FASTQ.
+async fn compress_fastq(raw: &Path, outdir: &Path, cpus: &str) -> Result<PathBuf, SRAError> {
+    let gz = PathBuf::from(format!("{}.gz", raw.to_string_lossy()));
+    // INFO: written under a staging name until pigz is done
+    let part = part_path(&gz);
+    let _partial = track_file(&part);
+
+    run_with_retry(
+        || {
+            let mut cmd = Command::new(PIGZ);
+            cmd.arg("--force")
+                .arg("-p")
+                .arg(cpus)
+                .arg("-n")
+                .arg("-S")
+                .arg(format!(".gz{}", PART_SUFFIX))
+                .arg(raw)
+                .current_dir(outdir);
+            cmd
+        },
+        1,
+        0,
+        PIGZ,
+    )
+    .await?;
+
+    // INFO: pigz keeps the accession name, apply --rename-map afterwards
+    let target = gz.with_file_name(renamed(
+        &gz.file_name().unwrap_or_default().to_string_lossy(),
+    ));
+    install(&part, &target)?;
+
+    Ok(target)
 }
 
 /// Remove the SRA file for a run accession.
@@ -247,8 +946,10 @@ fn cleanup_sra(accession: &str, outdir: &Path) -> Result<(), SRAError> {
 /// A boolean indicating if the layout is satisfied.
 fn layout_satisfied(layout: Layout, outdir: &Path, accession: &str) -> bool {
     let [single, r1, r2] = gz_candidates(accession, outdir);
-    let has_single = single.exists();
-    let has_paired = r1.exists() && r2.exists();
+    // INFO: with --chunk-reads a FASTQ only survives as its chunks
+    let present = |path: &PathBuf| path.exists() || !existing_chunks(path).is_empty();
+    let has_single = present(&single);
+    let has_paired = present(&r1) && present(&r2);
 
     match layout {
         Layout::Single => has_single,
@@ -271,6 +972,12 @@ fn remove_existing(paths: &[PathBuf; 3]) -> Result<(), SRAError> {
         if path.exists() {
             std::fs::remove_file(path)?;
         }
+        if part_path(path).exists() {
+            std::fs::remove_file(part_path(path))?;
+        }
+        for chunk in existing_chunks(path) {
+            std::fs::remove_file(chunk)?;
+        }
     }
     Ok(())
 }
@@ -287,12 +994,18 @@ fn remove_existing(paths: &[PathBuf; 3]) -> Result<(), SRAError> {
 fn existing_paths(paths: &[PathBuf; 3]) -> Vec<PathBuf> {
     paths
         .iter()
-        .filter(|p| p.exists())
-        .cloned()
+        .flat_map(|p| {
+            if p.exists() {
+                vec![p.clone()]
+            } else {
+                existing_chunks(p)
+            }
+        })
         .collect::<Vec<_>>()
 }
 
-/// Get the paths to the FASTQs for a run accession.
+/// Get the paths to the FASTQs for a run accession, after `--rename-map`
+/// and `--fasta`.
 ///
 /// # Arguments
 ///
@@ -304,9 +1017,9 @@ fn existing_paths(paths: &[PathBuf; 3]) -> Vec<PathBuf> {
 /// A vector of paths to the FASTQs.
 fn gz_candidates(accession: &str, outdir: &Path) -> [PathBuf; 3] {
     [
-        outdir.join(format!("{}.fastq.gz", accession)),
-        outdir.join(format!("{}_1.fastq.gz", accession)),
-        outdir.join(format!("{}_2.fastq.gz", accession)),
+        outdir.join(fasta_name(&renamed(&format!("{}.fastq.gz", accession)))),
+        outdir.join(fasta_name(&renamed(&format!("{}_1.fastq.gz", accession)))),
+        outdir.join(fasta_name(&renamed(&format!("{}_2.fastq.gz", accession)))),
     ]
 }
 
@@ -343,7 +1056,7 @@ fn raw_candidates(accession: &str, outdir: &Path) -> [PathBuf; 3] {
 ///
 /// # Example
 ///
-/// ```no_run
+/// ```ignore
 /// use rsfq::provs::sra::run_with_retry;
 ///
 /// run_with_retry(
@@ -357,7 +1070,7 @@ fn raw_candidates(accession: &str, outdir: &Path) -> [PathBuf; 3] {
 ///     "ls",
 /// ).await.unwrap();
 /// ```
-async fn run_with_retry<F>(
+pub(crate) async fn run_with_retry<F>(
     mut builder: F,
     attempts: usize,
     sleep: usize,
@@ -369,14 +1082,12 @@ where
     let mut current_attempt = 0;
     while current_attempt < attempts {
         current_attempt += 1;
-        let mut command = builder();
-        let status = command.status().await?;
-
-        if status.success() {
+        let outcome = runner().run(builder()).await?;
+        if outcome.success() {
             return Ok(());
         }
 
-        match status.code() {
+        match outcome.code {
             Some(3) => return Err(SRAError::NotFound(tool)),
             Some(code) => {
                 if current_attempt >= attempts {
@@ -0,0 +1,89 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    perms::{create_dirs, settle},
+    utils::PART_SUFFIX,
+};
+
+pub const QUARANTINE_DIR: &str = "quarantine";
+pub const REASON_EXT: &str = "reason";
+
+/// Move a file that failed its checks out of the way, into the
+/// `quarantine/` subdirectory of its directory, next to a `<file>.reason`
+/// note saying why.
+///
+/// The original path is left free, so the next attempt (or the next run)
+/// downloads the file again instead of skipping it as already there. A
+/// file quarantined earlier under the same name is replaced.
+///
+/// # Arguments
+/// * `path` - The failed file, possibly a `.part` download.
+/// * `reason` - Why it failed, e.g. `MD5 mismatch: expected ..., observed ...`.
+///
+/// # Returns
+/// The quarantined file, or `None` if it could not be moved (it is then
+/// removed).
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::quarantine::quarantine;
+/// use std::path::Path;
+/// quarantine(Path::new("DOWNLOADS/SRR123456_1.fastq.gz.part"), "truncated gzip");
+/// ```
+pub fn quarantine(path: &Path, reason: &str) -> Option<PathBuf> {
+    let dir = path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(QUARANTINE_DIR);
+    let name = path.file_name()?.to_string_lossy().to_string();
+    let name = name.strip_suffix(PART_SUFFIX).unwrap_or(&name);
+    let dest = dir.join(name);
+
+    let moved = create_dirs(&dir).and_then(|()| std::fs::rename(path, &dest));
+    if let Err(e) = moved {
+        log::warn!(
+            "WARNING: Could not quarantine {}, removing it!: {}",
+            path.display(),
+            e
+        );
+        let _ = std::fs::remove_file(path);
+        return None;
+    }
+    settle(&dest);
+
+    let note = dir.join(format!("{}.{}", name, REASON_EXT));
+    let content = format!(
+        "{}\t{}\n",
+        humantime::format_rfc3339_seconds(SystemTime::now()),
+        reason
+    );
+    match std::fs::write(&note, content) {
+        Ok(()) => settle(&note),
+        Err(e) => log::warn!("WARNING: Could not write {}!: {}", note.display(), e),
+    }
+
+    log::warn!("WARNING: Quarantined {} ({})", dest.display(), reason);
+    Some(dest)
+}
+
+/// Check whether a path lies in a quarantine directory.
+///
+/// # Arguments
+/// * `path` - The path to check.
+///
+/// # Returns
+/// `true` if any of its directories is `quarantine/`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::quarantine::is_quarantined;
+/// use std::path::Path;
+/// assert!(is_quarantined(Path::new("DOWNLOADS/quarantine/SRR123456_1.fastq.gz")));
+/// ```
+pub fn is_quarantined(path: &Path) -> bool {
+    path.parent()
+        .is_some_and(|parent| parent.iter().any(|part| part == QUARANTINE_DIR))
+}
@@ -0,0 +1,192 @@
+use once_cell::sync::OnceCell;
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::perms::settle;
+
+pub const REPORT_EXT: &str = "report";
+pub const REPORT_FILE: &str = "rsfq.report";
+
+static REPORT: OnceCell<PathBuf> = OnceCell::new();
+
+// INFO: concurrent downloads append to the same report
+static REPORT_LOCK: Mutex<()> = Mutex::new(());
+
+// INFO: the HTML report needs the whole batch, with or without a report file
+static RECORDS: Mutex<Vec<Record>> = Mutex::new(Vec::new());
+static REASONS: Mutex<BTreeMap<(String, String), String>> = Mutex::new(BTreeMap::new());
+
+/// Outcome of a single file in a batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Downloaded,
+    Skipped,
+    Failed,
+    Duplicate,
+}
+
+/// Display the status as written in the report
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Downloaded => write!(f, "DOWNLOADED"),
+            Status::Skipped => write!(f, "SKIPPED"),
+            Status::Failed => write!(f, "FAILED"),
+            Status::Duplicate => write!(f, "DUPLICATE"),
+        }
+    }
+}
+
+/// A report line: one file of one run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub run: String,
+    pub status: Status,
+    pub file: String,
+    pub md5: String,
+}
+
+impl Record {
+    /// Format the record as a report line.
+    ///
+    /// # Returns
+    /// The `run\tstatus\tfile\tmd5` line, without trailing newline.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::report::{Record, Status};
+    /// let record = Record {
+    ///     run: "SRR123456".to_string(),
+    ///     status: Status::Downloaded,
+    ///     file: "SRR123456_1.fastq.gz".to_string(),
+    ///     md5: "d41d8cd98f00b204e9800998ecf8427e".to_string(),
+    /// };
+    /// assert!(record.line().starts_with("SRR123456\tDOWNLOADED"));
+    /// ```
+    pub fn line(&self) -> String {
+        format!("{}\t{}\t{}\t{}", self.run, self.status, self.file, self.md5)
+    }
+}
+
+/// Set the file the batch report is appended to.
+///
+/// Without a report file, `report` is a no-op.
+///
+/// # Arguments
+/// * `path` - The report file, if any.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::report::init_report;
+/// use std::path::PathBuf;
+/// init_report(Some(PathBuf::from("rsfq.report")));
+/// ```
+pub fn init_report(path: Option<PathBuf>) {
+    if let Some(path) = path {
+        let _ = std::fs::remove_file(&path);
+        let _ = REPORT.set(path);
+    }
+}
+
+/// Append the outcome of a file to the batch report.
+///
+/// Only the file name is kept, since Nextflow task outputs are
+/// flattened into the output directory afterwards.
+///
+/// # Arguments
+/// * `run` - The run accession.
+/// * `status` - The outcome of the file.
+/// * `file` - The path or URL of the file.
+/// * `md5` - The expected checksum, `-` if unknown.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::report::{report, Status};
+/// report("SRR123456", Status::Failed, "ftp://host/SRR123456_1.fastq.gz", "-");
+/// ```
+pub fn report(run: &str, status: Status, file: &str, md5: &str) {
+    let record = Record {
+        run: run.to_string(),
+        status,
+        file: file_name(file),
+        md5: md5.to_string(),
+    };
+    RECORDS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(record.clone());
+
+    let Some(path) = REPORT.get() else {
+        return;
+    };
+
+    let _guard = REPORT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut handle| writeln!(handle, "{}", record.line()));
+
+    match written {
+        Ok(()) => settle(path),
+        Err(e) => log::warn!("WARNING: Could not write report {}!: {}", path.display(), e),
+    }
+}
+
+/// Say why a file failed, for the HTML report.
+///
+/// # Arguments
+/// * `run` - The run accession.
+/// * `file` - The path or URL of the file, `-` for the whole run.
+/// * `reason` - Why it failed.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::report::explain;
+/// explain("SRR123456", "ftp://host/SRR123456_1.fastq.gz", "MD5 mismatch");
+/// ```
+pub fn explain(run: &str, file: &str, reason: &str) {
+    REASONS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert((run.to_string(), file_name(file)), reason.to_string());
+}
+
+/// Get the outcomes reported so far in this batch.
+///
+/// # Returns
+/// The records, in the order they were reported.
+pub fn records() -> Vec<Record> {
+    RECORDS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Get why a file failed, if it was explained.
+///
+/// # Arguments
+/// * `record` - The failed file.
+///
+/// # Returns
+/// The reason given to `explain`, if any.
+pub fn reason(record: &Record) -> Option<String> {
+    REASONS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&(record.run.clone(), record.file.clone()))
+        .cloned()
+}
+
+/// Keep only the file name of a path or URL.
+fn file_name(file: &str) -> String {
+    Path::new(file)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(file)
+        .to_string()
+}
@@ -0,0 +1,425 @@
+use once_cell::sync::{Lazy, OnceCell};
+use regex::Regex;
+use serde_json::Value;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    net::{client, offline, send_with_retry, RetryPolicy},
+    provs::{ena::fetch_run_info, Provider},
+    runner::runner,
+    runtable::{as_ena_run, read_runinfo},
+    utils::validate_query,
+};
+
+const EUTILS_URL: &str = "https://eutils.ncbi.nlm.nih.gov/entrez/eutils";
+const EUTILS_MAX: usize = 10000; // ids per request
+const ENA_BATCH: usize = 100; // runs per ENA query
+const RUN_ACCESSION: &str = "run_accession";
+const FASTQ_FTP: &str = "fastq_ftp";
+
+/// The field of a run saying which archive serves its files, when it is
+/// not the selected provider
+pub const ARCHIVE: &str = "archive";
+/// Runs served by NCBI SRA, fetched with sra-tools
+pub const SRA_ARCHIVE: &str = "sra";
+
+static CROSS_ARCHIVE: OnceCell<bool> = OnceCell::new();
+static SRA_TOOLS: OnceCell<bool> = OnceCell::new();
+
+static GEO_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^GS[EM][0-9]+$")
+        .unwrap_or_else(|e| panic!("Failed to compile GEO_RE regex: {}", e))
+});
+// INFO: identifiers NCBI issues and knows before ENA mirrors them
+static NCBI_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^SR[RXPS][0-9]{6,}$|^PRJNA[0-9]+$|^SAMN[0-9]+$")
+        .unwrap_or_else(|e| panic!("Failed to compile NCBI_RE regex: {}", e))
+});
+// INFO: INSDC runs, mirrored by NCBI SRA whoever received them
+static INSDC_RUN_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^[EDS]RR[0-9]{6,}$")
+        .unwrap_or_else(|e| panic!("Failed to compile INSDC_RUN_RE regex: {}", e))
+});
+
+/// Let runs that ENA does not list, or lists without FASTQs yet, be
+/// fetched from NCBI SRA instead. Only the first call has effect.
+///
+/// # Arguments
+/// * `enabled` - Whether ENA or SRA is the provider and
+///   `--no-cross-archive` was not given.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::resolve::init_cross_archive;
+/// init_cross_archive(true);
+/// ```
+pub fn init_cross_archive(enabled: bool) {
+    let _ = CROSS_ARCHIVE.set(enabled);
+}
+
+/// Check whether runs may be fetched from another archive than ENA.
+///
+/// # Returns
+/// `true` if enabled, online and sra-tools are installed.
+pub fn cross_archive() -> bool {
+    CROSS_ARCHIVE.get().copied().unwrap_or(false)
+        && !offline()
+        && *SRA_TOOLS
+            .get_or_init(|| runner().installed("prefetch") && runner().installed("fasterq-dump"))
+}
+
+/// Check whether a run ENA failed to deliver can be fetched from NCBI SRA
+/// instead.
+///
+/// # Arguments
+/// * `run` - The run metadata.
+///
+/// # Returns
+/// `true` for INSDC runs when runs may be fetched from another archive.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::resolve::mirrored_by_ncbi;
+/// use std::collections::HashMap;
+///
+/// let run = HashMap::from([("run_accession".to_string(), "ERR123456".to_string())]);
+/// if mirrored_by_ncbi(&run) {
+///     println!("ERR123456 can be fetched with prefetch");
+/// }
+/// ```
+pub fn mirrored_by_ncbi(run: &HashMap<String, String>) -> bool {
+    cross_archive()
+        && run
+            .get(RUN_ACCESSION)
+            .is_some_and(|accession| INSDC_RUN_RE.is_match(accession))
+}
+
+/// Check whether an accession is a GEO series or sample.
+///
+/// # Arguments
+/// * `accession` - The accession, e.g. `GSE123456`.
+///
+/// # Returns
+/// `true` for `GSE` and `GSM` accessions.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::resolve::is_geo_accession;
+/// assert!(is_geo_accession("GSM123456"));
+/// assert!(!is_geo_accession("SRR123456"));
+/// ```
+pub fn is_geo_accession(accession: &str) -> bool {
+    GEO_RE.is_match(accession)
+}
+
+/// Get the provider that serves the files of a run.
+///
+/// # Arguments
+/// * `run` - The run metadata.
+/// * `provider` - The selected provider.
+///
+/// # Returns
+/// SRA for runs resolved to NCBI, unless AWS mirrors them, the selected
+/// provider otherwise.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::Provider;
+/// use rsfq::resolve::hosted_by;
+/// use std::collections::HashMap;
+///
+/// let run = HashMap::from([("archive".to_string(), "sra".to_string())]);
+/// assert!(matches!(hosted_by(&run, &Provider::ENA), Provider::SRA));
+/// ```
+pub fn hosted_by(run: &HashMap<String, String>, provider: &Provider) -> Provider {
+    match run.get(ARCHIVE).map(String::as_str) {
+        // INFO: the AWS bucket mirrors every NCBI run
+        Some(SRA_ARCHIVE) if !matches!(provider, Provider::AWS) => Provider::SRA,
+        _ => provider.clone(),
+    }
+}
+
+/// Resolve any supported identifier into the runs to download, choosing
+/// for each run the archive that actually serves its files.
+///
+/// ENA answers first. GEO series and samples are mapped to their runs
+/// through NCBI; NCBI identifiers ENA does not list yet, and INSDC runs ENA
+/// lists without FASTQs, are resolved to NCBI SRA when `cross_archive` is on.
+///
+/// # Arguments
+/// * `accession` - A study, sample, experiment, run or GEO accession.
+/// * `max_attempts` - The maximum number of attempts to make when retrieving data.
+/// * `sleep` - The number of seconds to sleep between attempts.
+///
+/// # Returns
+/// The runs, those served by NCBI marked with `archive=sra`, or why none
+/// were found.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::resolve::resolve;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let runs = resolve("GSE123456", 3, 5).await;
+/// }
+/// ```
+pub async fn resolve(
+    accession: &str,
+    max_attempts: usize,
+    sleep: usize,
+) -> Result<Vec<HashMap<String, String>>, String> {
+    if is_geo_accession(accession) {
+        if offline() {
+            return Err(format!(
+                "{} is a GEO accession, resolved through NCBI, which --offline forbids",
+                accession
+            ));
+        }
+        let runs = ncbi_runs(accession, max_attempts, sleep).await?;
+        log::info!("{} maps to {} runs in NCBI", accession, runs.len());
+        return Ok(prefer_ena(runs, max_attempts, sleep).await);
+    }
+
+    match fetch_run_info(validate_query(accession), max_attempts, sleep).await {
+        Ok(runs) => Ok(runs.into_iter().map(route).collect()),
+        Err(e) if cross_archive() && NCBI_RE.is_match(accession) => {
+            let runs = ncbi_runs(accession, max_attempts, sleep)
+                .await
+                .map_err(|ncbi| format!("{}; NCBI: {}", e, ncbi))?;
+            log::warn!(
+                "WARNING: ENA does not list {} yet, fetching its {} runs from NCBI SRA",
+                accession,
+                runs.len()
+            );
+            Ok(runs)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Send an ENA run without FASTQs to NCBI SRA, if it may be fetched there.
+fn route(mut run: HashMap<String, String>) -> HashMap<String, String> {
+    let lagging = run.get(FASTQ_FTP).is_none_or(|ftp| ftp.is_empty())
+        && run
+            .get(RUN_ACCESSION)
+            .is_some_and(|accession| INSDC_RUN_RE.is_match(accession));
+
+    if lagging && cross_archive() {
+        log::info!(
+            "ENA has no FASTQs for {} yet, fetching it from NCBI SRA",
+            run.get(RUN_ACCESSION).map(String::as_str).unwrap_or("-")
+        );
+        run.insert(ARCHIVE.to_string(), SRA_ARCHIVE.to_string());
+    }
+    run
+}
+
+/// Replace NCBI runs by their ENA metadata where ENA lists them, keeping
+/// the NCBI ones for the rest.
+async fn prefer_ena(
+    runs: Vec<HashMap<String, String>>,
+    max_attempts: usize,
+    sleep: usize,
+) -> Vec<HashMap<String, String>> {
+    let mut resolved = Vec::new();
+    let mut mirrored = HashSet::new();
+    for batch in runs.chunks(ENA_BATCH) {
+        let query = batch
+            .iter()
+            .filter_map(|run| run.get(RUN_ACCESSION))
+            .map(|run| format!("run_accession={}", run))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+
+        // INFO: none of the batch being on ENA is not an error here
+        for run in fetch_run_info(query, max_attempts, sleep)
+            .await
+            .unwrap_or_default()
+        {
+            if let Some(accession) = run.get(RUN_ACCESSION) {
+                mirrored.insert(accession.clone());
+            }
+            resolved.push(route(run));
+        }
+    }
+
+    let missing = runs
+        .into_iter()
+        .filter(|run| {
+            run.get(RUN_ACCESSION)
+                .is_some_and(|accession| !mirrored.contains(accession))
+        })
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        return resolved;
+    }
+    if cross_archive() {
+        log::warn!(
+            "WARNING: ENA does not list {} of the runs yet, fetching them from NCBI SRA",
+            missing.len()
+        );
+        resolved.extend(missing);
+    } else {
+        log::warn!(
+            "WARNING: ENA does not list {} of the runs yet, skipping them (they need sra-tools and no --no-cross-archive)",
+            missing.len()
+        );
+    }
+
+    resolved
+}
+
+/// Get the runs of an accession from NCBI, retrying failures.
+///
+/// # Arguments
+/// * `accession` - An NCBI or GEO accession.
+/// * `max_attempts` - The maximum number of attempts to make when retrieving data.
+/// * `sleep` - The number of seconds to sleep between attempts.
+///
+/// # Returns
+/// The runs shaped like ENA results and marked with `archive=sra`, or why
+/// none were found.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::resolve::ncbi_runs;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let runs = ncbi_runs("PRJNA123456", 3, 5).await;
+/// }
+/// ```
+pub async fn ncbi_runs(
+    accession: &str,
+    max_attempts: usize,
+    sleep: usize,
+) -> Result<Vec<HashMap<String, String>>, String> {
+    let policy = RetryPolicy::new(max_attempts, sleep);
+    match fetch_ncbi_runs(accession, policy).await {
+        Ok(runs) if !runs.is_empty() => Ok(runs),
+        Ok(_) => Err(format!("No runs found in NCBI for {}", accession)),
+        Err(e) => Err(format!("NCBI request failed for {}: {}", accession, e)),
+    }
+}
+
+/// Fetch the runs of an accession from NCBI E-utilities.
+async fn fetch_ncbi_runs(
+    accession: &str,
+    policy: RetryPolicy,
+) -> Result<Vec<HashMap<String, String>>, String> {
+    let ids = if is_geo_accession(accession) {
+        // INFO: GEO records live in gds and link to their SRA experiments
+        let gds = esearch("gds", &format!("{}[ACCN]", accession), policy).await?;
+        elink("gds", "sra", &gds, policy).await?
+    } else {
+        esearch("sra", accession, policy).await?
+    };
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let request = client().post(format!("{}/efetch.fcgi", EUTILS_URL)).form(&[
+        ("db", "sra"),
+        ("rettype", "runinfo"),
+        ("retmode", "csv"),
+        ("id", &ids.join(",")),
+    ]);
+    let runinfo = send_with_retry(request, policy)
+        .await?
+        .error_for_status()
+        .map_err(|e| e.to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(read_runinfo(&runinfo)?
+        .iter()
+        .map(|row| {
+            let mut run = as_ena_run(row);
+            run.insert(ARCHIVE.to_string(), SRA_ARCHIVE.to_string());
+            run
+        })
+        .collect())
+}
+
+/// Search an Entrez database, returning the matching ids.
+async fn esearch(db: &str, term: &str, policy: RetryPolicy) -> Result<Vec<String>, String> {
+    let answer = eutils(
+        "esearch.fcgi",
+        &[
+            ("db", db),
+            ("term", term),
+            ("retmode", "json"),
+            ("retmax", &EUTILS_MAX.to_string()),
+        ],
+        policy,
+    )
+    .await?;
+
+    Ok(answer["esearchresult"]["idlist"]
+        .as_array()
+        .map(|ids| ids.iter().filter_map(id_of).collect())
+        .unwrap_or_default())
+}
+
+/// Follow the links of Entrez ids into another database.
+async fn elink(
+    from: &str,
+    db: &str,
+    ids: &[String],
+    policy: RetryPolicy,
+) -> Result<Vec<String>, String> {
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let answer = eutils(
+        "elink.fcgi",
+        &[
+            ("dbfrom", from),
+            ("db", db),
+            ("retmode", "json"),
+            ("id", &ids.join(",")),
+        ],
+        policy,
+    )
+    .await?;
+
+    Ok(answer["linksets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|set| set["linksetdbs"].as_array().cloned().unwrap_or_default())
+        .flat_map(|links| links["links"].as_array().cloned().unwrap_or_default())
+        .filter_map(|id| id_of(&id))
+        .collect())
+}
+
+/// Call an E-utility, parsing its JSON answer.
+async fn eutils(
+    utility: &str,
+    params: &[(&str, &str)],
+    policy: RetryPolicy,
+) -> Result<Value, String> {
+    let request = client()
+        .post(format!("{}/{}", EUTILS_URL, utility))
+        .form(params);
+    send_with_retry(request, policy)
+        .await?
+        .error_for_status()
+        .map_err(|e| e.to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|answer| serde_json::from_str(&answer).map_err(|e| e.to_string()))
+}
+
+/// Read an Entrez id, given as a string or a number.
+fn id_of(id: &Value) -> Option<String> {
+    match id {
+        Value::String(id) => Some(id.clone()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
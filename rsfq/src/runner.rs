@@ -0,0 +1,655 @@
+use futures::future::BoxFuture;
+use once_cell::sync::OnceCell;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    process::Command,
+};
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{Read, Write},
+    path::PathBuf,
+    process::Stdio,
+    sync::{Arc, Mutex},
+};
+
+use crate::preempt::{track_child, Tracked};
+
+static RUNNER: OnceCell<Arc<dyn Runner>> = OnceCell::new();
+static SYSTEM: SystemRunner = SystemRunner;
+
+/// A program run to completion: its exit code and whatever it printed to
+/// piped stdout/stderr
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Outcome {
+    /// The exit code, `None` if the program was killed by a signal
+    pub code: Option<i32>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl Outcome {
+    /// Get an outcome with the given exit code and no output.
+    ///
+    /// # Arguments
+    /// * `code` - The exit code.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use rsfq::runner::Outcome;
+    /// assert!(Outcome::exit(0).success());
+    /// ```
+    pub fn exit(code: i32) -> Self {
+        Outcome {
+            code: Some(code),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the program exited with code 0.
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// A command as it would be run: program, arguments, working directory and
+/// what was written to its stdin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invocation {
+    pub program: String,
+    pub args: Vec<String>,
+    pub dir: Option<PathBuf>,
+    pub stdin: Vec<u8>,
+}
+
+impl Invocation {
+    /// Get the invocation of a command.
+    ///
+    /// # Arguments
+    /// * `cmd` - The command.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use rsfq::runner::Invocation;
+    /// use tokio::process::Command;
+    ///
+    /// let mut cmd = Command::new("prefetch");
+    /// cmd.arg("SRR123456");
+    /// assert_eq!(Invocation::of(&cmd).args, vec!["SRR123456"]);
+    /// ```
+    pub fn of(cmd: &Command) -> Self {
+        let cmd = cmd.as_std();
+        Invocation {
+            program: cmd.get_program().to_string_lossy().to_string(),
+            args: cmd
+                .get_args()
+                .map(|arg| arg.to_string_lossy().to_string())
+                .collect(),
+            dir: cmd.get_current_dir().map(PathBuf::from),
+            stdin: Vec::new(),
+        }
+    }
+}
+
+/// A program started with `Runner::spawn`, fed and read while it runs.
+///
+/// The pipes block, so a spawned program is driven from a blocking thread.
+pub struct Spawned {
+    /// The program's stdin, closed when dropped
+    pub stdin: Option<Box<dyn Write + Send>>,
+    /// The program's stdout, unless it is written to a file
+    pub stdout: Option<Box<dyn Read + Send>>,
+    process: Box<dyn Process>,
+}
+
+impl Spawned {
+    /// Stop the program.
+    pub fn kill(&mut self) -> std::io::Result<()> {
+        self.process.kill()
+    }
+
+    /// Close the program's stdin and wait for it to exit.
+    ///
+    /// # Returns
+    /// The outcome, without output: stdout was read or written to a file.
+    pub fn wait(&mut self) -> std::io::Result<Outcome> {
+        drop(self.stdin.take());
+        self.process.wait()
+    }
+}
+
+/// What a spawned program is controlled through once its pipes are taken
+trait Process: Send {
+    fn kill(&mut self) -> std::io::Result<()>;
+    fn wait(&mut self) -> std::io::Result<Outcome>;
+}
+
+/// Runs the external programs rsfq depends on (retrievers, prefetch,
+/// fasterq-dump, pigz), so they can be swapped for a mock
+pub trait Runner: Send + Sync {
+    /// Check whether a program can be run.
+    fn installed(&self, program: &str) -> bool;
+
+    /// Run a command to completion.
+    fn run(&self, cmd: Command) -> BoxFuture<'_, std::io::Result<Outcome>>;
+
+    /// Run a command to completion, handing every line it prints, to stdout
+    /// or stderr, to `on_line`. Carriage returns end lines too, so progress
+    /// bars come through as they are redrawn.
+    ///
+    /// By default the lines are handed over once the command exits; the
+    /// system runner hands them over as they are printed and keeps none of
+    /// them in the outcome.
+    fn run_streaming<'a>(
+        &'a self,
+        cmd: Command,
+        on_line: &'a (dyn Fn(&str) + Send + Sync),
+    ) -> BoxFuture<'a, std::io::Result<Outcome>> {
+        Box::pin(async move {
+            let outcome = self.run(cmd).await?;
+            for output in [&outcome.stdout, &outcome.stderr] {
+                output
+                    .split(|&b| b == b'\n' || b == b'\r')
+                    .filter(|line| !line.is_empty())
+                    .for_each(|line| on_line(&String::from_utf8_lossy(line)));
+            }
+            Ok(outcome)
+        })
+    }
+
+    /// Run a command to completion, copying what it prints to stdout into
+    /// `sink` and handing every line it prints to stderr to `on_line`.
+    ///
+    /// By default both are handed over once the command exits; the system
+    /// runner copies and forwards them as they are printed.
+    fn run_piped<'a>(
+        &'a self,
+        cmd: Command,
+        sink: &'a mut (dyn AsyncWrite + Unpin + Send),
+        on_line: &'a (dyn Fn(&str) + Send + Sync),
+    ) -> BoxFuture<'a, std::io::Result<Outcome>> {
+        Box::pin(async move {
+            let outcome = self.run(cmd).await?;
+            sink.write_all(&outcome.stdout).await?;
+            outcome
+                .stderr
+                .split(|&b| b == b'\n' || b == b'\r')
+                .filter(|line| !line.is_empty())
+                .for_each(|line| on_line(&String::from_utf8_lossy(line)));
+            Ok(Outcome {
+                code: outcome.code,
+                ..Default::default()
+            })
+        })
+    }
+
+    /// Run a command to completion, writing `input` to its stdin and
+    /// keeping what it prints to stdout and stderr.
+    fn run_with_input(
+        &self,
+        cmd: Command,
+        input: Vec<u8>,
+    ) -> BoxFuture<'_, std::io::Result<Outcome>>;
+
+    /// Start a command with its stdin piped and its stdout piped, or
+    /// written to `output`, for programs fed or read while they run.
+    fn spawn(&self, cmd: Command, output: Option<File>) -> std::io::Result<Spawned>;
+}
+
+/// Runs programs for real, from the PATH
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRunner;
+
+impl Runner for SystemRunner {
+    fn installed(&self, program: &str) -> bool {
+        which::which(program).is_ok()
+    }
+
+    fn run(&self, mut cmd: Command) -> BoxFuture<'_, std::io::Result<Outcome>> {
+        Box::pin(async move {
+            let child = cmd.spawn()?;
+            let _child = track_child(child.id());
+            let output = child.wait_with_output().await?;
+
+            Ok(Outcome {
+                code: output.status.code(),
+                stdout: output.stdout,
+                stderr: output.stderr,
+            })
+        })
+    }
+
+    fn run_streaming<'a>(
+        &'a self,
+        mut cmd: Command,
+        on_line: &'a (dyn Fn(&str) + Send + Sync),
+    ) -> BoxFuture<'a, std::io::Result<Outcome>> {
+        Box::pin(async move {
+            let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+            let _child = track_child(child.id());
+            let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+
+            let (stdout, stderr, status) = tokio::join!(
+                forward_lines(stdout, on_line),
+                forward_lines(stderr, on_line),
+                child.wait()
+            );
+            stdout?;
+            stderr?;
+
+            Ok(Outcome {
+                code: status?.code(),
+                ..Default::default()
+            })
+        })
+    }
+
+    fn run_piped<'a>(
+        &'a self,
+        mut cmd: Command,
+        sink: &'a mut (dyn AsyncWrite + Unpin + Send),
+        on_line: &'a (dyn Fn(&str) + Send + Sync),
+    ) -> BoxFuture<'a, std::io::Result<Outcome>> {
+        Box::pin(async move {
+            let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+            let _child = track_child(child.id());
+            let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+
+            let copy = async {
+                match stdout {
+                    Some(mut stdout) => tokio::io::copy(&mut stdout, sink).await.map(|_| ()),
+                    None => Ok(()),
+                }
+            };
+            let (copied, forwarded) = tokio::join!(copy, forward_lines(stderr, on_line));
+            // INFO: a program nobody reads from anymore would never exit
+            if let Err(e) = copied {
+                let _ = child.kill().await;
+                return Err(e);
+            }
+            forwarded?;
+
+            Ok(Outcome {
+                code: child.wait().await?.code(),
+                ..Default::default()
+            })
+        })
+    }
+
+    fn run_with_input(
+        &self,
+        mut cmd: Command,
+        input: Vec<u8>,
+    ) -> BoxFuture<'_, std::io::Result<Outcome>> {
+        Box::pin(async move {
+            let mut child = cmd
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?;
+            let _child = track_child(child.id());
+
+            if let Some(mut stdin) = child.stdin.take() {
+                tokio::io::AsyncWriteExt::write_all(&mut stdin, &input).await?;
+                // INFO: stdin is dropped here, so the program sees EOF
+            }
+            let output = child.wait_with_output().await?;
+
+            Ok(Outcome {
+                code: output.status.code(),
+                stdout: output.stdout,
+                stderr: output.stderr,
+            })
+        })
+    }
+
+    fn spawn(&self, cmd: Command, output: Option<File>) -> std::io::Result<Spawned> {
+        let mut cmd = cmd.into_std();
+        cmd.stdin(Stdio::piped());
+        match output {
+            Some(file) => cmd.stdout(Stdio::from(file)),
+            None => cmd.stdout(Stdio::piped()),
+        };
+
+        let mut child = cmd.spawn()?;
+        let _child = track_child(Some(child.id()));
+        Ok(Spawned {
+            stdin: child
+                .stdin
+                .take()
+                .map(|stdin| Box::new(stdin) as Box<dyn Write + Send>),
+            stdout: child
+                .stdout
+                .take()
+                .map(|stdout| Box::new(stdout) as Box<dyn Read + Send>),
+            process: Box::new(SystemProcess { child, _child }),
+        })
+    }
+}
+
+/// A program run for real
+struct SystemProcess {
+    child: std::process::Child,
+    _child: Tracked,
+}
+
+impl Process for SystemProcess {
+    fn kill(&mut self) -> std::io::Result<()> {
+        self.child.kill()
+    }
+
+    fn wait(&mut self) -> std::io::Result<Outcome> {
+        Ok(Outcome {
+            code: self.child.wait()?.code(),
+            ..Default::default()
+        })
+    }
+}
+
+/// Hand every line of a child's output to `on_line` as it is printed,
+/// ending lines at newlines and carriage returns.
+async fn forward_lines<R: AsyncRead + Unpin>(
+    output: Option<R>,
+    on_line: &(dyn Fn(&str) + Send + Sync),
+) -> std::io::Result<()> {
+    let Some(output) = output else {
+        return Ok(());
+    };
+
+    let mut reader = BufReader::new(output);
+    let mut line = Vec::new();
+    loop {
+        let buffer = reader.fill_buf().await?;
+        if buffer.is_empty() {
+            break;
+        }
+        for &byte in buffer {
+            if byte == b'\n' || byte == b'\r' {
+                if !line.is_empty() {
+                    on_line(&String::from_utf8_lossy(&line));
+                    line.clear();
+                }
+            } else {
+                line.push(byte);
+            }
+        }
+        let read = buffer.len();
+        reader.consume(read);
+    }
+    if !line.is_empty() {
+        on_line(&String::from_utf8_lossy(&line));
+    }
+
+    Ok(())
+}
+
+type Handler = Arc<dyn Fn(&Invocation) -> std::io::Result<Outcome> + Send + Sync>;
+
+/// Records every command instead of running it, answering with scripted
+/// outcomes; programs without a script exit with 0.
+///
+/// Handlers may write the files a program would produce, so whole downloads
+/// can run without the tools installed. A spawned program whose output goes
+/// to a file is answered once its stdin is closed, with the stdin in the
+/// invocation, and its stdout is written to the file; otherwise it is
+/// answered at once and its stdout is read back.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::provs::sra::download_run;
+/// use rsfq::runner::{init_runner, MockRunner, Outcome};
+/// use rsfq::utils::Layout;
+/// use std::sync::Arc;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mock = Arc::new(
+///         MockRunner::default()
+///             .on("fasterq-dump", |call| {
+///                 if call.args.iter().any(|arg| arg == "--version") {
+///                     return Ok(Outcome { stdout: b"fasterq-dump : 2.9.6".to_vec(), ..Outcome::exit(0) });
+///                 }
+///                 let dir = call.dir.clone().unwrap_or_default();
+///                 std::fs::write(dir.join("SRR123456.fastq"), "@r1\nACGT\n+\nIIII\n")?;
+///                 Ok(Outcome::exit(0))
+///             })
+///             .on("pigz", |call| {
+///                 let dir = call.dir.clone().unwrap_or_default();
+///                 std::fs::rename(dir.join("SRR123456.fastq"), dir.join("SRR123456.fastq.gz.part"))?;
+///                 Ok(Outcome::exit(0))
+///             }),
+///     );
+///     init_runner(mock.clone());
+///
+///     download_run("SRR123456", "/tmp/sra", 1, 1, 0, false, Layout::Single, None)
+///         .await
+///         .unwrap();
+///     assert_eq!(mock.calls()[0].program, "prefetch");
+/// }
+/// ```
+#[derive(Default)]
+pub struct MockRunner {
+    handlers: HashMap<String, Handler>,
+    missing: HashSet<String>,
+    calls: Arc<Mutex<Vec<Invocation>>>,
+}
+
+impl MockRunner {
+    /// Answer the runs of a program with a handler.
+    ///
+    /// # Arguments
+    /// * `program` - The program, as passed to `Command::new`.
+    /// * `handler` - Gets each invocation, returns its outcome.
+    ///
+    /// # Returns
+    /// The runner.
+    pub fn on<F>(mut self, program: &str, handler: F) -> Self
+    where
+        F: Fn(&Invocation) -> std::io::Result<Outcome> + Send + Sync + 'static,
+    {
+        self.handlers.insert(program.to_string(), Arc::new(handler));
+        self
+    }
+
+    /// Report a program as not installed.
+    ///
+    /// # Arguments
+    /// * `program` - The program, as passed to `Command::new`.
+    ///
+    /// # Returns
+    /// The runner.
+    pub fn without(mut self, program: &str) -> Self {
+        self.missing.insert(program.to_string());
+        self
+    }
+
+    /// Get the commands run so far, in order.
+    ///
+    /// # Returns
+    /// Every invocation recorded.
+    pub fn calls(&self) -> Vec<Invocation> {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl Runner for MockRunner {
+    fn installed(&self, program: &str) -> bool {
+        !self.missing.contains(program)
+    }
+
+    fn run(&self, cmd: Command) -> BoxFuture<'_, std::io::Result<Outcome>> {
+        let outcome = self.answer(Invocation::of(&cmd));
+        Box::pin(async move { outcome })
+    }
+
+    fn run_with_input(
+        &self,
+        cmd: Command,
+        input: Vec<u8>,
+    ) -> BoxFuture<'_, std::io::Result<Outcome>> {
+        let outcome = self.answer(Invocation {
+            stdin: input,
+            ..Invocation::of(&cmd)
+        });
+        Box::pin(async move { outcome })
+    }
+
+    fn spawn(&self, cmd: Command, output: Option<File>) -> std::io::Result<Spawned> {
+        let call = Invocation::of(&cmd);
+        let Some(output) = output else {
+            let outcome = self.answer(call)?;
+            return Ok(Spawned {
+                stdin: Some(Box::new(std::io::sink())),
+                stdout: Some(Box::new(std::io::Cursor::new(outcome.stdout))),
+                process: Box::new(MockProcess::Done(outcome.code)),
+            });
+        };
+
+        if !self.installed(&call.program) {
+            return Err(not_installed(&call.program));
+        }
+        let input = Arc::new(Mutex::new(Vec::new()));
+        let handler = self.handlers.get(&call.program).cloned();
+        Ok(Spawned {
+            stdin: Some(Box::new(MockInput(input.clone()))),
+            stdout: None,
+            process: Box::new(MockProcess::Fed {
+                call,
+                input,
+                output,
+                handler,
+                calls: self.calls.clone(),
+            }),
+        })
+    }
+}
+
+impl MockRunner {
+    /// Record a call and answer it with its handler.
+    fn answer(&self, call: Invocation) -> std::io::Result<Outcome> {
+        self.calls
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(call.clone());
+
+        if !self.installed(&call.program) {
+            return Err(not_installed(&call.program));
+        }
+        match self.handlers.get(&call.program) {
+            Some(handler) => handler(&call),
+            None => Ok(Outcome::exit(0)),
+        }
+    }
+}
+
+/// The error of running a program that is not installed.
+fn not_installed(program: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("{} is not installed", program),
+    )
+}
+
+/// The stdin of a mocked program, kept for its handler
+struct MockInput(Arc<Mutex<Vec<u8>>>);
+
+impl Write for MockInput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A mocked program: already answered, or waiting for its stdin
+enum MockProcess {
+    Done(Option<i32>),
+    Fed {
+        call: Invocation,
+        input: Arc<Mutex<Vec<u8>>>,
+        output: File,
+        handler: Option<Handler>,
+        calls: Arc<Mutex<Vec<Invocation>>>,
+    },
+}
+
+impl Process for MockProcess {
+    fn kill(&mut self) -> std::io::Result<()> {
+        *self = MockProcess::Done(None);
+        Ok(())
+    }
+
+    fn wait(&mut self) -> std::io::Result<Outcome> {
+        let code = match self {
+            MockProcess::Done(code) => *code,
+            MockProcess::Fed {
+                call,
+                input,
+                output,
+                handler,
+                calls,
+            } => {
+                let call = Invocation {
+                    stdin: std::mem::take(&mut *input.lock().unwrap_or_else(|e| e.into_inner())),
+                    ..call.clone()
+                };
+                calls
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(call.clone());
+                let outcome = match handler {
+                    Some(handler) => handler(&call)?,
+                    None => Outcome::exit(0),
+                };
+                output.write_all(&outcome.stdout)?;
+                outcome.code
+            }
+        };
+
+        *self = MockProcess::Done(code);
+        Ok(Outcome {
+            code,
+            ..Default::default()
+        })
+    }
+}
+
+/// Set the runner external programs go through, e.g. a `MockRunner` in
+/// tests. Only the first call has effect.
+///
+/// # Arguments
+/// * `runner` - The runner.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::runner::{init_runner, MockRunner};
+/// use std::sync::Arc;
+/// init_runner(Arc::new(MockRunner::default()));
+/// ```
+pub fn init_runner(runner: Arc<dyn Runner>) {
+    let _ = RUNNER.set(runner);
+}
+
+/// Get the runner external programs go through.
+///
+/// # Returns
+/// The runner set with `init_runner`, the system one by default.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::runner::runner;
+/// assert!(runner().installed("sh"));
+/// ```
+pub fn runner() -> &'static dyn Runner {
+    match RUNNER.get() {
+        Some(runner) => runner.as_ref(),
+        None => &SYSTEM,
+    }
+}
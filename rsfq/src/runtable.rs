@@ -0,0 +1,214 @@
+use std::{collections::HashMap, path::Path};
+
+const RUN: &str = "Run";
+const LIBRARY_LAYOUT: &str = "LibraryLayout";
+const SAMPLE_NAME: &str = "SampleName";
+
+// INFO: RunInfo columns, shared by NCBI and GSA, and the ENA fields they fill in
+const RUNINFO_FIELDS: &[(&str, &str)] = &[
+    ("Run", "run_accession"),
+    ("Experiment", "experiment_accession"),
+    ("BioSample", "sample_accession"),
+    ("BioProject", "study_accession"),
+    ("SRAStudy", "secondary_study_accession"),
+    ("SampleName", "sample_alias"),
+    ("LibraryLayout", "library_layout"),
+    ("LibraryStrategy", "library_strategy"),
+    ("LibrarySelection", "library_selection"),
+    ("LibrarySource", "library_source"),
+    ("Platform", "instrument_platform"),
+    ("Model", "instrument_model"),
+    ("ScientificName", "scientific_name"),
+    ("TaxID", "tax_id"),
+    ("spots", "read_count"),
+    ("bases", "base_count"),
+];
+
+/// A row of an NCBI Run Selector table (`SraRunTable.csv`/`.txt`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunRow {
+    pub run: String,
+    pub layout: Option<String>,
+    pub sample: Option<String>,
+}
+
+/// Read an NCBI Run Selector table.
+///
+/// Both the comma-separated and the older tab-separated exports are
+/// accepted; only the `Run`, `LibraryLayout` and `SampleName` columns are used.
+///
+/// # Arguments
+/// * `path` - The exported table.
+///
+/// # Returns
+/// `Ok(None)` if the file has no `Run` column (not a run table), the rows
+/// otherwise, or an error if the file cannot be read or parsed.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::runtable::read_run_table;
+/// use std::path::Path;
+/// let rows = read_run_table(Path::new("SraRunTable.csv")).unwrap().unwrap_or_default();
+/// ```
+pub fn read_run_table(path: &Path) -> Result<Option<Vec<RunRow>>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let header = content.lines().next().unwrap_or_default();
+    let delimiter = if header.contains('\t') { b'\t' } else { b',' };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+
+    let columns = reader.headers().map_err(|e| e.to_string())?.clone();
+    let column = |name: &str| columns.iter().position(|c| c.trim() == name);
+
+    let Some(run) = column(RUN) else {
+        return Ok(None);
+    };
+    let layout = column(LIBRARY_LAYOUT);
+    let sample = column(SAMPLE_NAME);
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("{}: {}", path.display(), e))?;
+        let field = |idx: Option<usize>| {
+            idx.and_then(|idx| record.get(idx))
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        if let Some(accession) = field(Some(run)) {
+            rows.push(RunRow {
+                run: accession,
+                layout: field(layout),
+                sample: field(sample),
+            });
+        }
+    }
+
+    let paired = rows
+        .iter()
+        .filter(|row| row.layout.as_deref() == Some("PAIRED"))
+        .count();
+    log::info!(
+        "Read {} runs from run table {} ({} paired, {} single/unknown)",
+        rows.len(),
+        path.display(),
+        paired,
+        rows.len() - paired
+    );
+
+    Ok(Some(rows))
+}
+
+/// Name runs after their `SampleName` column.
+///
+/// Samples sequenced over several runs get the run accession appended,
+/// so no two runs share an output name.
+///
+/// # Arguments
+/// * `rows` - The run table rows.
+///
+/// # Returns
+/// A map from run accession to output base name.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::runtable::{sample_names, RunRow};
+/// let rows = vec![RunRow {
+///     run: "SRR123456".to_string(),
+///     layout: Some("PAIRED".to_string()),
+///     sample: Some("liver_A".to_string()),
+/// }];
+/// assert_eq!(sample_names(&rows)["SRR123456"], "liver_A");
+/// ```
+pub fn sample_names(rows: &[RunRow]) -> HashMap<String, String> {
+    let mut runs_per_sample: HashMap<&str, usize> = HashMap::new();
+    for sample in rows.iter().filter_map(|row| row.sample.as_deref()) {
+        *runs_per_sample.entry(sample).or_default() += 1;
+    }
+
+    rows.iter()
+        .filter_map(|row| {
+            let sample = row.sample.as_deref()?;
+            let name = if runs_per_sample[sample] > 1 {
+                format!("{}_{}", sample, row.run)
+            } else {
+                sample.to_string()
+            };
+            Some((row.run.clone(), name))
+        })
+        .collect()
+}
+
+/// Read a RunInfo export, as NCBI E-utilities (`rettype=runinfo`) and the
+/// GSA return it.
+///
+/// # Arguments
+/// * `content` - The comma-separated export.
+///
+/// # Returns
+/// Every row as column to value, blank values left out, or an error if
+/// the export has no `Run` column or cannot be parsed.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::runtable::read_runinfo;
+/// let rows = read_runinfo("Run,LibraryLayout\nSRR123456,PAIRED\n").unwrap();
+/// assert_eq!(rows[0]["Run"], "SRR123456");
+/// ```
+pub fn read_runinfo(content: &str) -> Result<Vec<HashMap<String, String>>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content.as_bytes());
+
+    let columns = reader.headers().map_err(|e| e.to_string())?.clone();
+    if !columns.iter().any(|c| c.trim() == RUN) {
+        return Err(format!("the RunInfo export has no {} column", RUN));
+    }
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        let row = columns
+            .iter()
+            .zip(record.iter())
+            .map(|(column, value)| (column.trim().to_string(), value.trim().to_string()))
+            .filter(|(_, value)| !value.is_empty())
+            .collect::<HashMap<String, String>>();
+
+        // INFO: E-utilities repeat the header between batches
+        if row.get(RUN).is_some_and(|run| run != RUN) {
+            rows.push(row);
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Shape a RunInfo row like an ENA `read_run` result, without files.
+///
+/// # Arguments
+/// * `row` - The row, from `read_runinfo`.
+///
+/// # Returns
+/// The run metadata under ENA field names.
+///
+/// # Examples
+/// ```rust
+/// use rsfq::runtable::{as_ena_run, read_runinfo};
+/// let rows = read_runinfo("Run,LibraryLayout\nSRR123456,paired\n").unwrap();
+/// assert_eq!(as_ena_run(&rows[0])["library_layout"], "PAIRED");
+/// ```
+pub fn as_ena_run(row: &HashMap<String, String>) -> HashMap<String, String> {
+    let mut run = RUNINFO_FIELDS
+        .iter()
+        .filter_map(|(column, field)| Some((field.to_string(), row.get(*column)?.clone())))
+        .collect::<HashMap<String, String>>();
+
+    if let Some(layout) = run.get_mut("library_layout") {
+        *layout = layout.to_uppercase();
+    }
+    run
+}
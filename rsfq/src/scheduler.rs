@@ -0,0 +1,303 @@
+use once_cell::sync::OnceCell;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::net::{acquire_host_slot, download_tuner, DownloadTuner};
+
+// INFO: shorter waits are noise, not a bottleneck
+const BOTTLENECK_WAIT: Duration = Duration::from_secs(1);
+
+// INFO: the semaphore and how many slots it was created with
+static CPU_SLOTS: OnceCell<(Arc<Semaphore>, usize)> = OnceCell::new();
+// INFO: stages of every accession report to the same counters
+static USAGE: Mutex<BTreeMap<Resource, Usage>> = Mutex::new(BTreeMap::new());
+
+/// What the stages of a batch compete for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resource {
+    /// ENA API calls, under the adaptive metadata limit
+    Metadata,
+    /// Transfers, under the per-host caps and `--auto-concurrency`
+    Transfer,
+    /// Local hashing, dumping and compression, under `--cpu-slots`
+    Cpu,
+}
+
+impl std::fmt::Display for Resource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Resource::Metadata => write!(f, "metadata"),
+            Resource::Transfer => write!(f, "transfer"),
+            Resource::Cpu => write!(f, "cpu"),
+        }
+    }
+}
+
+/// How a resource was used over the batch
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Slots taken
+    pub acquired: usize,
+    /// Slots held right now
+    pub busy: usize,
+    /// Most slots held at once
+    pub peak: usize,
+    /// Time spent waiting for a slot, summed over every stage
+    pub waited: Duration,
+}
+
+/// A slot of a resource, counted as busy until dropped
+#[derive(Debug)]
+pub struct Busy {
+    resource: Resource,
+}
+
+impl Busy {
+    /// Count a slot of a resource as taken.
+    ///
+    /// # Arguments
+    /// * `resource` - The resource.
+    /// * `waited` - How long it took to get the slot.
+    ///
+    /// # Returns
+    /// The slot, released when dropped.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::scheduler::{Busy, Resource};
+    /// use std::time::Duration;
+    /// let _busy = Busy::new(Resource::Metadata, Duration::ZERO);
+    /// ```
+    pub fn new(resource: Resource, waited: Duration) -> Self {
+        let mut usage = USAGE.lock().unwrap_or_else(|e| e.into_inner());
+        let usage = usage.entry(resource).or_default();
+        usage.acquired += 1;
+        usage.busy += 1;
+        usage.peak = usage.peak.max(usage.busy);
+        usage.waited += waited;
+        Busy { resource }
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        let mut usage = USAGE.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(usage) = usage.get_mut(&self.resource) {
+            usage.busy = usage.busy.saturating_sub(1);
+        }
+    }
+}
+
+/// A transfer slot: the host cap and, with `--auto-concurrency`, a tuned
+/// download slot
+pub struct TransferSlot {
+    tuned: Option<(&'static DownloadTuner, SemaphorePermit<'static>)>,
+    _host: Option<OwnedSemaphorePermit>,
+    _busy: Busy,
+}
+
+impl TransferSlot {
+    /// Hand the slot back after a complete transfer, so the tuner counts it.
+    ///
+    /// # Arguments
+    /// * `bytes` - The size of the transferred file.
+    pub fn finished(mut self, bytes: u64) {
+        if let Some((tuner, permit)) = self.tuned.take() {
+            tuner.finished(permit, bytes);
+        }
+    }
+
+    /// Hand the slot back after a failed transfer, so the tuner backs off.
+    pub fn failed(mut self) {
+        if let Some((tuner, permit)) = self.tuned.take() {
+            tuner.failed();
+            tuner.release(permit);
+        }
+    }
+}
+
+impl Drop for TransferSlot {
+    fn drop(&mut self) {
+        if let Some((tuner, permit)) = self.tuned.take() {
+            tuner.release(permit);
+        }
+    }
+}
+
+/// A share of the local CPU slots
+pub struct CpuSlot {
+    _permit: Option<OwnedSemaphorePermit>,
+    _busy: Busy,
+}
+
+/// Set how many CPU-bound slots (hashing, dumping, compression) run at
+/// once. Only the first call has effect.
+///
+/// # Arguments
+/// * `slots` - The number of slots, from `--cpu-slots`; the number of CPUs
+///   if `None`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::scheduler::init_scheduler;
+/// init_scheduler(Some(8));
+/// ```
+pub fn init_scheduler(slots: Option<usize>) {
+    let slots = slots.unwrap_or_else(cpus).max(1);
+    let _ = CPU_SLOTS.set((Arc::new(Semaphore::new(slots)), slots));
+}
+
+/// Get the number of CPUs of the machine.
+fn cpus() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// Wait for a transfer slot for `url`: its host cap first, then a tuned
+/// download slot. The slot is meant to be held for the transfer only, not
+/// for verification, so hashing never keeps a host busy.
+///
+/// # Arguments
+/// * `url` - The URL (with or without scheme) about to be downloaded.
+///
+/// # Returns
+/// The slot, released when dropped, `finished` or `failed`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::scheduler::transfer_slot;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let slot = transfer_slot("ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR123456.fastq.gz").await;
+///     slot.finished(1_048_576);
+/// }
+/// ```
+pub async fn transfer_slot(url: &str) -> TransferSlot {
+    let started = Instant::now();
+    let tuned = match download_tuner() {
+        Some(tuner) => Some((tuner, tuner.acquire().await)),
+        None => None,
+    };
+    let host = acquire_host_slot(url).await;
+
+    TransferSlot {
+        tuned,
+        _host: host,
+        _busy: Busy::new(Resource::Transfer, started.elapsed()),
+    }
+}
+
+/// Wait for CPU slots for a local stage.
+///
+/// # Arguments
+/// * `weight` - The threads the stage runs, capped at the number of slots.
+///
+/// # Returns
+/// The slots, released when dropped.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::scheduler::cpu_slot;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let _slot = cpu_slot(4).await;
+/// }
+/// ```
+pub async fn cpu_slot(weight: usize) -> CpuSlot {
+    let started = Instant::now();
+    let (slots, total) = CPU_SLOTS.get_or_init(|| (Arc::new(Semaphore::new(cpus())), cpus()));
+
+    // INFO: a stage wider than the machine still runs, alone
+    let weight = weight.clamp(1, *total);
+    let permit = slots.clone().acquire_many_owned(weight as u32).await.ok();
+
+    CpuSlot {
+        _permit: permit,
+        _busy: Busy::new(Resource::Cpu, started.elapsed()),
+    }
+}
+
+/// Run a CPU or disk bound stage on a blocking thread, so it does not stall
+/// the async workers driving transfers. Callers hold a `cpu_slot` meanwhile.
+///
+/// # Arguments
+/// * `stage` - The stage to run.
+///
+/// # Returns
+/// What the stage returned; a panic in it is resumed here.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::scheduler::{blocking, cpu_slot};
+/// use rsfq::validate::gzip_intact;
+/// use std::path::PathBuf;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let _slot = cpu_slot(1).await;
+///     let path = PathBuf::from("SRR123456_1.fastq.gz");
+///     let intact = blocking(move || gzip_intact(&path)).await;
+/// }
+/// ```
+pub async fn blocking<T, F>(stage: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(stage)
+        .await
+        .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
+
+/// Get how every resource was used so far in this batch.
+///
+/// # Returns
+/// The usage, keyed by resource.
+pub fn usage() -> BTreeMap<Resource, Usage> {
+    USAGE.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Log how busy each resource was and which one stages waited on most, to
+/// tell what to raise for the next batch.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::scheduler::summarize_scheduler;
+/// summarize_scheduler();
+/// ```
+pub fn summarize_scheduler() {
+    let usage = usage();
+    if usage.is_empty() {
+        return;
+    }
+
+    for (resource, usage) in &usage {
+        log::info!(
+            "Scheduler: {} took {} slots, at most {} at once, waiting {:.1?} in total",
+            resource,
+            usage.acquired,
+            usage.peak,
+            usage.waited
+        );
+    }
+
+    if let Some((resource, usage)) = usage
+        .iter()
+        .filter(|(_, usage)| usage.waited >= BOTTLENECK_WAIT)
+        .max_by_key(|(_, usage)| usage.waited)
+    {
+        log::info!(
+            "Scheduler: {} was the bottleneck ({:.1?} waited)",
+            resource,
+            usage.waited
+        );
+    }
+}
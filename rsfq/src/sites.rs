@@ -0,0 +1,119 @@
+use once_cell::sync::OnceCell;
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{net::host_of, utils::Retriever};
+
+const SITES_FILE: &str = ".rsfq/retrievers.tsv";
+
+static SITE_RETRIEVERS: OnceCell<BTreeMap<String, Retriever>> = OnceCell::new();
+
+/// Get the file holding the default retriever of each download site,
+/// `~/.rsfq/retrievers.tsv`, as written by `rsfq bench --save`.
+///
+/// # Returns
+/// The file path.
+pub fn sites_file() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(SITES_FILE)
+}
+
+/// Load the per-site default retrievers. Only the first call has effect;
+/// called only when `-T` was not given, so an explicit retriever always wins.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::sites::init_site_retrievers;
+/// init_site_retrievers();
+/// ```
+pub fn init_site_retrievers() {
+    let sites = read_sites(&sites_file());
+    if !sites.is_empty() {
+        log::info!("Loaded default retrievers for {} sites", sites.len());
+    }
+    let _ = SITE_RETRIEVERS.set(sites);
+}
+
+/// Get the retriever to download a URL with: the saved default of its site,
+/// if any, else the given one.
+///
+/// # Arguments
+/// * `url` - The download URL.
+/// * `retriever` - The retriever selected with `-T`.
+///
+/// # Returns
+/// The retriever to use.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::sites::site_retriever;
+/// use rsfq::utils::Retriever;
+/// let retriever = site_retriever("ftp.sra.ebi.ac.uk/vol1/x.fastq.gz", &Retriever::Aria2c);
+/// ```
+pub fn site_retriever(url: &str, retriever: &Retriever) -> Retriever {
+    SITE_RETRIEVERS
+        .get()
+        .and_then(|sites| sites.get(&host_of(url)))
+        .unwrap_or(retriever)
+        .clone()
+}
+
+/// Save the default retriever of a site, replacing the previous one.
+///
+/// # Arguments
+/// * `host` - The site, e.g. `ftp.sra.ebi.ac.uk`.
+/// * `retriever` - Its new default retriever.
+///
+/// # Returns
+/// The sites file, or an error if it could not be written.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::sites::save_site_retriever;
+/// use rsfq::utils::Retriever;
+/// save_site_retriever("ftp.sra.ebi.ac.uk", &Retriever::Wget).unwrap();
+/// ```
+pub fn save_site_retriever(host: &str, retriever: &Retriever) -> std::io::Result<PathBuf> {
+    let path = sites_file();
+    let mut sites = read_sites(&path);
+    sites.insert(host.to_string(), retriever.clone());
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let content = sites
+        .iter()
+        .map(|(host, retriever)| format!("{}\t{}\n", host, retriever))
+        .collect::<String>();
+    std::fs::write(&path, content)?;
+
+    Ok(path)
+}
+
+/// Read `host<TAB>retriever` lines, skipping unreadable ones.
+fn read_sites(path: &Path) -> BTreeMap<String, Retriever> {
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return BTreeMap::new();
+    };
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (host, retriever) = line.split_once('\t')?;
+            match Retriever::from_str(retriever.trim()) {
+                Ok(retriever) => Some((host.trim().to_string(), retriever)),
+                Err(e) => {
+                    log::warn!("WARNING: Ignoring {} in {}: {}", host, path.display(), e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,642 @@
+use once_cell::sync::{Lazy, OnceCell};
+use walkdir::WalkDir;
+
+use crate::{
+    perms::settle,
+    utils::{run_dir, Checksum},
+};
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const STATE_FILE: &str = ".rsfq.state";
+
+// INFO: concurrent downloads append to the same manifest; other processes
+// sharing the directory are kept out by a lock on the manifest itself
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+// INFO: manifests read once per batch, kept in step with what is appended
+static LOADED: Lazy<Mutex<HashMap<PathBuf, State>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static HASH: OnceCell<Checksum> = OnceCell::new();
+
+static RETAIN: OnceCell<u64> = OnceCell::new();
+
+/// A verified file recorded in the state manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub run: String,
+    pub file: PathBuf,
+    pub md5: String,
+    pub bytes: u64,
+    pub timestamp: u64,
+    /// Local integrity hash as `algorithm:digest`, absent when MD5 is used
+    pub hash: Option<String>,
+    /// Seconds the file is kept for after `timestamp`, absent to keep it
+    pub retain: Option<u64>,
+}
+
+impl Entry {
+    /// Parse a manifest line formatted as
+    /// `run\tfile\tmd5\tbytes\ttimestamp[\thash[\tretain]]`, `-` standing for
+    /// no hash.
+    ///
+    /// # Arguments
+    /// * `line` - The manifest line.
+    ///
+    /// # Returns
+    /// An `Option<Entry>`, `None` for malformed lines.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use rsfq::state::Entry;
+    /// let entry = Entry::parse("SRR123456\tSRR123456_1.fastq.gz\tabc\t10\t1700000000");
+    /// assert!(entry.is_some());
+    /// ```
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        Some(Entry {
+            run: fields.next()?.to_string(),
+            file: PathBuf::from(fields.next()?),
+            md5: fields.next()?.to_string(),
+            bytes: fields.next()?.parse().ok()?,
+            timestamp: fields.next()?.parse().ok()?,
+            hash: fields
+                .next()
+                .filter(|hash| *hash != "-")
+                .map(str::to_string),
+            retain: fields.next().and_then(|retain| retain.parse().ok()),
+        })
+    }
+
+    /// Format the entry as a manifest line.
+    ///
+    /// # Returns
+    /// The tab-separated line, without trailing newline.
+    pub fn line(&self) -> String {
+        let mut line = format!(
+            "{}\t{}\t{}\t{}\t{}",
+            self.run,
+            self.file.display(),
+            self.md5,
+            self.bytes,
+            self.timestamp
+        );
+        if self.hash.is_some() || self.retain.is_some() {
+            line.push('\t');
+            line.push_str(self.hash.as_deref().unwrap_or("-"));
+        }
+        if let Some(retain) = self.retain {
+            line.push_str(&format!("\t{}", retain));
+        }
+        line
+    }
+
+    /// Check whether the retention period of the file is over.
+    ///
+    /// # Arguments
+    /// * `now` - Seconds since the UNIX epoch.
+    ///
+    /// # Returns
+    /// `true` if the file was recorded with a retention period that ended.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use rsfq::state::Entry;
+    /// let entry = Entry::parse("SRR123456\tSRR123456_1.fastq.gz\tabc\t10\t1700000000\t-\t86400");
+    /// assert!(entry.unwrap().expired(1800000000));
+    /// ```
+    pub fn expired(&self, now: u64) -> bool {
+        self.retain
+            .is_some_and(|retain| self.timestamp.saturating_add(retain) <= now)
+    }
+
+    /// Re-hash the recorded file on disk and compare it against the
+    /// checksum of the entry.
+    ///
+    /// Uses the local integrity hash when one was recorded, MD5 otherwise.
+    ///
+    /// # Arguments
+    /// * `path` - The file, including the output directory.
+    ///
+    /// # Returns
+    /// `Some(true)` if the file matches, `Some(false)` if it does not and
+    /// `None` if the recorded hash algorithm is unknown.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::state::Entry;
+    /// use std::path::Path;
+    /// let entry = Entry::parse("SRR123456\tSRR123456_1.fastq.gz\tabc\t10\t1700000000").unwrap();
+    /// let intact = entry.verify(Path::new("DOWNLOADS/SRR123456_1.fastq.gz"));
+    /// ```
+    pub fn verify(&self, path: &Path) -> Option<bool> {
+        let (checksum, expected) = match self.hash.as_deref().and_then(|h| h.split_once(':')) {
+            Some((algorithm, digest)) => (algorithm.parse::<Checksum>().ok()?, digest),
+            None => (Checksum::Md5, self.md5.as_str()),
+        };
+
+        Some(
+            checksum
+                .digest(path)
+                .is_some_and(|digest| digest == expected),
+        )
+    }
+}
+
+/// The state manifest of an output directory: every verified download so far
+#[derive(Debug, Default, Clone)]
+pub struct State {
+    root: PathBuf,
+    entries: HashMap<String, Vec<Entry>>,
+    // INFO: the same entries by path, including the output directory
+    files: HashMap<PathBuf, Entry>,
+}
+
+impl State {
+    /// Load the state manifest of an output directory.
+    ///
+    /// A missing manifest yields an empty state.
+    ///
+    /// # Arguments
+    /// * `outdir` - The output directory.
+    ///
+    /// # Returns
+    /// A `State` instance.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::state::State;
+    /// let state = State::load("DOWNLOADS");
+    /// println!("SRR123456 done: {}", state.is_complete("SRR123456"));
+    /// ```
+    pub fn load<P: AsRef<Path>>(outdir: P) -> Self {
+        let mut state = State {
+            root: outdir.as_ref().to_path_buf(),
+            ..Default::default()
+        };
+
+        let content = std::fs::read_to_string(state.root.join(STATE_FILE)).unwrap_or_default();
+        for entry in content.lines().filter_map(Entry::parse) {
+            state.insert(entry);
+        }
+
+        state
+    }
+
+    /// Add an entry, superseding an earlier record of the same file.
+    fn insert(&mut self, entry: Entry) {
+        let files = self.entries.entry(entry.run.clone()).or_default();
+        files.retain(|e| e.file != entry.file);
+        files.push(entry.clone());
+        self.files.insert(self.root.join(&entry.file), entry);
+    }
+
+    /// Drop the entry of a file, including the output directory.
+    fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.files.remove(path) {
+            if let Some(files) = self.entries.get_mut(&entry.run) {
+                files.retain(|e| e.file != entry.file);
+            }
+        }
+    }
+
+    /// Get the recorded entries of a run.
+    ///
+    /// # Arguments
+    /// * `run` - The run accession.
+    ///
+    /// # Returns
+    /// A slice of `Entry`, empty if the run was never recorded.
+    pub fn entries(&self, run: &str) -> &[Entry] {
+        self.entries.get(run).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Get every run recorded in the manifest.
+    ///
+    /// # Returns
+    /// An iterator over the run accessions.
+    pub fn runs(&self) -> impl Iterator<Item = &String> {
+        self.entries.keys()
+    }
+
+    /// Check whether every recorded file of a run is still present and intact.
+    ///
+    /// Files are considered intact when their size matches the recorded one.
+    ///
+    /// # Arguments
+    /// * `run` - The run accession.
+    ///
+    /// # Returns
+    /// `true` if the run was recorded and all its files are present.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::state::State;
+    /// let state = State::load("DOWNLOADS");
+    /// assert!(!state.is_complete("SRR000000"));
+    /// ```
+    pub fn is_complete(&self, run: &str) -> bool {
+        let entries = self.entries(run);
+
+        !entries.is_empty()
+            && entries.iter().all(|entry| {
+                std::fs::metadata(self.root.join(&entry.file))
+                    .map(|meta| meta.len() == entry.bytes)
+                    .unwrap_or(false)
+            })
+    }
+
+    /// Get the recorded size of a file.
+    ///
+    /// # Arguments
+    /// * `path` - The file, including the output directory.
+    ///
+    /// # Returns
+    /// The size in bytes, `None` if the file was never recorded.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::state::State;
+    /// use std::path::Path;
+    /// let state = State::load("DOWNLOADS");
+    /// let bytes = state.recorded_bytes(Path::new("DOWNLOADS/SRR123456_1.fastq.gz"));
+    /// ```
+    pub fn recorded_bytes(&self, path: &Path) -> Option<u64> {
+        self.entry_of(path).map(|entry| entry.bytes)
+    }
+
+    /// Re-hash a file on disk and compare it against its recorded checksum.
+    ///
+    /// Uses the local integrity hash when one was recorded, MD5 otherwise.
+    ///
+    /// # Arguments
+    /// * `path` - The file to verify.
+    ///
+    /// # Returns
+    /// `Some(true)` if the file matches, `Some(false)` if it does not and
+    /// `None` if the file was never recorded.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::state::State;
+    /// use std::path::Path;
+    /// let state = State::load("DOWNLOADS");
+    /// let intact = state.verify(Path::new("DOWNLOADS/SRR123456_1.fastq.gz"));
+    /// ```
+    pub fn verify(&self, path: &Path) -> Option<bool> {
+        self.entry_of(path)?.verify(path)
+    }
+
+    /// Find the entry recorded for a file.
+    ///
+    /// # Arguments
+    /// * `path` - The file, including the output directory.
+    ///
+    /// # Returns
+    /// An `Option<&Entry>`, `None` if the file was never recorded.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::state::State;
+    /// use std::path::Path;
+    /// let state = State::load("DOWNLOADS");
+    /// let entry = state.entry_of(Path::new("DOWNLOADS/SRR123456_1.fastq.gz"));
+    /// ```
+    pub fn entry_of(&self, path: &Path) -> Option<&Entry> {
+        self.files.get(path)
+    }
+}
+
+/// Find the entry recorded for a file in the manifest of an output
+/// directory, read once per batch rather than once per file.
+///
+/// # Arguments
+/// * `outdir` - The output directory holding the manifest.
+/// * `path` - The file, including the output directory.
+///
+/// # Returns
+/// An `Option<Entry>`, `None` if the file was never recorded.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::state::recorded;
+/// use std::path::Path;
+/// let entry = recorded(Path::new("DOWNLOADS"), Path::new("DOWNLOADS/SRR123456_1.fastq.gz"));
+/// ```
+pub fn recorded(outdir: &Path, path: &Path) -> Option<Entry> {
+    LOADED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(outdir.to_path_buf())
+        .or_insert_with(|| State::load(outdir))
+        .entry_of(path)
+        .cloned()
+}
+
+/// Set how long recorded files are kept for, until `rsfq expire` removes
+/// them. Only the first call has effect.
+///
+/// # Arguments
+/// * `retain` - The retention period, from `--retain`; `None` keeps files.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::state::init_retain;
+/// use std::time::Duration;
+/// init_retain(Some(Duration::from_secs(90 * 86400)));
+/// ```
+pub fn init_retain(retain: Option<std::time::Duration>) {
+    if let Some(retain) = retain {
+        let _ = RETAIN.set(retain.as_secs());
+    }
+}
+
+/// Set the checksum used for local integrity hashes in the manifest.
+///
+/// # Arguments
+/// * `checksum` - The checksum, MD5 adds nothing on top of the ENA MD5.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::state::init_hash;
+/// use rsfq::utils::Checksum;
+/// init_hash(Checksum::Blake3);
+/// ```
+pub fn init_hash(checksum: Checksum) {
+    let _ = HASH.set(checksum);
+}
+
+/// Get the checksum set with `init_hash` for local integrity hashes.
+///
+/// # Returns
+/// The checksum, `None` for MD5, which adds nothing on top of the ENA MD5.
+///
+/// # Examples
+/// ```rust
+/// use rsfq::state::integrity_hash;
+/// assert_eq!(integrity_hash(), None);
+/// ```
+pub fn integrity_hash() -> Option<Checksum> {
+    HASH.get()
+        .copied()
+        .filter(|checksum| *checksum != Checksum::Md5)
+}
+
+/// Append a verified file to the state manifest of its output directory.
+///
+/// A local integrity hash is added when one was set with `init_hash`; the
+/// file is only read for it when it was not hashed in flight. Call it from a
+/// blocking thread.
+///
+/// # Arguments
+/// * `outdir` - The output directory holding the manifest.
+/// * `run` - The run accession the file belongs to.
+/// * `path` - The path to the verified file.
+/// * `md5` - The verified MD5 checksum.
+/// * `hash` - The integrity hash, as `checksum:digest`, if it was hashed
+///   along with the MD5 while downloading.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::state::record;
+/// use std::path::Path;
+///
+/// record(
+///     Path::new("DOWNLOADS"),
+///     "SRR123456",
+///     Path::new("DOWNLOADS/SRR123456_1.fastq.gz"),
+///     "d41d8cd98f00b204e9800998ecf8427e",
+///     None,
+/// );
+/// ```
+pub fn record(outdir: &Path, run: &str, path: &Path, md5: &str, hash: Option<&str>) {
+    let bytes = std::fs::metadata(path).map(|m| m.len()).unwrap_or_default();
+    let file = path.strip_prefix(outdir).unwrap_or(path).to_path_buf();
+    let hash = match (integrity_hash(), hash) {
+        (Some(_), Some(hash)) => Some(hash.to_string()),
+        (Some(checksum), None) => checksum
+            .digest(path)
+            .map(|digest| format!("{}:{}", checksum, digest)),
+        (None, _) => None,
+    };
+    let entry = Entry {
+        run: run.to_string(),
+        file,
+        md5: md5.to_string(),
+        bytes,
+        timestamp: now(),
+        hash,
+        retain: RETAIN.get().copied(),
+    };
+
+    append(outdir, &[entry]);
+}
+
+/// Drop the entries of removed files from the manifest of an output
+/// directory, so they are not taken for verified downloads anymore.
+///
+/// # Arguments
+/// * `outdir` - The output directory.
+/// * `paths` - The removed files, including the output directory.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::state::forget;
+/// use std::path::{Path, PathBuf};
+/// forget(Path::new("DOWNLOADS"), &[PathBuf::from("DOWNLOADS/SRR123456_1.fastq.gz")]);
+/// ```
+pub fn forget(outdir: &Path, paths: &[PathBuf]) {
+    let _guard = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let manifest = outdir.join(STATE_FILE);
+    if !manifest.exists() {
+        return;
+    }
+    let _locked = match lock_manifest(&manifest) {
+        Ok(file) => file,
+        Err(e) => {
+            log::warn!(
+                "WARNING: Could not lock the state manifest {}!: {}",
+                manifest.display(),
+                e
+            );
+            return;
+        }
+    };
+    let Ok(content) = std::fs::read_to_string(&manifest) else {
+        return;
+    };
+    let kept = content
+        .lines()
+        .filter(|line| {
+            Entry::parse(line).is_none_or(|entry| !paths.contains(&outdir.join(&entry.file)))
+        })
+        .map(|line| format!("{}\n", line))
+        .collect::<String>();
+
+    if let Some(state) = LOADED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_mut(outdir)
+    {
+        paths.iter().for_each(|path| state.remove(path));
+    }
+
+    // INFO: rewritten aside and renamed, a crash never leaves it half-written
+    let staging = outdir.join(format!("{}.{}", STATE_FILE, std::process::id()));
+    match std::fs::write(&staging, kept).and_then(|()| std::fs::rename(&staging, &manifest)) {
+        Ok(()) => settle(&manifest),
+        Err(e) => {
+            let _ = std::fs::remove_file(&staging);
+            log::warn!(
+                "WARNING: Could not update the state manifest {}!: {}",
+                manifest.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Merge the manifests written by Nextflow tasks into the root manifest.
+///
+/// Task outputs are flattened into `outdir` (or their run directory) by
+/// `__move_to_root`, so merged entries keep only their file name.
+///
+/// # Arguments
+/// * `outdir` - The root output directory.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::state::merge_nested;
+/// use std::path::Path;
+/// merge_nested(Path::new("DOWNLOADS"));
+/// ```
+pub fn merge_nested(outdir: &Path) {
+    let entries = WalkDir::new(outdir)
+        .min_depth(2)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file() && e.file_name() == STATE_FILE)
+        .flat_map(|e| {
+            std::fs::read_to_string(e.path())
+                .unwrap_or_default()
+                .lines()
+                .filter_map(Entry::parse)
+                .collect::<Vec<Entry>>()
+        })
+        .map(|mut entry| {
+            let name = entry.file.file_name().unwrap_or_default().to_owned();
+            entry.file = run_dir(Path::new(""), &entry.run).join(name);
+            entry
+        })
+        .collect::<Vec<Entry>>();
+
+    if !entries.is_empty() {
+        append(outdir, &entries);
+    }
+}
+
+/// Append entries to the manifest of an output directory.
+///
+/// # Arguments
+/// * `outdir` - The output directory holding the manifest.
+/// * `entries` - The entries to append.
+fn append(outdir: &Path, entries: &[Entry]) {
+    let _guard = STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let manifest = outdir.join(STATE_FILE);
+    let mut file = lock_manifest(&manifest).unwrap_or_else(|e| {
+        log::error!("ERROR: Could not open the state manifest!: {}", e);
+        std::process::exit(1);
+    });
+    settle(&manifest);
+
+    let lines = entries
+        .iter()
+        .map(|entry| format!("{}\n", entry.line()))
+        .collect::<String>();
+    file.write_all(lines.as_bytes()).unwrap_or_else(|e| {
+        log::error!("ERROR: Could not write the state manifest!: {}", e);
+        std::process::exit(1);
+    });
+
+    if let Some(state) = LOADED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_mut(outdir)
+    {
+        entries
+            .iter()
+            .cloned()
+            .for_each(|entry| state.insert(entry));
+    }
+}
+
+/// Open a manifest for appending, holding an exclusive lock on it until the
+/// file is dropped, so processes sharing the output directory (e.g. Nextflow
+/// tasks) never append to it while it is rewritten.
+fn lock_manifest(manifest: &Path) -> std::io::Result<File> {
+    loop {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(manifest)?;
+        lock(&file)?;
+        // INFO: forget renames a new manifest into place, a lock won on the old one guards nothing
+        if is_current(&file, manifest) {
+            return Ok(file);
+        }
+    }
+}
+
+/// Wait for an exclusive lock on a file.
+#[cfg(unix)]
+fn lock(file: &File) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    loop {
+        // SAFETY: flock only acts on the descriptor, which outlives the call
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(());
+        }
+        let e = std::io::Error::last_os_error();
+        if e.kind() != std::io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn lock(_file: &File) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Check whether an open file is still the one found at its path.
+#[cfg(unix)]
+fn is_current(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(open), Ok(found)) => open.dev() == found.dev() && open.ino() == found.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_current(_file: &File, _path: &Path) -> bool {
+    true
+}
+
+/// Seconds since the UNIX epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,277 @@
+use once_cell::sync::OnceCell;
+use rusqlite::{params_from_iter, types::ValueRef, Connection};
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+const RUN_ACCESSION: &str = "run_accession";
+const DB_DIR: &str = ".rsfq";
+const DB_FILE: &str = "metadata.sqlite";
+const BUSY_TIMEOUT: u64 = 30; // 30 seconds
+                              // INFO: counts compared as numbers; ids, dates and `;`-separated lists stay text
+const NUMERIC_FIELDS: &[&str] = &["read_count", "base_count", "nominal_length", "nominal_sdev"];
+
+static STORE: OnceCell<PathBuf> = OnceCell::new();
+// INFO: one connection for the whole batch, opened on the first save
+static OPENED: Mutex<Option<Store>> = Mutex::new(None);
+
+/// A local SQLite store of run metadata, one row per run and one column per
+/// ENA field.
+///
+/// Columns are TEXT, so accessions and checksums keep their exact form;
+/// only counts (`read_count`, `base_count`...) use NUMERIC affinity and
+/// compare as numbers.
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    /// Open (or create) a metadata store.
+    ///
+    /// # Arguments
+    /// * `path` - The database file.
+    ///
+    /// # Returns
+    /// A `rusqlite::Result<Store>`.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::store::{default_db, Store};
+    /// let store = Store::open(&default_db()).unwrap();
+    /// ```
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(Duration::from_secs(BUSY_TIMEOUT))?;
+        conn.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS runs ({} TEXT PRIMARY KEY)",
+                RUN_ACCESSION
+            ),
+            [],
+        )?;
+
+        Ok(Store { conn })
+    }
+
+    /// Insert or replace runs, adding a column for every new field.
+    ///
+    /// # Arguments
+    /// * `rows` - The run metadata, as returned by ENA.
+    ///
+    /// # Returns
+    /// The number of runs stored.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::store::{default_db, Store};
+    /// use std::collections::HashMap;
+    /// let mut store = Store::open(&default_db()).unwrap();
+    /// let rows = vec![HashMap::from([("run_accession".to_string(), "SRR123456".to_string())])];
+    /// store.insert(&rows).unwrap();
+    /// ```
+    pub fn insert(&mut self, rows: &[HashMap<String, String>]) -> rusqlite::Result<usize> {
+        let tx = self.conn.transaction()?;
+
+        let mut columns = tx
+            .prepare("SELECT name FROM pragma_table_info('runs')")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<HashSet<String>>>()?;
+
+        let mut stored = 0;
+        for row in rows.iter().filter(|row| row.contains_key(RUN_ACCESSION)) {
+            for field in row.keys() {
+                if columns.insert(field.clone()) {
+                    let affinity = match NUMERIC_FIELDS.contains(&field.as_str()) {
+                        true => "NUMERIC",
+                        false => "TEXT",
+                    };
+                    tx.execute(
+                        &format!("ALTER TABLE runs ADD COLUMN {} {}", quote(field), affinity),
+                        [],
+                    )?;
+                }
+            }
+
+            let (fields, values): (Vec<&String>, Vec<&String>) = row.iter().unzip();
+            let sql = format!(
+                "INSERT OR REPLACE INTO runs ({}) VALUES ({})",
+                fields
+                    .iter()
+                    .map(|field| quote(field))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                vec!["?"; values.len()].join(", ")
+            );
+            tx.execute(&sql, params_from_iter(values))?;
+            stored += 1;
+        }
+
+        tx.commit()?;
+        Ok(stored)
+    }
+
+    /// Select the runs matching a filter expression.
+    ///
+    /// The expression is an SQL `WHERE` clause over ENA field names; double
+    /// quoted strings are taken as text, e.g.
+    /// `library_strategy = "RNA-Seq" AND read_count > 1e7`.
+    ///
+    /// # Arguments
+    /// * `filter` - The filter expression.
+    ///
+    /// # Returns
+    /// The matching runs, in accession order.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::store::{default_db, Store};
+    /// let store = Store::open(&default_db()).unwrap();
+    /// let runs = store.query(r#"library_strategy = "RNA-Seq""#).unwrap();
+    /// ```
+    pub fn query(&self, filter: &str) -> rusqlite::Result<Vec<HashMap<String, String>>> {
+        let sql = format!(
+            "SELECT * FROM runs WHERE {} ORDER BY {}",
+            single_quoted(filter),
+            RUN_ACCESSION
+        );
+        let mut statement = self.conn.prepare(&sql)?;
+        let names = statement
+            .column_names()
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<String>>();
+
+        let rows = statement.query_map([], |row| {
+            let mut run = HashMap::new();
+            for (idx, name) in names.iter().enumerate() {
+                let value = match row.get_ref(idx)? {
+                    ValueRef::Null => continue,
+                    ValueRef::Integer(value) => value.to_string(),
+                    ValueRef::Real(value) => value.to_string(),
+                    ValueRef::Text(value) | ValueRef::Blob(value) => {
+                        String::from_utf8_lossy(value).to_string()
+                    }
+                };
+                run.insert(name.clone(), value);
+            }
+            Ok(run)
+        })?;
+
+        rows.collect()
+    }
+}
+
+/// Get the default metadata store location, `~/.rsfq/metadata.sqlite`.
+///
+/// # Returns
+/// The database path.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::store::default_db;
+/// println!("{}", default_db().display());
+/// ```
+pub fn default_db() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(DB_DIR)
+        .join(DB_FILE)
+}
+
+/// Save every metadata fetch from ENA into a store. Only the first call has effect.
+///
+/// # Arguments
+/// * `path` - The database file.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::store::{default_db, init_store};
+/// init_store(default_db());
+/// ```
+pub fn init_store(path: PathBuf) {
+    let _ = STORE.set(path);
+}
+
+/// Save run metadata into the store set with `init_store`, if any.
+///
+/// The store is opened on the first save and kept open for the rest of
+/// the batch. Failures are logged and otherwise ignored: the store is a
+/// convenience, not part of the download.
+///
+/// # Arguments
+/// * `rows` - The run metadata, as returned by ENA.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::store::save;
+/// use std::collections::HashMap;
+/// save(&[HashMap::from([("run_accession".to_string(), "SRR123456".to_string())])]);
+/// ```
+pub fn save(rows: &[HashMap<String, String>]) {
+    let Some(path) = STORE.get() else {
+        return;
+    };
+
+    let mut opened = OPENED.lock().unwrap_or_else(|e| e.into_inner());
+    let store = match opened.as_mut() {
+        Some(store) => Ok(store),
+        None => Store::open(path).map(|store| opened.insert(store)),
+    };
+    match store.and_then(|store| store.insert(rows)) {
+        Ok(stored) => log::info!("Stored metadata of {} runs in {}", stored, path.display()),
+        Err(e) => log::warn!(
+            "WARNING: Could not store metadata in {}!: {}",
+            path.display(),
+            e
+        ),
+    }
+}
+
+/// Quote an identifier for SQLite.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Turn the double-quoted strings of a filter into SQL string literals.
+fn single_quoted(filter: &str) -> String {
+    let mut sql = String::with_capacity(filter.len());
+    let mut chars = filter.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                // INFO: copy single-quoted literals untouched
+                sql.push(c);
+                for c in chars.by_ref() {
+                    sql.push(c);
+                    if c == '\'' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                sql.push('\'');
+                for c in chars.by_ref() {
+                    match c {
+                        '"' => break,
+                        '\'' => sql.push_str("''"),
+                        _ => sql.push(c),
+                    }
+                }
+                sql.push('\'');
+            }
+            _ => sql.push(c),
+        }
+    }
+
+    sql
+}
@@ -0,0 +1,389 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    core::download,
+    net::transported,
+    perms::create_dirs,
+    quarantine::quarantine,
+    report::{explain, report, Status},
+    scheduler::{blocking, cpu_slot},
+    state::{integrity_hash, record},
+    utils::{fits_on_disk, human_bytes, renamed, resume_partial, Retriever},
+    validate::{existing_intact, skip_verify, truncated},
+};
+
+const RUN_ACCESSION: &str = "run_accession";
+const INSTRUMENT_PLATFORM: &str = "instrument_platform";
+const SUBMITTED_FTP: &str = "submitted_ftp";
+const SUBMITTED_MD5: &str = "submitted_md5";
+const SUBMITTED_BYTES: &str = "submitted_bytes";
+
+const OXFORD_NANOPORE: &str = "OXFORD_NANOPORE";
+const RAW_SIGNAL_EXTENSIONS: &[&str] = &[
+    ".fast5",
+    ".pod5",
+    ".tar",
+    ".tar.gz",
+    ".tgz",
+    ".fast5.gz",
+    ".pod5.gz",
+];
+// INFO: raw signal is often 10x the FASTQ, so flag anything this big
+const RAW_SIGNAL_WARN_BYTES: u64 = 100 * 1024 * 1024 * 1024; // 100 GB
+
+const PACBIO_SMRT: &str = "PACBIO_SMRT";
+const BAM_EXTENSIONS: &[&str] = &[".bam", ".bam.pbi", ".pbi"];
+
+/// A file as submitted to ENA, listed in the `submitted_*` fields of a run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubmittedFile {
+    pub ftp: String,
+    pub md5: String,
+    pub bytes: Option<u64>,
+}
+
+impl SubmittedFile {
+    /// Get the file name, without the FTP path.
+    ///
+    /// # Returns
+    /// The file name.
+    pub fn name(&self) -> &str {
+        self.ftp.rsplit('/').next().unwrap_or(&self.ftp)
+    }
+}
+
+/// List the submitted files of a run whose names match a predicate.
+///
+/// # Arguments
+/// * `run` - The run metadata, as returned by ENA.
+/// * `wanted` - Whether a file name should be kept.
+///
+/// # Returns
+/// The matching files, in the order ENA lists them.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::submitted::submitted_files;
+/// use std::collections::HashMap;
+/// let run = HashMap::from([
+///     ("submitted_ftp".to_string(), "ftp.sra.ebi.ac.uk/vol1/run/ERR1/reads.pod5".to_string()),
+///     ("submitted_md5".to_string(), "abc".to_string()),
+/// ]);
+/// assert_eq!(submitted_files(&run, |name| name.ends_with(".pod5")).len(), 1);
+/// ```
+pub fn submitted_files<F>(run: &HashMap<String, String>, wanted: F) -> Vec<SubmittedFile>
+where
+    F: Fn(&str) -> bool,
+{
+    let field = |name: &str| {
+        run.get(name)
+            .map(|value| value.split(';').map(str::to_string).collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+
+    let ftps = field(SUBMITTED_FTP);
+    let md5s = field(SUBMITTED_MD5);
+    let bytes = field(SUBMITTED_BYTES);
+
+    ftps.into_iter()
+        .enumerate()
+        .filter(|(_, ftp)| !ftp.is_empty())
+        .map(|(idx, ftp)| SubmittedFile {
+            ftp,
+            md5: md5s.get(idx).cloned().unwrap_or_default(),
+            bytes: bytes.get(idx).and_then(|b| b.parse::<u64>().ok()),
+        })
+        .filter(|file| wanted(file.name()))
+        .collect()
+}
+
+/// Download the raw signal archives (FAST5/POD5, or tarballs of them) of an
+/// Oxford Nanopore run, next to its basecalled FASTQs.
+///
+/// Files go to `<outdir>/<run_accession>/`, as submitters often reuse names
+/// across runs. Runs from other platforms are skipped.
+///
+/// # Arguments
+/// * `run` - The run metadata, as returned by ENA.
+/// * `outdir` - The output directory.
+/// * `attempts` - The number of attempts to download each file.
+/// * `sleep` - The sleep duration in seconds between attempts.
+/// * `force` - Whether to download again (and skip MD5 checks).
+/// * `retriever` - The retriever to use.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::submitted::download_raw_signal;
+/// use rsfq::utils::Retriever;
+/// use std::collections::HashMap;
+/// use std::path::Path;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let run = HashMap::from([("run_accession".to_string(), "ERR123456".to_string())]);
+///     download_raw_signal(&run, Path::new("DOWNLOADS"), 3, 5, false, &Retriever::Aria2c).await;
+/// }
+/// ```
+pub async fn download_raw_signal(
+    run: &HashMap<String, String>,
+    outdir: &Path,
+    attempts: usize,
+    sleep: usize,
+    force: bool,
+    retriever: &Retriever,
+) {
+    let accession = run.get(RUN_ACCESSION).map(String::as_str).unwrap_or("-");
+    if run.get(INSTRUMENT_PLATFORM).map(String::as_str) != Some(OXFORD_NANOPORE) {
+        log::info!("Skipping raw signal for {}: not a Nanopore run", accession);
+        return;
+    }
+
+    let files = submitted_files(run, |name| {
+        RAW_SIGNAL_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+    });
+    if files.is_empty() {
+        log::warn!(
+            "WARNING: No FAST5/POD5 archives were submitted for {}",
+            accession
+        );
+        return;
+    }
+
+    let total = files.iter().filter_map(|file| file.bytes).sum::<u64>();
+    log::info!(
+        "Found {} raw signal archives for {} ({})",
+        files.len(),
+        accession,
+        human_bytes(total)
+    );
+    if total > RAW_SIGNAL_WARN_BYTES {
+        log::warn!(
+            "WARNING: Raw signal of {} is {}; make sure there is enough disk space!",
+            accession,
+            human_bytes(total)
+        );
+    }
+
+    let target = outdir.join(accession);
+    download_submitted(
+        accession, &files, &target, attempts, sleep, force, retriever,
+    )
+    .await;
+}
+
+/// Check whether a run was sequenced on a PacBio instrument.
+///
+/// # Arguments
+/// * `run` - The run metadata, as returned by ENA.
+///
+/// # Returns
+/// `true` for PacBio SMRT runs.
+pub fn is_pacbio(run: &HashMap<String, String>) -> bool {
+    run.get(INSTRUMENT_PLATFORM).map(String::as_str) == Some(PACBIO_SMRT)
+}
+
+/// List the submitted BAMs of a PacBio run (subreads, CCS or HiFi reads)
+/// together with their `.pbi` indexes.
+///
+/// # Arguments
+/// * `run` - The run metadata, as returned by ENA.
+///
+/// # Returns
+/// The BAM and index files, empty for non-PacBio runs.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::submitted::pacbio_bams;
+/// use std::collections::HashMap;
+/// let run = HashMap::from([
+///     ("instrument_platform".to_string(), "PACBIO_SMRT".to_string()),
+///     ("submitted_ftp".to_string(), "x/m64012.hifi_reads.bam;x/m64012.hifi_reads.bam.pbi".to_string()),
+///     ("submitted_md5".to_string(), "abc;def".to_string()),
+/// ]);
+/// assert_eq!(pacbio_bams(&run).len(), 2);
+/// ```
+pub fn pacbio_bams(run: &HashMap<String, String>) -> Vec<SubmittedFile> {
+    if !is_pacbio(run) {
+        return vec![];
+    }
+
+    submitted_files(run, |name| {
+        BAM_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+    })
+}
+
+/// Download the submitted subreads/CCS BAMs (and `.pbi` indexes) of a
+/// PacBio run instead of its FASTQs, as HiFi workflows start from BAM.
+///
+/// Files go to `<outdir>/<run_accession>/`, as PacBio movie names are shared
+/// by every run cut from the same SMRT cell.
+///
+/// # Arguments
+/// * `run` - The run metadata, as returned by ENA.
+/// * `outdir` - The output directory.
+/// * `attempts` - The number of attempts to download each file.
+/// * `sleep` - The sleep duration in seconds between attempts.
+/// * `force` - Whether to download again (and skip MD5 checks).
+/// * `retriever` - The retriever to use.
+///
+/// # Returns
+/// `false` if the run has no submitted BAM, so FASTQs should be fetched instead.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::submitted::download_pacbio_bam;
+/// use rsfq::utils::Retriever;
+/// use std::collections::HashMap;
+/// use std::path::Path;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let run = HashMap::from([("run_accession".to_string(), "SRR123456".to_string())]);
+///     download_pacbio_bam(&run, Path::new("DOWNLOADS"), 3, 5, false, &Retriever::Aria2c).await;
+/// }
+/// ```
+pub async fn download_pacbio_bam(
+    run: &HashMap<String, String>,
+    outdir: &Path,
+    attempts: usize,
+    sleep: usize,
+    force: bool,
+    retriever: &Retriever,
+) -> bool {
+    let accession = run.get(RUN_ACCESSION).map(String::as_str).unwrap_or("-");
+    let files = pacbio_bams(run);
+    if files.is_empty() {
+        log::warn!(
+            "WARNING: No submitted BAM found for {}, downloading FASTQ instead",
+            accession
+        );
+        return false;
+    }
+
+    let total = files.iter().filter_map(|file| file.bytes).sum::<u64>();
+    log::info!(
+        "Found {} PacBio BAM/index files for {} ({})",
+        files.len(),
+        accession,
+        human_bytes(total)
+    );
+
+    let target = outdir.join(accession);
+    download_submitted(
+        accession, &files, &target, attempts, sleep, force, retriever,
+    )
+    .await;
+    true
+}
+
+/// Download submitted files, verifying their MD5 and recording them in the
+/// state manifest of `outdir`.
+///
+/// # Arguments
+/// * `accession` - The run the files belong to.
+/// * `files` - The files to download.
+/// * `outdir` - The directory the files are saved to.
+/// * `attempts` - The number of attempts to download each file.
+/// * `sleep` - The sleep duration in seconds between attempts.
+/// * `force` - Whether to download again (and skip MD5 checks).
+/// * `retriever` - The retriever to use.
+///
+/// # Returns
+/// The paths of the downloaded (or already present) files.
+pub async fn download_submitted(
+    accession: &str,
+    files: &[SubmittedFile],
+    outdir: &Path,
+    attempts: usize,
+    sleep: usize,
+    force: bool,
+    retriever: &Retriever,
+) -> Vec<PathBuf> {
+    if let Err(e) = create_dirs(outdir) {
+        log::error!("ERROR: Could not create {}!: {}", outdir.display(), e);
+        std::process::exit(1);
+    }
+
+    let mut paths = Vec::new();
+    for file in files {
+        if file.md5.is_empty() && !skip_verify() {
+            log::error!("ERROR: No MD5 checksum found for {}", file.ftp);
+            explain(accession, &file.ftp, "no MD5 checksum listed");
+            report(accession, Status::Failed, &file.ftp, "-");
+            continue;
+        }
+
+        // INFO: a file that no longer matches its size or checksum is fetched again
+        let existing = outdir.join(renamed(file.name()));
+        if !force
+            && existing.exists()
+            && !existing_intact(&existing, outdir, file.bytes, &file.md5).await
+        {
+            if truncated(&existing, outdir, file.bytes) && resume_partial(&existing) {
+                log::warn!(
+                    "WARNING: {} looks like an interrupted download, resuming it...",
+                    existing.display()
+                );
+            } else {
+                log::warn!("WARNING: Downloading {} again...", existing.display());
+                let stale = existing.clone();
+                blocking(move || {
+                    quarantine(&stale, "does not match its expected size or checksum")
+                })
+                .await;
+            }
+        }
+        let existed = existing.exists();
+        // INFO: the space stays reserved until the file is fetched
+        let _reserved = if existed {
+            None
+        } else {
+            let Some(reserved) = fits_on_disk(outdir, file.bytes) else {
+                explain(accession, &file.ftp, "not enough disk space");
+                report(accession, Status::Failed, &file.ftp, &file.md5);
+                continue;
+            };
+            Some(reserved)
+        };
+
+        match download(
+            &transported(&file.ftp),
+            outdir,
+            attempts,
+            sleep,
+            force,
+            &file.md5,
+            file.bytes,
+            retriever,
+        )
+        .await
+        {
+            Some(downloaded) => {
+                if !skip_verify() {
+                    // INFO: the integrity hash may still have to be read from disk
+                    let _cpu = match (integrity_hash(), &downloaded.hash) {
+                        (Some(_), None) => Some(cpu_slot(1).await),
+                        _ => None,
+                    };
+                    let (outdir, accession) = (outdir.to_path_buf(), accession.to_string());
+                    let (path, md5, hash) =
+                        (downloaded.path.clone(), file.md5.clone(), downloaded.hash);
+                    blocking(move || record(&outdir, &accession, &path, &md5, hash.as_deref()))
+                        .await;
+                }
+                report(accession, Status::Downloaded, &file.ftp, &file.md5);
+                paths.push(downloaded.path);
+            }
+            None if existed => {
+                report(accession, Status::Skipped, &file.ftp, &file.md5);
+                paths.push(existing);
+            }
+            None => report(accession, Status::Failed, &file.ftp, &file.md5),
+        }
+    }
+
+    paths
+}
@@ -0,0 +1,103 @@
+use dialoguer::console::{measure_text_width, Term};
+
+use std::collections::HashMap;
+
+const SEPARATOR: &str = "  ";
+const MIN_WIDTH: usize = 6; // characters
+const FALLBACK_WIDTH: usize = 120; // characters
+const ELLIPSIS: char = '…';
+
+/// Render runs as a column-aligned table of the given fields.
+///
+/// When the table is wider than `width`, the widest columns are shrunk
+/// first and their values truncated with an ellipsis.
+///
+/// # Arguments
+/// * `data` - The run metadata.
+/// * `fields` - The fields to show, one column each.
+/// * `width` - The available width, in characters.
+///
+/// # Returns
+/// The table, one line per run after the header, without trailing newline.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::table::render_table;
+/// use std::collections::HashMap;
+///
+/// let data = vec![HashMap::from([("run_accession".to_string(), "SRR123456".to_string())])];
+/// println!("{}", render_table(&data, &["run_accession".to_string()], 80));
+/// ```
+pub fn render_table(data: &[HashMap<String, String>], fields: &[String], width: usize) -> String {
+    let cell = |run: &HashMap<String, String>, field: &String| {
+        run.get(field)
+            .map(String::as_str)
+            .unwrap_or("-")
+            .to_string()
+    };
+
+    let mut widths = fields
+        .iter()
+        .map(|field| {
+            data.iter()
+                .map(|run| measure_text_width(&cell(run, field)))
+                .chain(std::iter::once(measure_text_width(field)))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<usize>>();
+
+    // INFO: shrink the widest column one character at a time until it fits
+    let separators = SEPARATOR.len() * fields.len().saturating_sub(1);
+    while widths.iter().sum::<usize>() + separators > width {
+        match widths.iter_mut().filter(|w| **w > MIN_WIDTH).max() {
+            Some(widest) => *widest -= 1,
+            None => break,
+        }
+    }
+
+    let line = |cells: Vec<String>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| {
+                let cell = truncate(cell, *width);
+                format!("{}{}", cell, " ".repeat(width - measure_text_width(&cell)))
+            })
+            .collect::<Vec<String>>()
+            .join(SEPARATOR)
+            .trim_end()
+            .to_string()
+    };
+
+    std::iter::once(line(fields.to_vec()))
+        .chain(
+            data.iter()
+                .map(|run| line(fields.iter().map(|field| cell(run, field)).collect())),
+        )
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Get the width of the terminal stdout is attached to.
+///
+/// # Returns
+/// The width in characters, or a fallback of 120 when it cannot be measured.
+pub fn terminal_width() -> usize {
+    Term::stdout()
+        .size_checked()
+        .map(|(_, columns)| columns as usize)
+        .unwrap_or(FALLBACK_WIDTH)
+}
+
+/// Cut a cell down to `width` characters, marking the cut with an ellipsis.
+fn truncate(cell: &str, width: usize) -> String {
+    if measure_text_width(cell) <= width {
+        return cell.to_string();
+    }
+
+    cell.chars()
+        .take(width.saturating_sub(1))
+        .chain(std::iter::once(ELLIPSIS))
+        .collect()
+}
@@ -0,0 +1,236 @@
+use serde_json::{json, Map, Value};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::{
+    hints::hint,
+    perms::settle,
+    table::{render_table, terminal_width},
+    utils::human_bytes,
+};
+
+const COLUMNS: &[&str] = &[
+    "accession",
+    "metadata",
+    "transfer",
+    "verify",
+    "retries",
+    "size",
+];
+const TOTAL: &str = "TOTAL";
+
+/// Where an accession spent its time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Metadata,
+    Transfer,
+    Verify,
+}
+
+/// Time spent and retries needed by one accession
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    pub metadata: Duration,
+    pub transfer: Duration,
+    pub verify: Duration,
+    // INFO: failed attempts, given up ones included
+    pub retries: usize,
+    pub bytes: u64,
+}
+
+impl Timings {
+    /// Add another accession's timings into these.
+    pub fn add(&mut self, other: &Timings) {
+        self.metadata += other.metadata;
+        self.transfer += other.transfer;
+        self.verify += other.verify;
+        self.retries += other.retries;
+        self.bytes += other.bytes;
+    }
+
+    /// Get the transfer rate, in bytes per second.
+    ///
+    /// # Returns
+    /// The rate, or `None` if nothing was transferred.
+    pub fn throughput(&self) -> Option<f64> {
+        let secs = self.transfer.as_secs_f64();
+        (self.bytes > 0 && secs > 0.0).then(|| self.bytes as f64 / secs)
+    }
+
+    /// Format the timings as a table row.
+    fn row(&self, accession: &str) -> HashMap<String, String> {
+        let values = [
+            accession.to_string(),
+            elapsed(self.metadata),
+            elapsed(self.transfer),
+            elapsed(self.verify),
+            self.retries.to_string(),
+            if self.bytes > 0 {
+                human_bytes(self.bytes)
+            } else {
+                "-".to_string()
+            },
+        ];
+        COLUMNS
+            .iter()
+            .map(|column| column.to_string())
+            .zip(values)
+            .collect()
+    }
+
+    /// Format the timings as a JSON object, times in seconds.
+    fn json(&self) -> Value {
+        json!({
+            "metadata_secs": self.metadata.as_secs_f64(),
+            "transfer_secs": self.transfer.as_secs_f64(),
+            "verify_secs": self.verify.as_secs_f64(),
+            "retries": self.retries,
+            "bytes": self.bytes,
+        })
+    }
+}
+
+/// Format a time for the table, `-` if nothing was spent.
+fn elapsed(time: Duration) -> String {
+    if time.is_zero() {
+        "-".to_string()
+    } else {
+        format!("{:.3?}", time)
+    }
+}
+
+// INFO: accessions of a list are processed concurrently
+static TIMINGS: Mutex<BTreeMap<String, Timings>> = Mutex::new(BTreeMap::new());
+
+/// Add time spent by an accession in a phase.
+///
+/// # Arguments
+/// * `accession` - The accession, the queried one for metadata and the run
+///   for transfers and verification.
+/// * `phase` - What the time was spent on.
+/// * `elapsed` - How long it took.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::timing::{time, Phase};
+/// use std::time::Duration;
+/// time("SRR123456", Phase::Transfer, Duration::from_secs(42));
+/// ```
+pub fn time(accession: &str, phase: Phase, elapsed: Duration) {
+    let mut timings = TIMINGS.lock().unwrap_or_else(|e| e.into_inner());
+    let entry = timings.entry(accession.to_string()).or_default();
+    match phase {
+        Phase::Metadata => entry.metadata += elapsed,
+        Phase::Transfer => entry.transfer += elapsed,
+        Phase::Verify => entry.verify += elapsed,
+    }
+}
+
+/// Add failed attempts of an accession.
+///
+/// # Arguments
+/// * `accession` - The run accession.
+/// * `retries` - The attempts that had to be retried.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::timing::retried;
+/// retried("SRR123456", 2);
+/// ```
+pub fn retried(accession: &str, retries: usize) {
+    if retries == 0 {
+        return;
+    }
+    let mut timings = TIMINGS.lock().unwrap_or_else(|e| e.into_inner());
+    timings.entry(accession.to_string()).or_default().retries += retries;
+}
+
+/// Add bytes an accession downloaded.
+///
+/// # Arguments
+/// * `accession` - The run accession.
+/// * `bytes` - The size of the downloaded file.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::timing::transferred;
+/// transferred("SRR123456", 1_000_000);
+/// ```
+pub fn transferred(accession: &str, bytes: u64) {
+    let mut timings = TIMINGS.lock().unwrap_or_else(|e| e.into_inner());
+    timings.entry(accession.to_string()).or_default().bytes += bytes;
+}
+
+/// Get the timings recorded so far in this batch.
+///
+/// # Returns
+/// The timings, keyed by accession.
+pub fn timings() -> BTreeMap<String, Timings> {
+    TIMINGS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Log the per-accession timings of the batch as a table with a `TOTAL`
+/// row, and optionally write them as JSON, to tell a slow metadata service
+/// from a slow mirror or a slow disk.
+///
+/// # Arguments
+/// * `json` - The JSON file to write, from `--timings`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::timing::summarize_timings;
+/// use std::path::Path;
+/// summarize_timings(Some(Path::new("timings.json")));
+/// ```
+pub fn summarize_timings(json: Option<&Path>) {
+    let timings = timings();
+    if timings.is_empty() {
+        return;
+    }
+
+    let mut total = Timings::default();
+    let mut rows = Vec::new();
+    for (accession, timing) in &timings {
+        total.add(timing);
+        rows.push(timing.row(accession));
+    }
+    if timings.len() > 1 {
+        rows.push(total.row(TOTAL));
+    }
+
+    let columns = COLUMNS.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+    log::info!(
+        "Timings:\n{}",
+        render_table(&rows, &columns, terminal_width())
+    );
+
+    let Some(path) = json else {
+        return;
+    };
+    let accessions = timings
+        .iter()
+        .map(|(accession, timing)| {
+            let mut value = timing.json();
+            if let Some(hint) = hint(accession) {
+                value["hint"] = Value::String(hint);
+            }
+            (accession.clone(), value)
+        })
+        .collect::<Map<String, Value>>();
+    let content = json!({
+        "accessions": accessions,
+        "total": total.json(),
+    });
+    match std::fs::write(path, format!("{:#}\n", content)) {
+        Ok(()) => {
+            settle(path);
+            log::info!("Timings written to {}", path.display());
+        }
+        Err(e) => log::warn!("WARNING: Could not write {}!: {}", path.display(), e),
+    }
+}
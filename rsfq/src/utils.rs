@@ -1,26 +1,60 @@
-use once_cell::sync::Lazy;
+use memmap2::Mmap;
+use once_cell::sync::{Lazy, OnceCell};
 use regex::Regex;
 use tokio::process::Command;
 use walkdir::WalkDir;
 
+use crate::{
+    cellranger::cellranger_name,
+    collision::{collide, on_collision, suffixed, OnCollision},
+    net::{credentials_for, headers_for, host_of, netrc_for, proxy_env, tls_url, wgetrc_for},
+    perms::{create_dirs, settle},
+    plugins::{plugin_path, provider_plugin, PluginKind, PLUGIN_PREFIX},
+    provs::drs::is_drs_uri,
+    quarantine::{is_quarantined, QUARANTINE_DIR},
+    runner::runner,
+    runtable::{read_run_table, sample_names},
+};
+
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
-use std::path::PathBuf;
+use std::io::{BufReader, BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+
+const HASH_BUFFER_SIZE: usize = 10 * 1_048_576; // 10 MB
+pub const PART_SUFFIX: &str = ".part";
+pub const ARIA2_SUFFIX: &str = ".aria2";
+pub const SEGMENTS_SUFFIX: &str = ".segments";
+pub const ARIA2C_CONNECTIONS: usize = 4;
+const ARIA2C_SUMMARY_INTERVAL: u64 = 10; // 10 seconds, its readout feeds progress lines
+
+static RENAME_MAP: OnceCell<HashMap<String, String>> = OnceCell::new();
+static FALLBACK: OnceCell<Vec<Retriever>> = OnceCell::new();
+static RETRIEVER_ARGS: OnceCell<(String, Vec<RetrieverArgs>)> = OnceCell::new();
+static PROTOCOL: OnceCell<Option<Protocol>> = OnceCell::new();
+static ARIA2C: OnceCell<usize> = OnceCell::new();
+static PER_RUN_DIRS: OnceCell<bool> = OnceCell::new();
+// INFO: the chain is built per file, missing tools are reported once
+static MISSING_RETRIEVERS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
 
 static PROJECT_STUDY_RE: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^PRJ[EDN][A-Z][0-9]+$|^[EDS]RP[0-9]{6,}$")
+    Regex::new(r"^PRJ[EDNC][A-Z][0-9]+$|^[EDS]RP[0-9]{6,}$|^CRA[0-9]{6,}$")
         .unwrap_or_else(|e| panic!("Failed to compile PROJECT_STUDY_RE regex: {}", e))
 });
 static SAMPLE_BIOSAMPLE_RE: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^SAM[EDN][A-Z]?[0-9]+$|^[EDS]RS[0-9]{6,}$")
+    Regex::new(r"^SAM[EDNC][A-Z]?[0-9]+$|^[EDS]RS[0-9]{6,}$")
         .unwrap_or_else(|e| panic!("Failed to compile SAMPLE_BIOSAMPLE_RE regex: {}", e))
 });
 static EXPERIMENT_RE: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^[EDS]RX[0-9]{6,}$")
+    Regex::new(r"^[EDSC]RX[0-9]{6,}$")
         .unwrap_or_else(|e| panic!("Failed to compile EXPERIMENT_RE regex: {}", e))
 });
+static RUN_PREFIX_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^([EDSC]RR[0-9]{6,})")
+        .unwrap_or_else(|e| panic!("Failed to compile RUN_PREFIX_RE regex: {}", e))
+});
 static RUN_RE: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^[EDS]RR[0-9]{6,}$")
+    Regex::new(r"^[EDSC]RR[0-9]{6,}$")
         .unwrap_or_else(|e| panic!("Failed to compile RUN_RE regex: {}", e))
 });
 
@@ -37,12 +71,10 @@ static RUN_RE: Lazy<Regex> = Lazy::new(|| {
 /// # Examples
 ///
 /// ```
-/// fn main() {
-///     use rsfq::utils::validate_query;
-///     let query = "PRJEB12345";
-///     let formatted_query = validate_query(query);
-///     assert_eq!(formatted_query, "(study_accession=PRJEB12345 OR secondary_study_accession=PRJEB12345)");
-/// }
+/// use rsfq::utils::validate_query;
+/// let query = "PRJEB12345";
+/// let formatted_query = validate_query(query);
+/// assert_eq!(formatted_query, "(study_accession=PRJEB12345 OR secondary_study_accession=PRJEB12345)");
 /// ```
 pub fn validate_query(query: &str) -> String {
     if PROJECT_STUDY_RE.is_match(query) {
@@ -59,6 +91,9 @@ pub fn validate_query(query: &str) -> String {
         format!("experiment_accession={}", query)
     } else if RUN_RE.is_match(query) {
         format!("run_accession={}", query)
+    } else if provider_plugin().is_some() || is_drs_uri(query) {
+        // INFO: archives behind a provider plugin or DRS have their own accessions
+        format!("run_accession={}", query)
     } else {
         log::error!(
             r"ERROR: {} is not a Study, Sample, Experiment, or Run accession.
@@ -70,6 +105,642 @@ pub fn validate_query(query: &str) -> String {
     }
 }
 
+/// Find every FASTQ file below a directory.
+///
+/// # Arguments
+///
+/// * `dir` - The directory to scan.
+///
+/// # Returns
+///
+/// The paths of all `.fastq.gz`/`.fq.gz` files, sorted.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::utils::local_fastqs;
+/// let fastqs = local_fastqs("DOWNLOADS");
+/// ```
+pub fn local_fastqs<P: AsRef<Path>>(dir: P) -> Vec<PathBuf> {
+    let mut fastqs = WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file() && !is_quarantined(e.path()))
+        .filter(|e| {
+            let name = e.file_name().to_string_lossy();
+            name.ends_with(".fastq.gz") || name.ends_with(".fq.gz")
+        })
+        .map(|e| e.into_path())
+        .collect::<Vec<PathBuf>>();
+
+    fastqs.sort();
+    fastqs
+}
+
+/// Infer the run accession from a FASTQ file name.
+///
+/// # Arguments
+///
+/// * `filename` - The file name, e.g. `SRR123456_1.fastq.gz`.
+///
+/// # Returns
+///
+/// An `Option<String>` with the run accession.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::utils::run_accession_of;
+/// assert_eq!(run_accession_of("SRR123456_1.fastq.gz").as_deref(), Some("SRR123456"));
+/// ```
+pub fn run_accession_of(filename: &str) -> Option<String> {
+    RUN_PREFIX_RE
+        .captures(filename)
+        .and_then(|caps| caps.get(1))
+        .map(|m| m.as_str().to_string())
+}
+
+/// Load the accession to base name map used to name output files.
+/// Only the first call has effect.
+///
+/// The file holds one `accession<TAB>name` pair per line; blank lines and
+/// lines starting with `#` are ignored. An NCBI Run Selector table is also
+/// accepted, naming runs after their `SampleName`.
+///
+/// With `shared`, several runs may share a name, as lanes of one sample
+/// do under `--cellranger-names`.
+///
+/// # Arguments
+/// * `file` - The map file, if any.
+/// * `shared` - Whether runs may share a name.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::utils::init_rename_map;
+/// use std::path::PathBuf;
+/// init_rename_map(Some(&PathBuf::from("map.tsv")), false);
+/// ```
+pub fn init_rename_map(file: Option<&PathBuf>, shared: bool) {
+    let Some(file) = file else {
+        return;
+    };
+
+    // INFO: a Run Selector table names runs after its SampleName column
+    if let Ok(Some(rows)) = read_run_table(file) {
+        let map = if shared {
+            rows.iter()
+                .filter_map(|row| Some((row.run.clone(), row.sample.clone()?)))
+                .collect()
+        } else {
+            sample_names(&rows)
+        };
+        log::info!("Loaded {} sample names from {}", map.len(), file.display());
+        let _ = RENAME_MAP.set(map);
+        return;
+    }
+
+    let content = std::fs::read_to_string(file).unwrap_or_else(|e| {
+        log::error!(
+            "ERROR: Could not read rename map {}!: {}",
+            file.display(),
+            e
+        );
+        std::process::exit(1);
+    });
+
+    let mut map: HashMap<String, String> = HashMap::new();
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (accession, name) = line
+            .split_once('\t')
+            .map(|(accession, name)| (accession.trim(), name.trim()))
+            .filter(|(accession, name)| !accession.is_empty() && !name.is_empty())
+            .unwrap_or_else(|| {
+                log::error!(
+                    "ERROR: Line {} of {} is not ACCESSION<TAB>NAME!",
+                    idx + 1,
+                    file.display()
+                );
+                std::process::exit(1);
+            });
+
+        // INFO: two runs sharing a name would overwrite each other
+        let mut name = name.to_string();
+        if let Some(other) = map
+            .iter()
+            .find(|(_, v)| !shared && **v == name)
+            .map(|(k, _)| k.clone())
+        {
+            match on_collision() {
+                OnCollision::Error => {
+                    log::error!(
+                        "ERROR: {} and {} are both renamed to {}! Use --on-collision to allow it",
+                        other,
+                        accession,
+                        name
+                    );
+                    std::process::exit(1);
+                }
+                OnCollision::Suffix => {
+                    let n = (2..)
+                        .find(|n| !map.values().any(|v| *v == format!("{}-{}", name, n)))
+                        .unwrap_or(2);
+                    log::warn!(
+                        "WARNING: {} and {} are both renamed to {}, naming {} {}-{}",
+                        other,
+                        accession,
+                        name,
+                        accession,
+                        name,
+                        n
+                    );
+                    name = format!("{}-{}", name, n);
+                }
+                OnCollision::Skip => {
+                    log::warn!(
+                        "WARNING: {} and {} are both renamed to {}, skipping {}",
+                        other,
+                        accession,
+                        name,
+                        accession
+                    );
+                    collide(accession);
+                }
+                // INFO: the earlier runs give way, so no two runs write the file at once
+                OnCollision::Overwrite => {
+                    log::warn!(
+                        "WARNING: {} and {} are both renamed to {}, skipping {}",
+                        other,
+                        accession,
+                        name,
+                        other
+                    );
+                    map.iter()
+                        .filter(|(_, v)| **v == name)
+                        .for_each(|(k, _)| collide(k));
+                }
+            }
+        }
+
+        map.insert(accession.to_string(), name);
+    }
+
+    log::info!("Loaded {} names from {}", map.len(), file.display());
+    let _ = RENAME_MAP.set(map);
+}
+
+/// Get the base name output files of a run are written under.
+///
+/// # Arguments
+/// * `accession` - The run accession.
+///
+/// # Returns
+/// The name from the rename map, or the accession itself.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::utils::base_name;
+/// assert_eq!(base_name("SRR123456"), "SRR123456");
+/// ```
+pub fn base_name(accession: &str) -> String {
+    RENAME_MAP
+        .get()
+        .and_then(|map| map.get(accession))
+        .cloned()
+        .unwrap_or_else(|| accession.to_string())
+}
+
+/// Apply the rename map to a FASTQ file name.
+///
+/// # Arguments
+/// * `filename` - The file name, e.g. `SRR123456_1.fastq.gz`.
+///
+/// # Returns
+/// The file name with its run accession replaced, e.g. `liver_1.fastq.gz`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::utils::renamed;
+/// assert_eq!(renamed("SRR123456_1.fastq.gz"), "SRR123456_1.fastq.gz");
+/// assert_eq!(renamed("CRR000001_r2.fq.gz"), "CRR000001_2.fq.gz");
+/// ```
+pub fn renamed(filename: &str) -> String {
+    match run_accession_of(filename) {
+        Some(accession) => {
+            let suffix = gsa_mate(&filename[accession.len()..]);
+            let suffix = suffix.as_str();
+            cellranger_name(&accession, suffix)
+                .unwrap_or_else(|| format!("{}{}", base_name(&accession), suffix))
+        }
+        None => filename.to_string(),
+    }
+}
+
+/// Name GSA mates like ENA ones, e.g. `_f1.fq.gz` becomes `_1.fq.gz` and
+/// `_r2.fq.gz` becomes `_2.fq.gz`.
+fn gsa_mate(suffix: &str) -> String {
+    for (gsa, ena) in [("_f1.", "_1."), ("_r2.", "_2.")] {
+        if let Some(rest) = suffix.strip_prefix(gsa) {
+            return format!("{}{}", ena, rest);
+        }
+    }
+    suffix.to_string()
+}
+
+/// Give every run its own subdirectory of the output directory. Only the
+/// first call has effect.
+///
+/// # Arguments
+/// * `enabled` - Whether `--per-run-dirs` was given.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::utils::init_per_run_dirs;
+/// init_per_run_dirs(true);
+/// ```
+pub fn init_per_run_dirs(enabled: bool) {
+    let _ = PER_RUN_DIRS.set(enabled);
+}
+
+/// Get the directory the files of a run go to: `<outdir>/<run>` with
+/// `--per-run-dirs`, the output directory itself otherwise.
+///
+/// The state manifest always stays at the root, recording paths relative
+/// to it.
+///
+/// # Arguments
+/// * `outdir` - The output directory.
+/// * `accession` - The run accession.
+///
+/// # Returns
+/// The run directory.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::utils::{init_per_run_dirs, run_dir};
+/// use std::path::Path;
+/// init_per_run_dirs(true);
+/// assert_eq!(run_dir(Path::new("DOWNLOADS"), "SRR123456"), Path::new("DOWNLOADS/SRR123456"));
+/// ```
+pub fn run_dir(outdir: &Path, accession: &str) -> PathBuf {
+    if PER_RUN_DIRS.get().copied().unwrap_or(false) {
+        outdir.join(accession)
+    } else {
+        outdir.to_path_buf()
+    }
+}
+
+/// Ask the user a yes/no question on the terminal.
+///
+/// Returns `false` without prompting when stdin is not a TTY.
+///
+/// # Arguments
+///
+/// * `question` - The question to ask.
+///
+/// # Returns
+///
+/// `true` if the user answered yes, `false` otherwise.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::utils::confirm;
+/// if confirm("Download 1000 runs?") {
+///     println!("Downloading...");
+/// }
+/// ```
+pub fn confirm(question: &str) -> bool {
+    if !std::io::stdin().is_terminal() {
+        log::warn!("WARNING: Cannot ask for confirmation, stdin is not a terminal");
+        return false;
+    }
+
+    eprint!("{} [y/N] ", question);
+    let _ = std::io::stderr().flush();
+
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Format a byte count as a human readable size.
+///
+/// # Arguments
+///
+/// * `bytes` - The number of bytes.
+///
+/// # Returns
+///
+/// A string such as `1.5 GB`.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::utils::human_bytes;
+/// assert_eq!(human_bytes(1_610_612_736), "1.5 GB");
+/// ```
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB", "PB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Get the free space of the filesystem holding a directory.
+///
+/// # Arguments
+///
+/// * `dir` - A directory on the filesystem.
+///
+/// # Returns
+///
+/// The bytes available to this user, or `None` if they could not be read.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::utils::free_space;
+/// use std::path::Path;
+/// let free = free_space(Path::new("DOWNLOADS"));
+/// ```
+#[cfg(unix)]
+pub fn free_space(dir: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: statvfs only writes into the buffer it is given
+    let stats = unsafe {
+        if libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stats.assume_init()
+    };
+
+    #[allow(clippy::unnecessary_cast)]
+    Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn free_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+/// Bytes of files being fetched that were promised free space, so parallel
+/// transfers cannot all claim the same space
+static RESERVED: std::sync::Mutex<u64> = std::sync::Mutex::new(0);
+
+/// Free space set aside for a file being fetched, given back when dropped
+pub struct Reserved(u64);
+
+impl Drop for Reserved {
+    fn drop(&mut self) {
+        let mut reserved = RESERVED.lock().unwrap_or_else(|e| e.into_inner());
+        *reserved = reserved.saturating_sub(self.0);
+    }
+}
+
+/// Check that a file of a known size fits in a directory before fetching it,
+/// so a full filesystem fails the file upfront instead of mid-transfer.
+///
+/// The bytes are reserved until the returned guard is dropped, so files
+/// fetched in parallel are checked against what the others still need.
+/// Bytes already written are then counted twice, which errs on the safe side.
+///
+/// # Arguments
+///
+/// * `dir` - The directory the file is written to.
+/// * `bytes` - The expected size of the file, if known.
+///
+/// # Returns
+///
+/// The reservation, held while the file is fetched, or `None` if the
+/// filesystem has less free space than `bytes` and the other reservations.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::utils::fits_on_disk;
+/// use std::path::Path;
+/// assert!(fits_on_disk(Path::new("DOWNLOADS"), Some(1_000)).is_some());
+/// ```
+pub fn fits_on_disk(dir: &Path, bytes: Option<u64>) -> Option<Reserved> {
+    let bytes = bytes.unwrap_or(0);
+    let mut reserved = RESERVED.lock().unwrap_or_else(|e| e.into_inner());
+    match free_space(dir) {
+        Some(free) if bytes + *reserved > free => {
+            log::error!(
+                "ERROR: Not enough space in {}! {} needed, {} free ({} reserved by other transfers)",
+                dir.display(),
+                human_bytes(bytes),
+                human_bytes(free),
+                human_bytes(*reserved)
+            );
+            None
+        }
+        _ => {
+            *reserved += bytes;
+            Some(Reserved(bytes))
+        }
+    }
+}
+
+/// Allocate the blocks of a file about to be written, without changing its
+/// length, so a full filesystem fails it now rather than mid-transfer and
+/// the file is laid out in one piece. Resuming still starts at its length.
+/// A no-op where the filesystem cannot preallocate.
+///
+/// # Arguments
+///
+/// * `file` - The file being written.
+/// * `bytes` - The expected size of the file.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::utils::preallocate;
+/// let file = std::fs::File::create("SRR123456_1.fastq.gz.part").unwrap();
+/// preallocate(&file, 1_000_000).unwrap();
+/// ```
+#[cfg(target_os = "linux")]
+pub fn preallocate(file: &File, bytes: u64) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: fallocate only reads the descriptor, which outlives the call
+    let code = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_KEEP_SIZE,
+            0,
+            bytes as libc::off_t,
+        )
+    };
+    // INFO: filesystems without fallocate just grow the file as it is written
+    match code {
+        0 => Ok(()),
+        _ => match std::io::Error::last_os_error() {
+            e if matches!(e.raw_os_error(), Some(libc::EOPNOTSUPP | libc::ENOSYS)) => Ok(()),
+            e => Err(e),
+        },
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn preallocate(_file: &File, _bytes: u64) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Get the staging path a file is written to until it is complete and
+/// verified, `<file>.part`, so it is never mistaken for a finished file.
+///
+/// # Arguments
+///
+/// * `path` - The final path of the file.
+///
+/// # Returns
+///
+/// The staging path, next to the final one.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::utils::part_path;
+/// use std::path::Path;
+/// assert_eq!(
+///     part_path(Path::new("SRR123456_1.fastq.gz")),
+///     Path::new("SRR123456_1.fastq.gz.part")
+/// );
+/// ```
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(PART_SUFFIX);
+    PathBuf::from(part)
+}
+
+/// Get the control file aria2c keeps next to a file it has not finished,
+/// `<file>.aria2`.
+///
+/// # Arguments
+///
+/// * `path` - The file aria2c writes to.
+///
+/// # Returns
+///
+/// The control file path.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::utils::aria2_control;
+/// use std::path::Path;
+/// assert_eq!(
+///     aria2_control(Path::new("SRR123456_1.fastq.gz.part")),
+///     Path::new("SRR123456_1.fastq.gz.part.aria2")
+/// );
+/// ```
+pub fn aria2_control(path: &Path) -> PathBuf {
+    let mut control = path.as_os_str().to_owned();
+    control.push(ARIA2_SUFFIX);
+    PathBuf::from(control)
+}
+
+/// Get the control file `-T native` keeps next to a file it downloads in
+/// segments, `<file>.segments`, listing how far each range got.
+///
+/// # Arguments
+///
+/// * `path` - The file being downloaded in segments.
+///
+/// # Returns
+///
+/// The control file path.
+///
+/// # Examples
+///
+/// ```rust
+/// use rsfq::utils::segments_control;
+/// use std::path::Path;
+/// assert_eq!(
+///     segments_control(Path::new("SRR123456_1.fastq.gz.part")),
+///     Path::new("SRR123456_1.fastq.gz.part.segments")
+/// );
+/// ```
+pub fn segments_control(path: &Path) -> PathBuf {
+    let mut control = path.as_os_str().to_owned();
+    control.push(SEGMENTS_SUFFIX);
+    PathBuf::from(control)
+}
+
+/// Turn an incomplete file back into the staging file of its download, so
+/// the next transfer continues it instead of starting over. Its aria2c or
+/// segments control file, if any, moves along.
+///
+/// # Arguments
+///
+/// * `path` - The incomplete file, under its final name.
+///
+/// # Returns
+///
+/// `true` if the file now is the staging file; `false` if a longer staging
+/// file was already there, and the file is dropped instead.
+///
+/// # Examples
+///
+/// ```rust, no_run
+/// use rsfq::utils::resume_partial;
+/// use std::path::Path;
+/// resume_partial(Path::new("DOWNLOADS/SRR123456_1.fastq.gz"));
+/// ```
+pub fn resume_partial(path: &Path) -> bool {
+    let part = part_path(path);
+    let len = |path: &Path| std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+
+    if len(&part) >= len(path) && part.exists() {
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(aria2_control(path));
+        let _ = std::fs::remove_file(segments_control(path));
+        return false;
+    }
+
+    drop_partial(&part);
+    if std::fs::rename(path, &part).is_err() {
+        return false;
+    }
+    let _ = std::fs::rename(aria2_control(path), aria2_control(&part));
+    let _ = std::fs::rename(segments_control(path), segments_control(&part));
+    true
+}
+
+/// Remove a staging file and its aria2c or segments control file, so the
+/// next transfer starts from scratch.
+///
+/// # Arguments
+///
+/// * `part` - The staging file.
+pub fn drop_partial(part: &Path) {
+    let _ = std::fs::remove_file(part);
+    let _ = std::fs::remove_file(aria2_control(part));
+    let _ = std::fs::remove_file(segments_control(part));
+}
+
 pub fn check_dependencies() {
     // INFO: should check aria2c is installed, otherwise install it
     todo!()
@@ -79,24 +750,87 @@ pub fn check_nf() {
     todo!()
 }
 
-/// Move all `.fastq.gz` files to the root output directory
-/// and deletes empty/nested .nf directories
+/// Move all `.fastq.gz` files to the root output directory (their run
+/// directory with `--per-run-dirs`) and deletes empty/nested .nf directories
 ///
 /// # Arguments
 /// * `outdir` - The output directory to move the files to
 pub fn __move_to_root(outdir: &PathBuf) {
+    let mut moved = HashSet::new();
     for entry in WalkDir::new(outdir)
         .into_iter()
         .filter_map(Result::ok)
         .filter(|e| {
-            e.file_type().is_file() && e.path().extension().map_or(false, |ext| ext == "gz")
+            e.file_type().is_file()
+                && (is_quarantined(e.path()) || e.path().extension().is_some_and(|ext| ext == "gz"))
         })
     {
-        let dest = outdir.join(entry.file_name());
-        std::fs::rename(entry.path(), dest).unwrap_or_else(|e| {
+        // INFO: files keep the run directory they were written to, failed
+        // ones (and their reason notes) stay quarantined
+        let dir = if is_quarantined(entry.path()) {
+            outdir.join(QUARANTINE_DIR)
+        } else {
+            entry
+                .path()
+                .parent()
+                .and_then(|parent| parent.file_name())
+                .map(|parent| parent.to_string_lossy().to_string())
+                .filter(|parent| run_accession_of(parent).as_deref() == Some(parent.as_str()))
+                .map(|run| run_dir(outdir, &run))
+                .unwrap_or_else(|| outdir.to_path_buf())
+        };
+        create_dirs(&dir).unwrap_or_else(|e| {
+            log::error!("ERROR: Failed to create {}!: {}", dir.display(), e);
+            std::process::exit(1);
+        });
+        let mut dest = dir.join(entry.file_name());
+        if entry.path() == dest {
+            continue;
+        }
+        // INFO: two tasks may have written the same name
+        if !moved.insert(dest.clone()) {
+            match on_collision() {
+                OnCollision::Error => {
+                    log::error!(
+                        "ERROR: More than one task wrote {}! Use --on-collision to allow it",
+                        dest.display()
+                    );
+                    std::process::exit(1);
+                }
+                OnCollision::Suffix => {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    dest = (2..)
+                        .map(|n| dir.join(suffixed(&name, n)))
+                        .find(|dest| !moved.contains(dest))
+                        .unwrap_or(dest);
+                    log::warn!(
+                        "WARNING: More than one task wrote {}, moving it to {}",
+                        name,
+                        dest.display()
+                    );
+                    moved.insert(dest.clone());
+                }
+                OnCollision::Overwrite => {
+                    log::warn!(
+                        "WARNING: More than one task wrote {}, overwriting it",
+                        dest.display()
+                    );
+                }
+                OnCollision::Skip => {
+                    log::warn!(
+                        "WARNING: More than one task wrote {}, keeping the first",
+                        dest.display()
+                    );
+                    let _ = std::fs::remove_file(entry.path());
+                    continue;
+                }
+            }
+        }
+        std::fs::rename(entry.path(), &dest).unwrap_or_else(|e| {
             log::error!("ERROR: Failed to move file: {}", e);
             std::process::exit(1);
         });
+        settle(&dest);
     }
 }
 
@@ -114,8 +848,11 @@ pub fn __clean_nf_dirs(outdir: &PathBuf) {
             std::process::exit(1);
         });
         let path = entry.path();
+        // INFO: run dirs and quarantine are kept, Nextflow work dirs are hashes
+        let name = entry.file_name().to_string_lossy().to_string();
+        let kept = name == QUARANTINE_DIR || run_accession_of(&name).is_some_and(|run| run == name);
 
-        if path.is_dir() {
+        if path.is_dir() && !kept {
             std::fs::remove_dir_all(&path).unwrap_or_else(|e| {
                 log::error!("ERROR: Failed to remove directory: {}", e);
                 std::process::exit(1);
@@ -129,10 +866,10 @@ pub fn __clean_nf_dirs(outdir: &PathBuf) {
 /// # Arguments
 /// * `outdir` - The output directory to move the files to
 /// * `extension` - The file extension to match
-/// * `file` - The output file name
+/// * `file` - The output file name, relative to `outdir`
 pub fn __concat(outdir: &PathBuf, extension: &str, file: &str) {
     let out_path = outdir.join(file);
-    let mut writer = BufWriter::new(File::create(out_path).unwrap_or_else(|e| {
+    let mut writer = BufWriter::new(File::create(&out_path).unwrap_or_else(|e| {
         log::error!("ERROR: Failed to create output file: {}", e);
         std::process::exit(1);
     }));
@@ -141,7 +878,9 @@ pub fn __concat(outdir: &PathBuf, extension: &str, file: &str) {
         .into_iter()
         .filter_map(Result::ok)
         .filter(|e| {
-            e.file_type().is_file() && e.path().extension().map_or(false, |ext| ext == extension)
+            e.file_type().is_file()
+                && e.path().extension().is_some_and(|ext| ext == extension)
+                && e.path() != out_path
         })
     {
         let mut reader = BufReader::new(File::open(entry.path()).unwrap_or_else(|e| {
@@ -153,17 +892,40 @@ pub fn __concat(outdir: &PathBuf, extension: &str, file: &str) {
             std::process::exit(1);
         });
     }
+    settle(&out_path);
 }
 
 /// Representation of a retriever
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum Retriever {
     Wget,
     Aria2c,
     Curl,
+    /// Downloads in-process over HTTPS, needing no external tool
+    Native,
+    /// An external retriever plugin, given as `plugin:<name>`
+    Plugin(String),
 }
 
 impl Retriever {
+    /// Check whether the retriever's tool is on the PATH.
+    ///
+    /// # Returns
+    /// `true` if the retriever can run.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::utils::Retriever;
+    /// assert!(Retriever::Curl.is_installed());
+    /// ```
+    pub fn is_installed(&self) -> bool {
+        match self {
+            // INFO: plugins are checked once by init_plugins
+            Retriever::Plugin(_) | Retriever::Native => true,
+            _ => runner().installed(&self.to_string()),
+        }
+    }
+
     /// Materialize a URL into a file using the specified retriever.
     ///
     /// # Arguments
@@ -171,7 +933,8 @@ impl Retriever {
     /// * `output` - The path to the output file.
     ///
     /// # Returns
-    /// A `Command` instance representing the command to execute.
+    /// A `Command` instance representing the command to execute, `None` for
+    /// the native retriever, which downloads with `net::fetch` instead.
     ///
     /// # Examples
     /// ```rust, no_run
@@ -183,36 +946,409 @@ impl Retriever {
     /// let output = PathBuf::from("/path/to/output");
     /// let command = retriever.materialize(url, &output);
     /// ```
-    pub fn materialize(&self, url: &str, output: &PathBuf) -> Command {
+    pub fn materialize(&self, url: &str, output: &Path) -> Option<Command> {
+        self.command(url, Sink::File(output))
+    }
+
+    /// Build the command of a retriever that can print a download to its
+    /// stdout, so rsfq writes and hashes it as it arrives.
+    ///
+    /// # Arguments
+    /// * `url` - The URL to download.
+    /// * `offset` - The bytes already downloaded, to ask the rest of.
+    ///
+    /// # Returns
+    /// The command for wget and curl, `None` for the other retrievers.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use rsfq::utils::Retriever;
+    ///
+    /// let url = "https://example.com/file.txt";
+    /// assert!(Retriever::Curl.pipe(url, 0).is_some());
+    /// assert!(Retriever::Aria2c.pipe(url, 0).is_none());
+    /// ```
+    pub fn pipe(&self, url: &str, offset: u64) -> Option<Command> {
         match self {
+            Retriever::Wget | Retriever::Curl => self.command(url, Sink::Stdout(offset)),
+            _ => None,
+        }
+    }
+
+    /// Build the command of a retriever writing to `sink`.
+    fn command(&self, url: &str, sink: Sink) -> Option<Command> {
+        // INFO: credentials are read from files by path, never passed as
+        // arguments, and only ever sent over HTTPS
+        let authenticated = credentials_for(url).is_some();
+        let secured = authenticated.then(|| tls_url(url));
+        let url = secured.as_deref().unwrap_or(url);
+
+        let mut cmd = match self {
             Retriever::Wget => {
+                let url = &with_protocol(url);
+                // INFO: -c picks a partial file up where it stopped, and
+                // dot:mega prints a progress line per 3 MB instead of per 50 KB
                 let mut cmd = Command::new("wget");
-                cmd.arg("--no-check-certificate")
-                    .arg("-O")
-                    .arg(output)
-                    .arg(url);
+                cmd.arg("--no-check-certificate").arg("--progress=dot:mega");
+                match sink {
+                    Sink::File(output) => cmd.arg("-c").arg("-O").arg(output),
+                    Sink::Stdout(0) => cmd.arg("-O").arg("-"),
+                    Sink::Stdout(offset) => cmd
+                        .arg(format!("--start-pos={}", offset))
+                        .arg("-O")
+                        .arg("-"),
+                };
+
+                if let Some(wgetrc) = authenticated.then(|| wgetrc_for(url)).flatten() {
+                    cmd.arg(format!("--config={}", wgetrc.display()));
+                }
+                for header in headers_for(url) {
+                    cmd.arg(format!("--header={}", header));
+                }
+                cmd.args(retriever_args(self, url));
 
+                cmd.arg(url);
                 cmd
             }
             Retriever::Aria2c => {
+                // INFO: aria2c guesses nothing, a URL without scheme goes over HTTP
+                let url = &match with_protocol(url) {
+                    url if url.contains("://") => url,
+                    url => format!("{}://{}", Protocol::Http, url),
+                };
+                let Sink::File(output) = sink else {
+                    return None;
+                };
+                let connections = aria2c_connections().to_string();
+                let (dir, name) = (
+                    output
+                        .parent()
+                        .filter(|dir| !dir.as_os_str().is_empty())
+                        .unwrap_or(Path::new(".")),
+                    output.file_name().unwrap_or_default(),
+                );
+
                 let mut cmd = Command::new("aria2c");
-                cmd.arg("-x4")
+                // INFO: falloc reserves the whole file upfront, failing early on a full disk
+                cmd.arg("-x")
+                    .arg(&connections)
+                    .arg("-s")
+                    .arg(&connections)
                     .arg("-c")
-                    .arg(format!("-o {}", output.display()))
-                    .arg(format!("http://{}", url));
+                    .arg("--file-allocation=falloc")
+                    .arg(format!("--summary-interval={}", ARIA2C_SUMMARY_INTERVAL))
+                    .arg("-d")
+                    .arg(dir)
+                    .arg("-o")
+                    .arg(name);
+
+                if let Some(netrc) = authenticated.then(|| netrc_for(url)).flatten() {
+                    cmd.arg(format!("--netrc-path={}", netrc.display()));
+                }
+                for header in headers_for(url) {
+                    cmd.arg(format!("--header={}", header));
+                }
+                cmd.args(retriever_args(self, url));
 
+                cmd.arg(url);
                 cmd
             }
             Retriever::Curl => {
+                let url = &with_protocol(url);
+                // INFO: -C - continues from the size of a partial file,
+                // without -o the download goes to stdout
                 let mut cmd = Command::new("curl");
-                cmd.arg("-o").arg(output).arg(url);
+                match sink {
+                    Sink::File(output) => cmd.arg("-C").arg("-").arg("-o").arg(output),
+                    Sink::Stdout(0) => &mut cmd,
+                    Sink::Stdout(offset) => cmd.arg("-C").arg(offset.to_string()),
+                };
 
+                if let Some(netrc) = authenticated.then(|| netrc_for(url)).flatten() {
+                    cmd.arg("--netrc-file").arg(netrc);
+                }
+                for header in headers_for(url) {
+                    cmd.arg("-H").arg(header.to_string());
+                }
+                cmd.args(retriever_args(self, url));
+
+                cmd.arg(url);
                 cmd
             }
+            // INFO: the URL, output, credentials and headers go through stdin
+            Retriever::Plugin(name) => Command::new(plugin_path(PluginKind::Retriever, name)),
+            Retriever::Native => return None,
+        };
+        cmd.envs(proxy_env());
+
+        Some(cmd)
+    }
+}
+
+/// Where a retriever writes a download
+enum Sink<'a> {
+    /// The file, resumed from its size
+    File(&'a Path),
+    /// Stdout, from the byte offset given
+    Stdout(u64),
+}
+
+/// Scheme given to URLs listed without one, such as ENA's `fastq_ftp`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Ftp,
+    Http,
+    Https,
+}
+
+/// Parse a string into a `Protocol`
+impl std::str::FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ftp" => Ok(Protocol::Ftp),
+            "http" => Ok(Protocol::Http),
+            "https" => Ok(Protocol::Https),
+            _ => Err(format!(
+                "Invalid protocol: {}, expected ftp, http or https",
+                s
+            )),
+        }
+    }
+}
+
+/// Display the `Protocol` as its URL scheme.
+impl std::fmt::Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Protocol::Ftp => write!(f, "ftp"),
+            Protocol::Http => write!(f, "http"),
+            Protocol::Https => write!(f, "https"),
+        }
+    }
+}
+
+/// Set the scheme URLs listed without one are fetched with. Only the first
+/// call has effect.
+///
+/// # Arguments
+/// * `protocol` - The scheme, from `--protocol`; each retriever's own
+///   default if `None`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::utils::{init_protocol, Protocol};
+/// init_protocol(Some(Protocol::Https));
+/// ```
+pub fn init_protocol(protocol: Option<Protocol>) {
+    let _ = PROTOCOL.set(protocol);
+}
+
+/// Give a URL listed without scheme the one chosen with `--protocol`.
+///
+/// # Arguments
+/// * `url` - The URL, with or without scheme.
+///
+/// # Returns
+/// The URL, untouched if it has a scheme or no protocol was chosen.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::utils::{init_protocol, with_protocol, Protocol};
+/// init_protocol(Some(Protocol::Ftp));
+/// assert_eq!(
+///     with_protocol("ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR123456/SRR123456.fastq.gz"),
+///     "ftp://ftp.sra.ebi.ac.uk/vol1/fastq/SRR123/SRR123456/SRR123456.fastq.gz"
+/// );
+/// ```
+pub fn with_protocol(url: &str) -> String {
+    match PROTOCOL.get().copied().flatten() {
+        Some(protocol) if !url.contains("://") => format!("{}://{}", protocol, url),
+        _ => url.to_string(),
+    }
+}
+
+/// Set how many connections aria2c opens per file. Only the first call has
+/// effect.
+///
+/// # Arguments
+/// * `connections` - The connections, from `--aria2c-connections`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::utils::init_aria2c_connections;
+/// init_aria2c_connections(8);
+/// ```
+pub fn init_aria2c_connections(connections: usize) {
+    let _ = ARIA2C.set(connections);
+}
+
+/// Get how many connections aria2c opens per file.
+fn aria2c_connections() -> usize {
+    ARIA2C.get().copied().unwrap_or(ARIA2C_CONNECTIONS)
+}
+
+/// Extra arguments for the selected retriever, given as `[host=]ARGS`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetrieverArgs {
+    /// The host they apply to, every host if `None`
+    pub host: Option<String>,
+    pub args: Vec<String>,
+}
+
+/// Parse a string into `RetrieverArgs`
+impl std::str::FromStr for RetrieverArgs {
+    type Err = String;
+
+    /// Parse a string into `RetrieverArgs`
+    ///
+    /// # Arguments
+    /// * `s` - Arguments split on whitespace, optionally prefixed by the
+    ///   host they apply to and `=`.
+    ///
+    /// # Returns
+    /// * `Result<Self, Self::Err>` - The parsed arguments.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::utils::RetrieverArgs;
+    /// use std::str::FromStr;
+    /// let args = RetrieverArgs::from_str("ftp.sra.ebi.ac.uk=--limit-rate=10m --tries=1").unwrap();
+    /// assert_eq!(args.host.as_deref(), Some("ftp.sra.ebi.ac.uk"));
+    /// assert_eq!(args.args, vec!["--limit-rate=10m", "--tries=1"]);
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        // INFO: arguments start with a dash, a host never does
+        let (host, args) = match s.split_once('=') {
+            Some((host, args))
+                if !host.is_empty()
+                    && !host.starts_with('-')
+                    && !host.contains(char::is_whitespace) =>
+            {
+                (Some(host.to_lowercase()), args)
+            }
+            _ => (None, s),
+        };
+
+        let args = args
+            .split_whitespace()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if args.is_empty() {
+            return Err(format!("No retriever arguments given: {}", s));
+        }
+
+        Ok(RetrieverArgs { host, args })
+    }
+}
+
+/// Display the `RetrieverArgs` as `[host=]ARGS`.
+impl std::fmt::Display for RetrieverArgs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(host) = &self.host {
+            write!(f, "{}=", host)?;
         }
+        write!(f, "{}", self.args.join(" "))
     }
 }
 
+/// Set the extra arguments appended to the commands of the selected
+/// retriever. Only the first call has effect.
+///
+/// Fallback retrievers run without them, since another tool would reject
+/// them.
+///
+/// # Arguments
+/// * `retriever` - The retriever selected with `-T`.
+/// * `args` - The arguments, from `--retriever-args`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::utils::{init_retriever_args, Retriever, RetrieverArgs};
+/// use std::str::FromStr;
+/// init_retriever_args(&Retriever::Wget, vec![RetrieverArgs::from_str("--limit-rate=10m").unwrap()]);
+/// ```
+pub fn init_retriever_args(retriever: &Retriever, args: Vec<RetrieverArgs>) {
+    if !args.is_empty() && matches!(retriever, Retriever::Native | Retriever::Plugin(_)) {
+        log::warn!(
+            "WARNING: --retriever-args has no effect with -T {}",
+            retriever
+        );
+    }
+    let _ = RETRIEVER_ARGS.set((retriever.to_string(), args));
+}
+
+/// Get the extra arguments a retriever gets for a URL.
+fn retriever_args(retriever: &Retriever, url: &str) -> Vec<String> {
+    let Some((selected, args)) = RETRIEVER_ARGS.get() else {
+        return Vec::new();
+    };
+    if *selected != retriever.to_string() {
+        return Vec::new();
+    }
+
+    let host = host_of(url);
+    args.iter()
+        .filter(|args| args.host.as_ref().is_none_or(|h| *h == host))
+        .flat_map(|args| args.args.iter().cloned())
+        .collect()
+}
+
+/// Set the retrievers tried, in order, when the selected one is missing or
+/// keeps failing. Only the first call has effect.
+///
+/// # Arguments
+/// * `retrievers` - The fallback chain, from `--fallback`; empty disables it.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::utils::{init_fallback, Retriever};
+/// init_fallback(vec![Retriever::Wget, Retriever::Curl]);
+/// ```
+pub fn init_fallback(retrievers: Vec<Retriever>) {
+    let _ = FALLBACK.set(retrievers);
+}
+
+/// Get the retrievers to try for a download: the selected one first, then
+/// the fallback chain, skipping duplicates and tools that are not installed.
+///
+/// # Arguments
+/// * `selected` - The selected retriever.
+///
+/// # Returns
+/// The retrievers to try, in order; empty if none is installed.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::utils::{retriever_chain, Retriever};
+/// let chain = retriever_chain(&Retriever::Aria2c);
+/// ```
+pub fn retriever_chain(selected: &Retriever) -> Vec<Retriever> {
+    let mut chain: Vec<Retriever> = Vec::new();
+    let mut seen: Vec<String> = Vec::new();
+    let fallback = FALLBACK.get().map(Vec::as_slice).unwrap_or_default();
+
+    for retriever in std::iter::once(selected).chain(fallback) {
+        if seen.contains(&retriever.to_string()) {
+            continue;
+        }
+        seen.push(retriever.to_string());
+        if retriever.is_installed() {
+            chain.push(retriever.clone());
+        } else {
+            let mut missing = MISSING_RETRIEVERS.lock().unwrap_or_else(|e| e.into_inner());
+            if !missing.contains(&retriever.to_string()) {
+                missing.push(retriever.to_string());
+                log::warn!("WARNING: {} is not installed, skipping it", retriever);
+            }
+        }
+    }
+
+    chain
+}
+
 /// Create a new `Retriever` instance from a string.
 ///
 /// # Arguments
@@ -235,6 +1371,10 @@ impl std::str::FromStr for Retriever {
             "wget" => Ok(Retriever::Wget),
             "aria2c" => Ok(Retriever::Aria2c),
             "curl" => Ok(Retriever::Curl),
+            "native" => Ok(Retriever::Native),
+            _ if s.starts_with(PLUGIN_PREFIX) && s.len() > PLUGIN_PREFIX.len() => {
+                Ok(Retriever::Plugin(s[PLUGIN_PREFIX.len()..].to_string()))
+            }
             _ => Err(format!("Invalid downloader: {}", s)),
         }
     }
@@ -255,6 +1395,8 @@ impl std::fmt::Display for Retriever {
             Retriever::Wget => write!(f, "wget"),
             Retriever::Aria2c => write!(f, "aria2c"),
             Retriever::Curl => write!(f, "curl"),
+            Retriever::Native => write!(f, "native"),
+            Retriever::Plugin(name) => write!(f, "{}{}", PLUGIN_PREFIX, name),
         }
     }
 }
@@ -319,3 +1461,160 @@ impl std::fmt::Display for Layout {
         }
     }
 }
+
+/// Enum representing the checksum used for local integrity manifests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    Md5,
+    Blake3,
+    Xxh3,
+}
+
+impl std::str::FromStr for Checksum {
+    type Err = String;
+
+    /// Parse a string into a Checksum
+    ///
+    /// # Arguments
+    /// * `s` - The string to parse.
+    ///
+    /// # Returns
+    /// * `Result<Self, Self::Err>` - The parsed Checksum.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::utils::Checksum;
+    /// use std::str::FromStr;
+    /// let checksum = Checksum::from_str("blake3");
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "md5" => Ok(Checksum::Md5),
+            "blake3" => Ok(Checksum::Blake3),
+            "xxh3" => Ok(Checksum::Xxh3),
+            _ => Err(format!("Invalid checksum: {}", s)),
+        }
+    }
+}
+
+/// Display the name of the `Checksum` instance.
+impl std::fmt::Display for Checksum {
+    /// Format the `Checksum` instance as a string.
+    ///
+    /// # Arguments
+    /// * `f` - The formatter to use.
+    ///
+    /// # Returns
+    /// * `std::fmt::Result` - The formatted string.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::utils::Checksum;
+    /// println!("{}", Checksum::Xxh3);
+    /// ```
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Checksum::Md5 => write!(f, "md5"),
+            Checksum::Blake3 => write!(f, "blake3"),
+            Checksum::Xxh3 => write!(f, "xxh3"),
+        }
+    }
+}
+
+impl Checksum {
+    /// Hash a file with this checksum.
+    ///
+    /// The file is memory-mapped and hashed in place, falling back to
+    /// buffered reads where mapping is not possible (empty files, some FUSE
+    /// and network mounts).
+    ///
+    /// # Arguments
+    /// * `path` - The file to hash.
+    ///
+    /// # Returns
+    /// An `Option<String>` with the hexadecimal digest, `None` if the file cannot be read.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::utils::Checksum;
+    /// use std::path::Path;
+    /// let digest = Checksum::Blake3.digest(Path::new("SRR123456_1.fastq.gz"));
+    /// ```
+    pub fn digest(&self, path: &Path) -> Option<String> {
+        let file = File::open(path).ok()?;
+        let mut hasher = Hasher::new(*self);
+
+        // SAFETY: the map is read-only and dropped before returning; a file
+        // truncated underneath us is caught by the checksum comparison
+        match unsafe { Mmap::map(&file) } {
+            Ok(map) => {
+                #[cfg(unix)]
+                let _ = map.advise(memmap2::Advice::Sequential);
+                map.chunks(HASH_BUFFER_SIZE)
+                    .for_each(|chunk| hasher.update(chunk));
+            }
+            Err(_) => {
+                let mut reader = BufReader::with_capacity(HASH_BUFFER_SIZE, file);
+                let mut buffer = vec![0; HASH_BUFFER_SIZE];
+                loop {
+                    let bytes_read = reader.read(&mut buffer).ok()?;
+                    if bytes_read == 0 {
+                        break;
+                    }
+                    hasher.update(&buffer[..bytes_read]);
+                }
+            }
+        }
+
+        Some(hasher.finish())
+    }
+}
+
+/// Running state of a `Checksum`, fed chunk by chunk
+pub enum Hasher {
+    Md5(md5::Context),
+    Blake3(Box<blake3::Hasher>),
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+}
+
+impl Hasher {
+    /// Start hashing with a checksum.
+    ///
+    /// # Arguments
+    /// * `checksum` - The checksum to compute.
+    ///
+    /// # Examples
+    /// ```rust, no_run
+    /// use rsfq::utils::{Checksum, Hasher};
+    /// let mut hasher = Hasher::new(Checksum::Md5);
+    /// hasher.update(b"@r1\nACGT\n+\nIIII\n");
+    /// println!("{}", hasher.finish());
+    /// ```
+    pub fn new(checksum: Checksum) -> Self {
+        match checksum {
+            Checksum::Md5 => Hasher::Md5(md5::Context::new()),
+            Checksum::Blake3 => Hasher::Blake3(Box::default()),
+            Checksum::Xxh3 => Hasher::Xxh3(Box::default()),
+        }
+    }
+
+    /// Hash the next bytes.
+    pub fn update(&mut self, chunk: &[u8]) {
+        match self {
+            Hasher::Md5(context) => context.consume(chunk),
+            Hasher::Blake3(hasher) => {
+                hasher.update(chunk);
+            }
+            Hasher::Xxh3(hasher) => hasher.update(chunk),
+        }
+    }
+
+    /// Get the hexadecimal digest of every byte hashed.
+    pub fn finish(self) -> String {
+        match self {
+            Hasher::Md5(context) => format!("{:x}", context.compute()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Hasher::Xxh3(hasher) => format!("{:016x}", hasher.digest()),
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use flate2::read::MultiGzDecoder;
+use once_cell::sync::OnceCell;
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use crate::{
+    scheduler::{blocking, cpu_slot},
+    state::recorded,
+    utils::{human_bytes, run_accession_of, Checksum},
+};
+
+pub const READ_ID_SAMPLE: usize = 100; // 100 records
+
+static CHECK_READ_IDS: OnceCell<bool> = OnceCell::new();
+static VERIFY_EXISTING: OnceCell<bool> = OnceCell::new();
+static SKIP_VERIFY: OnceCell<bool> = OnceCell::new();
+
+/// Enable the read ID cross-check of downloaded files. Only the first call has effect.
+///
+/// # Arguments
+/// * `enabled` - Whether downloaded files should be sampled.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::validate::init_read_id_check;
+/// init_read_id_check(true);
+/// ```
+pub fn init_read_id_check(enabled: bool) {
+    let _ = CHECK_READ_IDS.set(enabled);
+}
+
+/// Sample the first records of a gzipped FASTQ file and collect the run
+/// accessions their read IDs reference.
+///
+/// Read IDs without an accession (original submitter names) are ignored.
+///
+/// # Arguments
+/// * `fastq` - The gzipped FASTQ file.
+/// * `records` - The number of records to sample.
+///
+/// # Returns
+/// An `Option<Vec<String>>` with the distinct accessions found, `None` if
+/// the file cannot be read or decompressed.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::validate::sampled_accessions;
+/// use std::path::Path;
+/// let found = sampled_accessions(Path::new("SRR123456_1.fastq.gz"), 100);
+/// ```
+pub fn sampled_accessions(fastq: &Path, records: usize) -> Option<Vec<String>> {
+    let reader = BufReader::new(MultiGzDecoder::new(File::open(fastq).ok()?));
+    let mut found: Vec<String> = Vec::new();
+
+    for line in reader.lines().step_by(4).take(records) {
+        let line = line.ok()?;
+        let Some(id) = line.strip_prefix('@') else {
+            continue;
+        };
+
+        if let Some(accession) = run_accession_of(id) {
+            if !found.contains(&accession) {
+                found.push(accession);
+            }
+        }
+    }
+
+    Some(found)
+}
+
+/// Check that the read IDs of a downloaded file reference the expected run.
+///
+/// Always passes unless enabled with `init_read_id_check`.
+///
+/// # Arguments
+/// * `fastq` - The gzipped FASTQ file.
+/// * `accession` - The run accession the file was requested for.
+///
+/// # Returns
+/// `true` if the file passes (or the check is disabled), `false` if its read
+/// IDs name another run or the file cannot be read.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::validate::{check_read_ids, init_read_id_check};
+/// use std::path::Path;
+/// init_read_id_check(true);
+/// assert!(check_read_ids(Path::new("SRR123456_1.fastq.gz"), "SRR123456"));
+/// ```
+pub fn check_read_ids(fastq: &Path, accession: &str) -> bool {
+    if !CHECK_READ_IDS.get().copied().unwrap_or(false) {
+        return true;
+    }
+
+    match sampled_accessions(fastq, READ_ID_SAMPLE) {
+        Some(found) if found.iter().all(|run| run == accession) => true,
+        Some(found) => {
+            log::error!(
+                "ERROR: Read IDs in {} reference {} instead of {}!",
+                fastq.display(),
+                found.join(", "),
+                accession
+            );
+            false
+        }
+        None => {
+            log::error!("ERROR: Could not sample read IDs from {}!", fastq.display());
+            false
+        }
+    }
+}
+
+/// Decompress a gzipped file end to end to make sure no member is
+/// truncated or corrupted.
+///
+/// # Arguments
+/// * `path` - The gzipped file.
+///
+/// # Returns
+/// `true` if every gzip member decompresses cleanly.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::validate::gzip_intact;
+/// use std::path::Path;
+/// assert!(gzip_intact(Path::new("SRR123456_1.fastq.gz")));
+/// ```
+pub fn gzip_intact(path: &Path) -> bool {
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    let mut decoder = MultiGzDecoder::new(BufReader::new(file));
+
+    match io::copy(&mut decoder, &mut io::sink()) {
+        Ok(_) => true,
+        Err(e) => {
+            log::debug!("gzip check failed for {}: {}", path.display(), e);
+            false
+        }
+    }
+}
+
+/// Hash files that are already on disk before skipping them, instead of
+/// trusting a matching size. Only the first call has effect.
+///
+/// # Arguments
+/// * `enabled` - Whether `--verify-existing` was given.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::validate::init_verify_existing;
+/// init_verify_existing(true);
+/// ```
+pub fn init_verify_existing(enabled: bool) {
+    let _ = VERIFY_EXISTING.set(enabled);
+}
+
+/// Trust downloaded files without checking them against their MD5. Only
+/// the first call has effect.
+///
+/// # Arguments
+/// * `enabled` - Whether `--skip-verify` (or `--force`) was given.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::validate::init_skip_verify;
+/// init_skip_verify(true);
+/// ```
+pub fn init_skip_verify(enabled: bool) {
+    let _ = SKIP_VERIFY.set(enabled);
+}
+
+/// Check whether downloads skip their MD5 check.
+///
+/// # Returns
+/// `true` with `--skip-verify`.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::validate::skip_verify;
+/// if skip_verify() {
+///     println!("files are trusted as downloaded");
+/// }
+/// ```
+pub fn skip_verify() -> bool {
+    SKIP_VERIFY.get().copied().unwrap_or(false)
+}
+
+/// Check whether a file already on disk is shorter than it should be, so
+/// it may be the beginning of an interrupted download.
+///
+/// The expected size is the one `existing_intact` compares against.
+///
+/// # Arguments
+/// * `path` - The existing file.
+/// * `outdir` - The output directory holding the state manifest.
+/// * `bytes` - The size ENA reports for the file, if any.
+///
+/// # Returns
+/// `true` if the file is shorter than expected.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::validate::truncated;
+/// use std::path::Path;
+/// let partial = truncated(
+///     Path::new("DOWNLOADS/SRR123456_1.fastq.gz"),
+///     Path::new("DOWNLOADS"),
+///     Some(1024),
+/// );
+/// ```
+pub fn truncated(path: &Path, outdir: &Path, bytes: Option<u64>) -> bool {
+    let recorded = recorded(outdir, path).map(|entry| entry.bytes);
+    let Some(expected) = recorded.or(bytes) else {
+        return false;
+    };
+    std::fs::metadata(path).is_ok_and(|m| m.len() < expected)
+}
+
+/// Decide whether a file already on disk can be kept instead of downloaded again.
+///
+/// Its size is compared first, against the state manifest or else the size
+/// ENA reports, so a truncated or stale file is caught without reading it.
+/// Only with `--verify-existing` is a file of the right size hashed too,
+/// against the manifest or else the ENA MD5, on a blocking thread while
+/// holding a CPU slot.
+///
+/// # Arguments
+/// * `path` - The existing file.
+/// * `outdir` - The output directory holding the state manifest.
+/// * `bytes` - The size ENA reports for the file, if any.
+/// * `md5` - The MD5 ENA reports for the file.
+///
+/// # Returns
+/// `true` if the file can be kept.
+///
+/// # Examples
+/// ```rust, no_run
+/// use rsfq::validate::existing_intact;
+/// use std::path::Path;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let keep = existing_intact(
+///         Path::new("DOWNLOADS/SRR123456_1.fastq.gz"),
+///         Path::new("DOWNLOADS"),
+///         Some(1024),
+///         "d41d8cd98f00b204e9800998ecf8427e",
+///     )
+///     .await;
+/// }
+/// ```
+pub async fn existing_intact(path: &Path, outdir: &Path, bytes: Option<u64>, md5: &str) -> bool {
+    let entry = recorded(outdir, path);
+
+    // INFO: a recorded size wins, the file may have been cut or rewritten since
+    if let Some(expected) = entry.as_ref().map(|entry| entry.bytes).or(bytes) {
+        let found = std::fs::metadata(path).map(|m| m.len()).unwrap_or_default();
+        if found != expected {
+            log::warn!(
+                "WARNING: {} is {} but {} were expected!",
+                path.display(),
+                human_bytes(found),
+                human_bytes(expected)
+            );
+            return false;
+        }
+    }
+
+    if !VERIFY_EXISTING.get().copied().unwrap_or(false) {
+        return true;
+    }
+
+    let _cpu = cpu_slot(1).await;
+    let (file, md5) = (path.to_path_buf(), md5.to_string());
+    let intact = blocking(move || match entry.and_then(|entry| entry.verify(&file)) {
+        Some(intact) => intact,
+        None => Checksum::Md5
+            .digest(&file)
+            .is_some_and(|digest| digest == md5),
+    })
+    .await;
+    if !intact {
+        log::warn!("WARNING: {} does not match its checksum!", path.display());
+    }
+    intact
+}
@@ -0,0 +1,37 @@
+use rsfq::claims::{claim, init_claims, CLAIM_EXT};
+
+use std::fs::File;
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn stale_claims_are_taken_over_and_fresh_ones_skipped() {
+    let dir = std::env::temp_dir().join(format!("rsfq-claims-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    init_claims(Some(dir.clone()), Duration::from_secs(60));
+
+    // INFO: left by a machine that died an hour ago
+    let stale = dir.join(format!("SRR123456.{}", CLAIM_EXT));
+    std::fs::write(&stale, "gone\t1\n").unwrap();
+    File::options()
+        .write(true)
+        .open(&stale)
+        .and_then(|file| file.set_modified(SystemTime::now() - Duration::from_secs(3600)))
+        .unwrap();
+    // INFO: held by a machine still working on it
+    let fresh = dir.join(format!("SRR123457.{}", CLAIM_EXT));
+    std::fs::write(&fresh, "busy\t1\n").unwrap();
+
+    let taken = claim("SRR123456");
+    assert!(taken.is_some());
+    assert_ne!(std::fs::read_to_string(&stale).unwrap(), "gone\t1\n");
+    assert!(claim("SRR123456").is_none());
+
+    assert!(claim("SRR123457").is_none());
+    assert_eq!(std::fs::read_to_string(&fresh).unwrap(), "busy\t1\n");
+
+    drop(taken);
+    assert!(!stale.exists());
+    assert!(fresh.exists());
+    // INFO: nothing but the claims themselves is left behind
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+}
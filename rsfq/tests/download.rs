@@ -0,0 +1,103 @@
+use rsfq::core::download;
+use rsfq::runner::{init_runner, MockRunner, Outcome};
+use rsfq::utils::Retriever;
+
+use once_cell::sync::Lazy;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const FASTQ: &str = "@SRR123456.1\nACGT\n+\nIIII\n";
+const FASTQ_MD5: &str = "695e214a20aea987c17798049e106555";
+
+// INFO: curl prints `FASTQ` to stdout, wget is not installed
+static MOCK: Lazy<Arc<MockRunner>> = Lazy::new(|| {
+    Arc::new(
+        MockRunner::default()
+            .on("curl", |call| {
+                assert!(!call.args.iter().any(|arg| arg == "-o"));
+                Ok(Outcome {
+                    stdout: FASTQ.as_bytes().to_vec(),
+                    ..Outcome::exit(0)
+                })
+            })
+            .without("wget"),
+    )
+});
+
+/// Route every program of the test binary through the mock.
+fn mock() -> &'static MockRunner {
+    init_runner(MOCK.clone());
+    &MOCK
+}
+
+/// Count the calls of a program whose arguments mention `needle`.
+fn calls_of(mock: &MockRunner, program: &str, needle: &str) -> usize {
+    mock.calls()
+        .iter()
+        .filter(|call| call.program == program && call.args.iter().any(|arg| arg.contains(needle)))
+        .count()
+}
+
+/// A fresh directory for one test.
+fn outdir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rsfq-download-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn verified_download_gets_its_final_name() {
+    let mock = mock();
+    let dir = outdir("verified");
+    let url = "https://example.org/vol1/fastq/SRR123/SRR123456/SRR123456.fastq.gz";
+
+    let fetched = download(url, &dir, 0, 0, false, FASTQ_MD5, None, &Retriever::Curl).await;
+
+    let fastq = dir.join("SRR123456.fastq.gz");
+    assert_eq!(
+        fetched.map(|downloaded| downloaded.path),
+        Some(fastq.clone())
+    );
+    assert_eq!(std::fs::read_to_string(&fastq).unwrap(), FASTQ);
+    assert!(!dir.join("SRR123456.fastq.gz.part").exists());
+    assert_eq!(calls_of(mock, "curl", "SRR123456.fastq.gz"), 1);
+}
+
+#[tokio::test]
+async fn checksum_mismatch_keeps_the_final_name_free() {
+    let mock = mock();
+    let dir = outdir("mismatch");
+    let url = "https://example.org/vol1/fastq/SRR123/SRR123457/SRR123457.fastq.gz";
+
+    let fetched = download(
+        url,
+        &dir,
+        0,
+        0,
+        false,
+        "0".repeat(32).as_str(),
+        None,
+        &Retriever::Curl,
+    )
+    .await;
+
+    assert_eq!(fetched, None);
+    assert!(!dir.join("SRR123457.fastq.gz").exists());
+    assert!(!dir.join("SRR123457.fastq.gz.part").exists());
+    assert_eq!(calls_of(mock, "curl", "SRR123457.fastq.gz"), 1);
+}
+
+#[tokio::test]
+async fn missing_retriever_is_never_run() {
+    let mock = mock();
+    let dir = outdir("missing");
+    let url = "https://example.org/vol1/fastq/SRR123/SRR123458/SRR123458.fastq.gz";
+
+    let fetched = download(url, &dir, 0, 0, false, FASTQ_MD5, None, &Retriever::Wget).await;
+
+    assert_eq!(fetched, None);
+    assert!(!dir.join("SRR123458.fastq.gz").exists());
+    assert_eq!(calls_of(mock, "wget", "SRR123458"), 0);
+}
@@ -0,0 +1,218 @@
+use rsfq::net::{
+    client, fetch, init_connections, parse_retry_after, send_with_retry, HostLimit, HostLimiter,
+    Netrc, RetryPolicy,
+};
+use rsfq::utils::segments_control;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+// INFO: as the native retriever, files of two such segments are split in two ranges
+const SEGMENT: usize = 8 * 1024 * 1024;
+
+/// A request as the test server saw it.
+struct Request {
+    method: String,
+    range: Option<(usize, usize)>,
+}
+
+/// Serve every connection to a local port with `handle`, one thread each.
+fn serve<F>(handle: F) -> String
+where
+    F: Fn(Request, &mut TcpStream) + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = Arc::new(handle);
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let handle = handle.clone();
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let method = line
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+
+                let mut range = None;
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.to_lowercase().strip_prefix("range: bytes=") {
+                        let (start, end) = value.trim().split_once('-').unwrap();
+                        range = Some((start.parse().unwrap(), end.parse().unwrap()));
+                    }
+                }
+
+                let mut stream = stream;
+                handle(Request { method, range }, &mut stream);
+            });
+        }
+    });
+
+    url
+}
+
+/// A fresh directory for one test.
+fn outdir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rsfq-net-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn netrc_only_lends_named_machines() {
+    let netrc = Netrc::parse(
+        "machine FTP.example.org\n  login me\n  password pw\n\
+         macdef init\n  machine macro.example.org login no password no\n\n\
+         machine other.example.org login you password secret account ignored\n\
+         default login anonymous password me@example.org\n",
+    );
+
+    let creds = netrc.get("ftp.example.org").unwrap();
+    assert_eq!((creds.user.as_str(), creds.password.as_str()), ("me", "pw"));
+    let creds = netrc.get("OTHER.example.org").unwrap();
+    assert_eq!(
+        (creds.user.as_str(), creds.password.as_str()),
+        ("you", "secret")
+    );
+    assert!(netrc.get("macro.example.org").is_none());
+    assert!(netrc.get("ftp.sra.ebi.ac.uk").is_none());
+}
+
+#[tokio::test]
+async fn host_limits_hold_transfers_until_a_slot_frees() {
+    let limiter = HostLimiter::new(&[HostLimit::from_str("Example.org=1").unwrap()]);
+
+    let first = limiter.acquire("https://example.org/a.fastq.gz").await;
+    assert!(first.is_some());
+    let waiting = tokio::time::timeout(
+        Duration::from_millis(100),
+        limiter.acquire("example.org/b.fastq.gz"),
+    )
+    .await;
+    assert!(waiting.is_err());
+
+    drop(first);
+    let second = tokio::time::timeout(
+        Duration::from_millis(100),
+        limiter.acquire("ftp://user:pw@EXAMPLE.org:21/b.fastq.gz"),
+    )
+    .await;
+    assert!(second.unwrap().is_some());
+    assert!(limiter
+        .acquire("https://uncapped.example.org/c")
+        .await
+        .is_none());
+}
+
+#[test]
+fn retry_after_is_read_as_seconds_or_a_date_and_capped() {
+    let soon = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(120));
+    let wait = parse_retry_after(&soon).unwrap();
+    assert!((110..=120).contains(&wait));
+
+    assert_eq!(parse_retry_after(" 3 "), Some(3));
+    assert_eq!(parse_retry_after("999999"), Some(600));
+    assert_eq!(parse_retry_after("soon"), None);
+}
+
+#[tokio::test]
+async fn retry_after_replaces_the_backoff() {
+    let answered = Arc::new(AtomicUsize::new(0));
+    let counter = answered.clone();
+    let url = serve(move |_, stream| {
+        let answer = if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+            "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        } else {
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"
+        };
+        let _ = stream.write_all(answer.as_bytes());
+    });
+
+    // INFO: a minute of backoff, so only Retry-After explains a quick retry
+    let started = Instant::now();
+    let response = send_with_retry(client().get(&url), RetryPolicy::new(1, 60))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(answered.load(Ordering::SeqCst), 2);
+    let waited = started.elapsed();
+    assert!(waited >= Duration::from_secs(1) && waited < Duration::from_secs(30));
+}
+
+#[tokio::test]
+async fn truncated_segments_resume_from_their_progress() {
+    let body = Arc::new(
+        (0..2 * SEGMENT)
+            .map(|i| (i * 7 % 251) as u8)
+            .collect::<Vec<u8>>(),
+    );
+    let truncate = Arc::new(AtomicBool::new(true));
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+
+    let (served, cut, seen) = (body.clone(), truncate.clone(), ranges.clone());
+    let url = serve(move |request, stream| {
+        if request.method == "HEAD" {
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\nConnection: close\r\n\r\n",
+                served.len()
+            );
+            return;
+        }
+
+        let (start, end) = request.range.unwrap();
+        seen.lock().unwrap().push(start);
+        let _ = write!(
+            stream,
+            "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nConnection: close\r\n\r\n",
+            end + 1 - start,
+            start,
+            end,
+            served.len()
+        );
+        // INFO: the second range is cut halfway, once the first is surely in
+        if start >= SEGMENT && cut.load(Ordering::SeqCst) {
+            let _ = stream.write_all(&served[start..start + SEGMENT / 2]);
+            let _ = stream.flush();
+            std::thread::sleep(Duration::from_secs(1));
+            return;
+        }
+        let _ = stream.write_all(&served[start..=end]);
+    });
+
+    init_connections(2);
+    let part = outdir("segments").join("SRR123456.fastq.gz.part");
+    let url = format!("{}/SRR123456.fastq.gz", url);
+
+    assert!(fetch(&url, &part, &[]).await.is_err());
+    // INFO: past the whole first range, the length alone would resume in the middle of the second
+    assert!(part.metadata().unwrap().len() > SEGMENT as u64);
+    assert!(segments_control(&part).exists());
+    let mut first = std::mem::take(&mut *ranges.lock().unwrap());
+    first.sort();
+    assert_eq!(first, vec![0, SEGMENT]);
+
+    truncate.store(false, Ordering::SeqCst);
+    let fetched = fetch(&url, &part, &[]).await.unwrap();
+
+    assert_eq!(*ranges.lock().unwrap(), vec![SEGMENT]);
+    assert_eq!(fetched.bytes, body.len() as u64);
+    assert_eq!(fetched.md5, Some(format!("{:x}", md5::compute(&*body))));
+    assert!(std::fs::read(&part).unwrap() == *body);
+    assert!(!segments_control(&part).exists());
+}
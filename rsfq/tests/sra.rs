@@ -0,0 +1,146 @@
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use once_cell::sync::Lazy;
+use rsfq::net::init_retry;
+use rsfq::provs::sra::download_run;
+use rsfq::runner::{init_runner, MockRunner, Outcome};
+use rsfq::utils::Layout;
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// INFO: two paired spots, as fasterq-dump streams them with --qual-defline +$si:$ri
+const STREAM: &str = "@RUN.1 1\nACGT\n+1:1\nIIII\n\
+                      @RUN.1 1\nTTTT\n+1:2\nJJJJ\n\
+                      @RUN.2 2\nGGGG\n+2:1\nKKKK\n\
+                      @RUN.2 2\nCCCC\n+2:2\nLLLL\n";
+// INFO: fasterq-dump fails on this run after streaming its reads
+const FAILING: &str = "SRR900000003";
+
+// INFO: fasterq-dump streams `STREAM` under the run's name, pigz gzips its stdin
+static MOCK: Lazy<Arc<MockRunner>> = Lazy::new(|| {
+    Arc::new(
+        MockRunner::default()
+            .on("fasterq-dump", |call| {
+                if call.args.iter().any(|arg| arg == "--version") {
+                    return Ok(Outcome {
+                        stdout: b"fasterq-dump : 3.1.1".to_vec(),
+                        ..Outcome::exit(0)
+                    });
+                }
+                let run = &call.args[0];
+                Ok(Outcome {
+                    stdout: STREAM.replace("RUN", run).into_bytes(),
+                    ..Outcome::exit(if run == FAILING { 1 } else { 0 })
+                })
+            })
+            .on("pigz", |call| {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+                encoder.write_all(&call.stdin)?;
+                Ok(Outcome {
+                    stdout: encoder.finish()?,
+                    ..Outcome::exit(0)
+                })
+            }),
+    )
+});
+
+/// Route every program of the test binary through the mock, without
+/// retrying requests to the SRA Data Locator.
+fn mock() -> &'static MockRunner {
+    init_retry(0, 0);
+    init_runner(MOCK.clone());
+    &MOCK
+}
+
+/// A fresh directory for one test.
+fn outdir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rsfq-sra-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Read a gzipped FASTQ back.
+fn gunzip(path: &Path) -> String {
+    let mut content = String::new();
+    MultiGzDecoder::new(std::fs::File::open(path).unwrap())
+        .read_to_string(&mut content)
+        .unwrap();
+    content
+}
+
+#[tokio::test]
+async fn streamed_mates_go_to_one_file_each() {
+    let mock = mock();
+    let dir = outdir("mates");
+
+    let produced = download_run("SRR900000001", &dir, 4, 1, 0, false, Layout::Paired, None)
+        .await
+        .unwrap();
+
+    let (r1, r2) = (
+        dir.join("SRR900000001_1.fastq.gz"),
+        dir.join("SRR900000001_2.fastq.gz"),
+    );
+    assert_eq!(produced, vec![r1.clone(), r2.clone()]);
+    assert_eq!(
+        gunzip(&r1),
+        "@SRR900000001.1 1\nACGT\n+\nIIII\n@SRR900000001.2 2\nGGGG\n+\nKKKK\n"
+    );
+    assert_eq!(
+        gunzip(&r2),
+        "@SRR900000001.1 1\nTTTT\n+\nJJJJ\n@SRR900000001.2 2\nCCCC\n+\nLLLL\n"
+    );
+    assert!(!dir.join("SRR900000001_1.fastq.gz.part").exists());
+
+    // INFO: the two compressors share the thread budget
+    let pigz = mock
+        .calls()
+        .into_iter()
+        .filter(|call| call.program == "pigz" && call.stdin.starts_with(b"@SRR900000001."))
+        .collect::<Vec<_>>();
+    assert_eq!(pigz.len(), 2);
+    assert!(pigz.iter().all(|call| call.args[..2] == ["-p", "2"]));
+}
+
+#[tokio::test]
+async fn streaming_stops_after_the_spots_kept() {
+    mock();
+    let dir = outdir("spots");
+
+    download_run(
+        "SRR900000002",
+        &dir,
+        2,
+        1,
+        0,
+        false,
+        Layout::Paired,
+        Some(1),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        gunzip(&dir.join("SRR900000002_1.fastq.gz")),
+        "@SRR900000002.1 1\nACGT\n+\nIIII\n"
+    );
+    assert_eq!(
+        gunzip(&dir.join("SRR900000002_2.fastq.gz")),
+        "@SRR900000002.1 1\nTTTT\n+\nJJJJ\n"
+    );
+}
+
+#[tokio::test]
+async fn failed_dump_leaves_no_fastq() {
+    mock();
+    let dir = outdir("failed");
+
+    let dumped = download_run(FAILING, &dir, 2, 1, 0, false, Layout::Paired, None).await;
+
+    assert!(dumped.is_err());
+    for suffix in ["_1.fastq.gz", "_2.fastq.gz", "_1.fastq.gz.part"] {
+        assert!(!dir.join(format!("{}{}", FAILING, suffix)).exists());
+    }
+}